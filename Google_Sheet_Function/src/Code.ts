@@ -50,19 +50,30 @@ function UPDATE_QB_ACCOUNT(accountNumber, accountValue, cellAddress, spreadsheet
  * The Windows service is responsible for selecting the account and scheduling execution.
  */
 function doPost(e) {
+    let data = {};
     try {
-        const data = JSON.parse(e.postData.contents);
+        data = JSON.parse(e.postData.contents);
+        if (data.runId) {
+            // The service's run ID, so a cell's value can be traced to the run that wrote it
+            console.log(`[doPost] run ${data.runId}: ${data.action || 'update'} ${data.cellAddress || data.anchor || data.range || ''}`);
+        }
+        // Requests carrying an action are routed by name; plain payloads are single-cell updates
+        if (data.action === 'auth') {
+            // Lightweight key check so the service can fail fast before querying QuickBooks
+            const scriptApiKey = PropertiesService.getScriptProperties().getProperty('QB_API_KEY');
+            return jsonResponse({ success: true, authorized: !!data.apiKey && data.apiKey === scriptApiKey });
+        }
+        if (data.action) {
+            validateApiKey(data.apiKey);
+            return jsonResponse(handleAction(data));
+        }
         // Validate required fields
         if (!data.accountNumber || data.accountValue === undefined || !data.cellAddress) {
             console.error('[doPost] Missing required fields:', data);
             throw new Error('Missing required fields: accountNumber, accountValue, cellAddress');
         }
         // Validate API key for security
-        const scriptApiKey = PropertiesService.getScriptProperties().getProperty('QB_API_KEY');
-        if (!data.apiKey || data.apiKey !== scriptApiKey) {
-            console.error('[doPost] Invalid API key:', data.apiKey);
-            throw new Error('Invalid API key');
-        }
+        validateApiKey(data.apiKey);
         // Update the QuickBooks account data
         const result = UPDATE_QB_ACCOUNT(data.accountNumber, data.accountValue, data.cellAddress, data.spreadsheetId, // Pass spreadsheet ID if provided
             data.sheetName);
@@ -72,10 +83,200 @@ function doPost(e) {
     }
     catch (error) {
         console.error('[doPost] Error:', error);
-        return ContentService
-            .createTextOutput(JSON.stringify({ success: false, error: error instanceof Error ? error.message : String(error) }))
-            .setMimeType(ContentService.MimeType.JSON);
+        const message = error instanceof Error ? error.message : String(error);
+        // Error schema: { success: false, error, errorCode?, cell? } - errorCode lets the service explain known failures
+        const body = { success: false, error: message };
+        const code = classifyError(message);
+        if (code) {
+            body.errorCode = code;
+            body.cell = data.cellAddress || data.anchor;
+        }
+        return jsonResponse(body);
+    }
+}
+/**
+ * Map a Sheets error message onto a stable error code for the Windows service
+ * @param {string} message - The caught error's message
+ * @return {string|null} 'CELL_PROTECTED' for protected ranges, 'VALUE_OUT_OF_RANGE' for rejected values, otherwise null
+ */
+function classifyError(message) {
+    if (/protected cell|protected range|protected sheet|protection/i.test(message)) {
+        return 'CELL_PROTECTED';
+    }
+    if (/out of range|too large|exceeds the maximum|50000 characters|not a valid number/i.test(message)) {
+        return 'VALUE_OUT_OF_RANGE';
+    }
+    return null;
+}
+/**
+ * Throws unless the request's API key matches the one stored by setupQuickBooksIntegration()
+ * @param {string} apiKey - The API key sent by the Windows service
+ */
+function validateApiKey(apiKey) {
+    const scriptApiKey = PropertiesService.getScriptProperties().getProperty('QB_API_KEY');
+    if (!apiKey || apiKey !== scriptApiKey) {
+        console.error('[validateApiKey] Invalid API key:', apiKey);
+        throw new Error('Invalid API key');
+    }
+}
+/**
+ * Wrap a result object as a JSON web app response
+ * @param {Object} body - The response body
+ */
+function jsonResponse(body) {
+    return ContentService
+        .createTextOutput(JSON.stringify(body))
+        .setMimeType(ContentService.MimeType.JSON);
+}
+/**
+ * Dispatch an action request from the Windows service
+ * @param {Object} data - The parsed POST body; data.action selects the handler
+ * @return {Object} Response body (always includes success)
+ */
+function handleAction(data) {
+    switch (data.action) {
+        case 'appendRow':
+            return APPEND_QB_ROW(data.spreadsheetId, data.sheetName, data.anchor, data.header, data.values);
+        case 'writeRows':
+            return WRITE_QB_ROWS(data.spreadsheetId, data.sheetName, data.anchor, data.rows);
+        case 'clearRange':
+            return CLEAR_QB_RANGE(data.spreadsheetId, data.sheetName, data.range);
+        case 'writeCells':
+            return WRITE_QB_CELLS(data.spreadsheetId, data.cells);
+        case 'getFormula':
+            return GET_QB_FORMULA(data.spreadsheetId, data.sheetName, data.cellAddress);
+        case 'getDimensions':
+            return GET_QB_DIMENSIONS(data.spreadsheetId, data.sheetName);
+        case 'getValue':
+            return GET_QB_VALUE(data.spreadsheetId, data.sheetName, data.cellAddress);
+        default:
+            throw new Error(`Unknown action: ${data.action}`);
+    }
+}
+/**
+ * Appends a row of values beneath a header row anchored at a cell (chart data blocks)
+ *
+ * The header is (re)written at the anchor row, then values go into the first row below it
+ * whose anchor-column cell is empty.
+ * @param {string} spreadsheetId - The spreadsheet ID (optional, uses active if not provided)
+ * @param {string} sheetName - The name of the sheet (optional)
+ * @param {string} anchor - Top-left cell of the header row (e.g., "A1")
+ * @param {Array} header - Header titles
+ * @param {Array} values - Values for the new row
+ * @return {Object} Result including the row number written
+ */
+function APPEND_QB_ROW(spreadsheetId, sheetName, anchor, header, values) {
+    const sheet = getTargetSheet(spreadsheetId, sheetName);
+    const anchorRange = sheet.getRange(anchor);
+    const headerRow = anchorRange.getRow();
+    const firstColumn = anchorRange.getColumn();
+    if (header && header.length > 0) {
+        sheet.getRange(headerRow, firstColumn, 1, header.length).setValues([header]);
+    }
+    let row = headerRow + 1;
+    while (row <= sheet.getMaxRows() && sheet.getRange(row, firstColumn).getValue() !== '') {
+        row++;
+    }
+    sheet.getRange(row, firstColumn, 1, values.length).setValues([values]);
+    return { success: true, row: row };
+}
+/**
+ * Writes a rectangular block of rows with its top-left cell at the anchor (type-grouped reports)
+ * @param {string} spreadsheetId - The spreadsheet ID (optional, uses active if not provided)
+ * @param {string} sheetName - The name of the sheet (optional)
+ * @param {string} anchor - Top-left cell of the block (e.g., "A1")
+ * @param {Array} rows - Rows of values, all the same width
+ * @return {Object} Result including the number of rows written
+ */
+function WRITE_QB_ROWS(spreadsheetId, sheetName, anchor, rows) {
+    const sheet = getTargetSheet(spreadsheetId, sheetName);
+    if (rows && rows.length > 0) {
+        const anchorRange = sheet.getRange(anchor);
+        sheet.getRange(anchorRange.getRow(), anchorRange.getColumn(), rows.length, rows[0].length).setValues(rows);
+    }
+    return { success: true, rows: rows ? rows.length : 0 };
+}
+/**
+ * Writes a batch of single cells, reporting each one so a bad cell doesn't fail the rest
+ * @param {string} spreadsheetId - The spreadsheet ID (optional, uses active if not provided)
+ * @param {Array} cells - Objects of { sheetName, cellAddress, value }
+ * @return {Object} Result; results holds { cell, success, error?, errorCode? } per cell in request order
+ */
+function WRITE_QB_CELLS(spreadsheetId, cells) {
+    const results = (cells || []).map(function (c) {
+        try {
+            getTargetSheet(spreadsheetId, c.sheetName).getRange(c.cellAddress).setValue(c.value);
+            return { cell: c.cellAddress, success: true };
+        }
+        catch (error) {
+            console.error('[WRITE_QB_CELLS] Error at', c.cellAddress, error);
+            const message = error instanceof Error ? error.message : String(error);
+            const result = { cell: c.cellAddress, success: false, error: message };
+            const errorCode = classifyError(message);
+            if (errorCode) {
+                result.errorCode = errorCode;
+            }
+            return result;
+        }
+    });
+    return { success: true, results: results };
+}
+/**
+ * Clears the contents of a range, leaving its formatting in place
+ * @param {string} spreadsheetId - The spreadsheet ID (optional, uses active if not provided)
+ * @param {string} sheetName - The name of the sheet (optional)
+ * @param {string} range - The A1 range to clear (e.g., "A20:B200")
+ * @return {Object} Result
+ */
+function CLEAR_QB_RANGE(spreadsheetId, sheetName, range) {
+    getTargetSheet(spreadsheetId, sheetName).getRange(range).clearContent();
+    return { success: true };
+}
+/**
+ * Reports a cell's formula so the service can avoid overwriting it
+ * @param {string} spreadsheetId - The spreadsheet ID (optional, uses active if not provided)
+ * @param {string} sheetName - The name of the sheet (optional)
+ * @param {string} cellAddress - The cell to inspect (e.g., "B7")
+ * @return {Object} Result; formula is "" when the cell holds a plain value
+ */
+function GET_QB_FORMULA(spreadsheetId, sheetName, cellAddress) {
+    return { success: true, formula: getTargetSheet(spreadsheetId, sheetName).getRange(cellAddress).getFormula() };
+}
+/**
+ * Reports a cell's current value so the service can skip writes that would change nothing
+ * @param {string} spreadsheetId - The spreadsheet ID (optional, uses active if not provided)
+ * @param {string} sheetName - The name of the sheet (optional)
+ * @param {string} cellAddress - The cell to read (e.g., "B7")
+ * @return {Object} Result; value is a number, text, or "" when the cell is empty
+ */
+function GET_QB_VALUE(spreadsheetId, sheetName, cellAddress) {
+    return { success: true, value: getTargetSheet(spreadsheetId, sheetName).getRange(cellAddress).getValue() };
+}
+/**
+ * Reports a sheet's grid size so the service can check configured cells fit inside it
+ * @param {string} spreadsheetId - The spreadsheet ID (optional, uses active if not provided)
+ * @param {string} sheetName - The name of the sheet (optional)
+ * @return {Object} Result with rows and columns, counting empty ones
+ */
+function GET_QB_DIMENSIONS(spreadsheetId, sheetName) {
+    const sheet = getTargetSheet(spreadsheetId, sheetName);
+    return { success: true, rows: sheet.getMaxRows(), columns: sheet.getMaxColumns() };
+}
+/**
+ * Resolve the sheet a request targets
+ * @param {string} spreadsheetId - The spreadsheet ID (optional, uses active if not provided)
+ * @param {string} sheetName - The name of the sheet (optional, uses active if not provided)
+ * @return {Sheet} The sheet
+ */
+function getTargetSheet(spreadsheetId, sheetName) {
+    const spreadsheet = spreadsheetId ?
+        SpreadsheetApp.openById(spreadsheetId) :
+        SpreadsheetApp.getActiveSpreadsheet();
+    const sheet = sheetName ? spreadsheet.getSheetByName(sheetName) : spreadsheet.getActiveSheet();
+    if (!sheet) {
+        throw new Error(`Sheet "${sheetName}" not found`);
     }
+    return sheet;
 }
 /**
  * Test function to verify Web App deployment
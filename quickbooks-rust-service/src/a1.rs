@@ -0,0 +1,164 @@
+// A1 notation helpers
+// Parses and formats cell references such as "D5", "A1:C20" or "'My Sheet'!B2"
+
+use anyhow::{anyhow, Result};
+use std::fmt;
+
+/// A single cell position; column and row are 1-based like the spreadsheet UI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CellRef {
+    pub col: u32,
+    pub row: u32,
+}
+
+impl CellRef {
+    pub fn parse(address: &str) -> Result<Self> {
+        let address = address.trim();
+        let letters: String = address.chars().take_while(|c| c.is_ascii_alphabetic()).collect();
+        let digits = &address[letters.len()..];
+        if letters.is_empty() || digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(anyhow!("'{}' is not a valid A1 cell address", address));
+        }
+        let col = letters
+            .to_ascii_uppercase()
+            .chars()
+            .try_fold(0u32, |acc, c| acc.checked_mul(26)?.checked_add(c as u32 - 'A' as u32 + 1))
+            .ok_or_else(|| anyhow!("Column in '{}' is out of range", address))?;
+        let row: u32 = digits.parse().map_err(|_| anyhow!("Row in '{}' is out of range", address))?;
+        if row == 0 {
+            return Err(anyhow!("'{}' is not a valid A1 cell address (rows start at 1)", address));
+        }
+        Ok(Self { col, row })
+    }
+
+    /// Whether the cell lies inside a grid of `rows` x `columns`
+    pub fn within(&self, rows: u32, columns: u32) -> bool {
+        self.row <= rows && self.col <= columns
+    }
+}
+
+/// A rectangular range such as "A10:C40"; `start` is the top-left corner
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellRange {
+    pub start: CellRef,
+    pub end: CellRef,
+}
+
+impl CellRange {
+    pub fn parse(range: &str) -> Result<Self> {
+        let (first, second) = range
+            .split_once(':')
+            .ok_or_else(|| anyhow!("'{}' is not a valid A1 range (expected e.g. A1:C20)", range))?;
+        let (a, b) = (CellRef::parse(first)?, CellRef::parse(second)?);
+        Ok(Self {
+            start: CellRef { col: a.col.min(b.col), row: a.row.min(b.row) },
+            end: CellRef { col: a.col.max(b.col), row: a.row.max(b.row) },
+        })
+    }
+}
+
+impl fmt::Display for CellRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.start, self.end)
+    }
+}
+
+pub fn column_letters(mut col: u32) -> String {
+    let mut letters = Vec::new();
+    while col > 0 {
+        let rem = (col - 1) % 26;
+        letters.push((b'A' + rem as u8) as char);
+        col = (col - 1) / 26;
+    }
+    letters.iter().rev().collect()
+}
+
+impl fmt::Display for CellRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", column_letters(self.col), self.row)
+    }
+}
+
+/// Quote a sheet name for an A1 reference, doubling embedded apostrophes:
+/// "O'Brien's Data" becomes "'O''Brien''s Data'". Quoting is always valid, even when not required.
+pub fn quote_sheet_name(sheet_name: &str) -> String {
+    format!("'{}'", sheet_name.replace('\'', "''"))
+}
+
+/// A sheet-qualified reference such as "'My Sheet'!B2"
+pub fn qualified(sheet_name: &str, cell: &str) -> String {
+    format!("{}!{}", quote_sheet_name(sheet_name), cell)
+}
+
+/// Split an optional sheet prefix ("Sheet1!A1" or "'My Sheet'!A1") from the cell part
+pub fn split_sheet(address: &str) -> (Option<String>, &str) {
+    match address.rfind('!') {
+        Some(idx) => {
+            let sheet = &address[..idx];
+            let sheet = if sheet.len() >= 2 && sheet.starts_with('\'') && sheet.ends_with('\'') {
+                sheet[1..sheet.len() - 1].replace("''", "'")
+            } else {
+                sheet.to_string()
+            };
+            (Some(sheet), &address[idx + 1..])
+        }
+        None => (None, address),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_valid_cells() {
+        assert_eq!(CellRef::parse("A1").unwrap(), CellRef { col: 1, row: 1 });
+        assert_eq!(CellRef::parse(" d5 ").unwrap(), CellRef { col: 4, row: 5 });
+        assert_eq!(CellRef::parse("Z10").unwrap(), CellRef { col: 26, row: 10 });
+        assert_eq!(CellRef::parse("AA1").unwrap(), CellRef { col: 27, row: 1 });
+        assert_eq!(CellRef::parse("XFD1048576").unwrap(), CellRef { col: 16384, row: 1_048_576 });
+        for cell in ["A1", "Z10", "AA1", "XFD1048576"] {
+            assert_eq!(CellRef::parse(cell).unwrap().to_string(), cell);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_cells() {
+        for cell in ["", "A", "12", "A0", "1A", "A1B", "A-1", "A1:B2", "Sheet1!A1", "$A$1", "A99999999999"] {
+            assert!(CellRef::parse(cell).is_err(), "{}", cell);
+        }
+        assert!(CellRef::parse("AAAAAAAAA1").is_err(), "column overflow");
+    }
+
+    #[test]
+    fn ranges_normalize_their_corners() {
+        let range = CellRange::parse("C20:A10").unwrap();
+        assert_eq!(range.start, CellRef { col: 1, row: 10 });
+        assert_eq!(range.end, CellRef { col: 3, row: 20 });
+        assert_eq!(range.to_string(), "A10:C20");
+        assert!(CellRange::parse("A10").is_err());
+        assert!(CellRange::parse("A10:C").is_err());
+    }
+
+    #[test]
+    fn sheet_names_are_quoted_and_escaped() {
+        assert_eq!(quote_sheet_name("Balances"), "'Balances'");
+        assert_eq!(quote_sheet_name("O'Brien's Data"), "'O''Brien''s Data'");
+        assert_eq!(qualified("My Sheet", "B2"), "'My Sheet'!B2");
+        assert_eq!(qualified("O'Brien's Data", "C3"), "'O''Brien''s Data'!C3");
+    }
+
+    #[test]
+    fn split_sheet_undoes_qualified() {
+        assert_eq!(split_sheet("B2"), (None, "B2"));
+        assert_eq!(split_sheet("Sheet1!A1"), (Some("Sheet1".to_string()), "A1"));
+        assert_eq!(split_sheet("'My Sheet'!B2"), (Some("My Sheet".to_string()), "B2"));
+        let qualified = qualified("O'Brien's Data", "C3");
+        assert_eq!(split_sheet(&qualified), (Some("O'Brien's Data".to_string()), "C3"));
+        // A name containing "!" splits at the last one
+        assert_eq!(split_sheet("'Q1!Q2'!D4"), (Some("Q1!Q2".to_string()), "D4"));
+        // Malformed input is passed through for CellRef::parse to reject
+        let (_, cell) = split_sheet("Sheet1!");
+        assert!(CellRef::parse(cell).is_err());
+    }
+}
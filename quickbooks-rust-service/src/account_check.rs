@@ -0,0 +1,119 @@
+// Account mapping check
+// Reports whether each configured account name resolves to exactly one QuickBooks account
+
+use crate::config::Config;
+use crate::name_match::suggest;
+
+const SUGGESTION_LIMIT: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccountCheck {
+    Ok(String),
+    Missing { suggestions: Vec<String> },
+    Ambiguous(Vec<String>),
+}
+
+impl AccountCheck {
+    pub fn is_ok(&self) -> bool {
+        matches!(self, AccountCheck::Ok(_))
+    }
+}
+
+/// Check every account the config reads (sync blocks and chart accounts) against `qb_names`
+pub fn check_accounts(config: &Config, qb_names: &[String]) -> Vec<(String, AccountCheck)> {
+    let normalization = config.quickbooks.name_normalization.unwrap_or_default();
+    config
+        .account_names()
+        .into_iter()
+        .map(|name| {
+            let matches = normalization.find_matches(qb_names, name);
+            let check = match matches.as_slice() {
+                [single] => AccountCheck::Ok(single.to_string()),
+                [] => AccountCheck::Missing {
+                    suggestions: suggest(qb_names, name, SUGGESTION_LIMIT).into_iter().map(String::from).collect(),
+                },
+                many => AccountCheck::Ambiguous(many.iter().map(|s| s.to_string()).collect()),
+            };
+            (name.to_string(), check)
+        })
+        .collect()
+}
+
+/// Print one line per account; returns how many are missing or ambiguous
+pub fn print_report(results: &[(String, AccountCheck)]) -> usize {
+    for (name, check) in results {
+        match check {
+            AccountCheck::Ok(qb_name) if qb_name == name => println!("OK         {}", name),
+            AccountCheck::Ok(qb_name) => println!("OK         {} (matches '{}')", name, qb_name),
+            AccountCheck::Missing { suggestions } => {
+                if suggestions.is_empty() {
+                    println!("MISSING    {}", name);
+                } else {
+                    println!("MISSING    {} (did you mean: {})", name, suggestions.join(", "));
+                }
+            },
+            AccountCheck::Ambiguous(candidates) => {
+                println!("AMBIGUOUS  {} (matches: {})", name, candidates.join(", "));
+            },
+        }
+    }
+    results.iter().filter(|(_, check)| !check.is_ok()).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_mode::FileMode;
+    use crate::mock_quickbooks::MockQuickBooks;
+    use crate::qbxml_safe::qbxml_request_processor::AccountQueryOptions;
+    use crate::quickbooks::QuickBooksProcessor;
+    use figment::{Figment, providers::{Format, Toml}};
+
+    fn config(accounts: &[&str]) -> Config {
+        let mut toml = r#"
+            [quickbooks]
+            company_file = "AUTO"
+            name_normalization = "case_insensitive"
+
+            [google_sheets]
+            webapp_url = "https://script.google.com/macros/s/abc123/exec"
+            api_key = "key"
+        "#.to_string();
+        for (row, account) in accounts.iter().enumerate() {
+            toml.push_str(&format!(
+                "\n[[sync_blocks]]\nspreadsheet_id = \"1AbC\"\naccount_full_name = \"{}\"\nsheet_name = \"Balances\"\ncell_address = \"B{}\"\n",
+                account, row + 2
+            ));
+        }
+        Figment::from(Toml::string(&toml)).extract().unwrap()
+    }
+
+    // Account names as the mock's AccountQueryRs reports them
+    fn qb_names() -> Vec<String> {
+        let names = ["Checking", "Savings", "SAVINGS", "Loans:Long Term"];
+        let mock = MockQuickBooks::new(names.iter().map(|name| (name.to_string(), 1.0)).collect());
+        let ticket = mock.begin_session("", FileMode::DoNotCare).unwrap();
+        let xml = mock.get_account_xml(&ticket, &AccountQueryOptions::default()).unwrap().unwrap();
+        mock.parser().get_account_names(&xml)
+    }
+
+    #[test]
+    fn each_account_is_ok_missing_or_ambiguous() {
+        let results = check_accounts(&config(&["Checking", "checking", "Loans:Long Trem", "Payroll", "savings"]), &qb_names());
+        assert_eq!(results, [
+            ("Checking".to_string(), AccountCheck::Ok("Checking".to_string())),
+            ("Loans:Long Trem".to_string(), AccountCheck::Missing { suggestions: vec!["Loans:Long Term".to_string()] }),
+            ("Payroll".to_string(), AccountCheck::Missing { suggestions: vec![] }),
+            ("checking".to_string(), AccountCheck::Ok("Checking".to_string())),
+            ("savings".to_string(), AccountCheck::Ambiguous(vec!["SAVINGS".to_string(), "Savings".to_string()])),
+        ]);
+        assert_eq!(print_report(&results), 3);
+    }
+
+    #[test]
+    fn an_exact_name_is_never_ambiguous() {
+        let results = check_accounts(&config(&["Savings"]), &qb_names());
+        assert_eq!(results, [("Savings".to_string(), AccountCheck::Ok("Savings".to_string()))]);
+        assert_eq!(print_report(&results), 0);
+    }
+}
@@ -0,0 +1,110 @@
+// Account rows
+// Writes several fields of one account side by side from a single query
+
+use anyhow::Result;
+
+use crate::a1::CellRef;
+use crate::config::{AccountField, AccountRowConfig, MissingDescription};
+use crate::google_sheets::CellValue;
+use crate::row_block::RowBlock;
+use crate::AccountData;
+
+impl AccountRowConfig {
+    /// One row holding the configured fields of `account`, in order, starting at `anchor`
+    pub fn row(&self, account: &AccountData) -> Result<RowBlock> {
+        let row = self.fields.iter().map(|field| self.value(*field, account)).collect();
+        RowBlock::new(&self.anchor, vec![row], None)
+    }
+
+    /// Whether the row must leave a description cell untouched, so it can't go out as one range
+    pub fn skips_description(&self, account: &AccountData) -> bool {
+        account.description.is_none()
+            && self.missing_description.unwrap_or_default() == MissingDescription::Skip
+            && self.fields.contains(&AccountField::Description)
+    }
+
+    /// Each field's cell and value, leaving out description cells that `skips_description` keeps
+    pub fn cells(&self, account: &AccountData) -> Result<Vec<(String, CellValue)>> {
+        let anchor = CellRef::parse(&self.anchor)?;
+        let skip = self.skips_description(account);
+        Ok(self.fields.iter()
+            .enumerate()
+            .filter(|(_, field)| !(skip && **field == AccountField::Description))
+            .map(|(i, field)| {
+                let cell = CellRef { col: anchor.col + i as u32, row: anchor.row };
+                (cell.to_string(), self.value(*field, account))
+            })
+            .collect())
+    }
+
+    fn value(&self, field: AccountField, account: &AccountData) -> CellValue {
+        let sign = self.sign_convention.unwrap_or_default();
+        match field {
+            AccountField::FullName => CellValue::Text(account.account_full_name.clone()),
+            AccountField::Number => CellValue::Text(account.number.clone()),
+            AccountField::AccountType => CellValue::Text(account.account_type.to_string()),
+            AccountField::Description => CellValue::Text(account.description.clone().unwrap_or_default()),
+            AccountField::Balance => CellValue::Number(sign.apply(account.balance, &account.account_type)),
+            // Accounts without subaccounts report no TotalBalance; their own balance is the total
+            AccountField::TotalBalance => CellValue::Number(
+                sign.apply(account.total_balance.unwrap_or(account.balance), &account.account_type),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account_type::AccountType;
+
+    fn account(description: Option<&str>) -> AccountData {
+        AccountData {
+            account_full_name: "Checking".to_string(),
+            number: "1000".to_string(),
+            account_type: AccountType::Bank,
+            description: description.map(str::to_string),
+            balance: 1234.5,
+            total_balance: None,
+            raw_balance: None,
+            raw_total_balance: None,
+        }
+    }
+
+    fn row(missing_description: Option<MissingDescription>) -> AccountRowConfig {
+        AccountRowConfig {
+            spreadsheet_id: "1AbC".to_string(),
+            sheet_name: "Dashboard".to_string(),
+            account_full_name: "Checking".to_string(),
+            anchor: "B4".to_string(),
+            fields: vec![AccountField::FullName, AccountField::Description, AccountField::Balance],
+            sign_convention: None,
+            missing_description,
+        }
+    }
+
+    fn text(value: &str) -> CellValue {
+        CellValue::Text(value.to_string())
+    }
+
+    #[test]
+    fn the_description_lands_beside_the_name() {
+        let block = row(None).row(&account(Some("Main operating account"))).unwrap();
+        assert_eq!(block.rows, [vec![text("Checking"), text("Main operating account"), CellValue::Number(1234.5)]]);
+        let cells = row(None).cells(&account(Some("Main operating account"))).unwrap();
+        assert_eq!(cells[1], ("C4".to_string(), text("Main operating account")));
+    }
+
+    #[test]
+    fn a_missing_description_is_blanked_or_skipped() {
+        let blank = row(Some(MissingDescription::Blank));
+        assert!(!blank.skips_description(&account(None)));
+        assert_eq!(blank.row(&account(None)).unwrap().rows[0][1], text(""));
+
+        let skip = row(Some(MissingDescription::Skip));
+        assert!(skip.skips_description(&account(None)));
+        assert!(!skip.skips_description(&account(Some("Main operating account"))));
+        let cells: Vec<String> = skip.cells(&account(None)).unwrap().into_iter().map(|(cell, _)| cell).collect();
+        assert_eq!(cells, ["B4", "D4"]);
+    }
+}
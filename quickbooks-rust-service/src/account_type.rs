@@ -0,0 +1,144 @@
+// Account types
+// QuickBooks' AccountType as an enum, so code branching on it doesn't compare raw QBXML strings
+
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AccountType {
+    AccountsPayable,
+    AccountsReceivable,
+    Bank,
+    CostOfGoodsSold,
+    CreditCard,
+    Equity,
+    Expense,
+    FixedAsset,
+    Income,
+    LongTermLiability,
+    NonPosting,
+    OtherAsset,
+    OtherCurrentAsset,
+    OtherCurrentLiability,
+    OtherExpense,
+    OtherIncome,
+    // Any other AccountType text, kept as QuickBooks sent it (empty when the response had none)
+    Other(String),
+}
+
+impl AccountType {
+    /// The QBXML AccountType text, e.g. "Bank"
+    pub fn as_str(&self) -> &str {
+        match self {
+            AccountType::AccountsPayable => "AccountsPayable",
+            AccountType::AccountsReceivable => "AccountsReceivable",
+            AccountType::Bank => "Bank",
+            AccountType::CostOfGoodsSold => "CostOfGoodsSold",
+            AccountType::CreditCard => "CreditCard",
+            AccountType::Equity => "Equity",
+            AccountType::Expense => "Expense",
+            AccountType::FixedAsset => "FixedAsset",
+            AccountType::Income => "Income",
+            AccountType::LongTermLiability => "LongTermLiability",
+            AccountType::NonPosting => "NonPosting",
+            AccountType::OtherAsset => "OtherAsset",
+            AccountType::OtherCurrentAsset => "OtherCurrentAsset",
+            AccountType::OtherCurrentLiability => "OtherCurrentLiability",
+            AccountType::OtherExpense => "OtherExpense",
+            AccountType::OtherIncome => "OtherIncome",
+            AccountType::Other(text) => text,
+        }
+    }
+
+    /// Types treated as liabilities by `flip_liability`.
+    /// Equity, income and expense accounts are deliberately left alone.
+    pub fn is_liability(&self) -> bool {
+        matches!(
+            self,
+            AccountType::AccountsPayable
+                | AccountType::CreditCard
+                | AccountType::OtherCurrentLiability
+                | AccountType::LongTermLiability
+        )
+    }
+}
+
+impl Default for AccountType {
+    fn default() -> Self {
+        AccountType::Other(String::new())
+    }
+}
+
+impl From<&str> for AccountType {
+    fn from(text: &str) -> Self {
+        match text {
+            "AccountsPayable" => AccountType::AccountsPayable,
+            "AccountsReceivable" => AccountType::AccountsReceivable,
+            "Bank" => AccountType::Bank,
+            "CostOfGoodsSold" => AccountType::CostOfGoodsSold,
+            "CreditCard" => AccountType::CreditCard,
+            "Equity" => AccountType::Equity,
+            "Expense" => AccountType::Expense,
+            "FixedAsset" => AccountType::FixedAsset,
+            "Income" => AccountType::Income,
+            "LongTermLiability" => AccountType::LongTermLiability,
+            "NonPosting" => AccountType::NonPosting,
+            "OtherAsset" => AccountType::OtherAsset,
+            "OtherCurrentAsset" => AccountType::OtherCurrentAsset,
+            "OtherCurrentLiability" => AccountType::OtherCurrentLiability,
+            "OtherExpense" => AccountType::OtherExpense,
+            "OtherIncome" => AccountType::OtherIncome,
+            other => AccountType::Other(other.to_string()),
+        }
+    }
+}
+
+// Never fails: unknown text becomes AccountType::Other
+impl FromStr for AccountType {
+    type Err = Infallible;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Ok(AccountType::from(text))
+    }
+}
+
+impl fmt::Display for AccountType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qbxml_safe::qbxml_request_processor::ACCOUNT_TYPES;
+
+    #[test]
+    fn every_qbxml_account_type_parses_to_a_known_variant() {
+        for text in ACCOUNT_TYPES {
+            let parsed: AccountType = text.parse().unwrap();
+            assert!(!matches!(parsed, AccountType::Other(_)), "{} parsed as Other", text);
+            assert_eq!(parsed.as_str(), text);
+            assert_eq!(parsed.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn unknown_types_are_kept_as_other() {
+        assert_eq!("Suspense".parse::<AccountType>().unwrap(), AccountType::Other("Suspense".to_string()));
+        // Matching is exact, as QBXML spells the types
+        assert_eq!("bank".parse::<AccountType>().unwrap(), AccountType::Other("bank".to_string()));
+        assert_eq!(AccountType::default().as_str(), "");
+    }
+
+    #[test]
+    fn only_liability_types_are_liabilities() {
+        let liabilities: Vec<&str> = ACCOUNT_TYPES.iter()
+            .copied()
+            .filter(|text| AccountType::from(*text).is_liability())
+            .collect();
+        assert_eq!(liabilities, ["AccountsPayable", "CreditCard", "LongTermLiability", "OtherCurrentLiability"]);
+        assert!(!AccountType::Other("Liability".to_string()).is_liability());
+    }
+}
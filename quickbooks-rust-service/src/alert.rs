@@ -0,0 +1,71 @@
+// Threshold alerts for sync blocks
+// Evaluates a block's alert condition against the balance just written
+
+use crate::config::{AlertConfig, AlertOp};
+
+// Balances are currency, so "equal" means equal to the cent
+const EQ_TOLERANCE: f64 = 0.005;
+
+impl AlertOp {
+    pub fn evaluate(self, balance: f64, threshold: f64) -> bool {
+        match self {
+            AlertOp::Lt => balance < threshold,
+            AlertOp::Gt => balance > threshold,
+            AlertOp::Le => balance <= threshold,
+            AlertOp::Ge => balance >= threshold,
+            AlertOp::Eq => (balance - threshold).abs() < EQ_TOLERANCE,
+        }
+    }
+}
+
+impl AlertConfig {
+    pub fn is_triggered(&self, balance: f64) -> bool {
+        self.op.evaluate(balance, self.value)
+    }
+
+    /// Text for the alert cell; blank clears a flag left by an earlier run
+    pub fn cell_text(&self, balance: f64) -> String {
+        if self.is_triggered(balance) {
+            self.message.clone()
+        } else {
+            String::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alert(op: AlertOp, value: f64) -> AlertConfig {
+        AlertConfig { op, value, cell: "C2".to_string(), message: "Low balance".to_string() }
+    }
+
+    #[test]
+    fn each_operator_compares_against_the_threshold() {
+        assert!(AlertOp::Lt.evaluate(99.99, 100.0));
+        assert!(!AlertOp::Lt.evaluate(100.0, 100.0));
+        assert!(AlertOp::Gt.evaluate(100.01, 100.0));
+        assert!(!AlertOp::Gt.evaluate(100.0, 100.0));
+        assert!(AlertOp::Le.evaluate(100.0, 100.0));
+        assert!(!AlertOp::Le.evaluate(100.01, 100.0));
+        assert!(AlertOp::Ge.evaluate(100.0, 100.0));
+        assert!(!AlertOp::Ge.evaluate(99.99, 100.0));
+    }
+
+    #[test]
+    fn eq_allows_half_a_cent() {
+        assert!(AlertOp::Eq.evaluate(100.0, 100.0));
+        assert!(AlertOp::Eq.evaluate(100.004, 100.0));
+        assert!(AlertOp::Eq.evaluate(99.996, 100.0));
+        assert!(!AlertOp::Eq.evaluate(100.01, 100.0));
+        assert!(!AlertOp::Eq.evaluate(99.99, 100.0));
+    }
+
+    #[test]
+    fn cell_text_clears_once_the_alert_no_longer_holds() {
+        let alert = alert(AlertOp::Lt, 500.0);
+        assert_eq!(alert.cell_text(120.0), "Low balance");
+        assert_eq!(alert.cell_text(800.0), "");
+    }
+}
@@ -0,0 +1,156 @@
+// Tolerant amount parsing
+// Some QuickBooks versions return balances as formatted strings ("$1,234.56", "(500.00)")
+
+use crate::config::{CurrencyFormat, NegativeStyle};
+
+/// How formatted balances are written: which currency symbols to strip and the decimal separator.
+/// The thousands separator is whichever of '.' and ',' is not the decimal separator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AmountFormat {
+    pub currency_symbols: Vec<String>,
+    pub decimal_separator: char,
+}
+
+impl Default for AmountFormat {
+    fn default() -> Self {
+        Self {
+            currency_symbols: ["$", "€", "£", "¥"].iter().map(|s| s.to_string()).collect(),
+            decimal_separator: '.',
+        }
+    }
+}
+
+/// Parse a balance string, accepting currency symbols, thousands separators, surrounding
+/// whitespace and accounting negatives in parentheses. None if nothing numeric remains.
+pub fn parse_amount(text: &str, format: &AmountFormat) -> Option<f64> {
+    let mut stripped = text.to_string();
+    for symbol in &format.currency_symbols {
+        stripped = stripped.replace(symbol.as_str(), "");
+    }
+    let mut s = stripped.trim();
+    let mut negative = false;
+    if s.len() >= 2 && s.starts_with('(') && s.ends_with(')') {
+        negative = true;
+        s = s[1..s.len() - 1].trim();
+    }
+    if let Some(rest) = s.strip_suffix('-') {
+        negative = true;
+        s = rest;
+    }
+    let thousands = if format.decimal_separator == ',' { '.' } else { ',' };
+    let normalized: String = s
+        .chars()
+        .filter(|c| *c != thousands && !c.is_whitespace() && *c != '\'')
+        .map(|c| if c == format.decimal_separator { '.' } else { c })
+        .collect();
+    if normalized.is_empty() {
+        return None;
+    }
+    let value: f64 = normalized.parse().ok()?;
+    Some(if negative { -value.abs() } else { value })
+}
+
+impl CurrencyFormat {
+    /// `value` rounded to the configured decimals with symbol, grouping and sign applied
+    pub fn format(&self, value: f64) -> String {
+        let decimals = self.decimals.unwrap_or(2) as usize;
+        let fixed = format!("{:.*}", decimals, value.abs());
+        let (whole, fraction) = fixed.split_once('.').unwrap_or((&fixed, ""));
+        let thousands = self.thousands_separator.as_deref().unwrap_or(",");
+        let mut grouped = String::with_capacity(whole.len() + whole.len() / 3 * thousands.len());
+        for (i, digit) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i) % 3 == 0 {
+                grouped.push_str(thousands);
+            }
+            grouped.push(digit);
+        }
+        if !fraction.is_empty() {
+            grouped.push_str(self.decimal_separator.as_deref().unwrap_or("."));
+            grouped.push_str(fraction);
+        }
+        let symbol = self.symbol.as_deref().unwrap_or("$");
+        let amount = if self.symbol_after.unwrap_or(false) {
+            format!("{}{}", grouped, symbol)
+        } else {
+            format!("{}{}", symbol, grouped)
+        };
+        // Rounding can turn a tiny negative into zero; don't print "-$0.00"
+        let negative = value < 0.0 && fixed.chars().any(|c| c.is_ascii_digit() && c != '0');
+        match (negative, self.negative_style.unwrap_or_default()) {
+            (false, _) => amount,
+            (true, NegativeStyle::Minus) => format!("-{}", amount),
+            (true, NegativeStyle::Parentheses) => format!("({})", amount),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_us_formatted_balances() {
+        let us = AmountFormat::default();
+        assert_eq!(parse_amount("1,234.56", &us), Some(1234.56));
+        assert_eq!(parse_amount("($500.00)", &us), Some(-500.0));
+        assert_eq!(parse_amount("42", &us), Some(42.0));
+        assert_eq!(parse_amount(" 500.00- ", &us), Some(-500.0));
+    }
+
+    #[test]
+    fn negative_zero_is_zero() {
+        assert_eq!(parse_amount("-0", &AmountFormat::default()), Some(0.0));
+    }
+
+    #[test]
+    fn parses_comma_decimal_balances() {
+        let eu = AmountFormat { decimal_separator: ',', ..AmountFormat::default() };
+        assert_eq!(parse_amount("€1.234,56", &eu), Some(1234.56));
+        assert_eq!(parse_amount("(1.234,56 €)", &eu), Some(-1234.56));
+    }
+
+    #[test]
+    fn non_numeric_text_is_none() {
+        let us = AmountFormat::default();
+        assert_eq!(parse_amount("", &us), None);
+        assert_eq!(parse_amount("$", &us), None);
+        assert_eq!(parse_amount("n/a", &us), None);
+    }
+
+    #[test]
+    fn parses_the_formats_quickbooks_returns() {
+        let us = AmountFormat::default();
+        assert_eq!(parse_amount("$1,234.56", &us), Some(1234.56));
+        assert_eq!(parse_amount("(500.00)", &us), Some(-500.0));
+        assert_eq!(parse_amount("1234.56", &us), Some(1234.56));
+        let eu = AmountFormat { decimal_separator: ',', ..AmountFormat::default() };
+        assert_eq!(parse_amount("€1.234,56", &eu), Some(1234.56));
+    }
+
+    #[test]
+    fn only_configured_symbols_are_stripped() {
+        let swiss = AmountFormat { currency_symbols: vec!["CHF".to_string()], decimal_separator: '.' };
+        assert_eq!(parse_amount("CHF 1'234.50", &swiss), Some(1234.5));
+        assert_eq!(parse_amount("$1,234.56", &swiss), None);
+    }
+
+    #[test]
+    fn formats_balances_as_currency_text() {
+        let usd = CurrencyFormat::default();
+        assert_eq!(usd.format(1234.56), "$1,234.56");
+        assert_eq!(usd.format(-500.0), "-$500.00");
+        assert_eq!(usd.format(-0.001), "$0.00");
+        let parentheses = CurrencyFormat { negative_style: Some(NegativeStyle::Parentheses), ..CurrencyFormat::default() };
+        assert_eq!(parentheses.format(-500.0), "($500.00)");
+        let euro = CurrencyFormat {
+            symbol: Some(" €".to_string()),
+            symbol_after: Some(true),
+            thousands_separator: Some(".".to_string()),
+            decimal_separator: Some(",".to_string()),
+            ..CurrencyFormat::default()
+        };
+        assert_eq!(euro.format(1234567.891), "1.234.567,89 €");
+        let yen = CurrencyFormat { symbol: Some("¥".to_string()), decimals: Some(0), ..CurrencyFormat::default() };
+        assert_eq!(yen.format(-98765.4), "-¥98,765");
+    }
+}
@@ -0,0 +1,95 @@
+// Retry backoff
+// Capped exponential delays shared by every retry site, with optional full jitter
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Delay before retry `n` (0-based) is `base * 2^n`, never more than `cap`.
+/// With `jitter`, the delay is drawn uniformly from zero up to that ceiling ("full jitter").
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    pub base: Duration,
+    pub cap: Duration,
+    pub jitter: bool,
+}
+
+impl Backoff {
+    /// The same delay before every retry
+    pub fn fixed(delay: Duration) -> Self {
+        Self { base: delay, cap: delay, jitter: false }
+    }
+
+    /// Largest delay retry `retry` can wait
+    pub fn ceiling(&self, retry: u32) -> Duration {
+        let factor = 1u32.checked_shl(retry).unwrap_or(u32::MAX);
+        self.base.saturating_mul(factor).min(self.cap)
+    }
+
+    /// Delay before retry `retry`, drawing jitter from the clock
+    pub fn delay(&self, retry: u32) -> Duration {
+        self.delay_with(retry, random_unit())
+    }
+
+    /// Delay before retry `retry` with the jitter draw supplied as `unit` in [0, 1)
+    pub fn delay_with(&self, retry: u32, unit: f64) -> Duration {
+        let ceiling = self.ceiling(retry);
+        if self.jitter {
+            ceiling.mul_f64(unit.clamp(0.0, 1.0))
+        } else {
+            ceiling
+        }
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::fixed(Duration::ZERO)
+    }
+}
+
+// Good enough to spread retries apart; not for anything that needs real randomness.
+// A splitmix64 step over a counter seeded from the clock, so back-to-back draws differ.
+fn random_unit() -> f64 {
+    static STATE: AtomicU64 = AtomicU64::new(0);
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    let mut z = STATE.fetch_add(0x9E37_79B9_7F4A_7C15, Ordering::Relaxed) ^ nanos;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backoff(jitter: bool) -> Backoff {
+        Backoff { base: Duration::from_millis(1000), cap: Duration::from_secs(30), jitter }
+    }
+
+    #[test]
+    fn delays_double_up_to_the_cap() {
+        let delays: Vec<Duration> = (0..7).map(|retry| backoff(false).delay(retry)).collect();
+        let secs: Vec<u64> = delays.iter().map(Duration::as_secs).collect();
+        assert_eq!(secs, [1, 2, 4, 8, 16, 30, 30]);
+        // Shifts past the width of u32 saturate instead of wrapping
+        assert_eq!(backoff(false).delay(40), Duration::from_secs(30));
+        assert_eq!(Backoff::fixed(Duration::from_millis(250)).delay(5), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn jittered_delays_stay_within_the_ceiling() {
+        let jittered = backoff(true);
+        assert_eq!(jittered.delay_with(3, 0.0), Duration::ZERO);
+        assert_eq!(jittered.delay_with(3, 0.5), Duration::from_secs(4));
+        assert_eq!(jittered.delay_with(3, 7.0), Duration::from_secs(8));
+        for retry in 0..10 {
+            assert!(jittered.delay(retry) <= jittered.ceiling(retry));
+        }
+        let unit = random_unit();
+        assert!((0.0..1.0).contains(&unit));
+    }
+}
@@ -0,0 +1,145 @@
+// Last-known-good balance cache
+// Saved after every successful QuickBooks query and served when QuickBooks can't be reached
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalanceCache {
+    pub saved_at: DateTime<Utc>,
+    pub balances: BTreeMap<String, f64>,
+    // QBXML AccountType per account, so sign conventions still apply to cached data
+    #[serde(default)]
+    pub account_types: BTreeMap<String, String>,
+    // TotalBalance (including subaccounts) for blocks that write it
+    #[serde(default)]
+    pub total_balances: BTreeMap<String, f64>,
+    // Moving average per smoothed_cell, keyed by AccountSyncConfig::smoothing_key
+    #[serde(default)]
+    pub smoothed: BTreeMap<String, f64>,
+}
+
+/// Default weight of the newest balance in a moving average
+pub const DEFAULT_SMOOTHING_ALPHA: f64 = 0.3;
+
+/// One exponential moving average step; the first value seeds the average
+pub fn ema(alpha: f64, current: f64, previous: Option<f64>) -> f64 {
+    match previous {
+        Some(previous) => alpha * current + (1.0 - alpha) * previous,
+        None => current,
+    }
+}
+
+impl BalanceCache {
+    pub fn new(saved_at: DateTime<Utc>) -> Self {
+        Self { saved_at, balances: BTreeMap::new(), account_types: BTreeMap::new(), total_balances: BTreeMap::new(), smoothed: BTreeMap::new() }
+    }
+
+    /// Ok(None) when no cache has been written yet
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read balance cache {}", path.display()))?;
+        let cache = serde_json::from_str(&text)
+            .with_context(|| format!("Failed to parse balance cache {}", path.display()))?;
+        Ok(Some(cache))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create cache directory {}", dir.display()))?;
+        }
+        let text = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, text)
+            .with_context(|| format!("Failed to write balance cache {}", path.display()))
+    }
+
+    /// Drop every entry whose account `keep` rejects, returning the dropped names
+    pub fn prune(&mut self, keep: impl Fn(&str) -> bool) -> Vec<String> {
+        let dropped: Vec<String> = self.balances.keys()
+            .chain(self.account_types.keys())
+            .chain(self.total_balances.keys())
+            .filter(|name| !keep(name))
+            .cloned()
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        for name in &dropped {
+            self.balances.remove(name);
+            self.account_types.remove(name);
+            self.total_balances.remove(name);
+        }
+        dropped
+    }
+
+    pub fn age_secs(&self, now: DateTime<Utc>) -> i64 {
+        (now - self.saved_at).num_seconds()
+    }
+
+    /// Refuse cached data older than `max_age_secs` so a stale figure fails the run loudly
+    pub fn ensure_fresh(&self, now: DateTime<Utc>, max_age_secs: Option<u64>) -> Result<()> {
+        let age = self.age_secs(now);
+        match max_age_secs {
+            Some(max_age) if age > max_age as i64 => {
+                log::error!("[CACHE] Cached balances are {}s old; max_cache_age_secs is {}s", age, max_age);
+                anyhow::bail!(
+                    "Refusing to write cached balances saved at {} ({}s old, limit {}s)",
+                    self.saved_at, age, max_age
+                )
+            },
+            Some(max_age) => {
+                log::info!("[CACHE] Cached balances are {}s old (limit {}s)", age, max_age);
+                Ok(())
+            },
+            None => {
+                log::info!("[CACHE] Cached balances are {}s old (no max_cache_age_secs set)", age);
+                Ok(())
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fixed clock: every check below runs at this instant
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2024-03-01T12:00:00Z").unwrap().with_timezone(&Utc)
+    }
+
+    fn saved_ago(secs: i64) -> BalanceCache {
+        BalanceCache::new(now() - chrono::Duration::seconds(secs))
+    }
+
+    #[test]
+    fn cache_within_ttl_is_written() {
+        assert_eq!(saved_ago(3599).age_secs(now()), 3599);
+        saved_ago(3599).ensure_fresh(now(), Some(3600)).unwrap();
+        saved_ago(3600).ensure_fresh(now(), Some(3600)).unwrap();
+        // No limit set: any age is served
+        saved_ago(90 * 86400).ensure_fresh(now(), None).unwrap();
+    }
+
+    #[test]
+    fn cache_beyond_ttl_is_refused() {
+        let err = saved_ago(3601).ensure_fresh(now(), Some(3600)).unwrap_err();
+        assert_eq!(err.to_string(), "Refusing to write cached balances saved at 2024-03-01 10:59:59 UTC (3601s old, limit 3600s)");
+    }
+
+    #[test]
+    fn ema_seeds_with_the_first_value_then_weights_by_alpha() {
+        let first = ema(0.3, 100.0, None);
+        assert_eq!(first, 100.0);
+        let second = ema(0.3, 200.0, Some(first));
+        assert!((second - 130.0).abs() < 1e-9, "{}", second);
+        let third = ema(0.3, 100.0, Some(second));
+        assert!((third - 121.0).abs() < 1e-9, "{}", third);
+    }
+}
@@ -0,0 +1,98 @@
+// Schedule slots and missed-run catch-up
+// Computes when the slot after a given run was due and decides whether a `--catch-up` start
+// (e.g. at boot or logon) should sync now because a slot was missed
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Local, Utc};
+use std::str::FromStr;
+
+use crate::config::ScheduleConfig;
+
+// Default window: a missed slot older than this waits for the next scheduled run
+const DEFAULT_WINDOW_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatchUp {
+    // The first slot after the last run has passed and is inside the window
+    Run { missed_at: DateTime<Utc> },
+    // No slot has passed since the last run
+    UpToDate { next_at: DateTime<Utc> },
+    // A slot was missed, but so long ago that the regular schedule should take over
+    OutsideWindow { missed_at: DateTime<Utc> },
+    // Nothing records a previous run, so there is no slot to have missed
+    NoHistory,
+}
+
+/// Parse a cron expression with a seconds field ("sec min hour day-of-month month day-of-week")
+pub fn parse_cron(expression: &str) -> Result<cron::Schedule> {
+    cron::Schedule::from_str(expression)
+        .map_err(|e| anyhow::anyhow!("{}", e))
+        .with_context(|| format!("schedule.cron_expression '{}' is invalid", expression))
+}
+
+impl ScheduleConfig {
+    /// The first slot after `after`: the next cron_expression match in local time, otherwise
+    /// `interval_secs` later. None when the cron expression never fires again.
+    pub fn next_run(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match &self.cron_expression {
+            // Validated at config load
+            Some(expression) => parse_cron(expression).ok()?
+                .after(&after.with_timezone(&Local))
+                .next()
+                .map(|at| at.with_timezone(&Utc)),
+            None => Some(after + Duration::seconds(self.interval_secs? as i64)),
+        }
+    }
+
+    /// Slots follow the schedule from the last run; however many were missed, at most one run is made
+    pub fn catch_up(&self, last_run: Option<DateTime<Utc>>, now: DateTime<Utc>) -> CatchUp {
+        let Some(last_run) = last_run else { return CatchUp::NoHistory };
+        let Some(missed_at) = self.next_run(last_run) else { return CatchUp::NoHistory };
+        if missed_at > now {
+            return CatchUp::UpToDate { next_at: missed_at };
+        }
+        let window = Duration::seconds(self.catch_up_window_secs.unwrap_or(DEFAULT_WINDOW_SECS) as i64);
+        if now - missed_at > window {
+            CatchUp::OutsideWindow { missed_at }
+        } else {
+            CatchUp::Run { missed_at }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc)
+    }
+
+    fn schedule(interval_secs: Option<u64>, cron_expression: Option<&str>) -> ScheduleConfig {
+        ScheduleConfig {
+            interval_secs,
+            cron_expression: cron_expression.map(str::to_string),
+            catch_up: Some(true),
+            catch_up_window_secs: None,
+        }
+    }
+
+    #[test]
+    fn at_most_one_run_catches_up_a_missed_slot() {
+        let hourly = schedule(Some(3600), None);
+        let last_run = Some(at("2024-01-15T10:15:00Z"));
+        assert_eq!(hourly.catch_up(None, at("2024-01-15T12:00:00Z")), CatchUp::NoHistory);
+        assert_eq!(hourly.catch_up(last_run, at("2024-01-15T11:00:00Z")), CatchUp::UpToDate { next_at: at("2024-01-15T11:15:00Z") });
+        // Three slots missed still means one run, for the first of them
+        assert_eq!(hourly.catch_up(last_run, at("2024-01-15T13:30:00Z")), CatchUp::Run { missed_at: at("2024-01-15T11:15:00Z") });
+        assert_eq!(hourly.catch_up(last_run, at("2024-01-17T00:00:00Z")), CatchUp::OutsideWindow { missed_at: at("2024-01-15T11:15:00Z") });
+    }
+
+    #[test]
+    fn cron_slots_follow_the_expression() {
+        // Every minute on the minute, whatever the local zone
+        let every_minute = schedule(None, Some("0 * * * * *"));
+        assert_eq!(every_minute.next_run(at("2024-01-15T10:15:30Z")), Some(at("2024-01-15T10:16:00Z")));
+        assert!(parse_cron("0 * * * *").is_err());
+    }
+}
@@ -0,0 +1,55 @@
+// Chart-data blocks
+// Builds the header and per-run data rows appended beneath a chart's anchor
+
+use crate::config::ChartDataConfig;
+use crate::google_sheets::CellValue;
+
+const DEFAULT_DATE_HEADER: &str = "Date";
+
+impl ChartDataConfig {
+    pub fn header_row(&self) -> Vec<String> {
+        let mut row = vec![self.date_header.clone().unwrap_or_else(|| DEFAULT_DATE_HEADER.to_string())];
+        match &self.headers {
+            Some(headers) => row.extend(headers.iter().cloned()),
+            None => row.extend(self.accounts.iter().cloned()),
+        }
+        row
+    }
+
+    /// The row appended this run: the date, then one balance per account in column order
+    pub fn data_row(&self, date: &str, balances: &[f64]) -> Vec<CellValue> {
+        std::iter::once(CellValue::Text(date.to_string()))
+            .chain(balances.iter().map(|b| CellValue::Number(*b)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chart(headers: Option<&[&str]>) -> ChartDataConfig {
+        ChartDataConfig {
+            spreadsheet_id: "1AbC".to_string(),
+            sheet_name: "Chart".to_string(),
+            anchor: "A1".to_string(),
+            accounts: vec!["Checking".to_string(), "Savings".to_string()],
+            headers: headers.map(|headers| headers.iter().map(|h| h.to_string()).collect()),
+            date_header: None,
+        }
+    }
+
+    #[test]
+    fn data_row_is_the_date_then_balances_in_column_order() {
+        let row = chart(None).data_row("2024-03-01", &[1234.5, -20.0]);
+        assert_eq!(row, [CellValue::Text("2024-03-01".to_string()), CellValue::Number(1234.5), CellValue::Number(-20.0)]);
+    }
+
+    #[test]
+    fn header_row_defaults_to_account_names() {
+        assert_eq!(chart(None).header_row(), ["Date", "Checking", "Savings"]);
+        let mut titled = chart(Some(&["Operating", "Reserve"]));
+        titled.date_header = Some("Week of".to_string());
+        assert_eq!(titled.header_row(), ["Week of", "Operating", "Reserve"]);
+    }
+}
@@ -0,0 +1,74 @@
+// Clock skew check
+// Compares the local clock with a server's HTTP Date header so misleading timestamps are caught
+
+use chrono::{DateTime, Duration, Utc};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Skew above which the check warns when no max_clock_skew_secs is set
+pub const DEFAULT_MAX_SKEW_SECS: u64 = 120;
+
+// Cleared when the check finds the clock off and timestamps should not be written
+static TIMESTAMPS_TRUSTED: AtomicBool = AtomicBool::new(true);
+
+/// Whether timestamp cells may be written this run
+pub fn timestamps_trusted() -> bool {
+    TIMESTAMPS_TRUSTED.load(Ordering::Relaxed)
+}
+
+pub fn distrust_timestamps() {
+    TIMESTAMPS_TRUSTED.store(false, Ordering::Relaxed);
+}
+
+/// Parse an HTTP Date header ("Mon, 15 Jan 2024 10:00:00 GMT")
+pub fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value.trim()).ok().map(|date| date.with_timezone(&Utc))
+}
+
+/// How far the local clock is ahead of (positive) or behind (negative) the server's
+pub fn skew(local: DateTime<Utc>, server: DateTime<Utc>) -> Duration {
+    local - server
+}
+
+/// A warning when `skew` exceeds `max_secs`. The Date header has one-second resolution and the
+/// request takes time, so small differences are expected and ignored.
+pub fn skew_warning(skew: Duration, max_secs: u64) -> Option<String> {
+    let secs = skew.num_seconds();
+    (secs.unsigned_abs() > max_secs).then(|| format!(
+        "local clock is {}s {} the server's (limit {}s); written timestamps may be wrong",
+        secs.unsigned_abs(),
+        if secs > 0 { "ahead of" } else { "behind" },
+        max_secs
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The Date header a time source would send
+    fn server() -> DateTime<Utc> {
+        parse_http_date("Mon, 15 Jan 2024 10:00:00 GMT").unwrap()
+    }
+
+    #[test]
+    fn a_large_skew_warns_either_way() {
+        let ahead = skew(server() + Duration::minutes(10), server());
+        assert_eq!(ahead.num_seconds(), 600);
+        assert_eq!(
+            skew_warning(ahead, DEFAULT_MAX_SKEW_SECS).unwrap(),
+            "local clock is 600s ahead of the server's (limit 120s); written timestamps may be wrong"
+        );
+        let behind = skew(server() - Duration::seconds(45), server());
+        assert_eq!(
+            skew_warning(behind, 30).unwrap(),
+            "local clock is 45s behind the server's (limit 30s); written timestamps may be wrong"
+        );
+    }
+
+    #[test]
+    fn a_small_skew_is_ignored() {
+        assert_eq!(skew_warning(skew(server() + Duration::seconds(2), server()), DEFAULT_MAX_SKEW_SECS), None);
+        assert_eq!(skew_warning(skew(server() - Duration::seconds(120), server()), DEFAULT_MAX_SKEW_SECS), None);
+        assert_eq!(parse_http_date("yesterday"), None);
+    }
+}
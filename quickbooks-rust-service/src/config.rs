@@ -1,47 +1,1266 @@
 use anyhow::{Context, Result};
 use figment::{Figment, providers::{Format, Toml}};
 use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
 use std::path::Path;
+use std::time::Duration;
+
+use crate::a1::{qualified, split_sheet, CellRange, CellRef};
+use crate::amount::AmountFormat;
+use crate::backoff::Backoff;
+use crate::network::HttpRetry;
+use crate::timestamp;
+use crate::quickbooks::ConnectionType;
+use crate::qbxml_safe::qbxml_request_processor::{AccountQueryOptions, NameFilter, ProgIdRetry, SessionRetry};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub quickbooks: QuickBooksConfig,
     pub google_sheets: GoogleSheetsConfig,
+    #[serde(default)]
     pub sync_blocks: Vec<AccountSyncConfig>,
+    // CSV of extra sync blocks (spreadsheet_id, account_full_name, sheet_name, cell_address),
+    // resolved relative to the config file's directory and appended after the TOML blocks
+    pub sync_blocks_csv: Option<String>,
+    #[serde(default)]
+    pub chart_data: Vec<ChartDataConfig>,
+    #[serde(default)]
+    pub webhook_sink: Vec<WebhookSinkConfig>,
+    #[serde(default)]
+    pub type_report: Vec<TypeReportConfig>,
+    #[serde(default)]
+    pub prefix_block: Vec<PrefixBlockConfig>,
+    pub monitoring: Option<MonitoringConfig>,
+    pub limits: Option<LimitsConfig>,
+    pub hooks: Option<HooksConfig>,
+    pub network: Option<NetworkConfig>,
+    pub ofx_export: Option<OfxExportConfig>,
+    pub schedule: Option<ScheduleConfig>,
+    pub error_log: Option<ErrorLogConfig>,
+    #[serde(default)]
+    pub transaction_count: Vec<TransactionCountConfig>,
+    #[serde(default)]
+    pub customer_sync: Vec<CustomerSyncConfig>,
+    #[serde(default)]
+    pub file_modified: Vec<FileModifiedConfig>,
+    #[serde(default)]
+    pub percent_sync: Vec<PercentSyncConfig>,
+    #[serde(default)]
+    pub account_row: Vec<AccountRowConfig>,
+    #[serde(default)]
+    pub period_balance: Vec<PeriodBalanceConfig>,
+    // Set by --dry-run, never read from the file: every output is logged instead of sent
+    #[serde(skip)]
+    pub dry_run: bool,
+    // Set by --daemon: a run that finds no company file open is skipped instead of failing
+    #[serde(skip)]
+    pub daemon: bool,
 }
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AccountSyncConfig {
     pub spreadsheet_id: String,
     pub account_full_name: String,
     pub sheet_name: String,
+    pub cell_address: CellTargets,
+    pub alert: Option<AlertConfig>,
+    pub sign_convention: Option<SignConvention>,
+    // Blocks with an order are written one at a time, ascending, before the unordered blocks
+    // (which are written concurrently); use it when a sheet formula depends on another cell
+    pub order: Option<u32>,
+    // Write TotalBalance (the account plus all its subaccounts) instead of its own Balance
+    pub total_balance: Option<bool>,
+    // Write the balance text exactly as QuickBooks returned it (e.g. "1234.56") instead of a
+    // parsed number, avoiding any float round trip; the sheet interprets the text
+    pub raw_balance: Option<bool>,
+    // Cell that receives the run time after the balance; the pattern and zone default to the
+    // [google_sheets] timestamp_format/timestamp_timezone
+    pub timestamp_cell: Option<String>,
+    pub timestamp_format: Option<String>,
+    pub timestamp_timezone: Option<String>,
+    // Cell that receives attribution_text (default "Auto-synced from QuickBooks") with every
+    // balance, so collaborators can tell synced figures from manual edits
+    pub attribution_cell: Option<String>,
+    pub attribution_text: Option<String>,
+    // Write the balance as currency text formatted here (e.g. "$1,234.56") instead of a number
+    pub currency_format: Option<CurrencyFormat>,
+    // Cell that receives an exponential moving average of the balance, kept in the balance cache:
+    // smoothing_alpha * balance + (1 - smoothing_alpha) * previous average (default alpha 0.3)
+    pub smoothed_cell: Option<String>,
+    pub smoothing_alpha: Option<f64>,
+    // Send this block to another Apps Script deployment; each falls back to [google_sheets]
+    pub webapp_url: Option<String>,
+    pub api_key: Option<String>,
+}
+
+/// How a block spells its balance as currency text. Defaults give "$1,234.56" and "-$500.00".
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CurrencyFormat {
+    // Default "$"
+    pub symbol: Option<String>,
+    // Put the symbol after the number ("1.234,56 €"); default false
+    pub symbol_after: Option<bool>,
+    // Default 2
+    pub decimals: Option<u32>,
+    // Default "," (use "" for none)
+    pub thousands_separator: Option<String>,
+    // Default "."
+    pub decimal_separator: Option<String>,
+    // Negative amounts as "-$500.00" ("minus", default) or "($500.00)" ("parentheses")
+    pub negative_style: Option<NegativeStyle>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NegativeStyle {
+    #[default]
+    Minus,
+    Parentheses,
+}
+
+/// How a block presents the sign of its balance. `flip_liability` negates balances of
+/// AccountsPayable, CreditCard, OtherCurrentLiability and LongTermLiability accounts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignConvention {
+    #[default]
+    AsIs,
+    FlipLiability,
+}
+
+/// One cell or a list of mirror cells that all receive the block's balance.
+/// Entries may carry their own sheet ("Summary!B2"); otherwise the block's sheet_name applies.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CellTargets {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Default for CellTargets {
+    fn default() -> Self {
+        CellTargets::One(String::new())
+    }
+}
+
+impl CellTargets {
+    pub fn addresses(&self) -> &[String] {
+        match self {
+            CellTargets::One(address) => std::slice::from_ref(address),
+            CellTargets::Many(addresses) => addresses,
+        }
+    }
+}
+
+pub const DEFAULT_ATTRIBUTION_TEXT: &str = "Auto-synced from QuickBooks";
+
+impl AccountSyncConfig {
+    /// Every (sheet, cell) this block writes its balance to
+    pub fn targets(&self) -> Vec<(String, String)> {
+        self.cell_address
+            .addresses()
+            .iter()
+            .map(|address| {
+                let (sheet, cell) = split_sheet(address);
+                (sheet.unwrap_or_else(|| self.sheet_name.clone()), cell.to_string())
+            })
+            .collect()
+    }
+
+    /// Balance cache key for the block's moving average; blocks can smooth one account differently
+    pub fn smoothing_key(&self) -> Option<String> {
+        let cell = self.smoothed_cell.as_deref()?;
+        Some(format!("{}/{}", self.spreadsheet_id, qualified(&self.sheet_name, cell)))
+    }
+
+    /// The web app URL and API key this block writes with: its own when set, else the global ones
+    pub fn webapp<'a>(&'a self, global: &'a GoogleSheetsConfig) -> (&'a str, &'a str) {
+        (
+            self.webapp_url.as_deref().unwrap_or(&global.webapp_url),
+            self.api_key.as_deref().unwrap_or(&global.api_key),
+        )
+    }
+
+    /// The block's attribution cell and its text
+    pub fn attribution_value(&self) -> Option<(&str, &str)> {
+        let cell = self.attribution_cell.as_deref()?;
+        Some((cell, self.attribution_text.as_deref().unwrap_or(DEFAULT_ATTRIBUTION_TEXT)))
+    }
+
+    /// The block's timestamp cell and `now` formatted for it, falling back to the [google_sheets] defaults
+    pub fn timestamp_value(&self, defaults: &GoogleSheetsConfig, now: DateTime<Utc>) -> Option<(&str, String)> {
+        let cell = self.timestamp_cell.as_deref()?;
+        let format = self.timestamp_format.as_deref()
+            .or(defaults.timestamp_format.as_deref())
+            .unwrap_or(timestamp::DEFAULT_FORMAT);
+        // Validated at config load
+        let timezone = self.timestamp_timezone.as_deref()
+            .or(defaults.timestamp_timezone.as_deref())
+            .and_then(|tz| timestamp::parse_timezone(tz).ok());
+        Some((cell, timestamp::format_timestamp(now, format, timezone)))
+    }
+}
+
+/// Flag cell written after the balance: `message` when `balance <op> value`, blank otherwise
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertConfig {
+    pub op: AlertOp,
+    pub value: f64,
+    pub cell: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertOp {
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+}
+
+/// A growing chart source: a header row at `anchor`, then one appended row per run
+/// holding the date followed by each account's balance in `accounts` order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChartDataConfig {
+    pub spreadsheet_id: String,
+    pub sheet_name: String,
+    pub anchor: String,
+    pub accounts: Vec<String>,
+    // Column titles for the accounts; defaults to the account full names
+    pub headers: Option<Vec<String>>,
+    pub date_header: Option<String>,
+}
+
+/// A chart-of-accounts style report rebuilt every run: every account, grouped by account
+/// type with a header row per type, written as name/balance rows starting at `anchor`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TypeReportConfig {
+    pub spreadsheet_id: String,
+    pub sheet_name: String,
+    pub anchor: String,
+    // Area the report may occupy (e.g. "A1:B200"); rows below this run's report are cleared
+    // so accounts that disappeared since the last run don't linger
+    pub clear_range: Option<String>,
+    pub sign_convention: Option<SignConvention>,
+    // List accounts whose reported balance is zero (default true); accounts QuickBooks
+    // reported no balance for are never treated as zero
+    pub include_zero_balance: Option<bool>,
+}
+
+/// Every account whose full name starts with `full_name_prefix` (e.g. "Expenses:"), sorted by
+/// name and written as name/balance rows from `start_cell`, so the list follows QuickBooks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrefixBlockConfig {
+    pub spreadsheet_id: String,
+    pub sheet_name: String,
+    pub full_name_prefix: String,
+    pub start_cell: String,
+    // Area the rows may occupy (e.g. "A1:B100"); rows below this run's accounts are cleared
+    pub clear_range: Option<String>,
+    pub sign_convention: Option<SignConvention>,
+    // List accounts whose reported balance is zero (default true); accounts QuickBooks
+    // reported no balance for are never treated as zero
+    pub include_zero_balance: Option<bool>,
+}
+
+/// A field of an account that an account_row can write
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountField {
+    FullName,
+    Number,
+    AccountType,
+    Balance,
+    TotalBalance,
+    Description,
+}
+
+/// What an account_row writes in a description cell when the account has no Desc
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingDescription {
+    // Clear the cell
+    #[default]
+    Blank,
+    // Leave whatever the cell already holds
+    Skip,
+}
+
+/// Writes the chosen `fields` of one account as a single row from `anchor`, left to right
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountRowConfig {
+    pub spreadsheet_id: String,
+    pub sheet_name: String,
+    pub account_full_name: String,
+    pub anchor: String,
+    pub fields: Vec<AccountField>,
+    pub sign_convention: Option<SignConvention>,
+    // For the description field of an account without one: "blank" (default) or "skip"
+    pub missing_description: Option<MissingDescription>,
+}
+
+/// Writes `account_full_name`'s balance as of each date in `periods` (YYYY-MM-DD, from a
+/// balance sheet report per date) across a row from `start_cell`, `column_stride` columns apart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeriodBalanceConfig {
+    pub spreadsheet_id: String,
+    pub sheet_name: String,
+    pub account_full_name: String,
+    pub periods: Vec<chrono::NaiveDate>,
+    pub start_cell: String,
+    // Columns between consecutive periods (default 1: adjacent columns)
+    pub column_stride: Option<u32>,
+    pub sign_convention: Option<SignConvention>,
+}
+
+/// Writes how many transactions hit `account_full_name` between `from_date` and `to_date`
+/// (inclusive, YYYY-MM-DD) to `cell_address`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionCountConfig {
+    pub spreadsheet_id: String,
+    pub sheet_name: String,
+    pub cell_address: String,
+    pub account_full_name: String,
+    pub from_date: chrono::NaiveDate,
+    pub to_date: chrono::NaiveDate,
+}
+
+/// Writes a customer's open balance (what they owe, from CustomerQueryRq) to `cell_address`;
+/// name a job as "Customer:Job"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomerSyncConfig {
+    pub spreadsheet_id: String,
+    pub customer_full_name: String,
+    pub sheet_name: String,
     pub cell_address: String,
 }
 
+/// Writes each listed account's balance and its share of the group total (as a fraction,
+/// e.g. 0.25; format the percent cells as percentages) on one sheet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PercentSyncConfig {
+    pub spreadsheet_id: String,
+    pub sheet_name: String,
+    pub accounts: Vec<PercentMember>,
+    pub sign_convention: Option<SignConvention>,
+    // Percent cells when the group's balances sum to zero: "blank" (default) or "zero"
+    pub zero_total: Option<ZeroTotalPolicy>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PercentMember {
+    pub account: String,
+    pub balance_cell: String,
+    pub percent_cell: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZeroTotalPolicy {
+    #[default]
+    Blank,
+    Zero,
+}
+
+/// Writes when the QuickBooks data last changed (the newest transaction edit, else the company
+/// file's modified time), i.e. how fresh the data itself is. Formatted like sync block timestamp
+/// cells, with the same [google_sheets] defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileModifiedConfig {
+    pub spreadsheet_id: String,
+    pub sheet_name: String,
+    pub cell_address: String,
+    pub timestamp_format: Option<String>,
+    pub timestamp_timezone: Option<String>,
+}
+
+impl FileModifiedConfig {
+    pub fn format_time(&self, defaults: &GoogleSheetsConfig, modified: DateTime<Utc>) -> String {
+        let format = self.timestamp_format.as_deref()
+            .or(defaults.timestamp_format.as_deref())
+            .unwrap_or(timestamp::DEFAULT_FORMAT);
+        // Validated at config load
+        let timezone = self.timestamp_timezone.as_deref()
+            .or(defaults.timestamp_timezone.as_deref())
+            .and_then(|tz| timestamp::parse_timezone(tz).ok());
+        timestamp::format_timestamp(modified, format, timezone)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonitoringConfig {
+    // Rewritten with the current timestamp after every fully successful run; alert when it goes stale
+    pub heartbeat_file: Option<String>,
+    // Also count runs served from the balance cache as successful (default false)
+    pub heartbeat_on_cached: Option<bool>,
+    // At startup, compare the local clock with the Date header of the Sheets endpoint and warn
+    // when they differ by more than max_clock_skew_secs (default 120); off by default
+    pub check_clock_skew: Option<bool>,
+    pub max_clock_skew_secs: Option<u64>,
+    // Also leave timestamp cells unwritten for the run when the skew is too large (default false)
+    pub skip_timestamps_on_skew: Option<bool>,
+}
+
+/// Guardrails for very large configs, which can exhaust Sheets quotas and run for a long time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LimitsConfig {
+    // Sync blocks (TOML plus CSV) allowed before warning; default 200
+    pub max_sync_blocks: Option<usize>,
+    // Refuse to load the config instead of warning when a limit is exceeded (default false)
+    pub strict: Option<bool>,
+}
+
+/// Sheet tab that gets a row (time, run ID, failed blocks, error) appended for every failed run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorLogConfig {
+    pub spreadsheet_id: String,
+    pub sheet_name: String,
+    // Header row; each failure is appended beneath it
+    pub anchor: String,
+    // Longer error messages are cut to this many characters (default 500)
+    pub max_message_chars: Option<usize>,
+}
+
+/// When the sync runs: `--daemon` follows it itself, and `--catch-up` uses it to tell when a run
+/// an external scheduler should have made was missed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleConfig {
+    // Seconds between scheduled runs
+    pub interval_secs: Option<u64>,
+    // Cron expression with seconds, in local time, e.g. "0 0 * * * *" for hourly; takes precedence over interval_secs
+    pub cron_expression: Option<String>,
+    // Let `--catch-up` sync immediately when a slot passed since the cache was last saved (default false)
+    pub catch_up: Option<bool>,
+    // Only catch up a slot missed within this many seconds (default 86400); older ones wait for the schedule
+    pub catch_up_window_secs: Option<u64>,
+}
+
+/// Writes every queried account's balance to an OFX 2 file after each run (balances only, no transactions)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OfxExportConfig {
+    pub path: String,
+    // CURDEF of the statement; default "USD"
+    pub currency: Option<String>,
+}
+
+/// How requests to the web app leave this machine
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    // Route requests through this http(s) proxy, e.g. "http://proxy.corp:8080"
+    pub proxy_url: Option<String>,
+    // Comma-separated hosts/domains/CIDRs that bypass proxy_url
+    pub no_proxy: Option<String>,
+    // Refuse TLS older than this: "1.2" or "1.3" (default: whatever the TLS library allows)
+    pub min_tls_version: Option<TlsVersion>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TlsVersion {
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+/// Commands run after the sync, each a program followed by its arguments (no shell). Arguments may
+/// use {{run_id}}, {{source}}, {{written}}, {{missing}}, {{failed}} and {{error}}.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HooksConfig {
+    pub on_success: Option<Vec<String>>,
+    pub on_failure: Option<Vec<String>>,
+    // Fail the run when the on_success hook fails (default false: only log it)
+    pub fail_on_hook_error: Option<bool>,
+}
+
+/// How far a cell may differ from the balance and still count as unchanged
+pub const DEFAULT_UNCHANGED_EPSILON: f64 = 0.005;
+
+/// Accounts per request when paginate_accounts is set without a page_size
+pub const DEFAULT_PAGE_SIZE: u32 = 100;
+
+/// Sync block count above which loading warns when no [limits] section sets one
+pub const DEFAULT_MAX_SYNC_BLOCKS: usize = 200;
+
+/// Extra destination that receives every synced balance as a JSON POST.
+/// `body_template` may use {{account}}, {{balance}} and {{timestamp}}; string values are JSON-escaped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookSinkConfig {
+    pub url: String,
+    pub body_template: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QuickBooksConfig {
     pub enabled: Option<bool>,
     pub company_file: String,
+    // SDK backend: "qbxml" (default), "qbfc", or "auto" to try QBFC and fall back to QBXML.
+    // Older configs hold file-mode values here ("multi-user"); those keep QBXML
     pub connection_mode: Option<String>,
+    // "local" (default), "local_with_ui" (start QuickBooks if needed), "remote" or "remote_qboe"
+    pub connection_type: Option<ConnectionType>,
     pub application_name: Option<String>,
     pub application_id: Option<String>,
     pub connection_timeout: Option<u32>,
+    // Retries for resolving the QBXML ProgID when COM registration isn't ready yet (e.g. at boot)
+    pub progid_retry_attempts: Option<u32>,
+    // Delays double from progid_retry_delay_ms up to progid_retry_max_delay_ms (default 30000)
+    pub progid_retry_delay_ms: Option<u64>,
+    pub progid_retry_max_delay_ms: Option<u64>,
+    // Randomize each ProgID retry delay between zero and its ceiling (default false)
+    pub progid_retry_jitter: Option<bool>,
+    // Attempts at BeginSession while QuickBooks is busy or the company file is in use (default 1);
+    // delays double from begin_session_base_delay_ms (default 500) up to 30 seconds
+    pub begin_session_max_attempts: Option<u32>,
+    pub begin_session_base_delay_ms: Option<u64>,
+    // Retry the whole run once if connecting/starting the session fails (QuickBooks still launching)
+    pub retry_whole_run_on_startup_failure: Option<bool>,
+    pub startup_retry_delay_secs: Option<u64>,
+    // Randomize the startup retry delay between zero and startup_retry_delay_secs (default false)
+    pub startup_retry_jitter: Option<bool>,
+    // When QuickBooks is busy with a backup or verify: "fail" (default) fails the run (or falls
+    // back to cache_file); "skip" ends the run successfully without writing anything
+    pub on_busy: Option<BusyPolicy>,
+    // Abort unless the open company's EIN (from CompanyQuery) matches; punctuation is ignored
+    pub expected_company_ein: Option<String>,
+    // Query only active accounts (default false: QuickBooks decides)
+    pub active_only: Option<bool>,
+    // Query each account type separately and merge the results, for company files whose single
+    // account query fails or comes back truncated (default false)
+    pub query_by_type_batches: Option<bool>,
+    // Read the account list in pages of page_size (default 100) with a QBXML iterator, for
+    // company files too large for one response (default false)
+    pub paginate_accounts: Option<bool>,
+    pub page_size: Option<u32>,
+    // Save the last good balances here and write them when QuickBooks can't be reached
+    pub cache_file: Option<String>,
+    // Fail the run instead of writing cached balances older than this
+    pub max_cache_age_secs: Option<u64>,
+    // How configured account names are compared with QuickBooks full names (default none)
+    pub name_normalization: Option<NameNormalization>,
+    // For balances QuickBooks returns as formatted text: symbols to strip (default $ € £ ¥)
+    // and the decimal separator (default "."; use "," for e.g. "€1.234,56")
+    pub currency_symbols: Option<Vec<String>>,
+    pub decimal_separator: Option<char>,
+    // Server mode: keep one QuickBooks session open, pinging it after this many idle seconds,
+    // instead of opening a session per query
+    pub keep_alive_secs: Option<u64>,
+    // Server mode: space QuickBooks queries at least this many seconds apart
+    pub min_query_interval_secs: Option<u64>,
+    // Long-running modes answer lookups from the last QuickBooks response for this many seconds
+    // instead of querying again; unset or 0 disables the cache
+    pub in_memory_cache_ttl_secs: Option<u64>,
+    // Ask QuickBooks for matching accounts only (smaller responses on large files); ignored
+    // when it would exclude a configured account name
+    pub name_filter: Option<NameFilter>,
+    // List accounts whose balance is zero in --list-accounts (default true); type_report and
+    // prefix_block blocks have their own setting
+    pub include_zero_balance: Option<bool>,
+}
+
+impl QuickBooksConfig {
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn progid_retry(&self) -> ProgIdRetry {
+        let base = Duration::from_millis(self.progid_retry_delay_ms.unwrap_or(2000));
+        ProgIdRetry {
+            attempts: self.progid_retry_attempts.unwrap_or(1),
+            backoff: Backoff {
+                base,
+                cap: Duration::from_millis(self.progid_retry_max_delay_ms.unwrap_or(30_000)).max(base),
+                jitter: self.progid_retry_jitter.unwrap_or(false),
+            },
+        }
+    }
+
+    pub fn session_retry(&self) -> SessionRetry {
+        let base = Duration::from_millis(self.begin_session_base_delay_ms.unwrap_or(500));
+        SessionRetry {
+            attempts: self.begin_session_max_attempts.unwrap_or(1),
+            backoff: Backoff { base, cap: Duration::from_secs(30).max(base), jitter: false },
+        }
+    }
+
+    /// Delay before the one whole-run retry after a startup failure
+    pub fn startup_backoff(&self) -> Backoff {
+        Backoff {
+            jitter: self.startup_retry_jitter.unwrap_or(false),
+            ..Backoff::fixed(Duration::from_secs(self.startup_retry_delay_secs.unwrap_or(30)))
+        }
+    }
+
+    pub fn amount_format(&self) -> AmountFormat {
+        let default = AmountFormat::default();
+        AmountFormat {
+            currency_symbols: self.currency_symbols.clone().unwrap_or(default.currency_symbols),
+            decimal_separator: self.decimal_separator.unwrap_or(default.decimal_separator),
+        }
+    }
+}
+
+/// Normalization applied to both the configured and the QuickBooks full name before comparing.
+/// An exact match always wins; a normalized match is logged so the config can be corrected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NameNormalization {
+    #[default]
+    None,
+    CaseInsensitive,
+    CollapseSpaces,
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateCellPolicy {
+    Strict,
+    #[default]
+    Lenient,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FormulaCellPolicy {
+    #[default]
+    Overwrite,
+    Skip,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutOfRangePolicy {
+    #[default]
+    Error,
+    Clamp,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BusyPolicy {
+    #[default]
+    Fail,
+    Skip,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeySource {
+    #[default]
+    Inline,
+    Env,
+    Keyring,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SheetsBackend {
+    #[default]
+    AppsScript,
+    SheetsApi,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GoogleSheetsConfig {
+    // Required for the apps_script backend
+    #[serde(default)]
     pub webapp_url: String,
+    #[serde(default)]
     pub api_key: String,
+    // Where api_key comes from: "inline" (default, the value above), "env" (variable named by
+    // api_key_env, default QB_SHEETS_API_KEY) or "keyring" (OS credential store; keyring feature)
+    pub api_key_source: Option<ApiKeySource>,
+    pub api_key_env: Option<String>,
+    // Keyring entry for api_key_source = "keyring" (defaults quickbooks-sheets-sync / api_key)
+    pub keyring_service: Option<String>,
+    pub keyring_user: Option<String>,
     pub sheet_name: Option<String>,
     pub cell_address: Option<String>,
+    // Check the API key with the web app before querying QuickBooks (script must support action "auth")
+    pub verify_api_key: Option<bool>,
+    // Before querying QuickBooks, check every sync block cell lies within its sheet's rows and
+    // columns (script must support action "getDimensions"); default false
+    pub verify_cell_bounds: Option<bool>,
+    // Fail a write when the web app answers `{success: false}` even with HTTP 200 (default true)
+    pub require_success: Option<bool>,
+    // "apps_script" (default) posts to the web app; "sheets_api" writes through the Google
+    // Sheets API with a service account (build with the sheets-api feature)
+    pub backend: Option<SheetsBackend>,
+    // Path to the service account's JSON key; the spreadsheets must be shared with its email
+    pub service_account_key: Option<String>,
+    // What to do when two blocks write different values to the same cell in one run:
+    // "strict" fails before writing, "lenient" (default) warns and lets the last block win
+    pub duplicate_cell_policy: Option<DuplicateCellPolicy>,
+    // Before a single-cell write, check whether the cell holds a formula: "overwrite" (default)
+    // writes anyway, "skip" leaves the cell alone, "error" fails the write
+    pub on_formula_cell: Option<FormulaCellPolicy>,
+    // Before writing a balance, read the cell and skip the write when it already holds the
+    // balance to within unchanged_epsilon (default 0.005); force_write = true always writes
+    pub force_write: Option<bool>,
+    pub unchanged_epsilon: Option<f64>,
+    // SHA-256 fingerprint the web app's TLS certificate must have (cert-pinning feature)
+    pub pinned_cert_sha256: Option<String>,
+    // Send all unordered sync block cells of a spreadsheet in one request (apps_script backend);
+    // the web app reports each cell separately so one bad cell fails only its block
+    pub batch_writes: Option<bool>,
+    // Sanity bound on numbers written: larger magnitudes fail the write ("error", default)
+    // or are clamped to the bound ("clamp")
+    pub max_abs_value: Option<f64>,
+    pub out_of_range: Option<OutOfRangePolicy>,
+    // Round numbers to this many decimal places before writing
+    pub round_decimals: Option<u32>,
+    // Defaults for sync block timestamp cells: a strftime pattern (default RFC 3339) and an
+    // IANA time zone such as "America/New_York" (default the machine's local zone)
+    pub timestamp_format: Option<String>,
+    pub timestamp_timezone: Option<String>,
+    // Retry web app requests that fail with 429, a 5xx or a network error up to max_retries
+    // times (default 3); delays double from retry_base_delay_ms (default 1000) up to
+    // retry_max_delay_ms (default 30000), or follow the response's Retry-After
+    pub max_retries: Option<u32>,
+    pub retry_base_delay_ms: Option<u64>,
+    pub retry_max_delay_ms: Option<u64>,
+    // Randomize each retry delay between zero and its ceiling (default false)
+    pub retry_jitter: Option<bool>,
     // spreadsheet_id is now per sync_block, not required here
     // pub spreadsheet_id: Option<String>,
 }
 
+impl GoogleSheetsConfig {
+    pub fn http_retry(&self) -> HttpRetry {
+        let base = Duration::from_millis(self.retry_base_delay_ms.unwrap_or(1000));
+        HttpRetry {
+            max_retries: self.max_retries.unwrap_or(3),
+            backoff: Backoff {
+                base,
+                cap: Duration::from_millis(self.retry_max_delay_ms.unwrap_or(30_000)).max(base),
+                jitter: self.retry_jitter.unwrap_or(false),
+            },
+        }
+    }
+
+    /// Whether balance writes read the cell first; only force_write turns this off
+    pub fn checks_unchanged(&self) -> bool {
+        !self.force_write.unwrap_or(false)
+    }
+
+    /// Whether a cell holding `current` already has `balance`, to within unchanged_epsilon
+    pub fn is_unchanged(&self, current: f64, balance: f64) -> bool {
+        (current - balance).abs() <= self.unchanged_epsilon.unwrap_or(DEFAULT_UNCHANGED_EPSILON)
+    }
+}
+
 
 
 impl Config {
     pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
         let figment = Figment::from(Toml::file(path));
-        figment.extract().context("Failed to parse config file")
+        let mut config: Config = figment.extract().context("Failed to parse config file")?;
+        config.google_sheets.api_key = crate::secret::resolve_api_key(&config.google_sheets)
+            .context("Failed to resolve the Google Sheets API key")?;
+        if let Some(csv_path) = &config.sync_blocks_csv {
+            let csv_path = path.parent().unwrap_or_else(|| Path::new(".")).join(csv_path);
+            let csv_blocks = crate::mapping_csv::load_sync_blocks_csv(&csv_path)?;
+            log::info!("Loaded {} sync block(s) from {}", csv_blocks.len(), csv_path.display());
+            config.sync_blocks.extend(csv_blocks);
+        }
+        config.validate()?;
+        log::info!("Loaded {} sync block(s)", config.sync_blocks.len());
+        if let Some(warning) = config.check_limits()? {
+            log::warn!("{}", warning);
+        }
+        Ok(config)
+    }
+
+    /// A warning when a soft limit is exceeded, or an error if `[limits] strict` is set
+    pub fn check_limits(&self) -> Result<Option<String>> {
+        let limits = self.limits.as_ref();
+        let max = limits.and_then(|l| l.max_sync_blocks).unwrap_or(DEFAULT_MAX_SYNC_BLOCKS);
+        if self.sync_blocks.len() <= max {
+            return Ok(None);
+        }
+        let message = format!(
+            "{} sync blocks exceeds max_sync_blocks ({}); large runs can exhaust Google Sheets quotas. \
+             Consider fewer, wider blocks (type_report, prefix_block) or splitting the config across schedules.",
+            self.sync_blocks.len(),
+            max
+        );
+        if limits.and_then(|l| l.strict).unwrap_or(false) {
+            anyhow::bail!(message);
+        }
+        Ok(Some(message))
+    }
+
+    /// Every QuickBooks account full name the config reads, without duplicates
+    pub fn account_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.sync_blocks.iter().map(|b| b.account_full_name.as_str())
+            .chain(self.chart_data.iter().flat_map(|c| c.accounts.iter().map(String::as_str)))
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    // The sheets_api backend writes single cells only, so row-based outputs need the web app
+    fn validate_backend(&self) -> Result<()> {
+        let gs = &self.google_sheets;
+        match gs.backend.unwrap_or_default() {
+            SheetsBackend::AppsScript => {
+                if gs.webapp_url.is_empty() {
+                    anyhow::bail!("google_sheets.webapp_url is required for the apps_script backend");
+                }
+                validate_webapp_url("google_sheets.webapp_url", &gs.webapp_url)?;
+                for (i, block) in self.sync_blocks.iter().enumerate() {
+                    if let Some(url) = &block.webapp_url {
+                        validate_webapp_url(&format!("sync_blocks[{}] webapp_url", i), url)?;
+                    }
+                }
+            },
+            SheetsBackend::SheetsApi => {
+                if cfg!(not(feature = "sheets-api")) {
+                    anyhow::bail!("google_sheets.backend = \"sheets_api\" requires a build with the sheets-api feature");
+                }
+                if gs.service_account_key.is_none() {
+                    anyhow::bail!("google_sheets.service_account_key is required for the sheets_api backend");
+                }
+                if !self.chart_data.is_empty() || !self.type_report.is_empty() || !self.prefix_block.is_empty()
+                    || !self.account_row.is_empty() || !self.period_balance.is_empty() || !self.percent_sync.is_empty()
+                    || self.error_log.is_some()
+                {
+                    anyhow::bail!("chart_data, type_report, prefix_block, account_row, period_balance, percent_sync and error_log need the apps_script backend");
+                }
+                if self.sync_blocks.iter().any(|b| b.raw_balance.unwrap_or(false)) {
+                    anyhow::bail!("raw_balance is not supported with the sheets_api backend");
+                }
+                if gs.verify_cell_bounds.unwrap_or(false) {
+                    anyhow::bail!("google_sheets.verify_cell_bounds needs the apps_script backend");
+                }
+                if self.sync_blocks.iter().any(|b| b.webapp_url.is_some() || b.api_key.is_some()) {
+                    anyhow::bail!("sync block webapp_url/api_key overrides need the apps_script backend");
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// The AccountQueryRq filters for this config. The name filter is dropped (with a warning)
+    /// if any configured account name would not pass it, so no block silently loses its account.
+    pub fn query_options(&self) -> AccountQueryOptions {
+        let name_filter = self.quickbooks.name_filter.clone().filter(|filter| {
+            let excluded: Vec<&str> = self.account_names().into_iter().filter(|name| !filter.matches(name)).collect();
+            if !excluded.is_empty() {
+                log::warn!(
+                    "[QBXML] name_filter {:?} '{}' would exclude configured account(s) {}; querying all accounts",
+                    filter.match_criterion, filter.name, excluded.join(", ")
+                );
+            }
+            excluded.is_empty()
+        });
+        AccountQueryOptions {
+            active_only: self.quickbooks.active_only.unwrap_or(false),
+            name_filter,
+            account_type: None,
+            by_type_batches: self.quickbooks.query_by_type_batches.unwrap_or(false),
+            page_size: self.quickbooks.paginate_accounts.unwrap_or(false)
+                .then(|| self.quickbooks.page_size.unwrap_or(DEFAULT_PAGE_SIZE)),
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.validate_backend()?;
+        for (i, block) in self.sync_blocks.iter().enumerate() {
+            if block.spreadsheet_id.trim().is_empty() {
+                anyhow::bail!("sync_blocks[{}] ('{}') has no spreadsheet_id", i, block.account_full_name);
+            }
+            if block.cell_address.addresses().is_empty() {
+                anyhow::bail!("sync_blocks[{}] ('{}') has no cell_address", i, block.account_full_name);
+            }
+            for (_, cell) in block.targets() {
+                CellRef::parse(&cell).with_context(|| {
+                    format!("sync_blocks[{}] ('{}') has an invalid cell_address", i, block.account_full_name)
+                })?;
+            }
+            if let Some(cell) = &block.timestamp_cell {
+                CellRef::parse(cell).with_context(|| {
+                    format!("sync_blocks[{}] ('{}') has an invalid timestamp_cell", i, block.account_full_name)
+                })?;
+            }
+            if block.currency_format.is_some() && block.raw_balance.unwrap_or(false) {
+                anyhow::bail!("sync_blocks[{}] ('{}') sets both raw_balance and currency_format; pick one", i, block.account_full_name);
+            }
+            if let Some(alert) = &block.alert {
+                CellRef::parse(&alert.cell).with_context(|| {
+                    format!("sync_blocks[{}] ('{}') has an invalid alert cell", i, block.account_full_name)
+                })?;
+            }
+            if let Some(cell) = &block.attribution_cell {
+                CellRef::parse(cell).with_context(|| {
+                    format!("sync_blocks[{}] ('{}') has an invalid attribution_cell", i, block.account_full_name)
+                })?;
+            }
+            if let Some(cell) = &block.smoothed_cell {
+                CellRef::parse(cell).with_context(|| {
+                    format!("sync_blocks[{}] ('{}') has an invalid smoothed_cell", i, block.account_full_name)
+                })?;
+                if self.quickbooks.cache_file.is_none() {
+                    anyhow::bail!("sync_blocks[{}] smoothed_cell needs [quickbooks] cache_file to keep the moving average", i);
+                }
+            }
+            if let Some(alpha) = block.smoothing_alpha {
+                if !(alpha > 0.0 && alpha <= 1.0) {
+                    anyhow::bail!("sync_blocks[{}] smoothing_alpha must be greater than 0 and at most 1", i);
+                }
+            }
+            if let Some(format) = &block.timestamp_format {
+                timestamp::validate_format(format).with_context(|| format!("sync_blocks[{}] timestamp_format", i))?;
+            }
+            if let Some(tz) = &block.timestamp_timezone {
+                timestamp::parse_timezone(tz).with_context(|| format!("sync_blocks[{}] timestamp_timezone", i))?;
+            }
+        }
+        for (i, chart) in self.chart_data.iter().enumerate() {
+            CellRef::parse(&chart.anchor)
+                .with_context(|| format!("chart_data[{}] has an invalid anchor", i))?;
+            if chart.accounts.is_empty() {
+                anyhow::bail!("chart_data[{}] must list at least one account", i);
+            }
+            if let Some(headers) = &chart.headers {
+                if headers.len() != chart.accounts.len() {
+                    anyhow::bail!(
+                        "chart_data[{}] has {} headers but {} accounts; they must match",
+                        i, headers.len(), chart.accounts.len()
+                    );
+                }
+            }
+        }
+        for (i, report) in self.type_report.iter().enumerate() {
+            CellRef::parse(&report.anchor)
+                .with_context(|| format!("type_report[{}] has an invalid anchor", i))?;
+            if let Some(range) = &report.clear_range {
+                CellRange::parse(range)
+                    .with_context(|| format!("type_report[{}] has an invalid clear_range", i))?;
+            }
+        }
+        for (i, block) in self.prefix_block.iter().enumerate() {
+            if block.full_name_prefix.is_empty() {
+                anyhow::bail!("prefix_block[{}] has an empty full_name_prefix", i);
+            }
+            CellRef::parse(&block.start_cell)
+                .with_context(|| format!("prefix_block[{}] has an invalid start_cell", i))?;
+            if let Some(range) = &block.clear_range {
+                CellRange::parse(range)
+                    .with_context(|| format!("prefix_block[{}] has an invalid clear_range", i))?;
+            }
+        }
+        if let Some(format) = &self.google_sheets.timestamp_format {
+            timestamp::validate_format(format).context("google_sheets.timestamp_format")?;
+        }
+        if let Some(tz) = &self.google_sheets.timestamp_timezone {
+            timestamp::parse_timezone(tz).context("google_sheets.timestamp_timezone")?;
+        }
+        if let Some(ofx) = &self.ofx_export {
+            let currency = ofx.currency.as_deref().unwrap_or("USD");
+            if currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_uppercase()) {
+                anyhow::bail!("ofx_export.currency '{}' must be a three-letter ISO 4217 code such as USD", currency);
+            }
+        }
+        if let Some(error_log) = &self.error_log {
+            CellRef::parse(&error_log.anchor).context("error_log has an invalid anchor")?;
+            if error_log.max_message_chars == Some(0) {
+                anyhow::bail!("error_log.max_message_chars must be greater than 0");
+            }
+        }
+        if self.quickbooks.page_size == Some(0) {
+            anyhow::bail!("quickbooks.page_size must be greater than 0");
+        }
+        if let Some(schedule) = &self.schedule {
+            if schedule.interval_secs == Some(0) {
+                anyhow::bail!("schedule.interval_secs must be greater than 0");
+            }
+            if schedule.interval_secs.is_none() && schedule.cron_expression.is_none() {
+                anyhow::bail!("[schedule] needs cron_expression or interval_secs");
+            }
+            if let Some(expression) = &schedule.cron_expression {
+                crate::catch_up::parse_cron(expression)?;
+            }
+            if schedule.catch_up.unwrap_or(false) && self.quickbooks.cache_file.is_none() {
+                anyhow::bail!("schedule.catch_up needs [quickbooks] cache_file, which records when the last run happened");
+            }
+        }
+        if let Some(network) = &self.network {
+            crate::network::validate(network)?;
+        }
+        if let Some(pin) = &self.google_sheets.pinned_cert_sha256 {
+            crate::cert_pin::parse_fingerprint(pin).context("google_sheets.pinned_cert_sha256 is invalid")?;
+            if !cfg!(feature = "cert-pinning") {
+                anyhow::bail!("google_sheets.pinned_cert_sha256 requires a build with the cert-pinning feature (cargo build --features cert-pinning)");
+            }
+        }
+        if self.google_sheets.batch_writes.unwrap_or(false) {
+            if self.google_sheets.backend.unwrap_or_default() != SheetsBackend::AppsScript {
+                anyhow::bail!("google_sheets.batch_writes needs the apps_script backend");
+            }
+            if self.google_sheets.on_formula_cell.unwrap_or_default() != FormulaCellPolicy::Overwrite {
+                anyhow::bail!("google_sheets.on_formula_cell checks each cell before writing; it can't be combined with batch_writes");
+            }
+        }
+        if let Some(epsilon) = self.google_sheets.unchanged_epsilon {
+            if !epsilon.is_finite() || epsilon < 0.0 {
+                anyhow::bail!("google_sheets.unchanged_epsilon must be zero or a positive number, got {}", epsilon);
+            }
+        }
+        if let Some(max_abs) = self.google_sheets.max_abs_value {
+            if !(max_abs.is_finite() && max_abs > 0.0) {
+                anyhow::bail!("google_sheets.max_abs_value must be a positive number, got {}", max_abs);
+            }
+        }
+        for (i, row) in self.account_row.iter().enumerate() {
+            if row.fields.is_empty() {
+                anyhow::bail!("account_row[{}] ('{}') lists no fields", i, row.account_full_name);
+            }
+            CellRef::parse(&row.anchor)
+                .with_context(|| format!("account_row[{}] has an invalid anchor", i))?;
+        }
+        for (i, block) in self.period_balance.iter().enumerate() {
+            if block.periods.is_empty() {
+                anyhow::bail!("period_balance[{}] ('{}') lists no periods", i, block.account_full_name);
+            }
+            if block.column_stride == Some(0) {
+                anyhow::bail!("period_balance[{}] has column_stride 0; use 1 for adjacent columns", i);
+            }
+            CellRef::parse(&block.start_cell)
+                .with_context(|| format!("period_balance[{}] has an invalid start_cell", i))?;
+        }
+        for (i, count) in self.transaction_count.iter().enumerate() {
+            CellRef::parse(&count.cell_address)
+                .with_context(|| format!("transaction_count[{}] has an invalid cell_address", i))?;
+            if count.from_date > count.to_date {
+                anyhow::bail!(
+                    "transaction_count[{}] ('{}') has from_date {} after to_date {}",
+                    i, count.account_full_name, count.from_date, count.to_date
+                );
+            }
+        }
+        for (i, customer) in self.customer_sync.iter().enumerate() {
+            CellRef::parse(&customer.cell_address)
+                .with_context(|| format!("customer_sync[{}] ('{}') has an invalid cell_address", i, customer.customer_full_name))?;
+        }
+        for (i, block) in self.percent_sync.iter().enumerate() {
+            if block.accounts.is_empty() {
+                anyhow::bail!("percent_sync[{}] lists no accounts", i);
+            }
+            for member in &block.accounts {
+                for cell in [&member.balance_cell, &member.percent_cell] {
+                    CellRef::parse(cell).with_context(|| {
+                        format!("percent_sync[{}] ('{}') has an invalid cell", i, member.account)
+                    })?;
+                }
+            }
+        }
+        for (i, block) in self.file_modified.iter().enumerate() {
+            CellRef::parse(&block.cell_address)
+                .with_context(|| format!("file_modified[{}] has an invalid cell_address", i))?;
+            if let Some(format) = &block.timestamp_format {
+                timestamp::validate_format(format).with_context(|| format!("file_modified[{}] timestamp_format", i))?;
+            }
+            if let Some(tz) = &block.timestamp_timezone {
+                timestamp::parse_timezone(tz).with_context(|| format!("file_modified[{}] timestamp_timezone", i))?;
+            }
+        }
+        if let Some(hooks) = &self.hooks {
+            for (name, command) in [("on_success", &hooks.on_success), ("on_failure", &hooks.on_failure)] {
+                if let Some(command) = command {
+                    crate::hooks::check_command(command).with_context(|| format!("hooks.{} is invalid", name))?;
+                }
+            }
+        }
+        for (i, hook) in self.webhook_sink.iter().enumerate() {
+            crate::webhook::WebhookSink::from_config(hook)
+                .check_template()
+                .with_context(|| format!("webhook_sink[{}] has an invalid body_template", i))?;
+        }
+        Ok(())
+    }
+}
+
+// A web app address must be an absolute https:// URL, such as the deployment's /exec address
+fn validate_webapp_url(field: &str, url: &str) -> Result<()> {
+    let parsed = reqwest::Url::parse(url).with_context(|| format!("{} '{}' is not a URL", field, url))?;
+    if parsed.scheme() != "https" || parsed.host_str().is_none() {
+        anyhow::bail!("{} '{}' must be an https:// URL (the web app's /exec address)", field, url);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qbxml_safe::qbxml_request_processor::MatchCriterion;
+
+    fn google_sheets(toml: &str) -> GoogleSheetsConfig {
+        Figment::from(Toml::string(toml)).extract().unwrap()
+    }
+
+    const WEBAPP_URL: &str = "https://script.google.com/macros/s/abc123/exec";
+
+    fn config(webapp_url: &str, spreadsheet_id: &str) -> Config {
+        let toml = format!(r#"
+            [quickbooks]
+            company_file = "AUTO"
+
+            [google_sheets]
+            webapp_url = "{}"
+            api_key = "key"
+
+            [[sync_blocks]]
+            spreadsheet_id = "{}"
+            account_full_name = "Checking"
+            sheet_name = "Balances"
+            cell_address = "B2"
+        "#, webapp_url, spreadsheet_id);
+        Figment::from(Toml::string(&toml)).extract().unwrap()
+    }
+
+    #[test]
+    fn name_filter_is_dropped_when_it_would_exclude_a_configured_account() {
+        let mut config = config(WEBAPP_URL, "1AbC");
+        config.quickbooks.name_filter = Some(NameFilter { match_criterion: MatchCriterion::StartsWith, name: "check".to_string() });
+        assert_eq!(config.query_options().name_filter, config.quickbooks.name_filter);
+        config.quickbooks.name_filter = Some(NameFilter { match_criterion: MatchCriterion::Contains, name: "Savings".to_string() });
+        assert_eq!(config.query_options().name_filter, None);
+    }
+
+    #[test]
+    fn validate_accepts_an_https_webapp_and_a_spreadsheet_id() {
+        config(WEBAPP_URL, "1AbC").validate().unwrap();
+    }
+
+    #[test]
+    fn validate_checks_chart_anchor_and_headers() {
+        let mut config = config(WEBAPP_URL, "1AbC");
+        config.chart_data.push(ChartDataConfig {
+            spreadsheet_id: "1AbC".to_string(),
+            sheet_name: "Chart".to_string(),
+            anchor: "A1".to_string(),
+            accounts: vec!["Checking".to_string(), "Savings".to_string()],
+            headers: Some(vec!["Operating".to_string()]),
+            date_header: None,
+        });
+        let err = config.validate().unwrap_err();
+        assert_eq!(err.to_string(), "chart_data[0] has 1 headers but 2 accounts; they must match");
+        config.chart_data[0].headers = None;
+        config.chart_data[0].anchor = "1A".to_string();
+        assert_eq!(config.validate().unwrap_err().to_string(), "chart_data[0] has an invalid anchor");
+        config.chart_data[0].anchor = "A1".to_string();
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn validate_rejects_an_invalid_alert_cell() {
+        let mut config = config(WEBAPP_URL, "1AbC");
+        config.sync_blocks[0].alert = Some(AlertConfig {
+            op: AlertOp::Lt,
+            value: 0.0,
+            cell: "not a cell".to_string(),
+            message: "Overdrawn".to_string(),
+        });
+        let err = config.validate().unwrap_err();
+        assert!(format!("{:#}", err).contains("invalid alert cell"), "{:#}", err);
+        config.sync_blocks[0].alert.as_mut().unwrap().cell = "C2".to_string();
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn validate_checks_the_schedule() {
+        let schedule = |cron_expression: Option<&str>, interval_secs: Option<u64>| ScheduleConfig {
+            interval_secs,
+            cron_expression: cron_expression.map(str::to_string),
+            catch_up: None,
+            catch_up_window_secs: None,
+        };
+        let mut config = config(WEBAPP_URL, "1AbC");
+        for (valid, cron_expression, interval_secs) in [
+            (true, Some("0 0 * * * *"), None),
+            (true, Some("0 30 9 * * Mon-Fri"), None),
+            (true, None, Some(3600)),
+            (false, Some("0 0 * * *"), None),
+            (false, Some("every hour"), None),
+            (false, None, Some(0)),
+            (false, None, None),
+        ] {
+            config.schedule = Some(schedule(cron_expression, interval_secs));
+            assert_eq!(config.validate().is_ok(), valid, "{:?} {:?}", cron_expression, interval_secs);
+        }
+    }
+
+    #[test]
+    fn validate_rejects_non_https_webapp_urls() {
+        for url in ["http://script.google.com/macros/s/abc123/exec", "script.google.com/macros/s/abc123/exec", "https://"] {
+            let err = config(url, "1AbC").validate().unwrap_err();
+            assert!(format!("{:#}", err).contains("google_sheets.webapp_url"), "{}: {:#}", url, err);
+        }
+        let err = config("", "1AbC").validate().unwrap_err();
+        assert_eq!(err.to_string(), "google_sheets.webapp_url is required for the apps_script backend");
+    }
+
+    #[test]
+    fn validate_rejects_a_sync_block_webapp_over_http() {
+        let mut config = config(WEBAPP_URL, "1AbC");
+        config.sync_blocks[0].webapp_url = Some("http://example.com/exec".to_string());
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().starts_with("sync_blocks[0] webapp_url"), "{}", err);
+    }
+
+    #[test]
+    fn validate_rejects_an_empty_spreadsheet_id() {
+        for spreadsheet_id in ["", "   "] {
+            let err = config(WEBAPP_URL, spreadsheet_id).validate().unwrap_err();
+            assert_eq!(err.to_string(), "sync_blocks[0] ('Checking') has no spreadsheet_id");
+        }
+    }
+
+    #[test]
+    fn force_write_turns_off_the_unchanged_check() {
+        assert!(google_sheets("").checks_unchanged());
+        assert!(google_sheets("force_write = false").checks_unchanged());
+        assert!(!google_sheets("force_write = true").checks_unchanged());
+    }
+
+    #[test]
+    fn sheets_retries_take_the_configured_backoff() {
+        let default = google_sheets("").http_retry();
+        assert_eq!(default.max_retries, 3);
+        assert_eq!(default.backoff, Backoff { base: Duration::from_secs(1), cap: Duration::from_secs(30), jitter: false });
+        let tuned = google_sheets("retry_base_delay_ms = 500\nretry_max_delay_ms = 4000\nretry_jitter = true").http_retry();
+        assert_eq!(tuned.backoff, Backoff { base: Duration::from_millis(500), cap: Duration::from_secs(4), jitter: true });
+    }
+
+    #[test]
+    fn unchanged_compares_within_epsilon() {
+        let default = google_sheets("");
+        assert!(default.is_unchanged(1234.56, 1234.56));
+        assert!(default.is_unchanged(1234.564, 1234.56));
+        assert!(default.is_unchanged(-10.0, -10.004));
+        assert!(!default.is_unchanged(1234.57, 1234.56));
+        assert!(!default.is_unchanged(10.0, -10.0));
+        let exact = google_sheets("unchanged_epsilon = 0.0");
+        assert!(exact.is_unchanged(0.5, 0.5));
+        assert!(!exact.is_unchanged(0.5, 0.5000001));
+    }
+
+    #[test]
+    fn sync_block_limit_is_silent_below_warns_above_and_fails_when_strict() {
+        let mut config = config(WEBAPP_URL, "1AbC");
+        let block = config.sync_blocks[0].clone();
+        config.sync_blocks = vec![block; 3];
+        config.limits = Some(LimitsConfig { max_sync_blocks: Some(3), strict: Some(true) });
+        assert_eq!(config.check_limits().unwrap(), None);
+        config.limits = Some(LimitsConfig { max_sync_blocks: Some(2), strict: None });
+        let warning = config.check_limits().unwrap().unwrap();
+        assert!(warning.starts_with("3 sync blocks exceeds max_sync_blocks (2)"), "{}", warning);
+        config.limits = Some(LimitsConfig { max_sync_blocks: Some(2), strict: Some(true) });
+        assert_eq!(config.check_limits().unwrap_err().to_string(), warning);
+        // Without a [limits] section the default applies
+        config.limits = None;
+        assert_eq!(config.check_limits().unwrap(), None);
     }
 }
@@ -0,0 +1,64 @@
+// Run error log
+// Appends a row to a sheet tab for every failed run, so spreadsheet users see failures without log access
+
+use chrono::{DateTime, Utc};
+
+use crate::config::ErrorLogConfig;
+use crate::google_sheets::CellValue;
+use crate::report::{BlockStatus, SyncReport};
+
+const DEFAULT_MAX_MESSAGE_CHARS: usize = 500;
+
+impl ErrorLogConfig {
+    pub fn header(&self) -> Vec<String> {
+        ["Timestamp", "Run ID", "Failed blocks", "Error"].map(String::from).to_vec()
+    }
+
+    /// The row to append for this run, or None when it fully succeeded. `error` is the run's
+    /// error; without one, the failed blocks' own errors are reported.
+    pub fn row(&self, report: &SyncReport, error: Option<&str>, run_id: &str, now: DateTime<Utc>) -> Option<Vec<CellValue>> {
+        let failed: Vec<_> = report.blocks.iter().filter(|block| block.status == BlockStatus::Failed).collect();
+        if error.is_none() && failed.is_empty() {
+            return None;
+        }
+        let blocks = failed.iter()
+            .map(|block| format!("{} ('{}'!{})", block.account_full_name, block.sheet_name, block.cells.join(",")))
+            .collect::<Vec<_>>()
+            .join("; ");
+        let message = match error {
+            Some(error) => error.to_string(),
+            None => failed.iter().filter_map(|block| block.error.as_deref()).collect::<Vec<_>>().join("; "),
+        };
+        Some(vec![
+            CellValue::Text(now.to_rfc3339()),
+            CellValue::Text(run_id.to_string()),
+            CellValue::Text(blocks),
+            CellValue::Text(truncate(&message, self.max_message_chars.unwrap_or(DEFAULT_MAX_MESSAGE_CHARS))),
+        ])
+    }
+}
+
+// Sheets cells hold at most 50,000 characters, and a long error chain is unreadable in one anyway
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(max.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_errors_are_truncated() {
+        let log = ErrorLogConfig { spreadsheet_id: "1Log".to_string(), sheet_name: "Errors".to_string(), anchor: "A1".to_string(), max_message_chars: Some(10) };
+        let now = DateTime::parse_from_rfc3339("2024-03-01T12:00:00Z").unwrap().with_timezone(&Utc);
+        let row = log.row(&SyncReport::default(), Some("QuickBooks is not running"), "run-1", now).unwrap();
+        assert_eq!(row[0], CellValue::Text("2024-03-01T12:00:00+00:00".to_string()));
+        assert_eq!(row[3], CellValue::Text("QuickBook…".to_string()));
+        assert_eq!(truncate("short", 10), "short");
+    }
+}
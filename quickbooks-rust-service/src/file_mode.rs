@@ -1,4 +1,6 @@
 // FileMode enum for QuickBooks session modes
+// Mirrors the SDK's open modes; the service itself always begins sessions with DoNotCare
+#[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
 pub enum FileMode {
     SingleUser,
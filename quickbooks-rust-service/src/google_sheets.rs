@@ -1,5 +1,12 @@
 use anyhow::{Result, Context};
-use serde::Serialize;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::a1::{self, CellRange, CellRef};
+use crate::config::{FormulaCellPolicy, NetworkConfig};
+use crate::network::HttpRetry;
+use crate::sink::{BalanceSink, BalanceUpdate};
+use crate::value_guard::ValueGuard;
 
 pub struct GoogleSheetsClient {
     pub webapp_url: String,
@@ -7,44 +14,803 @@ pub struct GoogleSheetsClient {
     pub spreadsheet_id: String,
     pub sheet_name: Option<String>,
     pub cell_address: String,
+    // Treat a `{success: false}` body as a failed write (bodies without `success` still pass)
+    pub require_success: bool,
+    // Checked with a getFormula preflight before each single-cell write unless Overwrite
+    pub formula_policy: FormulaCellPolicy,
+    // Applied to every number before it is sent
+    pub value_guard: ValueGuard,
+    // Sent as runId with every request so the script can log which run touched a cell
+    pub run_id: Option<String>,
+    // Only connect when the server certificate has this SHA-256 fingerprint
+    pub pinned_cert: Option<[u8; 32]>,
+    // Proxy and TLS settings from [network]
+    pub network: Option<NetworkConfig>,
+    // Retries for 429s, 5xxs and network errors; none unless set
+    pub retry: HttpRetry,
+    // --dry-run: log each write instead of sending it, and skip the reads that precede writes
+    pub dry_run: bool,
+}
+
+/// A value written to a cell: balances go out as numbers, labels and flags as text.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum CellValue {
+    Number(f64),
+    Text(String),
+}
+
+/// Error code the Apps Script sets when a write hits a protected range
+pub const ERROR_CELL_PROTECTED: &str = "CELL_PROTECTED";
+
+/// Error code the Apps Script sets when Sheets rejects a value as too large or malformed
+pub const ERROR_VALUE_OUT_OF_RANGE: &str = "VALUE_OUT_OF_RANGE";
+
+/// Response body returned by the Apps Script: `{ success, error?, errorCode?, cell? }`
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WriteResponse {
+    #[serde(default)]
+    success: Option<bool>,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    error_code: Option<String>,
+    #[serde(default)]
+    cell: Option<String>,
 }
 
 #[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
 struct GoogleSheetsPayload<'a> {
-    accountNumber: &'a str,
-    accountValue: f64,
-    cellAddress: &'a str,
-    spreadsheetId: &'a str,
+    account_number: &'a str,
+    account_value: &'a CellValue,
+    cell_address: &'a str,
+    spreadsheet_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sheet_name: Option<&'a str>,
+    api_key: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run_id: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AppendRowPayload<'a> {
+    action: &'static str,
+    spreadsheet_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sheet_name: Option<&'a str>,
+    anchor: &'a str,
+    header: &'a [String],
+    values: &'a [CellValue],
+    api_key: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run_id: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WriteRowsPayload<'a> {
+    action: &'static str,
+    spreadsheet_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sheet_name: Option<&'a str>,
+    anchor: &'a str,
+    rows: &'a [Vec<CellValue>],
+    api_key: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run_id: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ClearRangePayload<'a> {
+    action: &'static str,
+    spreadsheet_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sheet_name: Option<&'a str>,
+    range: &'a str,
+    api_key: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run_id: Option<&'a str>,
+}
+
+/// One cell of a batched write
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CellWrite {
+    pub sheet_name: String,
+    pub cell_address: String,
+    pub value: CellValue,
+}
+
+/// One balance of a batched send; a sheet_name of None means the client's sheet
+#[derive(Debug, Clone, Copy)]
+pub struct BalanceCell<'a> {
+    pub account_full_name: &'a str,
+    pub balance: f64,
+    pub sheet_name: Option<&'a str>,
+    pub cell_address: &'a str,
+}
+
+/// Outcome of one cell of a batched write, in the same order as the request
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CellResult {
+    #[serde(default)]
+    pub cell: String,
+    pub success: bool,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub error_code: Option<String>,
+}
+
+impl CellResult {
+    /// Why the cell failed, explaining the script's known error codes
+    pub fn describe(&self) -> String {
+        explain_error_code(self.error_code.as_deref(), &self.cell, self.error.as_deref())
+            .unwrap_or_else(|| format!("{}: {}", self.cell, self.error.as_deref().unwrap_or("write failed")))
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WriteCellsPayload<'a> {
+    action: &'static str,
+    spreadsheet_id: &'a str,
+    cells: &'a [CellWrite],
+    api_key: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run_id: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct WriteCellsResponse {
+    #[serde(default)]
+    results: Option<Vec<CellResult>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GetDimensionsPayload<'a> {
+    action: &'static str,
+    spreadsheet_id: &'a str,
+    sheet_name: &'a str,
+    api_key: &'a str,
     #[serde(skip_serializing_if = "Option::is_none")]
-    sheetName: Option<&'a str>,
-    apiKey: &'a str,
+    run_id: Option<&'a str>,
+}
+
+// getFormula and getValue: one cell, named by sheet and address
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CellQueryPayload<'a> {
+    action: &'static str,
+    spreadsheet_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sheet_name: Option<&'a str>,
+    cell_address: &'a str,
+    api_key: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run_id: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuthCheckPayload<'a> {
+    action: &'static str,
+    api_key: &'a str,
 }
 
 impl GoogleSheetsClient {
     pub fn new(webapp_url: String, api_key: String, spreadsheet_id: String, sheet_name: Option<String>, cell_address: String) -> Self {
-        Self { webapp_url, api_key, spreadsheet_id, sheet_name, cell_address }
+        Self { webapp_url, api_key, spreadsheet_id, sheet_name, cell_address, require_success: true, formula_policy: FormulaCellPolicy::Overwrite, value_guard: ValueGuard::default(), run_id: None, pinned_cert: None, network: None, retry: HttpRetry::default(), dry_run: false }
+    }
+
+    pub fn with_require_success(mut self, require_success: bool) -> Self {
+        self.require_success = require_success;
+        self
+    }
+
+    pub fn with_formula_policy(mut self, formula_policy: FormulaCellPolicy) -> Self {
+        self.formula_policy = formula_policy;
+        self
+    }
+
+    pub fn with_pinned_cert(mut self, pinned_cert: Option<[u8; 32]>) -> Self {
+        self.pinned_cert = pinned_cert;
+        self
+    }
+
+    pub fn with_network(mut self, network: Option<NetworkConfig>) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Post to another web app deployment with its own API key
+    pub fn with_webapp(mut self, webapp_url: &str, api_key: &str) -> Self {
+        self.webapp_url = webapp_url.to_string();
+        self.api_key = api_key.to_string();
+        self
+    }
+
+    pub fn with_retry(mut self, retry: HttpRetry) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_run_id(mut self, run_id: &str) -> Self {
+        self.run_id = Some(run_id.to_string());
+        self
+    }
+
+    pub fn with_value_guard(mut self, value_guard: ValueGuard) -> Self {
+        self.value_guard = value_guard;
+        self
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    // Under --dry-run, log what would be sent and return true so the caller sends nothing
+    fn previewed(&self, what: std::fmt::Arguments<'_>, sheet_name: Option<&str>, target: &str) -> bool {
+        if self.dry_run {
+            log::info!("[DRY-RUN] Would {} {} '{}'!{}", what, self.spreadsheet_id, sheet_name.unwrap_or_default(), target);
+        }
+        self.dry_run
     }
 
     pub async fn send_balance(&self, account_number: &str, account_value: f64, sheet_name: Option<&str>, cell_address: Option<&str>) -> Result<()> {
+        let update = BalanceCell {
+            account_full_name: account_number,
+            balance: account_value,
+            sheet_name,
+            cell_address: cell_address.unwrap_or(&self.cell_address),
+        };
+        self.send_balances(&[update]).await
+    }
+
+    /// Write several balances in one writeCells request, failing if any cell fails. Under
+    /// on_formula_cell "skip" or "error" each cell is checked, so each is sent on its own.
+    pub async fn send_balances(&self, updates: &[BalanceCell<'_>]) -> Result<()> {
+        if self.formula_policy != FormulaCellPolicy::Overwrite {
+            for update in updates {
+                self.send_value(update.account_full_name, &CellValue::Number(update.balance), update.sheet_name, Some(update.cell_address)).await?;
+            }
+            return Ok(());
+        }
+        let results = self.write_cells(&balance_cell_writes(updates, self.sheet_name.as_deref())).await?;
+        check_balance_results(updates, &results)
+    }
+
+    pub async fn send_value(&self, account_number: &str, value: &CellValue, sheet_name: Option<&str>, cell_address: Option<&str>) -> Result<()> {
+        let sheet_name = sheet_name.or(self.sheet_name.as_deref());
+        let cell = checked_cell(cell_address.unwrap_or(&self.cell_address))?;
+        if self.dry_run {
+            let value = self.value_guard.check(value, cell)?;
+            self.previewed(format_args!("write {:?} for '{}' to", value, account_number), sheet_name, cell);
+            return Ok(());
+        }
+        if self.formula_policy != FormulaCellPolicy::Overwrite {
+            let formula = self.cell_formula(sheet_name, cell).await?;
+            if !formula_guard(self.formula_policy, formula.as_deref(), cell)? {
+                log::warn!("[SHEETS] Skipping {}: the cell holds a formula ({})", cell, formula.unwrap_or_default());
+                return Ok(());
+            }
+        }
+        let value = self.value_guard.check(value, cell)?;
         let payload = GoogleSheetsPayload {
-            accountNumber: account_number,
-            accountValue: account_value,
-            cellAddress: cell_address.unwrap_or(&self.cell_address),
-            spreadsheetId: &self.spreadsheet_id,
-            sheetName: sheet_name.or(self.sheet_name.as_deref()),
-            apiKey: &self.api_key,
-        };
-        let client = reqwest::Client::new();
-        let res = client.post(&self.webapp_url)
-            .json(&payload)
+            account_number,
+            account_value: &value,
+            cell_address: cell,
+            spreadsheet_id: &self.spreadsheet_id,
+            sheet_name,
+            api_key: &self.api_key,
+            run_id: self.run_id.as_deref(),
+        };
+        self.post(&payload, payload.cell_address).await
+    }
+
+    /// Append `values` as the next empty row beneath `anchor`, writing `header` at the anchor row
+    pub async fn append_row(&self, anchor: &str, header: &[String], values: &[CellValue]) -> Result<()> {
+        let anchor = checked_cell(anchor)?;
+        let values = values.iter()
+            .map(|value| self.value_guard.check(value, anchor))
+            .collect::<Result<Vec<CellValue>>>()?;
+        if self.previewed(format_args!("append {:?} beneath", values), self.sheet_name.as_deref(), anchor) {
+            return Ok(());
+        }
+        let payload = AppendRowPayload {
+            action: "appendRow",
+            spreadsheet_id: &self.spreadsheet_id,
+            sheet_name: self.sheet_name.as_deref(),
+            anchor,
+            header,
+            values: &values,
+            api_key: &self.api_key,
+            run_id: self.run_id.as_deref(),
+        };
+        // Appending isn't idempotent: a retry after a 5xx the script survived would add a second row
+        let text = self.send_post(&payload, self.retry, false).await?;
+        check_write_response(&text, anchor, self.require_success)
+    }
+
+    /// Write a block of rows with its top-left cell at `anchor`; rows must all be the same width
+    pub async fn write_rows(&self, anchor: &str, rows: &[Vec<CellValue>]) -> Result<()> {
+        let anchor = checked_cell(anchor)?;
+        let rows = rows.iter()
+            .map(|row| row.iter().map(|value| self.value_guard.check(value, anchor)).collect())
+            .collect::<Result<Vec<Vec<CellValue>>>>()?;
+        if self.previewed(format_args!("write rows {:?} at", rows), self.sheet_name.as_deref(), anchor) {
+            return Ok(());
+        }
+        let payload = WriteRowsPayload {
+            action: "writeRows",
+            spreadsheet_id: &self.spreadsheet_id,
+            sheet_name: self.sheet_name.as_deref(),
+            anchor,
+            rows: &rows,
+            api_key: &self.api_key,
+            run_id: self.run_id.as_deref(),
+        };
+        self.post(&payload, anchor).await
+    }
+
+    /// Write many cells of this spreadsheet in one request. A failing cell doesn't fail the
+    /// others: each gets its own result. A script that answers without `results` is taken to
+    /// have written every cell, as a plain HTTP 200 always was.
+    pub async fn write_cells(&self, cells: &[CellWrite]) -> Result<Vec<CellResult>> {
+        let cells = cells.iter()
+            .map(|cell| {
+                let cell_address = checked_cell(&cell.cell_address)?.to_string();
+                let value = self.value_guard.check(&cell.value, &cell_address)?;
+                Ok(CellWrite { sheet_name: cell.sheet_name.clone(), cell_address, value })
+            })
+            .collect::<Result<Vec<CellWrite>>>()?;
+        if self.dry_run {
+            return Ok(cells.iter()
+                .map(|cell| {
+                    self.previewed(format_args!("write {:?} to", cell.value), Some(&cell.sheet_name), &cell.cell_address);
+                    CellResult { cell: cell.cell_address.clone(), success: true, error: None, error_code: None }
+                })
+                .collect());
+        }
+        let payload = WriteCellsPayload {
+            action: "writeCells",
+            spreadsheet_id: &self.spreadsheet_id,
+            cells: &cells,
+            api_key: &self.api_key,
+            run_id: self.run_id.as_deref(),
+        };
+        let body = self.post_for_json(&payload).await?;
+        let results = serde_json::from_value::<WriteCellsResponse>(body.clone()).ok().and_then(|r| r.results);
+        match results {
+            Some(results) if results.len() == cells.len() => Ok(results),
+            Some(results) => anyhow::bail!("Google Sheets Web App returned {} result(s) for {} cell(s)", results.len(), cells.len()),
+            None => {
+                check_write_response(&body.to_string(), "batch", self.require_success)?;
+                Ok(cells.iter()
+                    .map(|cell| CellResult { cell: cell.cell_address.clone(), success: true, error: None, error_code: None })
+                    .collect())
+            },
+        }
+    }
+
+    /// Clear the contents (not formatting) of an A1 range such as "A20:B200"
+    pub async fn clear_range(&self, range: &str) -> Result<()> {
+        CellRange::parse(range).with_context(|| format!("Refusing to clear malformed range '{}'", range))?;
+        if self.previewed(format_args!("clear"), self.sheet_name.as_deref(), range) {
+            return Ok(());
+        }
+        let payload = ClearRangePayload {
+            action: "clearRange",
+            spreadsheet_id: &self.spreadsheet_id,
+            sheet_name: self.sheet_name.as_deref(),
+            range,
+            api_key: &self.api_key,
+            run_id: self.run_id.as_deref(),
+        };
+        self.post(&payload, range).await
+    }
+
+    /// The formula in `cell` (such as "=SUM(A1:A9)"), or None when it holds a plain value
+    pub async fn cell_formula(&self, sheet_name: Option<&str>, cell: &str) -> Result<Option<String>> {
+        let cell = checked_cell(cell)?;
+        let payload = CellQueryPayload {
+            action: "getFormula",
+            spreadsheet_id: &self.spreadsheet_id,
+            sheet_name,
+            cell_address: cell,
+            api_key: &self.api_key,
+            run_id: self.run_id.as_deref(),
+        };
+        let body = self.post_for_json(&payload).await?;
+        check_write_response(&body.to_string(), cell, true)
+            .context("Formula check failed; redeploy Code.ts so the web app supports getFormula")?;
+        let formula = body.get("formula")
+            .ok_or_else(|| anyhow::anyhow!("Web app did not report a formula for {}; redeploy Code.ts so it supports getFormula", cell))?;
+        Ok(formula.as_str().filter(|f| !f.is_empty()).map(str::to_string))
+    }
+
+    /// The number in `cell`, or None when it is empty. Numeric text counts as a number; any
+    /// other text is an error, since it can't be compared with a balance.
+    pub async fn get_current_balance(&self, sheet_name: Option<&str>, cell: &str) -> Result<Option<f64>> {
+        let cell = checked_cell(cell)?;
+        let payload = CellQueryPayload {
+            action: "getValue",
+            spreadsheet_id: &self.spreadsheet_id,
+            sheet_name: sheet_name.or(self.sheet_name.as_deref()),
+            cell_address: cell,
+            api_key: &self.api_key,
+            run_id: self.run_id.as_deref(),
+        };
+        let body = self.post_for_json(&payload).await?;
+        check_write_response(&body.to_string(), cell, true)
+            .context("Reading the cell failed; redeploy Code.ts so the web app supports getValue")?;
+        let value = body.get("value")
+            .ok_or_else(|| anyhow::anyhow!("Web app did not report a value for {}; redeploy Code.ts so it supports getValue", cell))?;
+        cell_number(value).with_context(|| format!("Cell {} can't be read as a balance", cell))
+    }
+
+    /// The sheet's grid size as (rows, columns), including empty rows and columns
+    pub async fn sheet_dimensions(&self, sheet_name: &str) -> Result<(u32, u32)> {
+        let payload = GetDimensionsPayload {
+            action: "getDimensions",
+            spreadsheet_id: &self.spreadsheet_id,
+            sheet_name,
+            api_key: &self.api_key,
+            run_id: self.run_id.as_deref(),
+        };
+        let body = self.post_for_json(&payload).await?;
+        check_write_response(&body.to_string(), sheet_name, true)
+            .context("Dimension check failed; redeploy Code.ts so the web app supports getDimensions")?;
+        let dimension = |key: &str| body.get(key).and_then(|v| v.as_u64()).and_then(|v| u32::try_from(v).ok());
+        match (dimension("rows"), dimension("columns")) {
+            (Some(rows), Some(columns)) => Ok((rows, columns)),
+            _ => Err(anyhow::anyhow!("Web app did not report the size of '{}'; redeploy Code.ts so it supports getDimensions", sheet_name)),
+        }
+    }
+
+    /// Ask the web app whether our API key is accepted.
+    /// Returns None when the script doesn't implement the auth action (no `authorized` field).
+    pub async fn check_auth(&self) -> Result<Option<bool>> {
+        let payload = AuthCheckPayload { action: "auth", api_key: &self.api_key };
+        let body = self.post_for_json(&payload).await?;
+        Ok(body.get("authorized").and_then(|v| v.as_bool()))
+    }
+
+    /// The web app server's current time, from the Date header of a plain GET. Any response
+    /// carries the header, so the status is ignored.
+    pub async fn server_time(&self) -> Result<chrono::DateTime<chrono::Utc>> {
+        let res = self.http_client()?
+            .get(&self.webapp_url)
             .send()
             .await
-            .context("Failed to send POST to Google Sheets Web App")?;
-        if !res.status().is_success() {
-            let status = res.status();
-            let text = res.text().await.unwrap_or_default();
-            anyhow::bail!("Google Sheets Web App returned error: {} - {}", status, text);
+            .context("Failed to reach the Google Sheets Web App for its time")?;
+        let date = res.headers().get(reqwest::header::DATE)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| anyhow::anyhow!("Google Sheets Web App response had no Date header"))?;
+        crate::clock_skew::parse_http_date(date)
+            .ok_or_else(|| anyhow::anyhow!("Unparseable Date header '{}'", date))
+    }
+
+    fn http_client(&self) -> Result<reqwest::Client> {
+        let builder = crate::network::client_builder(self.network.as_ref())?;
+        match self.pinned_cert {
+            None => builder.build().context("Failed to build HTTP client"),
+            #[cfg(feature = "cert-pinning")]
+            Some(pin) => crate::cert_pin::pinned_client(builder, pin, self.network.as_ref().and_then(|n| n.min_tls_version)),
+            #[cfg(not(feature = "cert-pinning"))]
+            Some(_) => anyhow::bail!("Certificate pinning requires a build with the cert-pinning feature"),
         }
-        Ok(())
+    }
+
+    async fn post_for_json<T: Serialize>(&self, payload: &T) -> Result<serde_json::Value> {
+        let text = self.send_post(payload, self.retry, true).await?;
+        Ok(serde_json::from_str(&text).unwrap_or(serde_json::Value::Null))
+    }
+
+    /// `cell` names the write target in error messages when the script doesn't echo it back
+    async fn post<T: Serialize>(&self, payload: &T, cell: &str) -> Result<()> {
+        let text = self.send_post(payload, self.retry, true).await?;
+        check_write_response(&text, cell, self.require_success)
+    }
+
+    // POST `payload`, retrying per `retry`; returns the body of a successful response.
+    // A request that isn't `idempotent` is only retried when the script can't have run it.
+    async fn send_post<T: Serialize>(&self, payload: &T, retry: HttpRetry, idempotent: bool) -> Result<String> {
+        let client = self.http_client()?;
+        let mut attempt = 0;
+        loop {
+            let sent = client.post(&self.webapp_url).json(payload).send().await;
+            let delay = match sent {
+                Ok(res) if res.status().is_success() => return Ok(res.text().await.unwrap_or_default()),
+                Ok(res) if attempt < retry.max_retries && retry.should_retry(res.status(), idempotent) => {
+                    let delay = retry.delay(attempt, Some(res.headers()));
+                    log::warn!("[SHEETS] Web app returned {}; retry {}/{} in {:?}", res.status(), attempt + 1, retry.max_retries, delay);
+                    delay
+                },
+                Ok(res) => {
+                    let status = res.status();
+                    let text = res.text().await.unwrap_or_default();
+                    anyhow::bail!("Google Sheets Web App returned error: {} - {}", status, text);
+                },
+                Err(e) if attempt < retry.max_retries && (idempotent || e.is_connect()) => {
+                    let delay = retry.delay(attempt, None);
+                    log::warn!("[SHEETS] Failed to reach the web app ({}); retry {}/{} in {:?}", e, attempt + 1, retry.max_retries, delay);
+                    delay
+                },
+                Err(e) => return Err(e).context("Failed to send POST to Google Sheets Web App"),
+            };
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// The writeCells cells for a send_balances batch; a BalanceCell without a sheet uses `default_sheet`
+fn balance_cell_writes(updates: &[BalanceCell<'_>], default_sheet: Option<&str>) -> Vec<CellWrite> {
+    updates.iter()
+        .map(|update| CellWrite {
+            sheet_name: update.sheet_name.or(default_sheet).unwrap_or_default().to_string(),
+            cell_address: update.cell_address.to_string(),
+            value: CellValue::Number(update.balance),
+        })
+        .collect()
+}
+
+/// Fail a send_balances batch when any of its cells failed, naming each failed account
+fn check_balance_results(updates: &[BalanceCell<'_>], results: &[CellResult]) -> Result<()> {
+    let failures: Vec<String> = updates.iter().zip(results)
+        .filter(|(_, result)| !result.success)
+        .map(|(update, result)| format!("'{}' at {}", update.account_full_name, result.describe()))
+        .collect();
+    if !failures.is_empty() {
+        anyhow::bail!("Google Sheets Web App failed to write {} of {} balance(s): {}", failures.len(), updates.len(), failures.join("; "));
+    }
+    Ok(())
+}
+
+/// A cell value from getValue: `{"success": true, "value": 1234.5}`, where value is a number,
+/// text, or "" (or null) for an empty cell
+fn cell_number(value: &serde_json::Value) -> Result<Option<f64>> {
+    match value {
+        serde_json::Value::Null => Ok(None),
+        serde_json::Value::Number(n) => Ok(n.as_f64()),
+        serde_json::Value::String(s) if s.trim().is_empty() => Ok(None),
+        serde_json::Value::String(s) => match s.trim().parse::<f64>() {
+            Ok(n) => Ok(Some(n)),
+            Err(_) => anyhow::bail!("it holds text ('{}')", s),
+        },
+        other => anyhow::bail!("it holds {}", other),
+    }
+}
+
+/// The cell part of a payload must be a bare A1 cell: the sheet travels separately in sheetName
+/// (where serde handles any quotes or punctuation), so a "Sheet!A1" prefix or stray text here
+/// would make the script write somewhere other than intended.
+fn checked_cell(cell: &str) -> Result<&str> {
+    let cell = cell.trim();
+    CellRef::parse(cell).with_context(|| format!("Refusing to send malformed cell address '{}'", cell))?;
+    Ok(cell)
+}
+
+/// The explanation for one of the script's known error codes, or None for other failures
+fn explain_error_code(code: Option<&str>, cell: &str, detail: Option<&str>) -> Option<String> {
+    let detail = detail.unwrap_or("no details");
+    match code {
+        Some(ERROR_CELL_PROTECTED) => Some(format!(
+            "cell {} appears to be protected in the spreadsheet — unprotect it or choose another cell. ({})",
+            cell, detail
+        )),
+        Some(ERROR_VALUE_OUT_OF_RANGE) => Some(format!(
+            "Google Sheets rejected the value for {} as out of range — check the QuickBooks balance, \
+             or set google_sheets.max_abs_value/round_decimals to guard it. ({})",
+            cell, detail
+        )),
+        _ => None,
+    }
+}
+
+/// Fail on a body reporting `success: false`, explaining protected cells specifically.
+/// Empty or non-JSON bodies and bodies without `success` count as success.
+fn check_write_response(body: &str, cell: &str, require_success: bool) -> Result<()> {
+    let Ok(response) = serde_json::from_str::<WriteResponse>(body) else {
+        return Ok(());
+    };
+    let cell = response.cell.as_deref().unwrap_or(cell);
+    if let Some(message) = explain_error_code(response.error_code.as_deref(), cell, response.error.as_deref()) {
+        anyhow::bail!(message);
+    }
+    if require_success && response.success == Some(false) {
+        anyhow::bail!(
+            "Google Sheets Web App reported failure writing {}: {}",
+            cell,
+            response.error.as_deref().unwrap_or("no error message")
+        );
+    }
+    Ok(())
+}
+
+/// Whether a write may go ahead given the target's current formula (None for plain values).
+/// Returns false when the write should be skipped, and fails under the Error policy.
+fn formula_guard(policy: FormulaCellPolicy, formula: Option<&str>, cell: &str) -> Result<bool> {
+    match (policy, formula) {
+        (FormulaCellPolicy::Overwrite, _) | (_, None) => Ok(true),
+        (FormulaCellPolicy::Skip, Some(_)) => Ok(false),
+        (FormulaCellPolicy::Error, Some(formula)) => anyhow::bail!(
+            "cell {} holds a formula ({}) and on_formula_cell is \"error\" — point the block at another cell or set on_formula_cell",
+            cell,
+            formula
+        ),
+    }
+}
+
+// Writes to the client's own sheet/cell
+#[async_trait]
+impl BalanceSink for GoogleSheetsClient {
+    fn describe(&self) -> String {
+        match &self.sheet_name {
+            Some(sheet_name) => format!("Google Sheets {}", a1::qualified(sheet_name, &self.cell_address)),
+            None => format!("Google Sheets <active>!{}", self.cell_address),
+        }
+    }
+
+    async fn send(&self, update: &BalanceUpdate<'_>) -> Result<()> {
+        self.send_balance(update.account_full_name, update.balance, None, None).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UPDATES: [BalanceCell<'static>; 2] = [
+        BalanceCell { account_full_name: "Checking", balance: 1234.5, sheet_name: None, cell_address: "B2" },
+        BalanceCell { account_full_name: "Savings", balance: -10.0, sheet_name: Some("Summary"), cell_address: "B3" },
+    ];
+
+    #[test]
+    fn balance_cells_serialize_as_write_cells_entries() {
+        let cells = balance_cell_writes(&UPDATES, Some("Balances"));
+        let json = serde_json::to_value(&cells).unwrap();
+        assert_eq!(json, serde_json::json!([
+            {"sheetName": "Balances", "cellAddress": "B2", "value": 1234.5},
+            {"sheetName": "Summary", "cellAddress": "B3", "value": -10.0},
+        ]));
+        // No sheet anywhere: the script's default sheet
+        assert_eq!(balance_cell_writes(&UPDATES[..1], None)[0].sheet_name, "");
+    }
+
+    #[test]
+    fn failed_balance_cells_fail_the_batch() {
+        let results: Vec<CellResult> = serde_json::from_str(
+            r#"[{"cell": "B2", "success": true}, {"cell": "B3", "success": false, "error": "CELL_PROTECTED"}]"#
+        ).unwrap();
+        let err = check_balance_results(&UPDATES, &results).unwrap_err().to_string();
+        assert_eq!(err, "Google Sheets Web App failed to write 1 of 2 balance(s): 'Savings' at B3: CELL_PROTECTED");
+        let protected: Vec<CellResult> = serde_json::from_str(
+            r#"[{"cell": "B2", "success": true}, {"cell": "B3", "success": false, "error": "You are trying to edit a protected cell", "errorCode": "CELL_PROTECTED"}]"#
+        ).unwrap();
+        let err = check_balance_results(&UPDATES, &protected).unwrap_err().to_string();
+        assert!(err.contains("'Savings' at cell B3 appears to be protected"), "{}", err);
+        let ok: Vec<CellResult> = serde_json::from_str(r#"[{"success": true}, {"success": true}]"#).unwrap();
+        assert!(check_balance_results(&UPDATES, &ok).is_ok());
+    }
+
+    #[tokio::test]
+    async fn dry_run_sends_nothing() {
+        // Nothing listens on port 9, so any request would fail
+        let client = GoogleSheetsClient::new("https://127.0.0.1:9/exec".to_string(), "key".to_string(), "1AbC".to_string(), Some("Balances".to_string()), "B2".to_string())
+            .with_formula_policy(FormulaCellPolicy::Error)
+            .with_dry_run(true);
+        client.send_value("Checking", &CellValue::Number(12.5), None, None).await.unwrap();
+        client.append_row("A1", &["Run".to_string()], &[CellValue::Text("failed".to_string())]).await.unwrap();
+        client.write_rows("A1", &[vec![CellValue::Number(1.0)]]).await.unwrap();
+        client.clear_range("A1:B9").await.unwrap();
+        let results = client.write_cells(&balance_cell_writes(&UPDATES, Some("Balances"))).await.unwrap();
+        assert!(results.iter().all(|result| result.success));
+        assert_eq!(results.len(), 2);
+        // Validation still runs
+        assert!(client.send_value("Checking", &CellValue::Number(1.0), None, Some("not a cell")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn protected_cell_errors_name_the_cell() {
+        let protected = r#"{"success": false, "error": "You are trying to edit a protected cell or object.", "errorCode": "CELL_PROTECTED"}"#;
+        let server = crate::test_server::TestServer::start(vec![(200, protected.to_string())]).await;
+        let client = GoogleSheetsClient::new(format!("{}/exec", server.url), "key".to_string(), "1AbC".to_string(), Some("Balances".to_string()), "B2".to_string());
+        let err = client.send_value("Checking", &CellValue::Number(12.5), None, None).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cell B2 appears to be protected in the spreadsheet — unprotect it or choose another cell. (You are trying to edit a protected cell or object.)"
+        );
+
+        // The script's echoed cell wins over ours; other failures keep the generic message
+        let err = check_write_response(r#"{"success": false, "cell": "C9", "errorCode": "CELL_PROTECTED"}"#, "B2", false).unwrap_err();
+        assert!(err.to_string().starts_with("cell C9 appears to be protected"), "{}", err);
+        let err = check_write_response(r#"{"success": false, "error": "Sheet not found"}"#, "B2", true).unwrap_err();
+        assert_eq!(err.to_string(), "Google Sheets Web App reported failure writing B2: Sheet not found");
+        check_write_response(r#"{"success": false, "error": "Sheet not found"}"#, "B2", false).unwrap();
+    }
+
+    #[test]
+    fn get_value_responses_parse_to_a_number_or_empty() {
+        assert_eq!(cell_number(&serde_json::json!(1234.5)).unwrap(), Some(1234.5));
+        assert_eq!(cell_number(&serde_json::json!(" -500 ")).unwrap(), Some(-500.0));
+        assert_eq!(cell_number(&serde_json::json!("")).unwrap(), None);
+        assert_eq!(cell_number(&serde_json::Value::Null).unwrap(), None);
+        assert_eq!(cell_number(&serde_json::json!(0)).unwrap(), Some(0.0));
+        assert_eq!(cell_number(&serde_json::json!("Total")).unwrap_err().to_string(), "it holds text ('Total')");
+    }
+
+    #[tokio::test]
+    async fn get_current_balance_reads_the_cell_through_get_value() {
+        let server = crate::test_server::TestServer::start(vec![
+            (200, r#"{"success": true, "value": 1234.5}"#.to_string()),
+            (200, r#"{"success": true, "value": ""}"#.to_string()),
+        ]).await;
+        let client = GoogleSheetsClient::new(format!("{}/exec", server.url), "key".to_string(), "1AbC".to_string(), Some("Balances".to_string()), "B2".to_string());
+        assert_eq!(client.get_current_balance(None, "B2").await.unwrap(), Some(1234.5));
+        assert_eq!(client.get_current_balance(Some("Summary"), "C3").await.unwrap(), None);
+        let requests = server.requests();
+        let first = requests[0].json();
+        assert_eq!((first["action"].as_str(), first["sheetName"].as_str(), first["cellAddress"].as_str()), (Some("getValue"), Some("Balances"), Some("B2")));
+        assert_eq!(requests[1].json()["sheetName"], "Summary");
+    }
+
+    #[tokio::test]
+    async fn a_200_is_a_failure_only_when_the_body_says_so() {
+        let failed = r#"{"success": false, "error": "Sheet not found"}"#.to_string();
+        let server = crate::test_server::TestServer::start(vec![(200, failed.clone()), (200, String::new()), (200, failed)]).await;
+        let client = GoogleSheetsClient::new(format!("{}/exec", server.url), "key".to_string(), "1AbC".to_string(), Some("Balances".to_string()), "B2".to_string());
+        let err = client.send_balance("Checking", 12.5, None, None).await.unwrap_err();
+        assert_eq!(err.to_string(), "Google Sheets Web App reported failure writing batch: Sheet not found");
+        // Scripts that answer with no body at all
+        client.send_balance("Checking", 12.5, None, None).await.unwrap();
+        let client = client.with_require_success(false);
+        client.send_balance("Checking", 12.5, None, None).await.unwrap();
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    // The actions the client sent for one send_value under `policy`, or the error it returned
+    async fn formula_cell_actions(policy: FormulaCellPolicy, formula: &str) -> Result<Vec<String>> {
+        let formula = serde_json::json!({"success": true, "formula": formula}).to_string();
+        let server = crate::test_server::TestServer::start(vec![(200, formula), (200, r#"{"success": true}"#.to_string())]).await;
+        let client = GoogleSheetsClient::new(format!("{}/exec", server.url), "key".to_string(), "1AbC".to_string(), Some("Balances".to_string()), "B2".to_string())
+            .with_formula_policy(policy);
+        client.send_value("Checking", &CellValue::Number(12.5), None, None).await?;
+        Ok(server.requests().iter()
+            .map(|request| request.json()["action"].as_str().unwrap_or("write").to_string())
+            .collect())
+    }
+
+    #[tokio::test]
+    async fn each_formula_policy_handles_a_formula_cell() {
+        assert_eq!(formula_cell_actions(FormulaCellPolicy::Overwrite, "=SUM(B3:B9)").await.unwrap(), ["write"]);
+        assert_eq!(formula_cell_actions(FormulaCellPolicy::Skip, "=SUM(B3:B9)").await.unwrap(), ["getFormula"]);
+        let err = formula_cell_actions(FormulaCellPolicy::Error, "=SUM(B3:B9)").await.unwrap_err();
+        assert!(err.to_string().starts_with("cell B2 holds a formula (=SUM(B3:B9)) and on_formula_cell is \"error\""), "{}", err);
+        // Plain values are written under every policy
+        assert_eq!(formula_cell_actions(FormulaCellPolicy::Skip, "").await.unwrap(), ["getFormula", "write"]);
+        assert_eq!(formula_cell_actions(FormulaCellPolicy::Error, "").await.unwrap(), ["getFormula", "write"]);
+    }
+
+    #[tokio::test]
+    async fn out_of_range_values_get_a_targeted_error() {
+        let rejected = r#"{"success": false, "error": "Exception: Number out of range", "errorCode": "VALUE_OUT_OF_RANGE"}"#;
+        let batch = r#"{"success": false, "results": [{"cell": "B2", "success": false, "error": "Exception: Number out of range", "errorCode": "VALUE_OUT_OF_RANGE"}]}"#;
+        let server = crate::test_server::TestServer::start(vec![(200, rejected.to_string()), (200, batch.to_string())]).await;
+        let client = GoogleSheetsClient::new(format!("{}/exec", server.url), "key".to_string(), "1AbC".to_string(), Some("Balances".to_string()), "B2".to_string());
+        let expected = "Google Sheets rejected the value for B2 as out of range — check the QuickBooks balance, \
+             or set google_sheets.max_abs_value/round_decimals to guard it. (Exception: Number out of range)";
+        let err = client.send_value("Checking", &CellValue::Number(1e308), None, None).await.unwrap_err();
+        assert_eq!(err.to_string(), expected);
+        let err = client.send_balance("Checking", 1e308, None, None).await.unwrap_err();
+        assert_eq!(err.to_string(), format!("Google Sheets Web App failed to write 1 of 1 balance(s): 'Checking' at {}", expected));
     }
 }
@@ -0,0 +1,17 @@
+// Heartbeat file
+// A dead-man's switch: rewritten with the current time after each fully successful run
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::path::Path;
+
+/// Write `now` (RFC 3339) to `path`, creating its directory if needed; the file's mtime
+/// advances with it, so a watchdog can check either
+pub fn write_heartbeat(path: &Path, now: DateTime<Utc>) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create heartbeat directory {}", dir.display()))?;
+    }
+    std::fs::write(path, format!("{}\n", now.to_rfc3339()))
+        .with_context(|| format!("Failed to write heartbeat file {}", path.display()))
+}
@@ -0,0 +1,126 @@
+// Post-run hooks
+// Runs a configured command after the sync succeeds or fails, with run summary placeholders
+
+use anyhow::{Context, Result};
+use std::process::Command;
+
+/// What a hook can see of the finished run
+#[derive(Debug, Clone, Default)]
+pub struct RunSummary {
+    pub run_id: String,
+    // "live", "cached", ... as in the manifest
+    pub source: String,
+    // Company file QuickBooks had open; empty when the run didn't query QuickBooks live
+    pub company_file: String,
+    pub written: usize,
+    pub missing: usize,
+    pub failed: usize,
+    pub error: String,
+}
+
+/// Substitute placeholders in one argument, in a single pass so values containing "{{...}}"
+/// are never re-expanded. Each argument is passed to the program as-is, with no shell in
+/// between, so a value can't inject extra arguments or commands.
+pub fn render_arg(arg: &str, summary: &RunSummary) -> Result<String> {
+    let mut out = String::with_capacity(arg.len());
+    let mut rest = arg;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| anyhow::anyhow!("Unterminated placeholder in hook argument '{}'", arg))?;
+        match after[..end].trim() {
+            "run_id" => out.push_str(&summary.run_id),
+            "source" => out.push_str(&summary.source),
+            "company_file" => out.push_str(&summary.company_file),
+            "written" => out.push_str(&summary.written.to_string()),
+            "missing" => out.push_str(&summary.missing.to_string()),
+            "failed" => out.push_str(&summary.failed.to_string()),
+            "error" => out.push_str(&summary.error),
+            other => anyhow::bail!("Unknown placeholder '{{{{{}}}}}' in hook argument", other),
+        }
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// The program and its arguments with placeholders filled in
+pub fn render_command(command: &[String], summary: &RunSummary) -> Result<Vec<String>> {
+    if command.is_empty() {
+        anyhow::bail!("Hook command is empty; give the program and then its arguments");
+    }
+    command.iter().map(|arg| render_arg(arg, summary)).collect()
+}
+
+/// Render with sample values so placeholder mistakes surface at config load
+pub fn check_command(command: &[String]) -> Result<()> {
+    render_command(command, &RunSummary::default()).map(|_| ())
+}
+
+/// Run the hook and wait for it, logging its exit code and output; Err if it couldn't start or exited non-zero
+pub fn run_hook(name: &str, command: &[String], summary: &RunSummary) -> Result<()> {
+    let argv = render_command(command, summary)?;
+    let output = Command::new(&argv[0])
+        .args(&argv[1..])
+        .output()
+        .with_context(|| format!("Failed to start {} hook '{}'", name, argv[0]))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    log::info!("[HOOK] {} hook '{}' exited with {}", name, argv[0], output.status);
+    if !stdout.trim().is_empty() {
+        log::info!("[HOOK] stdout: {}", stdout.trim());
+    }
+    if !stderr.trim().is_empty() {
+        log::warn!("[HOOK] stderr: {}", stderr.trim());
+    }
+    if !output.status.success() {
+        anyhow::bail!("{} hook '{}' exited with {}", name, argv[0], output.status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary() -> RunSummary {
+        RunSummary {
+            run_id: "run-1".to_string(),
+            source: "live".to_string(),
+            company_file: "C:\\Books\\Acme.QBW".to_string(),
+            written: 3,
+            missing: 1,
+            failed: 0,
+            error: "boom; echo {{run_id}} $(whoami)".to_string(),
+        }
+    }
+
+    fn command(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn placeholders_are_substituted_once_per_argument() {
+        let argv = render_command(&command(&["notify", "--run={{run_id}}", "{{ written }}/{{missing}}/{{failed}}", "{{error}}"]), &summary()).unwrap();
+        assert_eq!(argv, ["notify", "--run=run-1", "3/1/0", "boom; echo {{run_id}} $(whoami)"]);
+        assert!(check_command(&command(&["notify", "{{owner}}"])).is_err());
+        assert!(check_command(&[]).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn the_hook_runs_with_the_substituted_arguments() {
+        let path = std::env::temp_dir().join(format!("qb_sync_hook_{}.txt", std::process::id()));
+        // Each argument on its own line, exactly as the program received it
+        let hook = command(&["sh", "-c", "printf '%s\\n' \"$@\" > \"$0\"", &path.to_string_lossy(), "{{run_id}}", "{{company_file}}", "{{error}}"]);
+        run_hook("on_success", &hook, &summary()).unwrap();
+        let received = std::fs::read_to_string(&path);
+        std::fs::remove_file(&path).ok();
+        assert_eq!(received.unwrap(), "run-1\nC:\\Books\\Acme.QBW\nboom; echo {{run_id}} $(whoami)\n");
+
+        let err = run_hook("on_failure", &command(&["sh", "-c", "exit 3"]), &summary()).unwrap_err();
+        assert!(err.to_string().starts_with("on_failure hook 'sh' exited with"), "{}", err);
+    }
+}
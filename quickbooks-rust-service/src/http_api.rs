@@ -0,0 +1,96 @@
+// HTTP API mode
+// Serves live QuickBooks balances on demand; every query goes through the single COM worker
+
+use anyhow::{Context, Result};
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Json, Response};
+use axum::routing::get;
+use axum::Router;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::qb_worker::QbWorker;
+
+#[derive(Debug, Deserialize)]
+struct BalanceParams {
+    account: String,
+}
+
+pub fn router(worker: Arc<QbWorker>) -> Router {
+    Router::new()
+        .route("/health", get(health))
+        .route("/balance", get(balance))
+        .with_state(worker)
+}
+
+pub async fn serve(addr: &str, worker: Arc<QbWorker>) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to bind HTTP API to {}", addr))?;
+    log::info!("[HTTP] Serving balances on http://{}", addr);
+    axum::serve(listener, router(worker)).await.context("HTTP API server failed")
+}
+
+async fn health() -> Json<serde_json::Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+// GET /balance?account=<full name>
+async fn balance(State(worker): State<Arc<QbWorker>>, Query(params): Query<BalanceParams>) -> Response {
+    match worker.query(&params.account).await {
+        Ok(balance) => Json(json!({ "account": params.account, "balance": balance })).into_response(),
+        Err(e) => {
+            log::error!("[HTTP] Balance query for '{}' failed: {:#}", params.account, e);
+            (StatusCode::BAD_GATEWAY, Json(json!({ "account": params.account, "error": format!("{:#}", e) }))).into_response()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_quickbooks::MockQuickBooks;
+    use crate::qb_worker::WorkerSettings;
+    use crate::qbxml_safe::qbxml_request_processor::{AccountQueryOptions, SessionRetry};
+
+    // Serve the API on an ephemeral port, backed by a worker over the mock processor
+    async fn start() -> String {
+        let settings = WorkerSettings {
+            company_file: String::new(),
+            query_options: AccountQueryOptions::default(),
+            keep_alive: None,
+            min_query_interval: None,
+            cache_ttl: None,
+            session_retry: SessionRetry::default(),
+        };
+        let worker = QbWorker::spawn(settings, || Ok(MockQuickBooks::new([("Checking & Savings".to_string(), 1234.56)].into()))).unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router(Arc::new(worker))).await });
+        url
+    }
+
+    #[tokio::test]
+    async fn endpoints_answer_from_the_worker() {
+        let url = start().await;
+        let client = reqwest::Client::new();
+
+        let health = client.get(format!("{}/health", url)).send().await.unwrap();
+        assert_eq!(health.status(), 200);
+        assert_eq!(health.json::<serde_json::Value>().await.unwrap(), json!({ "status": "ok" }));
+
+        let found = client.get(format!("{}/balance", url)).query(&[("account", "Checking & Savings")]).send().await.unwrap();
+        assert_eq!(found.status(), 200);
+        assert_eq!(found.json::<serde_json::Value>().await.unwrap(), json!({ "account": "Checking & Savings", "balance": 1234.56 }));
+
+        let missing = client.get(format!("{}/balance?account=Nope", url)).send().await.unwrap();
+        assert_eq!(missing.status(), 502);
+        let body = missing.json::<serde_json::Value>().await.unwrap();
+        assert_eq!(body["error"], "Account 'Nope' not found in QuickBooks");
+
+        // The account parameter is required
+        assert_eq!(client.get(format!("{}/balance", url)).send().await.unwrap().status(), 400);
+    }
+}
@@ -2,5 +2,25 @@
 // Using SafeVariant wrappers for robust VARIANT/COM handling
 
 pub mod file_mode;
+pub mod a1;
+pub mod amount;
+pub mod account_type;
+pub mod network;
+pub mod clock_skew;
+pub mod hooks;
+pub mod timestamp;
+pub mod cert_pin;
+pub mod backoff;
+pub mod catch_up;
 pub mod config;
+pub mod mapping_csv;
+pub mod sink;
+pub mod secret;
+pub mod webhook;
+pub mod qb_error;
 pub mod qbxml_safe;
+pub mod qbfc_safe;
+pub mod quickbooks;
+pub mod mock_quickbooks;
+#[cfg(test)]
+mod test_server;
@@ -1,23 +1,121 @@
 mod file_mode;
 mod config;
+// Much of these serves only the COM path, which is Windows-only
+#[cfg_attr(not(windows), allow(dead_code))]
+mod qb_error;
+#[cfg_attr(not(windows), allow(dead_code))]
 mod qbxml_safe;
+#[cfg_attr(not(windows), allow(dead_code))]
+mod qbfc_safe;
+mod alert;
+mod a1;
+mod amount;
+mod account_type;
+mod catch_up;
+mod error_log;
+mod ofx;
+mod network;
+mod percent_sync;
+mod clock_skew;
+mod hooks;
+mod timestamp;
+mod cert_pin;
+mod backoff;
+mod chart;
+mod sink;
+mod secret;
+mod webhook;
+mod mapping_csv;
+mod cache;
+mod transform;
+mod row_block;
+mod type_report;
+mod prefix_block;
+mod account_row;
+mod period_balance;
+mod name_match;
+mod session;
+mod heartbeat;
+mod account_check;
+mod rollup;
+mod report;
+mod write_plan;
+mod value_guard;
+// COM worker for long-running modes; the one-shot sync drives the processor directly
+#[cfg_attr(any(not(windows), not(feature = "http-api")), allow(dead_code))]
+mod quickbooks;
+mod mock_quickbooks;
+#[cfg_attr(not(feature = "http-api"), allow(dead_code))]
+mod qb_worker;
+#[cfg(feature = "http-api")]
+mod http_api;
+#[cfg(test)]
+mod test_server;
 
 use anyhow::{Result, Context};
 use log::info;
+use chrono::{DateTime, Utc};
 use std::env;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+use tokio_cron_scheduler::{Job, JobScheduler};
 
-use crate::config::Config;
+use crate::account_type::AccountType;
+use crate::cache::BalanceCache;
+use crate::config::{AccountSyncConfig, AlertConfig, BusyPolicy, Config, SheetsBackend};
 use crate::file_mode::FileMode;
-use crate::qbxml_safe::qbxml_request_processor::QbxmlRequestProcessor;
+use crate::mock_quickbooks::MockQuickBooks;
+use crate::qb_error::QbError;
+use crate::qbxml_safe::qbxml_request_processor::{self, AccountParser};
+use crate::quickbooks::QuickBooksProcessor;
+use crate::report::{BlockStatus, RunManifest, SyncReport};
+use crate::row_block::RowBlock;
+use crate::write_plan::BlockWrite;
+use crate::session::with_quickbooks_session;
 mod google_sheets;
-use google_sheets::GoogleSheetsClient;
+#[cfg(feature = "sheets-api")]
+mod sheets_api;
+use google_sheets::{CellValue, CellWrite, GoogleSheetsClient};
+use sink::{BalanceSink, BalanceUpdate};
+use webhook::WebhookSink;
+use ofx::OfxSink;
 
 #[derive(Debug, Clone)]
 pub struct AccountData {
     pub account_full_name: String,
     pub number: String,
-    pub account_type: String,
+    pub account_type: AccountType,
+    // Desc, when the account has one (not available from the cache)
+    pub description: Option<String>,
     pub balance: f64,
+    // Balance including subaccounts, when QuickBooks reported it
+    pub total_balance: Option<f64>,
+    // Balance and TotalBalance text exactly as QuickBooks returned it (not available from the cache)
+    pub raw_balance: Option<String>,
+    pub raw_total_balance: Option<String>,
+}
+
+impl AccountData {
+    /// QuickBooks reported a balance and it is zero. A balance that wasn't reported (only the
+    /// parsed fallback, e.g. from the cache) is not a genuine zero.
+    pub fn has_zero_balance(&self) -> bool {
+        self.raw_balance.is_some() && self.balance == 0.0
+    }
+}
+
+impl From<qbxml_request_processor::AccountInfo> for AccountData {
+    fn from(info: qbxml_request_processor::AccountInfo) -> Self {
+        Self {
+            account_full_name: info.account_full_name,
+            number: info.number,
+            account_type: info.account_type,
+            description: info.description,
+            balance: info.balance,
+            total_balance: info.total_balance,
+            raw_balance: info.raw_balance,
+            raw_total_balance: info.raw_total_balance,
+        }
+    }
 }
 
 fn print_instructions() {
@@ -32,7 +130,22 @@ fn print_instructions() {
     println!("   2. A company file must be open in QuickBooks");
     println!("   3. The FullName of the account in config.toml must exist in QuickBooks");
     println!();
-    println!("Usage: main_account_query [--verbose]");
+    println!("Usage: qb_sync [--verbose] [--check-auth] [--validate-accounts] [--list-accounts] [--dry-run] [--prune-cache] [--catch-up] [--daemon] [--report-file <path>] [--manifest <path>] [--save-response <path>] [--from-response <path>] [--mock <path>] [--retry-failed <path>] [--serve <addr>]");
+    println!("  --check-auth   Verify the Google Sheets API key with the web app and exit");
+    println!("  --validate-accounts  Check every configured account exists in QuickBooks, write nothing, and exit");
+    println!("  --list-accounts      Print every QuickBooks account with its type and balance, write nothing, and exit");
+    println!("  --dry-run            Run as usual but log every write (all block kinds, sinks, OFX, cache) instead of sending it");
+    println!("  --prune-cache        Drop cached balances for accounts no longer in config and exit");
+    println!("  --catch-up           Sync only if a [schedule] slot was missed since the last run, else exit");
+    println!("  --daemon             Sync now, then at every [schedule] slot (cron_expression or interval_secs) until stopped");
+    println!("  --report-file <path> Write the end-of-run JSON report (per-block outcomes) to <path> instead of stdout");
+    println!("  --manifest <path>    Write a JSON manifest of the run (per-block outcomes, versions, timing); \"-\" prints it to stdout");
+    println!("  --save-response <path>  Also save QuickBooks' raw account query response (contains every balance)");
+    println!("  --from-response <path>  Skip QuickBooks and write from a response saved with --save-response");
+    println!("  --mock <path>           Skip QuickBooks and write canned balances from a JSON object of account name to balance");
+    println!("  --retry-failed <path>   Rewrite only the sync blocks that failed in the run recorded by manifest <path>");
+    println!("  --serve <addr> Serve GET /balance?account=<name> and /health (http-api feature)");
+    println!("Ctrl+C during a run ends the QuickBooks session cleanly before exiting (code 130); press it twice to exit at once.");
     println!("All account sync blocks are now read from config/config.toml; no account_full_name, sheet_name, or cell_address parameter is required.");
     println!();
 }
@@ -62,71 +175,1802 @@ async fn real_main() -> anyhow::Result<()> {
     } else {
         env_logger::builder().filter_level(log::LevelFilter::Info).init();
     }
+    let check_auth_only = args.iter().any(|a| a == "--check-auth");
     // Load configuration
-    let config = Config::load_from_file("config/config.toml")
+    let mut config = Config::load_from_file("config/config.toml")
         .context("Failed to load configuration file")?;
-    run_qbxml(config).await
-}
-
-async fn run_qbxml(config: Config) -> Result<()> {
-    unsafe {
-        let hr = winapi::um::combaseapi::CoInitializeEx(std::ptr::null_mut(), winapi::um::objbase::COINIT_APARTMENTTHREADED);
-        if hr < 0 {
-            return Err(anyhow::anyhow!("Failed to initialize COM system: HRESULT=0x{:08X}", hr));
-        }
-    }
-
-    let processor = QbxmlRequestProcessor::new().context("Failed to create QBXML request processor")?;
-
-    let app_id = config.quickbooks.application_id.as_deref().unwrap_or("QuickBooks-Sheets-Sync");
-
-    let app_name = config.quickbooks.application_name.as_deref().unwrap_or("QuickBooks Sheets Sync");
-
-    processor.open_connection(app_id, app_name)?;
-
-    let company_file = match config.quickbooks.company_file.as_str() { "AUTO" => "", path => path };
-    println!("[DEBUG] Company file: {}", company_file);
-    let ticket = processor.begin_session(company_file, crate::FileMode::DoNotCare)?;
-    match processor.get_account_xml(&ticket) {
-        Ok(Some(response_xml)) => {
-            let gs_cfg = &config.google_sheets;
-            for sync in &config.sync_blocks {
-                match processor.get_account_balance(&response_xml, &sync.account_full_name) {
-                    Ok(Some(account_balance)) => {
-                        info!("[QBXML] Account '{}' balance is: {:?}", sync.account_full_name, account_balance);
-                        // Create a new GoogleSheetsClient for each sync block with correct spreadsheet_id and cell_address
-                        let gs_client = GoogleSheetsClient::new(
-                            gs_cfg.webapp_url.clone(),
-                            gs_cfg.api_key.clone(),
-                            sync.spreadsheet_id.clone(),
-                            Some(sync.sheet_name.clone()),
-                            sync.cell_address.clone(),
-                        );
-                        gs_client.send_balance(
-                            &sync.account_full_name,
-                            account_balance,
-                            Some(&sync.sheet_name),
-                            Some(&sync.cell_address),
-                        ).await?;
-                    },
-                    Ok(None) => {
-                        info!("[QBXML] No valid balance for account '{}'.", sync.account_full_name);
-                    },
-                    Err(e) => {
-                        eprintln!("[QBXML] Error parsing balance for '{}': {:#}", sync.account_full_name, e);
-                    }
-                }
+    apply_flags(&mut config, &args);
+    if let Some(addr) = arg_value(&args, "--serve") {
+        return serve(&config, addr).await;
+    }
+    session::install_ctrl_c_handler();
+    for warning in rollup::double_count_warnings(&config) {
+        log::warn!("[ROLLUP] {}", warning);
+    }
+    if args.iter().any(|a| a == "--prune-cache") {
+        return prune_cache(&config);
+    }
+    if args.iter().any(|a| a == "--list-accounts") {
+        return list_accounts(&config).await;
+    }
+    if args.iter().any(|a| a == "--validate-accounts") {
+        return validate_accounts(&config).await;
+    }
+    if check_auth_only || (config.google_sheets.verify_api_key.unwrap_or(false) && !config.dry_run) {
+        verify_api_key(&config, check_auth_only).await?;
+        if check_auth_only {
+            return Ok(());
+        }
+    }
+    if args.iter().any(|a| a == "--catch-up") && !should_catch_up(&config, Utc::now())? {
+        return Ok(());
+    }
+    if config.google_sheets.verify_cell_bounds.unwrap_or(false) && !config.dry_run {
+        verify_cell_bounds(&config).await?;
+    }
+    if config.daemon {
+        return run_daemon(&config, &args).await;
+    }
+    sync_once(&config, &args).await
+}
+
+/// --daemon: sync now, then again at every [schedule] slot until stopped. Each run opens and
+/// ends its own QuickBooks session, so QuickBooks is free between runs; a failed run is logged
+/// and the next slot still runs.
+async fn run_daemon(config: &Config, args: &[String]) -> Result<()> {
+    let Some(schedule) = &config.schedule else {
+        anyhow::bail!("--daemon needs a [schedule] section with cron_expression or interval_secs");
+    };
+    // The job only signals a slot; runs stay on this task, one at a time. One slot that comes due
+    // during a run waits for it to finish, and any more are dropped rather than queued.
+    let (slots, mut due) = tokio::sync::mpsc::channel::<()>(1);
+    let on_slot = move |_, _| {
+        if slots.try_send(()).is_err() {
+            log::warn!("[SCHEDULE] Skipping a slot: the previous run is still going");
+        }
+        Box::pin(async {}) as std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>>
+    };
+    // Validated at config load
+    let job = match (&schedule.cron_expression, schedule.interval_secs) {
+        (Some(expression), _) => Job::new_async_tz(expression.as_str(), chrono::Local, on_slot),
+        (None, Some(secs)) => Job::new_repeated_async(std::time::Duration::from_secs(secs), on_slot),
+        (None, None) => anyhow::bail!("--daemon needs [schedule] cron_expression or interval_secs"),
+    }
+    .context("Failed to schedule the sync")?;
+    let mut scheduler = JobScheduler::new().await.context("Failed to start the scheduler")?;
+    let job_id = scheduler.add(job).await.context("Failed to schedule the sync")?;
+    scheduler.start().await.context("Failed to start the scheduler")?;
+    loop {
+        if let Err(e) = sync_once(config, args).await {
+            log::error!("[SCHEDULE] Run failed: {:#}", e);
+        }
+        match scheduler.next_tick_for_job(job_id).await {
+            Ok(Some(next_at)) => info!("[SCHEDULE] Next run at {}", next_at.with_timezone(&chrono::Local)),
+            Ok(None) => {
+                info!("[SCHEDULE] The schedule has no further runs; exiting");
+                return Ok(());
+            },
+            Err(e) => log::warn!("[SCHEDULE] Couldn't read the next run time: {}", e),
+        }
+        if due.recv().await.is_none() {
+            return Ok(());
+        }
+    }
+}
+
+/// One sync: query, write every block, then record the run (manifest, hooks, heartbeat)
+async fn sync_once(config: &Config, args: &[String]) -> Result<()> {
+    check_clock_skew(config).await;
+    let started_at = chrono::Utc::now();
+    info!("Run ID {}", report::run_id());
+    let mut report = SyncReport::default();
+    let result = match (arg_value(args, "--from-response"), arg_value(args, "--mock"), arg_value(args, "--retry-failed")) {
+        (Some(path), _, _) => run_from_response(config, Path::new(path), &mut report).await,
+        (None, Some(path), _) => run_mock(config, Path::new(path), &mut report).await,
+        (None, None, Some(path)) => run_retry_failed(config, Path::new(path), &mut report).await,
+        (None, None, None) => run_qbxml(config, arg_value(args, "--save-response").map(Path::new), &mut report).await,
+    };
+    // Concurrent writes finish in any order; report them in config order
+    report.sort_blocks();
+    let summary = run_summary(&report, &result);
+    write_error_log(config, &report, &result).await;
+    // Emitted before a failed run exits non-zero, so callers can parse a partial success. A
+    // manifest printed to stdout already holds the report, so it isn't printed twice.
+    let manifest_path = arg_value(args, "--manifest");
+    let report_file = arg_value(args, "--report-file").map(Path::new);
+    if report_file.is_some() || manifest_path != Some("-") {
+        if let Err(e) = report.emit(report_file) {
+            log::warn!("{:#}", e);
+        }
+    }
+    if config.dry_run {
+        info!("[DRY-RUN] Nothing was sent; the manifest, hooks and heartbeat were skipped");
+        return result.map(|_| ());
+    }
+    if let Some(path) = manifest_path {
+        let finished_at = chrono::Utc::now();
+        let manifest = RunManifest {
+            schema_version: report::MANIFEST_SCHEMA_VERSION,
+            run_id: report::run_id().to_string(),
+            started_at,
+            finished_at,
+            duration_ms: (finished_at - started_at).num_milliseconds(),
+            config_hash: report::config_hash(&std::fs::read("config/config.toml").unwrap_or_default()),
+            qbxml_version: qbxml_request_processor::QBXML_VERSION.to_string(),
+            succeeded: result.is_ok(),
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+            failed_blocks: report.failed_blocks(),
+            report,
+        };
+        if let Err(e) = manifest.save(Path::new(path)) {
+            log::warn!("{:#}", e);
+        }
+    }
+    let hook_result = run_hooks(config, &summary, result.is_ok());
+    record_heartbeat(config, result?, chrono::Utc::now());
+    hook_result
+}
+
+fn run_summary(report: &SyncReport, result: &Result<RunOutcome>) -> hooks::RunSummary {
+    let count = |status: BlockStatus| report.blocks.iter().filter(|block| block.status == status).count();
+    hooks::RunSummary {
+        run_id: report::run_id().to_string(),
+        source: report.source.clone().unwrap_or_default(),
+        company_file: report.company_file.clone().unwrap_or_default(),
+        written: count(BlockStatus::Written),
+        missing: count(BlockStatus::Missing),
+        failed: count(BlockStatus::Failed),
+        error: result.as_ref().err().map(|e| format!("{:#}", e)).unwrap_or_default(),
+    }
+}
+
+/// Append this run's failure to the [error_log] tab; nothing is written for a fully successful run.
+/// A failed append only warns, since it must not mask the run's own result.
+async fn write_error_log(config: &Config, report: &SyncReport, result: &Result<RunOutcome>) {
+    let Some(error_log) = &config.error_log else { return };
+    let error = result.as_ref().err().map(|e| format!("{:#}", e));
+    let Some(row) = error_log.row(report, error.as_deref(), report::run_id(), Utc::now()) else { return };
+    let gs_client = sheets_client(config, error_log.spreadsheet_id.clone(), Some(error_log.sheet_name.clone()), error_log.anchor.clone());
+    match gs_client.append_row(&error_log.anchor, &error_log.header(), &row).await {
+        Ok(()) => info!("[ERRORLOG] Appended this run's failure to '{}'!{}", error_log.sheet_name, error_log.anchor),
+        Err(e) => log::warn!("[ERRORLOG] Could not append to the error log: {:#}", e),
+    }
+}
+
+/// Run the on_success or on_failure hook. Hook failures are logged; only a failed on_success hook
+/// with fail_on_hook_error set changes the run's result.
+fn run_hooks(config: &Config, summary: &hooks::RunSummary, succeeded: bool) -> Result<()> {
+    let Some(hooks_cfg) = &config.hooks else { return Ok(()) };
+    let (name, command) = if succeeded {
+        ("on_success", &hooks_cfg.on_success)
+    } else {
+        ("on_failure", &hooks_cfg.on_failure)
+    };
+    let Some(command) = command else { return Ok(()) };
+    match hooks::run_hook(name, command, summary) {
+        Ok(()) => Ok(()),
+        Err(e) if succeeded && hooks_cfg.fail_on_hook_error.unwrap_or(false) => Err(e),
+        Err(e) => {
+            log::warn!("[HOOK] {:#}", e);
+            Ok(())
+        },
+    }
+}
+
+/// Query QuickBooks once and report whether each configured account resolves to exactly one account
+async fn validate_accounts(config: &Config) -> Result<()> {
+    let query_options = config.query_options();
+    let qb_names = with_quickbooks_session(config, |session| {
+        Ok(session.account_xml(&query_options)?
+            .map(|xml| session.processor().parser().get_account_names(&xml))
+            .unwrap_or_default())
+    }).await?;
+    let results = account_check::check_accounts(config, &qb_names);
+    let problems = account_check::print_report(&results);
+    if problems > 0 {
+        anyhow::bail!("{} of {} configured account(s) are missing or ambiguous", problems, results.len());
+    }
+    println!("All {} configured account(s) found in QuickBooks", results.len());
+    Ok(())
+}
+
+/// --list-accounts: print the chart of accounts from one query, hiding zero balances when
+/// [quickbooks] include_zero_balance = false
+async fn list_accounts(config: &Config) -> Result<()> {
+    let query_options = config.query_options();
+    let accounts = with_quickbooks_session(config, |session| {
+        match session.account_xml(&query_options)? {
+            Some(xml) => parse_accounts(session.processor().parser(), &xml),
+            None => Ok(Vec::new()),
+        }
+    }).await?;
+    let listed = listed_accounts(&accounts, config.quickbooks.include_zero_balance.unwrap_or(true));
+    for account in &listed {
+        println!("{:<50} {:<24} {:>16.2}", account.account_full_name, account.account_type.to_string(), account.balance);
+    }
+    println!("{} of {} account(s) listed", listed.len(), accounts.len());
+    Ok(())
+}
+
+/// The accounts --list-accounts prints. Only a genuine zero is hidden: an account whose balance
+/// QuickBooks didn't report is still listed.
+fn listed_accounts(accounts: &[AccountData], include_zero_balance: bool) -> Vec<&AccountData> {
+    accounts.iter().filter(|account| include_zero_balance || !account.has_zero_balance()).collect()
+}
+
+/// Where a successful run's balances came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunOutcome {
+    Live,
+    Cached,
+    // Written from a saved response (--from-response) or canned balances (--mock); says nothing
+    // about QuickBooks' health
+    Replayed,
+    // Only the previous run's failed blocks were rewritten (--retry-failed)
+    Retried,
+    // QuickBooks was busy and on_busy = "skip", or a daemon found no company file open; nothing was written
+    Skipped,
+    NoData,
+}
+
+// Touch the heartbeat file when the run counts as fully successful; failures only warn
+fn record_heartbeat(config: &Config, outcome: RunOutcome, now: DateTime<Utc>) {
+    let Some(monitoring) = &config.monitoring else { return };
+    let Some(path) = monitoring.heartbeat_file.as_deref() else { return };
+    let counts = match outcome {
+        RunOutcome::Live => true,
+        RunOutcome::Cached => monitoring.heartbeat_on_cached.unwrap_or(false),
+        RunOutcome::Replayed | RunOutcome::Retried | RunOutcome::Skipped | RunOutcome::NoData => false,
+    };
+    if !counts {
+        log::warn!("Not updating heartbeat file {}: run outcome was {:?}", path, outcome);
+        return;
+    }
+    if let Err(e) = heartbeat::write_heartbeat(Path::new(path), now) {
+        log::warn!("{:#}", e);
+    }
+}
+
+/// Settings given on the command line rather than in config.toml
+fn apply_flags(config: &mut Config, args: &[String]) {
+    config.dry_run = args.iter().any(|a| a == "--dry-run");
+    config.daemon = args.iter().any(|a| a == "--daemon");
+}
+
+// Value following a flag, e.g. `--serve 127.0.0.1:8080`
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter().position(|a| a == flag).and_then(|i| args.get(i + 1)).map(String::as_str)
+}
+
+#[cfg(feature = "http-api")]
+async fn serve(config: &Config, addr: &str) -> Result<()> {
+    let worker = std::sync::Arc::new(qb_worker::QbWorker::spawn_qbxml(config)?);
+    http_api::serve(addr, worker).await
+}
+
+#[cfg(not(feature = "http-api"))]
+async fn serve(_config: &Config, _addr: &str) -> Result<()> {
+    Err(anyhow::anyhow!("--serve requires a build with the http-api feature (cargo build --features http-api)"))
+}
+
+/// Warn when the local clock disagrees with the Sheets endpoint's, optionally suppressing
+/// timestamp cells for the run. Opt-in; a failed check only warns.
+async fn check_clock_skew(config: &Config) {
+    let Some(monitoring) = config.monitoring.as_ref().filter(|m| m.check_clock_skew.unwrap_or(false) && !config.dry_run) else { return };
+    let client = GoogleSheetsClient::new(config.google_sheets.webapp_url.clone(), String::new(), String::new(), None, String::new())
+        .with_pinned_cert(pinned_cert(config))
+        .with_network(config.network.clone());
+    let server = match client.server_time().await {
+        Ok(server) => server,
+        Err(e) => {
+            log::warn!("[CLOCK] Could not check clock skew: {:#}", e);
+            return;
+        },
+    };
+    let skew = clock_skew::skew(Utc::now(), server);
+    match clock_skew::skew_warning(skew, monitoring.max_clock_skew_secs.unwrap_or(clock_skew::DEFAULT_MAX_SKEW_SECS)) {
+        Some(warning) => {
+            log::warn!("[CLOCK] {}", warning);
+            if monitoring.skip_timestamps_on_skew.unwrap_or(false) {
+                log::warn!("[CLOCK] Timestamp cells will not be written this run");
+                clock_skew::distrust_timestamps();
             }
         },
+        None => info!("[CLOCK] Local clock is within {}s of the server's", skew.num_seconds().abs()),
+    }
+}
+
+/// Fail before querying QuickBooks when a sync block cell lies outside its sheet's grid, which the
+/// web app would otherwise reject with an opaque range error. Each sheet's size is fetched once.
+async fn verify_cell_bounds(config: &Config) -> Result<()> {
+    // (web app URL and API key, spreadsheet, sheet)
+    type SheetKey<'a> = ((&'a str, &'a str), &'a str, String);
+    let mut by_sheet: BTreeMap<SheetKey, BTreeSet<String>> = BTreeMap::new();
+    for sync in &config.sync_blocks {
+        let webapp = sync.webapp(&config.google_sheets);
+        for (sheet, cell) in sync.targets() {
+            by_sheet.entry((webapp, sync.spreadsheet_id.as_str(), sheet)).or_default().insert(cell);
+        }
+        let extras = sync.alert.as_ref().map(|alert| alert.cell.as_str()).into_iter()
+            .chain(sync.timestamp_cell.as_deref())
+            .chain(sync.attribution_cell.as_deref());
+        for cell in extras {
+            by_sheet.entry((webapp, sync.spreadsheet_id.as_str(), sync.sheet_name.clone())).or_default().insert(cell.to_string());
+        }
+    }
+    let mut outside = Vec::new();
+    for (((webapp_url, api_key), spreadsheet_id, sheet), cells) in &by_sheet {
+        let client = sheets_client(config, spreadsheet_id.to_string(), Some(sheet.clone()), String::new()).with_webapp(webapp_url, api_key);
+        let (rows, columns) = client.sheet_dimensions(sheet).await
+            .with_context(|| format!("Could not read the size of sheet '{}'", sheet))?;
+        for cell in cells {
+            // Validated at config load
+            let Ok(cell_ref) = a1::CellRef::parse(cell) else { continue };
+            if !cell_ref.within(rows, columns) {
+                outside.push(format!("'{}'!{} (sheet is {} rows x {} columns)", sheet, cell, rows, columns));
+            }
+        }
+    }
+    if !outside.is_empty() {
+        anyhow::bail!("Cell(s) outside their sheet: {}", outside.join(", "));
+    }
+    info!("[SHEETS] All {} configured cell(s) are within their sheets", by_sheet.values().map(BTreeSet::len).sum::<usize>());
+    Ok(())
+}
+
+/// Fail fast on a rotated/expired API key before doing any QuickBooks work.
+/// Scripts without the auth action are tolerated unless the check was explicitly requested.
+async fn verify_api_key(config: &Config, required: bool) -> Result<()> {
+    let gs_cfg = &config.google_sheets;
+    let client = GoogleSheetsClient::new(gs_cfg.webapp_url.clone(), gs_cfg.api_key.clone(), String::new(), None, String::new())
+        .with_pinned_cert(pinned_cert(config))
+        .with_network(config.network.clone());
+    match client.check_auth().await.context("API key check failed")? {
+        Some(true) => {
+            info!("Google Sheets API key accepted");
+            Ok(())
+        },
+        Some(false) => Err(anyhow::anyhow!("Google Sheets web app rejected the API key; update api_key in config.toml")),
+        None if required => Err(anyhow::anyhow!("Google Sheets web app does not support the auth check; redeploy the latest Apps Script")),
+        None => {
+            log::warn!("Google Sheets web app does not support the auth check; skipping API key verification");
+            Ok(())
+        },
+    }
+}
+
+/// Run the write pipeline against an AccountQueryRs saved with --save-response, without QuickBooks.
+/// Transaction counts need a live session and are skipped; the balance cache is left untouched.
+async fn run_from_response(config: &Config, path: &Path, report: &mut SyncReport) -> Result<RunOutcome> {
+    let response_xml = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read saved response {}", path.display()))?;
+    let parser = AccountParser { amount_format: config.quickbooks.amount_format() };
+    let accounts = parse_accounts(&parser, &response_xml)?;
+    info!("[QBXML] Replaying {} account(s) from {}", accounts.len(), path.display());
+    warn_live_only_blocks(config);
+    report.source = Some("saved_response".to_string());
+    write_accounts(config, &accounts, None, report).await;
+    report.outputs_result()?;
+    Ok(RunOutcome::Replayed)
+}
+
+/// Run the write pipeline against canned balances served by MockQuickBooks, for trying a config
+/// (or the Sheets side) on a machine without QuickBooks. Only account-driven blocks are written.
+async fn run_mock(config: &Config, path: &Path, report: &mut SyncReport) -> Result<RunOutcome> {
+    let mock = MockQuickBooks::load(path)?;
+    let ticket = mock.begin_session(&config.quickbooks.company_file, FileMode::DoNotCare)?;
+    let query_options = config.query_options();
+    let accounts = session::run_in_session(session::Session::new(mock, ticket, false), |session| {
+        match session.account_xml(&query_options)? {
+            Some(xml) => parse_accounts(session.processor().parser(), &xml),
+            None => Ok(Vec::new()),
+        }
+    })?;
+    info!("[QBXML] Using {} mock account(s) from {}", accounts.len(), path.display());
+    warn_live_only_blocks(config);
+    report.source = Some("mock".to_string());
+    write_accounts(config, &accounts, None, report).await;
+    report.outputs_result()?;
+    Ok(RunOutcome::Replayed)
+}
+
+// Blocks that need their own QuickBooks queries are skipped when writing without a live session
+fn warn_live_only_blocks(config: &Config) {
+    if !config.transaction_count.is_empty() {
+        log::warn!("[TXN] Skipping {} transaction_count block(s): they need a live QuickBooks session", config.transaction_count.len());
+    }
+    if !config.customer_sync.is_empty() {
+        log::warn!("[CUSTOMER] Skipping {} customer_sync block(s): they need a live QuickBooks session", config.customer_sync.len());
+    }
+    if !config.file_modified.is_empty() {
+        log::warn!("[FILE] Skipping {} file_modified block(s): they need a live QuickBooks session", config.file_modified.len());
+    }
+}
+
+/// Query QuickBooks once and rewrite only the sync blocks a previous run's manifest lists as failed.
+/// Other block kinds, sinks aside, are left alone; the run fails if any retried block fails again.
+async fn run_retry_failed(config: &Config, manifest_path: &Path, report: &mut SyncReport) -> Result<RunOutcome> {
+    let previous = RunManifest::load(manifest_path)?;
+    // block_index is a position in the config, so it only means the same block under the same config
+    let config_hash = report::config_hash(&std::fs::read("config/config.toml").unwrap_or_default());
+    if previous.config_hash != config_hash {
+        anyhow::bail!("config.toml has changed since run {}; its failed blocks can't be matched reliably", previous.run_id);
+    }
+    let failed: BTreeSet<usize> = previous.failed_blocks.iter().copied().collect();
+    report.source = Some("retry".to_string());
+    if failed.is_empty() {
+        info!("[RETRY] Run {} had no failed blocks; nothing to retry", previous.run_id);
+        return Ok(RunOutcome::Retried);
+    }
+    info!("[RETRY] Retrying {} failed block(s) from run {}", failed.len(), previous.run_id);
+    let query_options = config.query_options();
+    let accounts = with_quickbooks_session(config, |session| {
+        let processor = session.processor();
+        match session.account_xml(&query_options)? {
+            Some(xml) => parse_accounts(processor.parser(), &xml),
+            None => Ok(Vec::new()),
+        }
+    }).await?;
+    let lookup = account_lookup(config, &accounts);
+    write_balances(config, &lookup, Some(&failed), report).await?;
+    Ok(RunOutcome::Retried)
+}
+
+/// Resolves configured account names against parsed accounts, honouring name_normalization
+fn account_lookup<'a>(config: &Config, accounts: &'a [AccountData]) -> impl Fn(&str) -> Result<Option<AccountData>> + 'a {
+    let normalization = config.quickbooks.name_normalization.unwrap_or_default();
+    let qb_names: Vec<String> = accounts.iter().map(|a| a.account_full_name.clone()).collect();
+    move |name: &str| {
+        Ok(normalization.find_match(&qb_names, name)
+            .and_then(|qb_name| accounts.iter().find(|a| a.account_full_name == qb_name))
+            .cloned())
+    }
+}
+
+/// Write every account-driven block from one set of parsed accounts, saving the balance cache first
+/// A failed output kind is recorded in the report and doesn't stop the others; see SyncReport::outputs_result
+async fn write_accounts(config: &Config, accounts: &[AccountData], cache_path: Option<&Path>, report: &mut SyncReport) {
+    let lookup = account_lookup(config, accounts);
+    let smoothed = match cache_path {
+        Some(path) => save_balance_cache(config, path, &lookup),
+        None => BTreeMap::new(),
+    };
+    let result = write_balances(config, &lookup, None, report).await;
+    report.record_output("sync blocks", result);
+    report.record_output("smoothed_cell", write_smoothed_cells(config, &smoothed).await);
+    report.record_output("type_report", write_type_reports(config, accounts).await);
+    report.record_output("prefix_block", write_prefix_blocks(config, accounts).await);
+    report.record_output("account_row", write_account_rows(config, &lookup).await);
+    report.record_output("percent_sync", write_percent_syncs(config, &lookup).await);
+    if let Some(ofx) = &config.ofx_export {
+        // A file for downstream tools; failing to write it doesn't fail the sync
+        let now = Utc::now();
+        let sink = OfxSink::from_config(ofx, now, report::run_id());
+        let timestamp = now.with_timezone(&chrono::Local).to_rfc3339();
+        let updates: Vec<BalanceUpdate> = accounts.iter()
+            .map(|account| BalanceUpdate {
+                account_full_name: &account.account_full_name,
+                account_number: Some(account.number.as_str()).filter(|number| !number.is_empty()),
+                balance: account.balance,
+                timestamp: &timestamp,
+            })
+            .collect();
+        if config.dry_run {
+            info!("[DRY-RUN] Would write {} balance(s) to {}", updates.len(), sink.describe());
+        } else {
+            match sink.send_all(&updates).await {
+                Ok(()) => info!("[OFX] Wrote {} balance(s) to {}", updates.len(), ofx.path),
+                Err(e) => log::warn!("[OFX] {:#}", e),
+            }
+        }
+    }
+}
+
+/// What was read from QuickBooks while the session was open
+struct QbSnapshot {
+    accounts: Vec<AccountData>,
+    // One result per [[transaction_count]] block, in config order
+    transaction_counts: Vec<Result<usize>>,
+    // One result per [[customer_sync]] block, in config order
+    customer_balances: Vec<Result<Option<f64>>>,
+    // Balance sheet report XML for each period-end any [[period_balance]] block asks for
+    balance_sheets: HashMap<chrono::NaiveDate, Result<String>>,
+    // Newest TimeModified of any transaction QuickBooks reports, for [[file_modified]] blocks
+    last_activity: Option<DateTime<Utc>>,
+    company_file: Option<String>,
+    product_name: Option<String>,
+}
+
+/// The open company file's path, logged for the operator. Informational only: a failure to read
+/// it is a warning and must not fail the sync.
+fn connected_company_file<P: QuickBooksProcessor>(session: &session::Session<P>) -> Option<String> {
+    match session.company_file_name() {
+        Ok(path) if !path.is_empty() => {
+            info!("[QBXML] Connected to company file: {}", path);
+            Some(path)
+        },
+        Ok(_) => None,
+        Err(e) => {
+            log::warn!("[QBXML] Could not read the open company file name: {:#}", e);
+            None
+        },
+    }
+}
+
+/// `save_response` receives a copy of the raw AccountQueryRs for offline debugging
+async fn run_qbxml(config: &Config, save_response: Option<&Path>, report: &mut SyncReport) -> Result<RunOutcome> {
+    let cache_path = config.quickbooks.cache_file.as_deref().map(Path::new);
+    let query_options = config.query_options();
+    // Read everything while the session is open; the Sheets writes happen after it is released
+    let snapshot = with_quickbooks_session(config, |session| {
+        let processor = session.processor();
+        let company_file = connected_company_file(session);
+        let response_xml = match session.account_xml(&query_options)? {
+            Some(xml) => xml,
+            None => return Ok(None),
+        };
+        if let Some(path) = save_response {
+            match std::fs::write(path, &response_xml) {
+                Ok(()) => info!("[QBXML] Saved the raw response to {} (it lists every account and balance; share it with care)", path.display()),
+                Err(e) => log::warn!("[QBXML] Could not save the raw response to {}: {}", path.display(), e),
+            }
+        }
+        let accounts = parse_accounts(processor.parser(), &response_xml)?;
+        let transaction_counts = config.transaction_count.iter()
+            .map(|count| session.transaction_count(&count.account_full_name, count.from_date, count.to_date))
+            .collect();
+        let customer_balances = config.customer_sync.iter()
+            .map(|customer| session.customer_balance(&customer.customer_full_name))
+            .collect();
+        // One report per distinct date, shared by every block that lists it
+        let mut balance_sheets = HashMap::new();
+        for &as_of in config.period_balance.iter().flat_map(|block| &block.periods) {
+            balance_sheets.entry(as_of).or_insert_with(|| session.balance_sheet(as_of));
+        }
+        let last_activity = if config.file_modified.is_empty() {
+            None
+        } else {
+            let since = Utc::now() - chrono::Duration::days(LAST_ACTIVITY_LOOKBACK_DAYS);
+            session.last_activity(since)
+                .map_err(|e| log::warn!("[FILE] Could not ask QuickBooks for its last activity: {:#}", e))
+                .ok()
+                .flatten()
+        };
+        // Informational only; a failure here must not fail the sync
+        Ok(Some(QbSnapshot {
+            accounts,
+            transaction_counts,
+            customer_balances,
+            balance_sheets,
+            last_activity,
+            company_file,
+            product_name: session.product_name().ok().flatten(),
+        }))
+    }).await;
+    match snapshot {
+        Ok(Some(QbSnapshot { accounts, transaction_counts, customer_balances, balance_sheets, last_activity, company_file, product_name })) => {
+            report.source = Some("live".to_string());
+            report.company_file = company_file;
+            report.quickbooks_product = product_name;
+            write_accounts(config, &accounts, cache_path, report).await;
+            report.record_output("transaction_count", write_transaction_counts(config, transaction_counts).await);
+            report.record_output("customer_sync", write_customer_balances(config, customer_balances).await);
+            report.record_output("period_balance", write_period_balances(config, &accounts, &balance_sheets).await);
+            let result = write_file_modified(config, last_activity, report.company_file.as_deref()).await;
+            report.record_output("file_modified", result);
+            report.outputs_result()?;
+            Ok(RunOutcome::Live)
+        },
         Ok(None) => {
             info!("[QBXML] No response_xml received");
+            report.source = Some("no_data".to_string());
+            Ok(RunOutcome::NoData)
+        },
+        Err(e) => match skip_reason(config, &e) {
+            Some((source, why)) => {
+                log::warn!("[QBXML] {:#}; skipping this run ({})", e, why);
+                report.source = Some(source.to_string());
+                Ok(RunOutcome::Skipped)
+            },
+            // The wrong company file is open: stop rather than paper over it with cached balances
+            None if matches!(e.downcast_ref::<QbError>(), Some(QbError::CompanyMismatch { .. })) => Err(e),
+            None => match cache_path {
+                Some(path) => {
+                    eprintln!("[QBXML] QuickBooks unavailable: {:#}", e);
+                    report.source = Some("cached".to_string());
+                    write_cached_balances(config, path, report).await?;
+                    Ok(RunOutcome::Cached)
+                },
+                None => Err(e),
+            },
+        },
+    }
+}
+
+/// When QuickBooks couldn't be used for a reason that should skip this run rather than fail it
+/// or fall back to the cache: the report source and why, for the log
+fn skip_reason(config: &Config, e: &anyhow::Error) -> Option<(&'static str, &'static str)> {
+    match e.downcast_ref::<QbError>() {
+        Some(QbError::Busy { .. }) if config.quickbooks.on_busy.unwrap_or_default() == BusyPolicy::Skip => {
+            Some(("skipped_busy", "on_busy = \"skip\""))
+        },
+        // A daemon waits for someone to open the file; a one-shot run reports it as a failure
+        Some(QbError::NoCompanyFile { .. }) if config.daemon => Some(("skipped_no_company_file", "--daemon waits for the next slot")),
+        _ => None,
+    }
+}
+
+/// Every account in an AccountQueryRs, in QuickBooks order, from one parse of the response
+fn parse_accounts(parser: &AccountParser, response_xml: &str) -> Result<Vec<AccountData>> {
+    Ok(parser.accounts(response_xml)?.into_iter().map(AccountData::from).collect())
+}
+
+/// Save this run's balances, advancing each smoothed_cell's moving average from the previous
+/// cache; returns the new averages by smoothing key
+fn save_balance_cache<F>(config: &Config, path: &Path, lookup: &F) -> BTreeMap<String, f64>
+where
+    F: Fn(&str) -> Result<Option<AccountData>>,
+{
+    let previous = BalanceCache::load(path).unwrap_or_else(|e| {
+        log::warn!("[CACHE] {:#}", e);
+        None
+    });
+    let mut cache = BalanceCache::new(chrono::Utc::now());
+    for name in config.account_names() {
+        if let Ok(Some(account)) = lookup(name) {
+            cache.balances.insert(name.to_string(), account.balance);
+            if let Some(total) = account.total_balance {
+                cache.total_balances.insert(name.to_string(), total);
+            }
+            cache.account_types.insert(name.to_string(), account.account_type.to_string());
+        }
+    }
+    for sync in &config.sync_blocks {
+        let Some(key) = sync.smoothing_key() else { continue };
+        let Ok(Some(account)) = lookup(&sync.account_full_name) else { continue };
+        // The same figure the block writes: TotalBalance when asked for, after the sign convention
+        let balance = match (sync.total_balance.unwrap_or(false), account.total_balance) {
+            (true, Some(total)) => total,
+            _ => account.balance,
+        };
+        let balance = sync.sign_convention.unwrap_or_default().apply(balance, &account.account_type);
+        let previous_average = previous.as_ref().and_then(|p| p.smoothed.get(&key).copied());
+        let alpha = sync.smoothing_alpha.unwrap_or(crate::cache::DEFAULT_SMOOTHING_ALPHA);
+        cache.smoothed.insert(key, crate::cache::ema(alpha, balance, previous_average));
+    }
+    // The cache is rebuilt from this run only; say which old entries that drops
+    if let Some(mut previous) = previous {
+        let dropped = previous.prune(|name| cache.balances.contains_key(name));
+        if !dropped.is_empty() {
+            info!("[CACHE] Pruned {} entr(ies) no longer synced: {}", dropped.len(), dropped.join(", "));
+        }
+    }
+    if config.dry_run {
+        info!("[DRY-RUN] Would save {} balance(s) to the cache at {}", cache.balances.len(), path.display());
+    } else if let Err(e) = cache.save(path) {
+        log::warn!("[CACHE] {:#}", e);
+    }
+    cache.smoothed
+}
+
+/// Write each sync block's moving average (see save_balance_cache) to its smoothed_cell
+async fn write_smoothed_cells(config: &Config, smoothed: &BTreeMap<String, f64>) -> Result<()> {
+    for sync in &config.sync_blocks {
+        let (Some(cell), Some(average)) = (&sync.smoothed_cell, sync.smoothing_key().and_then(|key| smoothed.get(&key))) else { continue };
+        let gs_client = sync_client(config, sync, Some(sync.sheet_name.clone()), cell.clone());
+        gs_client.send_value(&sync.account_full_name, &CellValue::Number(*average), None, None).await?;
+        info!("[SMOOTH] Wrote '{}' moving average {} to '{}'!{}", sync.account_full_name, average, sync.sheet_name, cell);
+    }
+    Ok(())
+}
+
+/// --catch-up: whether a scheduled slot was missed since the balance cache was last saved
+fn should_catch_up(config: &Config, now: DateTime<Utc>) -> Result<bool> {
+    let Some(schedule) = config.schedule.as_ref().filter(|s| s.catch_up.unwrap_or(false)) else {
+        anyhow::bail!("--catch-up needs [schedule] cron_expression or interval_secs, and catch_up = true");
+    };
+    // Validated at config load: catch_up requires cache_file
+    let path = Path::new(config.quickbooks.cache_file.as_deref().unwrap_or_default());
+    let last_run = BalanceCache::load(path)?.map(|cache| cache.saved_at);
+    match schedule.catch_up(last_run, now) {
+        catch_up::CatchUp::Run { missed_at } => {
+            info!("[SCHEDULE] Missed the run due at {}; syncing now", missed_at);
+            Ok(true)
+        },
+        catch_up::CatchUp::UpToDate { next_at } => {
+            info!("[SCHEDULE] No run missed; next one due at {}", next_at);
+            Ok(false)
+        },
+        catch_up::CatchUp::OutsideWindow { missed_at } => {
+            log::warn!("[SCHEDULE] Missed the run due at {}, outside catch_up_window_secs; waiting for the schedule", missed_at);
+            Ok(false)
+        },
+        catch_up::CatchUp::NoHistory => {
+            info!("[SCHEDULE] No balance cache at {} yet; waiting for the schedule", path.display());
+            Ok(false)
+        },
+    }
+}
+
+/// --prune-cache: drop cached balances for accounts no block syncs any more, without querying QuickBooks
+fn prune_cache(config: &Config) -> Result<()> {
+    let Some(path) = config.quickbooks.cache_file.as_deref().map(Path::new) else {
+        anyhow::bail!("--prune-cache needs [quickbooks] cache_file");
+    };
+    let Some(mut cache) = BalanceCache::load(path)? else {
+        info!("[CACHE] No balance cache at {}; nothing to prune", path.display());
+        return Ok(());
+    };
+    let synced = config.account_names();
+    let dropped = cache.prune(|name| synced.contains(&name));
+    if dropped.is_empty() {
+        info!("[CACHE] Every cached account is still synced; {} left unchanged", path.display());
+        return Ok(());
+    }
+    for name in &dropped {
+        info!("[CACHE] Pruned '{}'", name);
+    }
+    cache.save(path)?;
+    info!("[CACHE] Pruned {} entr(ies) from {}", dropped.len(), path.display());
+    Ok(())
+}
+
+/// Serve the last good balances, unless they are older than max_cache_age_secs
+async fn write_cached_balances(config: &Config, path: &Path, report: &mut SyncReport) -> Result<()> {
+    let cache = BalanceCache::load(path)?
+        .ok_or_else(|| anyhow::anyhow!("QuickBooks unavailable and no balance cache at {}", path.display()))?;
+    cache.ensure_fresh(chrono::Utc::now(), config.quickbooks.max_cache_age_secs)?;
+    log::warn!("[CACHE] Writing cached balances saved at {}", cache.saved_at);
+    write_balances(config, |name: &str| {
+        Ok(cache.balances.get(name).map(|balance| AccountData {
+            account_full_name: name.to_string(),
+            number: String::new(),
+            account_type: cache.account_types.get(name).map(|t| AccountType::from(t.as_str())).unwrap_or_default(),
+            description: None,
+            balance: *balance,
+            total_balance: cache.total_balances.get(name).copied(),
+            raw_balance: None,
+            raw_total_balance: None,
+        }))
+    }, None, report).await
+}
+
+/// A Sheets client for one target, configured from the [google_sheets] section
+fn sheets_client(config: &Config, spreadsheet_id: String, sheet_name: Option<String>, cell_address: String) -> GoogleSheetsClient {
+    let gs_cfg = &config.google_sheets;
+    GoogleSheetsClient::new(gs_cfg.webapp_url.clone(), gs_cfg.api_key.clone(), spreadsheet_id, sheet_name, cell_address)
+        .with_require_success(gs_cfg.require_success.unwrap_or(true))
+        .with_formula_policy(gs_cfg.on_formula_cell.unwrap_or_default())
+        .with_value_guard(value_guard::ValueGuard::from_config(gs_cfg))
+        .with_run_id(report::run_id())
+        .with_pinned_cert(pinned_cert(config))
+        .with_network(config.network.clone())
+        .with_retry(gs_cfg.http_retry())
+        .with_dry_run(config.dry_run)
+}
+
+/// sheets_client for a sync block, whose own webapp_url/api_key win over [google_sheets]
+fn sync_client(config: &Config, sync: &AccountSyncConfig, sheet_name: Option<String>, cell_address: String) -> GoogleSheetsClient {
+    let (webapp_url, api_key) = sync.webapp(&config.google_sheets);
+    sheets_client(config, sync.spreadsheet_id.clone(), sheet_name, cell_address).with_webapp(webapp_url, api_key)
+}
+
+// Validated at config load, so a set pin always parses here
+fn pinned_cert(config: &Config) -> Option<[u8; 32]> {
+    config.google_sheets.pinned_cert_sha256.as_deref().and_then(|pin| cert_pin::parse_fingerprint(pin).ok())
+}
+
+/// Write every sync block (or only those whose index is in `only`) and chart row using `lookup`
+/// to resolve account balances; chart rows are skipped when `only` is given
+async fn write_balances<F>(config: &Config, lookup: F, only: Option<&BTreeSet<usize>>, report: &mut SyncReport) -> Result<()>
+where
+    F: Fn(&str) -> Result<Option<AccountData>>,
+{
+    let webhook_sinks: Vec<WebhookSink> = config.webhook_sink.iter().map(WebhookSink::from_config).collect();
+    let now = Utc::now();
+    let mut resolved = Vec::with_capacity(config.sync_blocks.len());
+    for (index, sync) in config.sync_blocks.iter().enumerate() {
+        if only.is_some_and(|only| !only.contains(&index)) {
+            continue;
+        }
+        match lookup(&sync.account_full_name) {
+            Ok(Some(account)) => {
+                if account.number.is_empty() {
+                    info!("[QBXML] Account '{}' ({}) balance is: {:?}", sync.account_full_name, account.account_type, account.balance);
+                } else {
+                    info!("[QBXML] Account '{}' ({} #{}) balance is: {:?}", sync.account_full_name, account.account_type, account.number, account.balance);
+                }
+                resolved.push((index, sync, account));
+            },
+            Ok(None) => {
+                if config.quickbooks.active_only.unwrap_or(false) {
+                    info!("[QBXML] No valid balance for account '{}'. active_only is enabled; the account may be inactive in QuickBooks.", sync.account_full_name);
+                } else {
+                    info!("[QBXML] No valid balance for account '{}'.", sync.account_full_name);
+                }
+                report.record(index, sync, BlockStatus::Missing, None, None);
+            },
+            Err(e) => {
+                eprintln!("[QBXML] Error parsing balance for '{}': {:#}", sync.account_full_name, e);
+                report.record(index, sync, BlockStatus::Failed, None, Some(format!("{:#}", e)));
+            }
+        }
+    }
+    let mut planned: Vec<BlockWrite> = resolved.iter().map(|(index, sync, account)| BlockWrite::plan(*index, sync, account)).collect();
+    write_plan::coalesce(&mut planned, config.google_sheets.duplicate_cell_policy.unwrap_or_default())?;
+    // Ordered blocks go first, strictly in sequence (stable for equal orders); the rest run concurrently.
+    // A failed block doesn't stop the others: every block is attempted and the failures reported together.
+    let (mut ordered, unordered): (Vec<_>, Vec<_>) = planned.into_iter().partition(|write| write.sync.order.is_some());
+    ordered.sort_by_key(|write| write.sync.order);
+    let mut failures = Vec::new();
+    for write in &ordered {
+        let result = write_block(config, write, &webhook_sinks, now).await;
+        record_write(report, write, &result);
+        if let Err(e) = result {
+            failures.push(format!("'{}': {:#}", write.sync.account_full_name, e));
+        }
+    }
+    if config.google_sheets.batch_writes.unwrap_or(false) {
+        if let Err(e) = write_blocks_batched(config, &unordered, &webhook_sinks, now, report).await {
+            failures.push(format!("{:#}", e));
+        }
+    } else {
+        let results = futures::future::join_all(
+            unordered.iter().map(|write| write_block(config, write, &webhook_sinks, now))
+        ).await;
+        for (write, result) in unordered.iter().zip(results) {
+            record_write(report, write, &result);
+            if let Err(e) = result {
+                failures.push(format!("'{}': {:#}", write.sync.account_full_name, e));
+            }
+        }
+    }
+    if only.is_none() {
+        if let Err(e) = write_chart_rows(config, &lookup).await {
+            failures.push(format!("chart_data: {:#}", e));
+        }
+    }
+    if !failures.is_empty() {
+        anyhow::bail!("Sync block write(s) failed: {}", failures.join("; "));
+    }
+    Ok(())
+}
+
+/// Append today's row to each chart_data block; a chart with an unresolved account is skipped
+async fn write_chart_rows<F>(config: &Config, lookup: &F) -> Result<()>
+where
+    F: Fn(&str) -> Result<Option<AccountData>>,
+{
+    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+    for chart in &config.chart_data {
+        let mut balances = Vec::with_capacity(chart.accounts.len());
+        for account in &chart.accounts {
+            match lookup(account) {
+                Ok(Some(account)) => balances.push(account.balance),
+                Ok(None) => {
+                    eprintln!("[CHART] No valid balance for account '{}'; skipping chart row at {}", account, chart.anchor);
+                    break;
+                },
+                Err(e) => {
+                    eprintln!("[CHART] Error parsing balance for '{}': {:#}", account, e);
+                    break;
+                }
+            }
+        }
+        if balances.len() != chart.accounts.len() {
+            continue;
+        }
+        let gs_client = sheets_client(
+            config,
+            chart.spreadsheet_id.clone(),
+            Some(chart.sheet_name.clone()),
+            chart.anchor.clone(),
+        );
+        gs_client.append_row(&chart.anchor, &chart.header_row(), &chart.data_row(&today, &balances)).await?;
+        info!("[CHART] Appended {} row to chart data at '{}'!{}", today, chart.sheet_name, chart.anchor);
+    }
+    Ok(())
+}
+
+/// Rebuild every type-grouped report from the full account list
+async fn write_type_reports(config: &Config, accounts: &[AccountData]) -> Result<()> {
+    for report in &config.type_report {
+        let block = report.layout(accounts)?;
+        write_row_block(config, &report.spreadsheet_id, &report.sheet_name, &report.anchor, report.clear_range.as_deref(), &block).await?;
+        info!("[REPORT] Wrote {} account(s) in {} row(s) at '{}'!{}", accounts.len(), block.rows.len(), report.sheet_name, report.anchor);
+    }
+    Ok(())
+}
+
+/// Write the accounts under each prefix block's prefix
+async fn write_prefix_blocks(config: &Config, accounts: &[AccountData]) -> Result<()> {
+    for prefix in &config.prefix_block {
+        let block = prefix.rows(accounts)?;
+        write_row_block(config, &prefix.spreadsheet_id, &prefix.sheet_name, &prefix.start_cell, prefix.clear_range.as_deref(), &block).await?;
+        info!("[PREFIX] Wrote {} account(s) under '{}' at '{}'!{}", block.rows.len(), prefix.full_name_prefix, prefix.sheet_name, prefix.start_cell);
+    }
+    Ok(())
+}
+
+/// Write each account_row block; an account missing from QuickBooks skips only that block
+async fn write_account_rows<F>(config: &Config, lookup: F) -> Result<()>
+where
+    F: Fn(&str) -> Result<Option<AccountData>>,
+{
+    for account_row in &config.account_row {
+        let Some(account) = lookup(&account_row.account_full_name)? else {
+            eprintln!("[ROW] Account '{}' not found in QuickBooks", account_row.account_full_name);
+            continue;
+        };
+        if account_row.skips_description(&account) {
+            // Leave the description cell as it is: write the other fields cell by cell
+            let cells: Vec<CellWrite> = account_row.cells(&account)?.into_iter()
+                .map(|(cell_address, value)| CellWrite { sheet_name: account_row.sheet_name.clone(), cell_address, value })
+                .collect();
+            let gs_client = sheets_client(config, account_row.spreadsheet_id.clone(), None, String::new());
+            for result in gs_client.write_cells(&cells).await? {
+                if !result.success {
+                    anyhow::bail!("account_row '{}': {}", account_row.account_full_name, result.describe());
+                }
+            }
+        } else {
+            let block = account_row.row(&account)?;
+            write_row_block(config, &account_row.spreadsheet_id, &account_row.sheet_name, &account_row.anchor, None, &block).await?;
+        }
+        info!("[ROW] Wrote {} field(s) of '{}' at '{}'!{}", account_row.fields.len(), account.account_full_name, account_row.sheet_name, account_row.anchor);
+    }
+    Ok(())
+}
+
+/// Write each period_balance block from the balance sheet reports; a period whose report
+/// failed or lacks the account is left unwritten
+async fn write_period_balances(config: &Config, accounts: &[AccountData], balance_sheets: &HashMap<chrono::NaiveDate, Result<String>>) -> Result<()> {
+    let parser = AccountParser { amount_format: config.quickbooks.amount_format() };
+    for block in &config.period_balance {
+        // Reports carry no account type; take it from the live account list for sign_convention
+        let account_type = accounts.iter()
+            .find(|a| a.account_full_name == block.account_full_name)
+            .map(|a| a.account_type.clone())
+            .unwrap_or_default();
+        let sign = block.sign_convention.unwrap_or_default();
+        let mut cells = Vec::new();
+        for (as_of, cell_address) in block.periods.iter().zip(block.cells()?) {
+            let amount = match &balance_sheets[as_of] {
+                Ok(report) => parser.get_report_amount(report, &block.account_full_name),
+                Err(e) => {
+                    eprintln!("[PERIOD] Balance sheet as of {} failed: {:#}", as_of, e);
+                    continue;
+                },
+            };
+            match amount {
+                Some(amount) => cells.push(CellWrite {
+                    sheet_name: block.sheet_name.clone(),
+                    cell_address,
+                    value: CellValue::Number(sign.apply(amount, &account_type)),
+                }),
+                None => eprintln!("[PERIOD] '{}' is not on the balance sheet as of {}", block.account_full_name, as_of),
+            }
+        }
+        if cells.is_empty() {
+            continue;
+        }
+        let gs_client = sheets_client(config, block.spreadsheet_id.clone(), Some(block.sheet_name.clone()), block.start_cell.clone());
+        for result in gs_client.write_cells(&cells).await?.iter().filter(|r| !r.success) {
+            eprintln!("[PERIOD] Failed to write {}", result.describe());
+        }
+        info!("[PERIOD] Wrote {} period balance(s) of '{}' at '{}'!{}", cells.len(), block.account_full_name, block.sheet_name, block.start_cell);
+    }
+    Ok(())
+}
+
+/// Write each percent_sync block's balances and shares. A member missing from QuickBooks would
+/// make every share wrong, so it skips the whole block.
+async fn write_percent_syncs<F>(config: &Config, lookup: F) -> Result<()>
+where
+    F: Fn(&str) -> Result<Option<AccountData>>,
+{
+    'blocks: for block in &config.percent_sync {
+        let sign = block.sign_convention.unwrap_or_default();
+        let mut balances = Vec::with_capacity(block.accounts.len());
+        for member in &block.accounts {
+            match lookup(&member.account)? {
+                Some(account) => balances.push(sign.apply(account.balance, &account.account_type)),
+                None => {
+                    eprintln!("[PERCENT] Account '{}' not found in QuickBooks; skipping its percent_sync block", member.account);
+                    continue 'blocks;
+                },
+            }
+        }
+        let cells: Vec<CellWrite> = block.cells(&balances).into_iter()
+            .map(|(cell, value)| CellWrite { sheet_name: block.sheet_name.clone(), cell_address: cell.to_string(), value })
+            .collect();
+        let gs_client = sheets_client(config, block.spreadsheet_id.clone(), Some(block.sheet_name.clone()), String::new());
+        for result in gs_client.write_cells(&cells).await?.iter().filter(|r| !r.success) {
+            eprintln!("[PERCENT] Failed to write {}", result.describe());
+        }
+        info!("[PERCENT] Wrote {} account(s) with their share of the total to '{}'", block.accounts.len(), block.sheet_name);
+    }
+    Ok(())
+}
+
+/// Write each transaction_count block's count; a failed query skips only that block
+async fn write_transaction_counts(config: &Config, counts: Vec<Result<usize>>) -> Result<()> {
+    for (block, count) in config.transaction_count.iter().zip(counts) {
+        let count = match count {
+            Ok(count) => count,
+            Err(e) => {
+                eprintln!("[TXN] Error counting transactions for '{}': {:#}", block.account_full_name, e);
+                continue;
+            },
+        };
+        let gs_client = sheets_client(config, block.spreadsheet_id.clone(), Some(block.sheet_name.clone()), block.cell_address.clone());
+        gs_client.send_value(&block.account_full_name, &CellValue::Number(count as f64), None, None).await?;
+        info!("[TXN] {} transaction(s) hit '{}' from {} to {}", count, block.account_full_name, block.from_date, block.to_date);
+    }
+    Ok(())
+}
+
+/// Write each customer_sync block's balance; a failed query or unknown customer skips only that block
+async fn write_customer_balances(config: &Config, balances: Vec<Result<Option<f64>>>) -> Result<()> {
+    for (block, balance) in config.customer_sync.iter().zip(balances) {
+        let balance = match balance {
+            Ok(Some(balance)) => balance,
+            Ok(None) => {
+                eprintln!("[CUSTOMER] Customer '{}' not found in QuickBooks or has no readable balance", block.customer_full_name);
+                continue;
+            },
+            Err(e) => {
+                eprintln!("[CUSTOMER] Error querying customer '{}': {:#}", block.customer_full_name, e);
+                continue;
+            },
+        };
+        let gs_client = sheets_client(config, block.spreadsheet_id.clone(), Some(block.sheet_name.clone()), block.cell_address.clone());
+        gs_client.send_value(&block.customer_full_name, &CellValue::Number(balance), None, None).await?;
+        info!("[CUSTOMER] Customer '{}' balance is: {:?}", block.customer_full_name, balance);
+    }
+    Ok(())
+}
+
+/// How far back to look for the company's last transaction change; a quieter file falls back to its
+/// modified time on disk
+const LAST_ACTIVITY_LOOKBACK_DAYS: i64 = 30;
+
+/// Write when the company data last changed to each file_modified block: the newest transaction
+/// TimeModified QuickBooks reports, else the company file's modified time on disk (which needs a
+/// path this machine can see). Failing to find either only warns.
+async fn write_file_modified(config: &Config, last_activity: Option<DateTime<Utc>>, company_file: Option<&str>) -> Result<()> {
+    if config.file_modified.is_empty() {
+        return Ok(());
+    }
+    let modified = match (last_activity, company_file) {
+        (Some(at), _) => {
+            info!("[FILE] QuickBooks data last changed {}", at.to_rfc3339());
+            at
+        },
+        (None, Some(path)) => match std::fs::metadata(path).and_then(|meta| meta.modified()) {
+            Ok(time) => {
+                let modified = DateTime::<Utc>::from(time);
+                info!("[FILE] No transaction changed in {} days; company file {} last modified {}", LAST_ACTIVITY_LOOKBACK_DAYS, path, modified.to_rfc3339());
+                modified
+            },
+            Err(e) => {
+                log::warn!("[FILE] Could not read the modified time of {}: {}", path, e);
+                return Ok(());
+            },
         },
+        (None, None) => {
+            log::warn!("[FILE] QuickBooks reported neither recent activity nor the company file path; skipping file_modified");
+            return Ok(());
+        },
+    };
+    for block in &config.file_modified {
+        let gs_client = sheets_client(config, block.spreadsheet_id.clone(), Some(block.sheet_name.clone()), block.cell_address.clone());
+        let text = block.format_time(&config.google_sheets, modified);
+        gs_client.send_value("company file", &CellValue::Text(text), None, None).await?;
+    }
+    Ok(())
+}
+
+/// Write a row block at `anchor` and clear the stale rows beneath it
+async fn write_row_block(config: &Config, spreadsheet_id: &str, sheet_name: &str, anchor: &str, clear_range: Option<&str>, block: &RowBlock) -> Result<()> {
+    let gs_client = sheets_client(
+        config,
+        spreadsheet_id.to_string(),
+        Some(sheet_name.to_string()),
+        anchor.to_string(),
+    );
+    gs_client.write_rows(anchor, &block.rows).await?;
+    match (&block.clear, clear_range) {
+        (Some(clear), _) => gs_client.clear_range(&clear.to_string()).await?,
+        (None, Some(range)) => log::warn!("Rows at '{}'!{} now extend past clear_range {}", sheet_name, anchor, range),
+        (None, None) => {},
+    }
+    Ok(())
+}
+
+/// Write a block's mirror cells, then its alert/timestamp cells, through the Google Sheets API
+#[cfg(feature = "sheets-api")]
+async fn write_block_sheets_api(config: &Config, write: &BlockWrite<'_>, update: &BalanceUpdate<'_>, extra_cells: &[(&str, CellValue)]) -> Result<()> {
+    let sync = write.sync;
+    if config.dry_run {
+        let cells = write.targets.iter().map(|(sheet_name, cell_address)| (sheet_name.as_str(), cell_address.as_str(), &write.cell_value))
+            .chain(extra_cells.iter().map(|(cell, value)| (sync.sheet_name.as_str(), *cell, value)));
+        for (sheet_name, cell_address, value) in cells {
+            info!("[DRY-RUN] Would write {:?} for '{}' to {} '{}'!{}", value, sync.account_full_name, sync.spreadsheet_id, sheet_name, cell_address);
+        }
+        return Ok(());
+    }
+    let key_path = config.google_sheets.service_account_key.as_deref()
+        .ok_or_else(|| anyhow::anyhow!("google_sheets.service_account_key is not set"))?;
+    let client = sheets_api::SheetsApiClient::shared(key_path)?;
+    for (sheet_name, cell_address) in &write.targets {
+        let sink = sheets_api::SheetsApiSink {
+            client: client.clone(),
+            spreadsheet_id: sync.spreadsheet_id.clone(),
+            range: a1::qualified(sheet_name, cell_address),
+        };
+        sink.send(update).await?;
+    }
+    for (cell, value) in extra_cells {
+        client.update_cell(&sync.spreadsheet_id, &a1::qualified(&sync.sheet_name, cell), value).await?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "sheets-api"))]
+async fn write_block_sheets_api(_config: &Config, _write: &BlockWrite<'_>, _update: &BalanceUpdate<'_>, _extra_cells: &[(&str, CellValue)]) -> Result<()> {
+    Err(anyhow::anyhow!("The sheets_api backend requires a build with the sheets-api feature (cargo build --features sheets-api)"))
+}
+
+fn record_write(report: &mut SyncReport, write: &BlockWrite<'_>, result: &Result<f64>) {
+    match result {
+        Ok(value) => report.record(write.index, write.sync, BlockStatus::Written, Some(*value), None),
         Err(e) => {
-            eprintln!("[QBXML] Error querying Quickbooks: {:#}", e);
+            eprintln!("[SHEETS] Failed to write '{}': {:#}", write.sync.account_full_name, e);
+            report.record(write.index, write.sync, BlockStatus::Failed, None, Some(format!("{:#}", e)));
+        },
+    }
+}
+
+/// Write one block's balance to its cells, then its sinks and alert/timestamp cells; returns the value written
+async fn write_block(config: &Config, write: &BlockWrite<'_>, webhook_sinks: &[WebhookSink], now: DateTime<Utc>) -> Result<f64> {
+    let sync = write.sync;
+    let account_balance = write.balance;
+    let timestamp = now.with_timezone(&chrono::Local).to_rfc3339();
+    let update = BalanceUpdate {
+        account_full_name: &sync.account_full_name,
+        account_number: None,
+        balance: account_balance,
+        timestamp: &timestamp,
+    };
+    let extra_cells = extra_cells(config, sync, account_balance, now);
+    if config.google_sheets.backend.unwrap_or_default() == SheetsBackend::SheetsApi {
+        write_block_sheets_api(config, write, &update, &extra_cells).await?;
+    } else {
+        // Create a new GoogleSheetsClient for each sync block with correct spreadsheet_id and cell_address
+        let gs_client = sync_client(
+            config,
+            sync,
+            Some(sync.sheet_name.clone()),
+            write.targets.first().map(|(_, cell)| cell.clone()).unwrap_or_default(),
+        );
+        // Mirror cells all receive the same balance
+        for (sheet_name, cell_address) in &write.targets {
+            if cell_unchanged(config, &gs_client, &write.cell_value, sheet_name, cell_address).await {
+                info!("[SHEETS] '{}'!{} for '{}' is unchanged, skipping", sheet_name, cell_address, sync.account_full_name);
+                continue;
+            }
+            gs_client.send_value(
+                &sync.account_full_name,
+                &write.cell_value,
+                Some(sheet_name),
+                Some(cell_address),
+            ).await?;
         }
+        for (cell, value) in &extra_cells {
+            gs_client.send_value(
+                &sync.account_full_name,
+                value,
+                Some(&sync.sheet_name),
+                Some(cell),
+            ).await?;
+        }
+    }
+    send_to_sinks(config, webhook_sinks, &update).await;
+    Ok(account_balance)
+}
+
+/// Whether the cell already holds this number, so writing it would only add a revision.
+/// Not checked with force_write; text values and read failures all mean "write it".
+async fn cell_unchanged(config: &Config, gs_client: &GoogleSheetsClient, value: &CellValue, sheet_name: &str, cell_address: &str) -> bool {
+    let CellValue::Number(balance) = value else { return false };
+    if !config.google_sheets.checks_unchanged() || config.dry_run {
+        return false;
+    }
+    match gs_client.get_current_balance(Some(sheet_name), cell_address).await {
+        Ok(Some(current)) => config.google_sheets.is_unchanged(current, *balance),
+        Ok(None) => false,
+        Err(e) => {
+            log::warn!("[SHEETS] Could not read '{}'!{} before writing ({:#}); writing anyway", sheet_name, cell_address, e);
+            false
+        },
+    }
+}
+
+/// The alert cell and its text for a block with an alert configured
+fn alert_value(sync: &AccountSyncConfig, balance: f64) -> Option<(&AlertConfig, CellValue)> {
+    sync.alert.as_ref().map(|alert| {
+        let text = alert.cell_text(balance);
+        if !text.is_empty() {
+            info!("[ALERT] Account '{}' balance {} triggered alert: {}", sync.account_full_name, balance, text);
+        }
+        (alert, CellValue::Text(text))
+    })
+}
+
+/// Cells written after a block's balance: its alert flag, timestamp and attribution
+fn extra_cells<'a>(config: &Config, sync: &'a AccountSyncConfig, balance: f64, now: DateTime<Utc>) -> Vec<(&'a str, CellValue)> {
+    let mut cells = Vec::new();
+    if let Some((alert, text)) = alert_value(sync, balance) {
+        cells.push((alert.cell.as_str(), text));
+    }
+    if let Some((cell, text)) = sync.timestamp_value(&config.google_sheets, now).filter(|_| clock_skew::timestamps_trusted()) {
+        cells.push((cell, CellValue::Text(text)));
+    }
+    if let Some((cell, text)) = sync.attribution_value() {
+        cells.push((cell, CellValue::Text(text.to_string())));
+    }
+    cells
+}
+
+// Sink failures are logged, never fatal
+async fn send_to_sinks(config: &Config, webhook_sinks: &[WebhookSink], update: &BalanceUpdate<'_>) {
+    for sink in webhook_sinks {
+        if config.dry_run {
+            info!("[DRY-RUN] Would send '{}' {} to {}", update.account_full_name, update.balance, sink.describe());
+            continue;
+        }
+        if let Err(e) = sink.send(update).await {
+            eprintln!("[SINK] Failed to send '{}' to {}: {:#}", update.account_full_name, sink.describe(), e);
+        }
+    }
+}
+
+/// Write blocks with one writeCells request per web app and spreadsheet. Each block is recorded
+/// from its own cells' results, so one rejected cell fails only its block; the run fails if any block did.
+async fn write_blocks_batched(config: &Config, writes: &[BlockWrite<'_>], webhook_sinks: &[WebhookSink], now: DateTime<Utc>, report: &mut SyncReport) -> Result<()> {
+    let timestamp = now.with_timezone(&chrono::Local).to_rfc3339();
+    let mut by_spreadsheet: BTreeMap<((&str, &str), &str), Vec<usize>> = BTreeMap::new();
+    for (i, write) in writes.iter().enumerate() {
+        by_spreadsheet.entry((write.sync.webapp(&config.google_sheets), write.sync.spreadsheet_id.as_str())).or_default().push(i);
+    }
+    let mut errors: Vec<Option<String>> = vec![None; writes.len()];
+    for (((webapp_url, api_key), spreadsheet_id), blocks) in by_spreadsheet {
+        let gs_client = sheets_client(config, spreadsheet_id.to_string(), None, String::new()).with_webapp(webapp_url, api_key);
+        // owners[n] is the block that cells[n] belongs to
+        let mut cells = Vec::new();
+        let mut owners = Vec::new();
+        for &i in &blocks {
+            let write = &writes[i];
+            for (sheet_name, cell_address) in &write.targets {
+                if cell_unchanged(config, &gs_client, &write.cell_value, sheet_name, cell_address).await {
+                    info!("[SHEETS] '{}'!{} for '{}' is unchanged, skipping", sheet_name, cell_address, write.sync.account_full_name);
+                    continue;
+                }
+                cells.push(CellWrite { sheet_name: sheet_name.clone(), cell_address: cell_address.clone(), value: write.cell_value.clone() });
+                owners.push(i);
+            }
+            for (cell, value) in extra_cells(config, write.sync, write.balance, now) {
+                cells.push(CellWrite { sheet_name: write.sync.sheet_name.clone(), cell_address: cell.to_string(), value });
+                owners.push(i);
+            }
+        }
+        if cells.is_empty() {
+            continue;
+        }
+        match gs_client.write_cells(&cells).await {
+            Ok(results) => {
+                for (result, &i) in results.iter().zip(&owners) {
+                    if !result.success && errors[i].is_none() {
+                        errors[i] = Some(result.describe());
+                    }
+                }
+                info!("[SHEETS] Batched {} cell(s) for {} block(s) to {}", cells.len(), blocks.len(), spreadsheet_id);
+            },
+            Err(e) => {
+                for &i in &blocks {
+                    errors[i] = Some(format!("{:#}", e));
+                }
+            },
+        }
+    }
+    let mut failed = 0;
+    for (write, error) in writes.iter().zip(errors) {
+        match error {
+            None => {
+                report.record(write.index, write.sync, BlockStatus::Written, Some(write.balance), None);
+                let update = BalanceUpdate { account_full_name: &write.sync.account_full_name, account_number: None, balance: write.balance, timestamp: &timestamp };
+                send_to_sinks(config, webhook_sinks, &update).await;
+            },
+            Some(error) => {
+                eprintln!("[SHEETS] Failed to write '{}': {}", write.sync.account_full_name, error);
+                report.record(write.index, write.sync, BlockStatus::Failed, None, Some(error));
+                failed += 1;
+            },
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("{} of {} batched block(s) failed to write", failed, writes.len());
     }
-    processor.end_session(&ticket)?;
-    processor.close_connection()?;
-    unsafe { winapi::um::combaseapi::CoUninitialize(); }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use figment::{Figment, providers::{Format, Toml}};
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn config() -> Config {
+        let toml = r#"
+            [quickbooks]
+            company_file = "AUTO"
+
+            [google_sheets]
+            webapp_url = "https://script.google.com/macros/s/abc123/exec"
+            api_key = "key"
+        "#;
+        Figment::from(Toml::string(toml)).extract().unwrap()
+    }
+
+    fn block(account_full_name: &str, cell_address: &str) -> AccountSyncConfig {
+        AccountSyncConfig {
+            spreadsheet_id: "1AbC".to_string(),
+            account_full_name: account_full_name.to_string(),
+            sheet_name: "Balances".to_string(),
+            cell_address: config::CellTargets::One(cell_address.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn account(account_full_name: &str, balance: f64) -> AccountData {
+        AccountData {
+            account_full_name: account_full_name.to_string(),
+            number: String::new(),
+            account_type: AccountType::Bank,
+            description: None,
+            balance,
+            total_balance: None,
+            raw_balance: None,
+            raw_total_balance: None,
+        }
+    }
+
+    // A web app that accepts every write, and a config pointed at it that writes without reading first
+    async fn sheets_server() -> (crate::test_server::TestServer, Config) {
+        let server = crate::test_server::TestServer::start(vec![(200, r#"{"success": true}"#.to_string())]).await;
+        let mut config = config();
+        config.google_sheets.webapp_url = format!("{}/exec", server.url);
+        config.google_sheets.force_write = Some(true);
+        (server, config)
+    }
+
+    #[tokio::test]
+    async fn only_a_failed_run_appends_an_error_log_row() {
+        let (server, mut config) = sheets_server().await;
+        config.error_log = Some(config::ErrorLogConfig {
+            spreadsheet_id: "1Log".to_string(),
+            sheet_name: "Errors".to_string(),
+            anchor: "A1".to_string(),
+            max_message_chars: None,
+        });
+        let mut report = SyncReport::default();
+        report.record(0, &block("Checking", "B2"), report::BlockStatus::Written, Some(100.0), None);
+        write_error_log(&config, &report, &Ok(RunOutcome::Live)).await;
+        assert!(server.requests().is_empty());
+
+        report.record(1, &block("Savings", "B3"), report::BlockStatus::Failed, None, Some("HTTP 500".to_string()));
+        write_error_log(&config, &report, &Err(anyhow::anyhow!("1 block(s) failed to write"))).await;
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        let json = requests[0].json();
+        assert_eq!(json["action"], "appendRow");
+        assert_eq!(json["spreadsheetId"], "1Log");
+        assert_eq!(json["header"], serde_json::json!(["Timestamp", "Run ID", "Failed blocks", "Error"]));
+        let values = json["values"].as_array().unwrap();
+        assert_eq!(values[1], report::run_id());
+        assert_eq!(values[2], "Savings ('Balances'!B3)");
+        assert_eq!(values[3], "1 block(s) failed to write");
+    }
+
+    #[tokio::test]
+    async fn a_cell_outside_its_sheet_is_flagged_with_the_sheet_size() {
+        use crate::test_server::TestServer;
+        let server = TestServer::start(vec![
+            (200, r#"{"success": true, "rows": 1000, "columns": 26}"#.to_string()),
+        ]).await;
+        let mut config = config();
+        config.google_sheets.webapp_url = format!("{}/exec", server.url);
+        config.sync_blocks = vec![block("Checking", "B2"), block("Savings", "Z999999")];
+        let err = verify_cell_bounds(&config).await.unwrap_err();
+        assert_eq!(err.to_string(), "Cell(s) outside their sheet: 'Balances'!Z999999 (sheet is 1000 rows x 26 columns)");
+        // Both cells share one sheet, so its size was fetched once
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].json()["action"], "getDimensions");
+        assert_eq!(requests[0].json()["sheetName"], "Balances");
+
+        config.sync_blocks = vec![block("Checking", "B2"), block("Savings", "Z1000")];
+        verify_cell_bounds(&config).await.unwrap();
+    }
+
+    #[test]
+    fn a_block_webapp_overrides_the_global_one() {
+        let config = config();
+        let global = sync_client(&config, &block("Checking", "B2"), None, "B2".to_string());
+        assert_eq!((global.webapp_url.as_str(), global.api_key.as_str()), ("https://script.google.com/macros/s/abc123/exec", "key"));
+
+        let mut team = block("Payroll", "B3");
+        team.webapp_url = Some("https://script.google.com/macros/s/team/exec".to_string());
+        let overridden = sync_client(&config, &team, None, "B3".to_string());
+        // Only the URL is overridden; the key still comes from [google_sheets]
+        assert_eq!((overridden.webapp_url.as_str(), overridden.api_key.as_str()), ("https://script.google.com/macros/s/team/exec", "key"));
+        team.api_key = Some("team-key".to_string());
+        assert_eq!(sync_client(&config, &team, None, "B3".to_string()).api_key, "team-key");
+    }
+
+    #[test]
+    fn the_company_file_reaches_the_report_and_summary() {
+        let mock = MockQuickBooks::new([("Checking".to_string(), 12.5)].into());
+        let ticket = mock.begin_session("", FileMode::DoNotCare).unwrap();
+        let company_file = session::run_in_session(session::Session::new(mock, ticket, false), |session| Ok(connected_company_file(session))).unwrap();
+        assert_eq!(company_file.as_deref(), Some("Mock Company.QBW"));
+
+        let report = SyncReport { company_file, ..Default::default() };
+        assert_eq!(serde_json::to_value(&report).unwrap()["company_file"], "Mock Company.QBW");
+        assert_eq!(run_summary(&report, &Ok(RunOutcome::Live)).company_file, "Mock Company.QBW");
+    }
+
+    #[test]
+    fn dry_run_flag_reaches_every_sheets_client() {
+        let mut config = config();
+        apply_flags(&mut config, &args(&["qb_sync", "--mock", "balances.json", "--dry-run"]));
+        assert!(config.dry_run);
+        assert!(sheets_client(&config, "1AbC".to_string(), None, "B2".to_string()).dry_run);
+
+        apply_flags(&mut config, &args(&["qb_sync", "--mock", "balances.json"]));
+        assert!(!config.dry_run);
+        assert!(!sheets_client(&config, "1AbC".to_string(), None, "B2".to_string()).dry_run);
+    }
+
+    #[test]
+    fn a_busy_quickbooks_skips_the_run_only_when_configured() {
+        let busy = anyhow::Error::new(QbError::Busy { hresult: 0, detail: "A backup is in progress".to_string() })
+            .context("BeginSession failed");
+        let mut config = config();
+        assert_eq!(skip_reason(&config, &busy), None);
+        config.quickbooks.on_busy = Some(BusyPolicy::Skip);
+        assert_eq!(skip_reason(&config, &busy), Some(("skipped_busy", "on_busy = \"skip\"")));
+        let in_use = anyhow::Error::new(QbError::FileInUse { hresult: 0 });
+        assert_eq!(skip_reason(&config, &in_use), None);
+    }
+
+    #[test]
+    fn only_a_daemon_skips_a_run_with_no_company_file() {
+        let no_file = anyhow::Error::new(QbError::NoCompanyFile { hresult: None });
+        let mut config = config();
+        apply_flags(&mut config, &args(&["qb_sync"]));
+        assert_eq!(skip_reason(&config, &no_file), None);
+        apply_flags(&mut config, &args(&["qb_sync", "--daemon"]));
+        assert_eq!(skip_reason(&config, &no_file).map(|(source, _)| source), Some("skipped_no_company_file"));
+        let mismatch = anyhow::Error::new(QbError::CompanyMismatch { expected: "a.qbw".to_string(), found: None });
+        assert_eq!(skip_reason(&config, &mismatch), None);
+    }
+
+    #[test]
+    fn listing_hides_only_genuine_zero_balances() {
+        let account = |name: &str, balance: f64, raw_balance: Option<&str>| AccountData {
+            account_full_name: name.to_string(),
+            number: String::new(),
+            account_type: AccountType::Bank,
+            description: None,
+            balance,
+            total_balance: None,
+            raw_balance: raw_balance.map(str::to_string),
+            raw_total_balance: None,
+        };
+        let accounts = [
+            account("Checking", 12.5, Some("12.50")),
+            account("Petty Cash", 0.0, Some("0.00")),
+            account("Savings", 0.0, None),
+        ];
+        let names = |listed: Vec<&AccountData>| listed.iter().map(|a| a.account_full_name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(listed_accounts(&accounts, true)), ["Checking", "Petty Cash", "Savings"]);
+        assert_eq!(names(listed_accounts(&accounts, false)), ["Checking", "Savings"]);
+    }
+
+    #[test]
+    fn catch_up_runs_only_for_a_missed_slot() {
+        let at = |time: &str| DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc);
+        let path = std::env::temp_dir().join(format!("qb_sync_catch_up_{}.json", std::process::id()));
+        let mut config = config();
+        config.quickbooks.cache_file = Some(path.to_string_lossy().into_owned());
+        config.schedule = Some(config::ScheduleConfig {
+            interval_secs: Some(3600),
+            cron_expression: None,
+            catch_up: Some(true),
+            catch_up_window_secs: Some(6 * 3600),
+        });
+        // No cache yet: nothing to have missed
+        let _ = std::fs::remove_file(&path);
+        assert!(!should_catch_up(&config, at("2024-01-15T12:00:00Z")).unwrap());
+
+        BalanceCache::new(at("2024-01-15T10:15:00Z")).save(&path).unwrap();
+        // The 11:15 slot hasn't come yet
+        assert!(!should_catch_up(&config, at("2024-01-15T11:00:00Z")).unwrap());
+        // It passed while the service was down
+        assert!(should_catch_up(&config, at("2024-01-15T12:00:00Z")).unwrap());
+        // Missed too long ago: the schedule takes over
+        assert!(!should_catch_up(&config, at("2024-01-16T12:00:00Z")).unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn smoothed_cells_advance_their_average_each_run() {
+        let path = std::env::temp_dir().join(format!("qb_sync_smoothing_{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut config = config();
+        let mut smoothed = block("Checking", "B2");
+        smoothed.smoothed_cell = Some("C2".to_string());
+        smoothed.smoothing_alpha = Some(0.5);
+        let key = smoothed.smoothing_key().unwrap();
+        config.sync_blocks = vec![smoothed];
+        let run = |balance: f64| {
+            let averages = save_balance_cache(&config, &path, &|name: &str| Ok(Some(account(name, balance))));
+            averages[&key]
+        };
+        // Seeded with the first balance, then half the new balance plus half the old average
+        assert_eq!(run(100.0), 100.0);
+        assert_eq!(run(200.0), 150.0);
+        assert_eq!(run(50.0), 100.0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn prune_cache_drops_only_accounts_no_longer_synced() {
+        let path = std::env::temp_dir().join(format!("qb_sync_prune_cache_{}.json", std::process::id()));
+        let mut config = config();
+        config.quickbooks.cache_file = Some(path.to_string_lossy().into_owned());
+        config.sync_blocks = vec![block("Checking", "B2"), block("Savings", "B3")];
+        let mut cache = BalanceCache::new(Utc::now());
+        for (name, balance) in [("Checking", 100.0), ("Savings", 200.0), ("Old Loan", -50.0)] {
+            cache.balances.insert(name.to_string(), balance);
+            cache.account_types.insert(name.to_string(), "Bank".to_string());
+        }
+        cache.total_balances.insert("Old Loan".to_string(), -50.0);
+        cache.save(&path).unwrap();
+
+        prune_cache(&config).unwrap();
+        let pruned = BalanceCache::load(&path).unwrap().unwrap();
+        assert_eq!(pruned.balances.keys().collect::<Vec<_>>(), ["Checking", "Savings"]);
+        assert_eq!(pruned.account_types.keys().collect::<Vec<_>>(), ["Checking", "Savings"]);
+        assert!(pruned.total_balances.is_empty());
+        assert_eq!(pruned.balances["Savings"], 200.0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn check_auth_accepts_only_an_authorized_key() {
+        use crate::test_server::TestServer;
+        let server = TestServer::start(vec![
+            (200, r#"{"authorized": true}"#.to_string()),
+            (200, r#"{"authorized": false}"#.to_string()),
+            (200, r#"{"success": true}"#.to_string()),
+        ]).await;
+        let mut config = config();
+        config.google_sheets.webapp_url = format!("{}/exec", server.url);
+
+        verify_api_key(&config, true).await.unwrap();
+        let err = verify_api_key(&config, true).await.unwrap_err();
+        assert!(err.to_string().contains("rejected the API key"), "{:#}", err);
+        // A script without the auth action fails only an explicit --check-auth
+        assert!(verify_api_key(&config, true).await.unwrap_err().to_string().contains("does not support the auth check"));
+        verify_api_key(&config, false).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 4);
+        assert!(requests[0].request_line.starts_with("POST /exec "), "{}", requests[0].request_line);
+        assert_eq!(requests[0].header("content-type"), Some("application/json"));
+        assert_eq!(requests[0].json(), serde_json::json!({"action": "auth", "apiKey": "key"}));
+    }
+
+    #[tokio::test]
+    async fn mirror_cells_each_receive_the_same_balance() {
+        let (server, config) = sheets_server().await;
+        let sync = AccountSyncConfig {
+            cell_address: config::CellTargets::Many(vec!["B2".to_string(), "Summary!C3".to_string(), "'Q1 Detail'!D4".to_string()]),
+            ..block("Checking", "B2")
+        };
+        let write = BlockWrite::plan(0, &sync, &account("Checking", 1234.56));
+        assert_eq!(write_block(&config, &write, &[], Utc::now()).await.unwrap(), 1234.56);
+
+        let sent: Vec<(String, String, serde_json::Value)> = server.requests().iter()
+            .map(|request| request.json())
+            .map(|body| (body["sheetName"].as_str().unwrap().to_string(), body["cellAddress"].as_str().unwrap().to_string(), body["accountValue"].clone()))
+            .collect();
+        assert_eq!(sent, [
+            ("Balances".to_string(), "B2".to_string(), serde_json::json!(1234.56)),
+            ("Summary".to_string(), "C3".to_string(), serde_json::json!(1234.56)),
+            ("Q1 Detail".to_string(), "D4".to_string(), serde_json::json!(1234.56)),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn ordered_blocks_are_written_in_sequence_before_the_rest() {
+        let (server, mut config) = sheets_server().await;
+        config.sync_blocks = vec![
+            AccountSyncConfig { order: Some(2), ..block("Savings", "B3") },
+            block("Payroll", "B4"),
+            AccountSyncConfig { order: Some(1), ..block("Checking", "B2") },
+            AccountSyncConfig { order: Some(2), ..block("Escrow", "B5") },
+        ];
+        let mut report = SyncReport::default();
+        write_balances(&config, |name: &str| Ok(Some(account(name, 1.0))), None, &mut report).await.unwrap();
+
+        let written: Vec<String> = server.requests().iter().map(|request| request.json()["cellAddress"].as_str().unwrap().to_string()).collect();
+        // Equal orders keep config order; the unordered block comes last
+        assert_eq!(written, ["B2", "B3", "B5", "B4"]);
+        assert_eq!(report.blocks.iter().map(|block| block.block_index).collect::<Vec<_>>(), [2, 0, 3, 1]);
+    }
+
+    #[tokio::test]
+    async fn a_mixed_batch_fails_only_the_blocks_with_failed_cells() {
+        let mixed = r#"{"success": false, "results": [
+            {"cell": "B2", "success": true},
+            {"cell": "B3", "success": true},
+            {"cell": "C3", "success": false, "error": "Exception: Range not found"},
+            {"cell": "B4", "success": true}
+        ]}"#;
+        let server = crate::test_server::TestServer::start(vec![(200, mixed.to_string()), (200, r#"{"success": true}"#.to_string())]).await;
+        let mut config = config();
+        config.google_sheets.webapp_url = format!("{}/exec", server.url);
+        config.google_sheets.force_write = Some(true);
+        let savings = AccountSyncConfig { cell_address: config::CellTargets::Many(vec!["B3".to_string(), "C3".to_string()]), ..block("Savings", "B3") };
+        let (checking, payroll) = (block("Checking", "B2"), block("Payroll", "B4"));
+        let writes = [
+            BlockWrite::plan(0, &checking, &account("Checking", 1.0)),
+            BlockWrite::plan(1, &savings, &account("Savings", 2.0)),
+            BlockWrite::plan(2, &payroll, &account("Payroll", 3.0)),
+        ];
+
+        let mut report = SyncReport::default();
+        let err = write_blocks_batched(&config, &writes, &[], Utc::now(), &mut report).await.unwrap_err();
+        assert_eq!(err.to_string(), "1 of 3 batched block(s) failed to write");
+        let outcomes: Vec<(BlockStatus, Option<&str>)> = report.blocks.iter().map(|block| (block.status, block.error.as_deref())).collect();
+        assert_eq!(outcomes, [
+            (BlockStatus::Written, None),
+            (BlockStatus::Failed, Some("C3: Exception: Range not found")),
+            (BlockStatus::Written, None),
+        ]);
+        assert_eq!(server.requests()[0].json()["cells"].as_array().unwrap().len(), 4);
+
+        // A script that answers without results wrote every cell
+        let mut report = SyncReport::default();
+        write_blocks_batched(&config, &writes, &[], Utc::now(), &mut report).await.unwrap();
+        assert!(report.blocks.iter().all(|block| block.status == BlockStatus::Written));
+    }
+
+    #[tokio::test]
+    async fn heartbeat_advances_only_after_a_successful_run() {
+        let path = std::env::temp_dir().join(format!("qb_sync_heartbeat_{}", std::process::id()));
+        let mut config = config();
+        config.monitoring = Some(config::MonitoringConfig {
+            heartbeat_file: Some(path.to_string_lossy().into_owned()),
+            heartbeat_on_cached: None,
+            check_clock_skew: None,
+            max_clock_skew_secs: None,
+            skip_timestamps_on_skew: None,
+        });
+        let at = |text: &str| DateTime::parse_from_rfc3339(text).unwrap().with_timezone(&Utc);
+        let heartbeat = || std::fs::read_to_string(&path).unwrap();
+
+        record_heartbeat(&config, RunOutcome::Live, at("2024-03-01T12:00:00Z"));
+        assert_eq!(heartbeat(), "2024-03-01T12:00:00+00:00\n");
+        record_heartbeat(&config, RunOutcome::Live, at("2024-03-01T13:00:00Z"));
+        assert_eq!(heartbeat(), "2024-03-01T13:00:00+00:00\n");
+        // Cached data counts only when heartbeat_on_cached is set
+        record_heartbeat(&config, RunOutcome::Cached, at("2024-03-01T14:00:00Z"));
+        assert_eq!(heartbeat(), "2024-03-01T13:00:00+00:00\n");
+
+        // A failed run never reaches the heartbeat
+        let missing = std::env::temp_dir().join(format!("qb_sync_no_such_mock_{}.json", std::process::id()));
+        let args = args(&["qb_sync", "--mock", &missing.to_string_lossy()]);
+        assert!(sync_once(&config, &args).await.is_err());
+        assert_eq!(heartbeat(), "2024-03-01T13:00:00+00:00\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn every_write_and_the_manifest_share_the_run_id() {
+        let (server, mut config) = sheets_server().await;
+        config.sync_blocks = vec![block("Checking", "B2"), block("Savings", "B3"), block("Payroll", "B4")];
+        let dir = std::env::temp_dir().join(format!("qb_sync_run_id_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (mock, manifest) = (dir.join("balances.json"), dir.join("manifest.json"));
+        std::fs::write(&mock, r#"{"Checking": 10.0, "Savings": 20.0, "Payroll": 30.0}"#).unwrap();
+        let args = args(&["qb_sync", "--mock", &mock.to_string_lossy(), "--manifest", &manifest.to_string_lossy()]);
+        let result = sync_once(&config, &args).await;
+        let saved = RunManifest::load(&manifest);
+        std::fs::remove_dir_all(&dir).ok();
+        result.unwrap();
+
+        let run_ids: Vec<serde_json::Value> = server.requests().iter().map(|request| request.json()["runId"].clone()).collect();
+        assert_eq!(run_ids.len(), 3);
+        assert!(run_ids.iter().all(|run_id| *run_id == report::run_id()), "{:?}", run_ids);
+        assert_eq!(saved.unwrap().run_id, report::run_id());
+    }
+
+    #[tokio::test]
+    async fn retry_writes_only_the_blocks_the_manifest_lists_as_failed() {
+        let (server, mut config) = sheets_server().await;
+        config.sync_blocks = vec![block("Checking", "B2"), block("Savings", "B3"), block("Payroll", "B4"), block("Visa", "B5")];
+        let mut previous = SyncReport::default();
+        for (i, sync) in config.sync_blocks.iter().enumerate() {
+            let status = if i % 2 == 1 { BlockStatus::Failed } else { BlockStatus::Written };
+            previous.record(i, sync, status, None, None);
+        }
+        let at = DateTime::parse_from_rfc3339("2024-03-01T12:00:00Z").unwrap().with_timezone(&Utc);
+        let manifest = RunManifest {
+            schema_version: report::MANIFEST_SCHEMA_VERSION,
+            run_id: "run-1".to_string(),
+            started_at: at,
+            finished_at: at,
+            duration_ms: 0,
+            config_hash: "hash".to_string(),
+            qbxml_version: "13.0".to_string(),
+            succeeded: false,
+            error: Some("2 of 4 block(s) failed".to_string()),
+            failed_blocks: previous.failed_blocks(),
+            report: previous,
+        };
+        let path = std::env::temp_dir().join(format!("qb_sync_retry_manifest_{}.json", std::process::id()));
+        manifest.save(&path).unwrap();
+        let manifest = RunManifest::load(&path);
+        std::fs::remove_file(&path).ok();
+        let manifest = manifest.unwrap();
+        let failed: BTreeSet<usize> = manifest.failed_blocks.iter().copied().collect();
+
+        let mut report = SyncReport::default();
+        write_balances(&config, |name: &str| Ok(Some(account(name, 1.0))), Some(&failed), &mut report).await.unwrap();
+        let written: Vec<String> = server.requests().iter().map(|request| request.json()["cellAddress"].as_str().unwrap().to_string()).collect();
+        assert_eq!(written, ["B3", "B5"]);
+        assert_eq!(report.blocks.iter().map(|block| block.block_index).collect::<Vec<_>>(), [1, 3]);
+    }
+
+    #[tokio::test]
+    async fn attribution_text_is_written_only_when_configured() {
+        let (server, config) = sheets_server().await;
+        let checking = account("Checking", 12.5);
+        let plain = block("Checking", "B2");
+        let attributed = AccountSyncConfig { attribution_cell: Some("C2".to_string()), ..block("Checking", "B2") };
+        let custom = AccountSyncConfig { attribution_text: Some("From QB nightly".to_string()), ..attributed.clone() };
+        for sync in [&plain, &attributed, &custom] {
+            write_block(&config, &BlockWrite::plan(0, sync, &checking), &[], Utc::now()).await.unwrap();
+        }
+
+        let sent: Vec<(String, serde_json::Value)> = server.requests().iter()
+            .map(|request| request.json())
+            .map(|body| (body["cellAddress"].as_str().unwrap().to_string(), body["accountValue"].clone()))
+            .collect();
+        assert_eq!(sent, [
+            ("B2".to_string(), serde_json::json!(12.5)),
+            ("B2".to_string(), serde_json::json!(12.5)),
+            ("C2".to_string(), serde_json::json!("Auto-synced from QuickBooks")),
+            ("B2".to_string(), serde_json::json!(12.5)),
+            ("C2".to_string(), serde_json::json!("From QB nightly")),
+        ]);
+    }
+}
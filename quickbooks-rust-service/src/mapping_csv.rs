@@ -0,0 +1,102 @@
+// CSV account-to-cell mappings
+// Loads sync blocks from a spreadsheet-exported CSV so large mapping sets needn't live in TOML
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::a1::CellRef;
+use crate::config::{AccountSyncConfig, CellTargets};
+
+#[derive(Debug, Deserialize)]
+struct CsvSyncRow {
+    spreadsheet_id: String,
+    account_full_name: String,
+    sheet_name: String,
+    cell_address: String,
+}
+
+pub fn load_sync_blocks_csv(path: &Path) -> Result<Vec<AccountSyncConfig>> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open sync_blocks_csv {}", path.display()))?;
+    parse_sync_blocks_csv(file).with_context(|| format!("Invalid sync_blocks_csv {}", path.display()))
+}
+
+/// Parse mapping rows; quoted fields may contain commas (e.g. "Loans, Long-term").
+/// Errors name the 1-based line of the offending row.
+pub fn parse_sync_blocks_csv<R: std::io::Read>(reader: R) -> Result<Vec<AccountSyncConfig>> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(reader);
+    let headers = reader.headers().context("Failed to read CSV header row")?.clone();
+    let mut blocks = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| match e.position() {
+            Some(pos) => anyhow!("line {}: {}", pos.line(), e),
+            None => anyhow!("{}", e),
+        })?;
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let row: CsvSyncRow = record
+            .deserialize(Some(&headers))
+            .map_err(|e| anyhow!("line {}: {}", line, e))?;
+        for (field, value) in [
+            ("spreadsheet_id", &row.spreadsheet_id),
+            ("account_full_name", &row.account_full_name),
+            ("sheet_name", &row.sheet_name),
+            ("cell_address", &row.cell_address),
+        ] {
+            if value.is_empty() {
+                anyhow::bail!("line {}: {} is empty", line, field);
+            }
+        }
+        CellRef::parse(&row.cell_address).with_context(|| format!("line {}", line))?;
+        blocks.push(AccountSyncConfig {
+            spreadsheet_id: row.spreadsheet_id,
+            account_full_name: row.account_full_name,
+            sheet_name: row.sheet_name,
+            cell_address: CellTargets::One(row.cell_address),
+            ..Default::default()
+        });
+    }
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rows_with_quoted_commas() {
+        let csv = "spreadsheet_id,account_full_name,sheet_name,cell_address
+abc123, Checking ,Balances,B2
+abc123,\"Loans, Long-term\",\"Summary, 2024\",C10
+";
+        let blocks = parse_sync_blocks_csv(csv.as_bytes()).unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].account_full_name, "Checking");
+        assert_eq!(blocks[1].account_full_name, "Loans, Long-term");
+        assert_eq!(blocks[1].sheet_name, "Summary, 2024");
+        assert!(matches!(&blocks[1].cell_address, CellTargets::One(cell) if cell == "C10"));
+    }
+
+    #[test]
+    fn missing_column_is_an_error() {
+        let csv = "spreadsheet_id,account_full_name,sheet_name\nabc123,Checking,Balances\n";
+        let err = parse_sync_blocks_csv(csv.as_bytes()).unwrap_err();
+        assert!(format!("{:#}", err).contains("cell_address"), "{:#}", err);
+    }
+
+    #[test]
+    fn bad_rows_name_their_line() {
+        let header = "spreadsheet_id,account_full_name,sheet_name,cell_address\n";
+        let bad_cell = format!("{}abc123,Checking,Balances,B2\nabc123,Savings,Balances,2B\n", header);
+        let err = parse_sync_blocks_csv(bad_cell.as_bytes()).unwrap_err();
+        assert!(format!("{:#}", err).starts_with("line 3"), "{:#}", err);
+
+        let empty = format!("{}abc123,,Balances,B2\n", header);
+        let err = parse_sync_blocks_csv(empty.as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "line 2: account_full_name is empty");
+
+        let short_row = format!("{}abc123,Checking,Balances\n", header);
+        let err = parse_sync_blocks_csv(short_row.as_bytes()).unwrap_err();
+        assert!(err.to_string().starts_with("line 2"), "{:#}", err);
+    }
+}
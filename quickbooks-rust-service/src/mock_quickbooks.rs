@@ -0,0 +1,139 @@
+// Mock QuickBooks backend
+// Serves canned balances through QuickBooksProcessor so the sync and Sheets paths run without
+// QuickBooks, including on platforms where QuickBooks can't run
+
+use anyhow::{Context, Result};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::file_mode::FileMode;
+use crate::qbxml_safe::qbxml_request_processor::{AccountParser, MatchCriterion, NameFilter};
+use crate::quickbooks::QuickBooksProcessor;
+
+// Every canned account is reported with this AccountType
+const MOCK_ACCOUNT_TYPE: &str = "Bank";
+// What the mock reports for HostQuery's ProductName and the open company file
+const MOCK_PRODUCT_NAME: &str = "QuickBooks Mock";
+const MOCK_COMPANY_FILE: &str = "Mock Company.QBW";
+const MOCK_ITERATOR_ID: &str = "{mock-iterator}";
+
+/// Balances by account FullName, answered as an AccountQueryRs
+#[derive(Debug, Clone, Default)]
+pub struct MockQuickBooks {
+    pub balances: BTreeMap<String, f64>,
+    parser: AccountParser,
+    // Accounts already returned by the open iterator
+    iterator_position: Cell<usize>,
+}
+
+impl MockQuickBooks {
+    pub fn new(balances: BTreeMap<String, f64>) -> Self {
+        Self { balances, parser: AccountParser::default(), iterator_position: Cell::new(0) }
+    }
+
+    /// Read balances from a JSON object of FullName to balance, e.g. {"Checking": 1234.56}
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read mock balances {}", path.display()))?;
+        let balances = serde_json::from_str(&text)
+            .with_context(|| format!("{} must be a JSON object of account name to balance", path.display()))?;
+        Ok(Self::new(balances))
+    }
+
+    // The AccountQueryRs QuickBooks would send for `request`: AccountType and NameFilter filters
+    // and iterators are honoured; every canned account is active
+    fn account_query_rs(&self, request: roxmltree::Node) -> String {
+        let text = |name: &str| request.children()
+            .find(|node| node.tag_name().name() == name)
+            .and_then(|node| node.text())
+            .map(str::trim);
+        let name_filter = request.children().find(|node| node.tag_name().name() == "NameFilter").map(|filter| {
+            let child = |name: &str| filter.children().find(|node| node.tag_name().name() == name).and_then(|node| node.text()).unwrap_or_default();
+            let match_criterion = match child("MatchCriterion") {
+                "StartsWith" => MatchCriterion::StartsWith,
+                "EndsWith" => MatchCriterion::EndsWith,
+                _ => MatchCriterion::Contains,
+            };
+            NameFilter { match_criterion, name: child("Name").to_string() }
+        });
+        let matching: Vec<(&String, &f64)> = self.balances.iter()
+            .filter(|_| text("AccountType").is_none_or(|account_type| account_type == MOCK_ACCOUNT_TYPE))
+            .filter(|(name, _)| name_filter.as_ref().is_none_or(|filter| filter.matches(name)))
+            .collect();
+        let (page, iterator) = match request.attribute("iterator") {
+            Some(iterator) => {
+                let start = if iterator == "Start" { 0 } else { self.iterator_position.get() };
+                let max = text("MaxReturned").and_then(|max| max.parse().ok()).unwrap_or(usize::MAX);
+                let page: Vec<_> = matching.iter().copied().skip(start).take(max).collect();
+                self.iterator_position.set(start + page.len());
+                let remaining = matching.len().saturating_sub(start + page.len());
+                (page, format!(" iteratorRemainingCount=\"{}\" iteratorID=\"{}\"", remaining, MOCK_ITERATOR_ID))
+            },
+            None => (matching, String::new()),
+        };
+        if page.is_empty() {
+            return format!("    <AccountQueryRs statusCode=\"1\" statusSeverity=\"Info\" statusMessage=\"A query request did not find a matching object in QuickBooks\"{} />\n", iterator);
+        }
+        // Balances are written exactly as held, as QuickBooks sends its own unrounded amounts
+        let accounts: String = page.iter()
+            .map(|(name, balance)| format!(
+                "      <AccountRet>\n        <FullName>{}</FullName>\n        <AccountType>{}</AccountType>\n        <Balance>{}</Balance>\n      </AccountRet>\n",
+                xml_escape(name), MOCK_ACCOUNT_TYPE, balance
+            ))
+            .collect();
+        format!("    <AccountQueryRs statusCode=\"0\" statusSeverity=\"Info\" statusMessage=\"Status OK\"{}>\n{}    </AccountQueryRs>\n", iterator, accounts)
+    }
+}
+
+// Wrap response elements in the QBXML envelope
+fn response_document(body: &str) -> String {
+    format!("<?xml version=\"1.0\" ?>\n<QBXML>\n  <QBXMLMsgsRs>\n{}  </QBXMLMsgsRs>\n</QBXML>\n", body)
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+impl QuickBooksProcessor for MockQuickBooks {
+    fn begin_session(&self, _company_file: &str, _file_mode: FileMode) -> Result<String> {
+        Ok("mock-ticket".to_string())
+    }
+
+    // AccountQuery and HostQuery are answered; any other query finds nothing, as it would in an
+    // empty company file
+    fn process_request(&self, _ticket: &str, request: &str) -> Result<String> {
+        let doc = roxmltree::Document::parse(request).context("Mock QuickBooks received malformed QBXML")?;
+        let body: String = doc.descendants()
+            .filter(|node| node.is_element() && node.parent_element().is_some_and(|parent| parent.tag_name().name() == "QBXMLMsgsRq"))
+            .map(|rq| match rq.tag_name().name() {
+                "AccountQueryRq" => self.account_query_rs(rq),
+                "HostQueryRq" => format!(
+                    "    <HostQueryRs statusCode=\"0\" statusSeverity=\"Info\" statusMessage=\"Status OK\">\n      <HostRet>\n        <ProductName>{}</ProductName>\n      </HostRet>\n    </HostQueryRs>\n",
+                    MOCK_PRODUCT_NAME
+                ),
+                name => format!(
+                    "    <{}Rs statusCode=\"1\" statusSeverity=\"Info\" statusMessage=\"A query request did not find a matching object in QuickBooks\" />\n",
+                    name.strip_suffix("Rq").unwrap_or(name)
+                ),
+            })
+            .collect();
+        Ok(response_document(&body))
+    }
+
+    fn end_session(&self, _ticket: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn close_connection(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn current_company_file_name(&self) -> Result<String> {
+        Ok(MOCK_COMPANY_FILE.to_string())
+    }
+
+    fn parser(&self) -> &AccountParser {
+        &self.parser
+    }
+}
@@ -0,0 +1,122 @@
+// Account name matching
+// Resolves a configured account name to the QuickBooks full name it refers to
+
+use crate::config::NameNormalization;
+
+impl NameNormalization {
+    pub fn normalize(self, name: &str) -> String {
+        let collapsed = match self {
+            NameNormalization::CollapseSpaces | NameNormalization::Both => name.split_whitespace().collect::<Vec<_>>().join(" "),
+            _ => name.trim().to_string(),
+        };
+        match self {
+            NameNormalization::CaseInsensitive | NameNormalization::Both => collapsed.to_lowercase(),
+            _ => collapsed,
+        }
+    }
+
+    /// The QuickBooks name matching `wanted`: an exact match if there is one, otherwise the
+    /// first name that is equal after normalization
+    pub fn find_match<'a>(self, qb_names: &'a [String], wanted: &str) -> Option<&'a str> {
+        let wanted = wanted.trim();
+        if let Some(exact) = qb_names.iter().find(|n| n.as_str() == wanted) {
+            return Some(exact);
+        }
+        if self == NameNormalization::None {
+            return None;
+        }
+        let key = self.normalize(wanted);
+        let found = qb_names.iter().find(|n| self.normalize(n) == key)?;
+        log::info!(
+            "[QBXML] Configured account '{}' matched QuickBooks account '{}' only after {:?} normalization; consider fixing the name",
+            wanted, found, self
+        );
+        Some(found)
+    }
+
+    /// Every QuickBooks name `wanted` could refer to: the exact match alone if there is one,
+    /// otherwise all names equal after normalization (more than one means the name is ambiguous)
+    pub fn find_matches<'a>(self, qb_names: &'a [String], wanted: &str) -> Vec<&'a str> {
+        let wanted = wanted.trim();
+        if let Some(exact) = qb_names.iter().find(|n| n.as_str() == wanted) {
+            return vec![exact];
+        }
+        if self == NameNormalization::None {
+            return Vec::new();
+        }
+        let key = self.normalize(wanted);
+        qb_names.iter().filter(|n| self.normalize(n) == key).map(String::as_str).collect()
+    }
+}
+
+/// Up to `limit` QuickBooks names closest to `wanted`, for "did you mean" hints.
+/// Compares case-insensitively by edit distance; names sharing the last segment rank first.
+pub fn suggest<'a>(qb_names: &'a [String], wanted: &str, limit: usize) -> Vec<&'a str> {
+    let wanted = NameNormalization::Both.normalize(wanted);
+    let leaf = wanted.rsplit(':').next().unwrap_or(&wanted).to_string();
+    let mut scored: Vec<(bool, usize, &str)> = qb_names
+        .iter()
+        .map(|name| {
+            let candidate = NameNormalization::Both.normalize(name);
+            let same_leaf = candidate.rsplit(':').next() == Some(leaf.as_str());
+            (!same_leaf, edit_distance(&wanted, &candidate), name.as_str())
+        })
+        .filter(|(different_leaf, distance, _)| !*different_leaf || *distance <= wanted.chars().count() / 3 + 1)
+        .collect();
+    scored.sort();
+    scored.into_iter().take(limit).map(|(_, _, name)| name).collect()
+}
+
+// Levenshtein distance over chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != *cb);
+            row[j + 1] = substitution.min(prev[j + 1] + 1).min(row[j] + 1);
+        }
+        prev = row;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> Vec<String> {
+        vec!["Checking".to_string(), "Loans:Long Term".to_string()]
+    }
+
+    #[test]
+    fn none_matches_only_exact_names() {
+        let names = names();
+        assert_eq!(NameNormalization::None.find_match(&names, " Checking "), Some("Checking"));
+        assert_eq!(NameNormalization::None.find_match(&names, "checking"), None);
+        assert_eq!(NameNormalization::None.find_match(&names, "Loans:Long  Term"), None);
+    }
+
+    #[test]
+    fn case_insensitive_ignores_case_only() {
+        let names = names();
+        assert_eq!(NameNormalization::CaseInsensitive.find_match(&names, "CHECKING"), Some("Checking"));
+        assert_eq!(NameNormalization::CaseInsensitive.find_match(&names, "Loans:Long  Term"), None);
+    }
+
+    #[test]
+    fn collapse_spaces_ignores_spacing_only() {
+        let names = names();
+        assert_eq!(NameNormalization::CollapseSpaces.find_match(&names, "Loans:Long \t Term"), Some("Loans:Long Term"));
+        assert_eq!(NameNormalization::CollapseSpaces.find_match(&names, "checking"), None);
+    }
+
+    #[test]
+    fn both_ignores_case_and_spacing() {
+        let names = names();
+        assert_eq!(NameNormalization::Both.find_match(&names, "loans:LONG   term"), Some("Loans:Long Term"));
+        assert_eq!(NameNormalization::Both.find_match(&names, "Savings"), None);
+        assert_eq!(NameNormalization::Both.normalize("  Loans:Long   Term "), "loans:long term");
+    }
+}
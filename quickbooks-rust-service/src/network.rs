@@ -0,0 +1,164 @@
+// Outbound network settings
+// Proxy and TLS options applied to the HTTP client that talks to the web app
+
+use anyhow::{Context, Result};
+use std::time::Duration;
+
+use crate::backoff::Backoff;
+use crate::config::{NetworkConfig, TlsVersion};
+
+/// Retries for requests to the web app that fail with 429, a 5xx or a network error.
+/// Other statuses (e.g. 401/403) are final on the first attempt.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HttpRetry {
+    pub max_retries: u32,
+    pub backoff: Backoff,
+}
+
+impl HttpRetry {
+    pub fn is_retriable(status: reqwest::StatusCode) -> bool {
+        status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+    }
+
+    /// Whether to retry `status`; a request that isn't idempotent is only retried after a 429,
+    /// since a 5xx may come back after the script already acted on it
+    pub fn should_retry(&self, status: reqwest::StatusCode, idempotent: bool) -> bool {
+        Self::is_retriable(status) && (idempotent || status == reqwest::StatusCode::TOO_MANY_REQUESTS)
+    }
+
+    /// Delay before retry `retry`: the server's Retry-After when it sent one, else the backoff.
+    /// Either way the wait never exceeds the backoff cap.
+    pub fn delay(&self, retry: u32, headers: Option<&reqwest::header::HeaderMap>) -> Duration {
+        headers
+            .and_then(retry_after)
+            .map(|delay| delay.min(self.backoff.cap))
+            .unwrap_or_else(|| self.backoff.delay(retry))
+    }
+}
+
+// Retry-After as delay-seconds or an HTTP date
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = crate::clock_skew::parse_http_date(value)?;
+    Some((at - chrono::Utc::now()).to_std().unwrap_or(Duration::ZERO))
+}
+
+impl TlsVersion {
+    pub fn as_reqwest(self) -> reqwest::tls::Version {
+        match self {
+            TlsVersion::Tls12 => reqwest::tls::Version::TLS_1_2,
+            TlsVersion::Tls13 => reqwest::tls::Version::TLS_1_3,
+        }
+    }
+}
+
+/// Check proxy_url is an http(s) URL reqwest accepts
+pub fn validate(network: &NetworkConfig) -> Result<()> {
+    if let Some(url) = &network.proxy_url {
+        let parsed = reqwest::Url::parse(url).with_context(|| format!("network.proxy_url '{}' is not a URL", url))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            anyhow::bail!("network.proxy_url '{}' must be an http:// or https:// URL", url);
+        }
+        reqwest::Proxy::all(url.as_str()).with_context(|| format!("network.proxy_url '{}' is not a usable proxy", url))?;
+    }
+    Ok(())
+}
+
+/// A client builder carrying the configured proxy and minimum TLS version. Without a proxy_url,
+/// reqwest keeps honouring the HTTP(S)_PROXY environment variables.
+pub fn client_builder(network: Option<&NetworkConfig>) -> Result<reqwest::ClientBuilder> {
+    let mut builder = reqwest::Client::builder();
+    let Some(network) = network else { return Ok(builder) };
+    if let Some(url) = &network.proxy_url {
+        let proxy = reqwest::Proxy::all(url.as_str())
+            .with_context(|| format!("Invalid proxy URL '{}'", url))?
+            .no_proxy(network.no_proxy.as_deref().and_then(reqwest::NoProxy::from_string));
+        builder = builder.proxy(proxy);
+    }
+    if let Some(version) = network.min_tls_version {
+        builder = builder.min_tls_version(version.as_reqwest());
+    }
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+    use reqwest::StatusCode;
+
+    fn retry() -> HttpRetry {
+        HttpRetry {
+            max_retries: 3,
+            backoff: Backoff { base: Duration::from_secs(1), cap: Duration::from_secs(30), jitter: false },
+        }
+    }
+
+    fn retry_after_header(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn only_throttling_and_server_errors_are_retried() {
+        assert!(HttpRetry::is_retriable(StatusCode::TOO_MANY_REQUESTS));
+        assert!(HttpRetry::is_retriable(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(HttpRetry::is_retriable(StatusCode::SERVICE_UNAVAILABLE));
+        for status in [StatusCode::OK, StatusCode::BAD_REQUEST, StatusCode::UNAUTHORIZED, StatusCode::FORBIDDEN, StatusCode::NOT_FOUND] {
+            assert!(!HttpRetry::is_retriable(status), "{}", status);
+        }
+    }
+
+    #[test]
+    fn non_idempotent_requests_retry_only_throttling() {
+        assert!(retry().should_retry(StatusCode::TOO_MANY_REQUESTS, false));
+        assert!(!retry().should_retry(StatusCode::INTERNAL_SERVER_ERROR, false));
+        assert!(retry().should_retry(StatusCode::INTERNAL_SERVER_ERROR, true));
+        assert!(!retry().should_retry(StatusCode::UNAUTHORIZED, true));
+    }
+
+    #[test]
+    fn retry_after_wins_over_the_backoff_up_to_the_cap() {
+        assert_eq!(retry().delay(2, None), Duration::from_secs(4));
+        assert_eq!(retry().delay(2, Some(&HeaderMap::new())), Duration::from_secs(4));
+        assert_eq!(retry().delay(2, Some(&retry_after_header("7"))), Duration::from_secs(7));
+        assert_eq!(retry().delay(0, Some(&retry_after_header("3600"))), Duration::from_secs(30));
+        // A date in the past means "now"; anything unparseable falls back to the backoff
+        assert_eq!(retry().delay(0, Some(&retry_after_header("Mon, 15 Jan 2024 10:00:00 GMT"))), Duration::ZERO);
+        assert_eq!(retry().delay(1, Some(&retry_after_header("soon"))), Duration::from_secs(2));
+    }
+
+    fn network(proxy_url: &str, no_proxy: Option<&str>) -> NetworkConfig {
+        NetworkConfig { proxy_url: Some(proxy_url.to_string()), no_proxy: no_proxy.map(str::to_string), min_tls_version: None }
+    }
+
+    #[tokio::test]
+    async fn requests_go_through_the_configured_proxy() {
+        let proxy = crate::test_server::TestServer::start(vec![(200, "proxied".to_string())]).await;
+        let client = client_builder(Some(&network(&proxy.url, None))).unwrap().build().unwrap();
+        let res = client.get("http://sheets.example.invalid/exec").send().await.unwrap();
+        assert_eq!(res.text().await.unwrap(), "proxied");
+        let requests = proxy.requests();
+        // A forward proxy gets the absolute URL
+        assert!(requests[0].request_line.starts_with("GET http://sheets.example.invalid/exec "), "{}", requests[0].request_line);
+
+        // Hosts in no_proxy are reached directly
+        let direct = crate::test_server::TestServer::start(vec![(200, "direct".to_string())]).await;
+        let client = client_builder(Some(&network(&proxy.url, Some("127.0.0.1")))).unwrap().build().unwrap();
+        let res = client.get(format!("{}/exec", direct.url)).send().await.unwrap();
+        assert_eq!(res.text().await.unwrap(), "direct");
+        assert_eq!(proxy.requests().len(), 1);
+    }
+
+    #[test]
+    fn proxy_urls_are_validated() {
+        validate(&network("http://proxy.corp:8080", None)).unwrap();
+        validate(&NetworkConfig { proxy_url: None, no_proxy: None, min_tls_version: Some(TlsVersion::Tls13) }).unwrap();
+        assert_eq!(validate(&network("proxy.corp:8080", None)).unwrap_err().to_string(), "network.proxy_url 'proxy.corp:8080' must be an http:// or https:// URL");
+        assert!(validate(&network("not a url", None)).is_err());
+    }
+}
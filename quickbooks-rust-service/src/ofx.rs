@@ -0,0 +1,150 @@
+// OFX balance snapshot
+// A sink that writes every queried balance as an OFX 2 statement's <BALLIST> for tools that ingest OFX
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::path::PathBuf;
+
+use crate::config::OfxExportConfig;
+use crate::sink::{BalanceSink, BalanceUpdate};
+
+// OFX element size limits for BAL/NAME and BAL/DESC
+const NAME_MAX: usize = 32;
+const DESC_MAX: usize = 80;
+
+/// Writes one snapshot file per run; `send_all` with the run's balances is the normal use,
+/// and a lone `send` writes a snapshot of that one account
+pub struct OfxSink {
+    pub path: PathBuf,
+    pub currency: String,
+    pub as_of: DateTime<Utc>,
+    pub run_id: String,
+}
+
+impl OfxSink {
+    pub fn from_config(config: &OfxExportConfig, as_of: DateTime<Utc>, run_id: &str) -> Self {
+        Self {
+            path: PathBuf::from(&config.path),
+            currency: config.currency.clone().unwrap_or_else(|| "USD".to_string()),
+            as_of,
+            run_id: run_id.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl BalanceSink for OfxSink {
+    fn describe(&self) -> String {
+        format!("OFX snapshot {}", self.path.display())
+    }
+
+    async fn send(&self, update: &BalanceUpdate<'_>) -> Result<()> {
+        self.send_all(std::slice::from_ref(update)).await
+    }
+
+    /// Render and write the snapshot, creating the directory if needed
+    async fn send_all(&self, updates: &[BalanceUpdate<'_>]) -> Result<()> {
+        if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create OFX directory {}", dir.display()))?;
+        }
+        std::fs::write(&self.path, render(updates, &self.currency, self.as_of, &self.run_id))
+            .with_context(|| format!("Failed to write OFX snapshot {}", self.path.display()))
+    }
+}
+
+/// A balance-only OFX 2.1.1 document: one bank statement with no transactions whose BALLIST
+/// holds a BAL per account (NAME is the account number, or the name cut to 32 characters;
+/// DESC is the full name). LEDGERBAL is required by the schema and left at zero.
+pub fn render(updates: &[BalanceUpdate<'_>], currency: &str, as_of: DateTime<Utc>, run_id: &str) -> String {
+    let date = as_of.format("%Y%m%d%H%M%S").to_string();
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n");
+    out.push_str("<?OFX OFXHEADER=\"200\" VERSION=\"211\" SECURITY=\"NONE\" OLDFILEUID=\"NONE\" NEWFILEUID=\"NONE\"?>\n");
+    out.push_str("<OFX>\n");
+    out.push_str("  <SIGNONMSGSRSV1>\n    <SONRS>\n");
+    out.push_str("      <STATUS><CODE>0</CODE><SEVERITY>INFO</SEVERITY></STATUS>\n");
+    out.push_str(&format!("      <DTSERVER>{}</DTSERVER>\n      <LANGUAGE>ENG</LANGUAGE>\n", date));
+    out.push_str("    </SONRS>\n  </SIGNONMSGSRSV1>\n");
+    out.push_str("  <BANKMSGSRSV1>\n    <STMTTRNRS>\n");
+    out.push_str(&format!("      <TRNUID>{}</TRNUID>\n", escape(run_id)));
+    out.push_str("      <STATUS><CODE>0</CODE><SEVERITY>INFO</SEVERITY></STATUS>\n");
+    out.push_str("      <STMTRS>\n");
+    out.push_str(&format!("        <CURDEF>{}</CURDEF>\n", escape(currency)));
+    out.push_str("        <BANKACCTFROM><BANKID>QUICKBOOKS</BANKID><ACCTID>SNAPSHOT</ACCTID><ACCTTYPE>CHECKING</ACCTTYPE></BANKACCTFROM>\n");
+    out.push_str(&format!("        <LEDGERBAL><BALAMT>0.00</BALAMT><DTASOF>{}</DTASOF></LEDGERBAL>\n", date));
+    out.push_str("        <BALLIST>\n");
+    for update in updates {
+        let name = update.account_number.unwrap_or(update.account_full_name);
+        out.push_str(&format!(
+            "          <BAL><NAME>{}</NAME><DESC>{}</DESC><BALTYPE>DOLLAR</BALTYPE><VALUE>{:.2}</VALUE><DTASOF>{}</DTASOF></BAL>\n",
+            escape(&truncate(name, NAME_MAX)),
+            escape(&truncate(update.account_full_name, DESC_MAX)),
+            update.balance,
+            date
+        ));
+    }
+    out.push_str("        </BALLIST>\n");
+    out.push_str("      </STMTRS>\n    </STMTTRNRS>\n  </BANKMSGSRSV1>\n</OFX>\n");
+    out
+}
+
+fn truncate(text: &str, max: usize) -> String {
+    text.chars().take(max).collect()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update<'a>(name: &'a str, number: Option<&'a str>, balance: f64) -> BalanceUpdate<'a> {
+        BalanceUpdate { account_full_name: name, account_number: number, balance, timestamp: "2024-01-15T10:30:00+00:00" }
+    }
+
+    // (NAME, DESC, VALUE) of every BAL, read back with an XML parser
+    fn balances(ofx: &str) -> Vec<(String, String, String)> {
+        let doc = roxmltree::Document::parse(ofx).unwrap();
+        let child = |node: roxmltree::Node, tag: &str| {
+            node.children().find(|c| c.has_tag_name(tag)).and_then(|c| c.text()).unwrap_or_default().to_string()
+        };
+        doc.descendants()
+            .filter(|node| node.has_tag_name("BAL"))
+            .map(|bal| (child(bal, "NAME"), child(bal, "DESC"), child(bal, "VALUE")))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn snapshot_reads_back_as_ofx() {
+        let path = std::env::temp_dir().join(format!("qb_sync_ofx_{}", std::process::id())).join("balances.ofx");
+        let sink = OfxSink {
+            path: path.clone(),
+            currency: "CAD".to_string(),
+            as_of: DateTime::parse_from_rfc3339("2024-01-15T10:30:00Z").unwrap().with_timezone(&Utc),
+            run_id: "run-1".to_string(),
+        };
+        let long_name = "Expenses:Professional Fees:Legal and Accounting Services";
+        sink.send_all(&[
+            update("Checking", Some("1000"), 1234.5),
+            update("Smith & Sons <AR>", None, -20.0),
+            update(long_name, None, 0.0),
+        ]).await.unwrap();
+
+        let ofx = std::fs::read_to_string(&path).unwrap();
+        let doc = roxmltree::Document::parse(&ofx).unwrap();
+        let text = |tag: &str| doc.descendants().find(|n| n.has_tag_name(tag)).and_then(|n| n.text()).unwrap_or_default().to_string();
+        assert_eq!(text("CURDEF"), "CAD");
+        assert_eq!(text("TRNUID"), "run-1");
+        assert_eq!(text("DTSERVER"), "20240115103000");
+        assert_eq!(balances(&ofx), [
+            ("1000".to_string(), "Checking".to_string(), "1234.50".to_string()),
+            ("Smith & Sons <AR>".to_string(), "Smith & Sons <AR>".to_string(), "-20.00".to_string()),
+            (long_name.chars().take(NAME_MAX).collect(), long_name.to_string(), "0.00".to_string()),
+        ]);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
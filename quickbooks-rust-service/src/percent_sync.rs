@@ -0,0 +1,83 @@
+// Percent-of-total blocks
+// Writes each account's balance and its share of the group's total
+
+use crate::config::{PercentSyncConfig, ZeroTotalPolicy};
+use crate::google_sheets::CellValue;
+
+/// Each balance as a fraction of their sum (0.25 for 25%), or None for all when the sum is zero.
+/// Sheets shows fractions as percentages once the cells are formatted as such.
+pub fn shares(balances: &[f64]) -> Option<Vec<f64>> {
+    let total: f64 = balances.iter().sum();
+    // A group whose balances cancel out sums to a float that may be only nearly zero
+    if total.abs() < 1e-9 {
+        return None;
+    }
+    Some(balances.iter().map(|balance| balance / total).collect())
+}
+
+impl PercentSyncConfig {
+    /// Balance and percent cells for each member, given their balances in member order
+    pub fn cells(&self, balances: &[f64]) -> Vec<(&str, CellValue)> {
+        let shares = shares(balances);
+        let mut cells = Vec::with_capacity(self.accounts.len() * 2);
+        for (i, (member, balance)) in self.accounts.iter().zip(balances).enumerate() {
+            cells.push((member.balance_cell.as_str(), CellValue::Number(*balance)));
+            let percent = match (&shares, self.zero_total.unwrap_or_default()) {
+                (Some(shares), _) => CellValue::Number(shares[i]),
+                (None, ZeroTotalPolicy::Zero) => CellValue::Number(0.0),
+                (None, ZeroTotalPolicy::Blank) => CellValue::Text(String::new()),
+            };
+            cells.push((member.percent_cell.as_str(), percent));
+        }
+        cells
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PercentMember;
+
+    fn group(zero_total: Option<ZeroTotalPolicy>) -> PercentSyncConfig {
+        let member = |account: &str, row: u32| PercentMember {
+            account: account.to_string(),
+            balance_cell: format!("B{}", row),
+            percent_cell: format!("C{}", row),
+        };
+        PercentSyncConfig {
+            spreadsheet_id: "1AbC".to_string(),
+            sheet_name: "Composition".to_string(),
+            accounts: vec![member("Checking", 2), member("Savings", 3)],
+            sign_convention: None,
+            zero_total,
+        }
+    }
+
+    #[test]
+    fn shares_are_fractions_of_the_total() {
+        assert_eq!(shares(&[25.0, 75.0]).unwrap(), [0.25, 0.75]);
+        // A negative member (an overdrawn account) still shares the net total
+        assert_eq!(shares(&[150.0, -50.0]).unwrap(), [1.5, -0.5]);
+        let group = group(None);
+        let cells = group.cells(&[300.0, 100.0]);
+        assert_eq!(cells, [
+            ("B2", CellValue::Number(300.0)),
+            ("C2", CellValue::Number(0.75)),
+            ("B3", CellValue::Number(100.0)),
+            ("C3", CellValue::Number(0.25)),
+        ]);
+    }
+
+    #[test]
+    fn a_zero_total_never_divides() {
+        assert_eq!(shares(&[0.0, 0.0]), None);
+        assert_eq!(shares(&[0.1 + 0.2, -0.3]), None);
+        let (blank_group, zero_group) = (group(None), group(Some(ZeroTotalPolicy::Zero)));
+        let blank = blank_group.cells(&[10.0, -10.0]);
+        assert_eq!(blank[1], ("C2", CellValue::Text(String::new())));
+        assert_eq!(blank[2], ("B3", CellValue::Number(-10.0)));
+        let zero = zero_group.cells(&[10.0, -10.0]);
+        assert_eq!(zero[1], ("C2", CellValue::Number(0.0)));
+        assert_eq!(zero[3], ("C3", CellValue::Number(0.0)));
+    }
+}
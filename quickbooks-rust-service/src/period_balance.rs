@@ -0,0 +1,18 @@
+// Period balances
+// Writes an account's balance as of several period-ends across a row, one column per period
+
+use anyhow::Result;
+
+use crate::a1::CellRef;
+use crate::config::PeriodBalanceConfig;
+
+impl PeriodBalanceConfig {
+    /// The cell for each period: the first at `start_cell`, each next one `column_stride` columns right
+    pub fn cells(&self) -> Result<Vec<String>> {
+        let start = CellRef::parse(&self.start_cell)?;
+        let stride = self.column_stride.unwrap_or(1);
+        Ok((0..self.periods.len() as u32)
+            .map(|i| CellRef { col: start.col + i * stride, row: start.row }.to_string())
+            .collect())
+    }
+}
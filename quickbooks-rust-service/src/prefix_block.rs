@@ -0,0 +1,105 @@
+// Prefix blocks
+// Writes every account whose full name starts with a prefix as name/balance rows
+
+use anyhow::Result;
+
+use crate::config::PrefixBlockConfig;
+use crate::google_sheets::CellValue;
+use crate::row_block::RowBlock;
+use crate::AccountData;
+
+impl PrefixBlockConfig {
+    /// Matching accounts (less zero balances when excluded) sorted by full name, one name/balance row each from `start_cell` down
+    pub fn rows(&self, accounts: &[AccountData]) -> Result<RowBlock> {
+        let mut matches: Vec<&AccountData> = accounts
+            .iter()
+            .filter(|a| a.account_full_name.starts_with(&self.full_name_prefix))
+            .filter(|a| self.include_zero_balance.unwrap_or(true) || !a.has_zero_balance())
+            .collect();
+        matches.sort_by(|a, b| a.account_full_name.cmp(&b.account_full_name));
+        let sign = self.sign_convention.unwrap_or_default();
+        let rows = matches
+            .into_iter()
+            .map(|a| vec![
+                CellValue::Text(a.account_full_name.clone()),
+                CellValue::Number(sign.apply(a.balance, &a.account_type)),
+            ])
+            .collect();
+        RowBlock::new(&self.start_cell, rows, self.clear_range.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account_type::AccountType;
+    use crate::a1::CellRange;
+
+    fn account(name: &str, balance: f64, raw_balance: Option<&str>) -> AccountData {
+        AccountData {
+            account_full_name: name.to_string(),
+            number: String::new(),
+            account_type: AccountType::Expense,
+            description: None,
+            balance,
+            total_balance: None,
+            raw_balance: raw_balance.map(str::to_string),
+            raw_total_balance: None,
+        }
+    }
+
+    fn block(include_zero_balance: Option<bool>) -> PrefixBlockConfig {
+        PrefixBlockConfig {
+            spreadsheet_id: "sheet".to_string(),
+            sheet_name: "Expenses".to_string(),
+            full_name_prefix: "Expenses:".to_string(),
+            start_cell: "A2".to_string(),
+            clear_range: Some("A1:B10".to_string()),
+            sign_convention: None,
+            include_zero_balance,
+        }
+    }
+
+    fn names(block: &RowBlock) -> Vec<&str> {
+        block.rows.iter()
+            .map(|row| match &row[0] { CellValue::Text(name) => name.as_str(), other => panic!("not a name: {:?}", other) })
+            .collect()
+    }
+
+    fn accounts() -> Vec<AccountData> {
+        vec![
+            account("Expenses:Rent", 1200.0, Some("1200.00")),
+            account("Income:Sales", 500.0, Some("500.00")),
+            account("Expenses:Fees", 0.0, Some("0.00")),
+            // No Balance reported: 0.0 is only the fallback
+            account("Expenses:Misc", 0.0, None),
+        ]
+    }
+
+    #[test]
+    fn rows_list_matching_accounts_sorted_by_name() {
+        let rows = block(None).rows(&accounts()).unwrap();
+        assert_eq!(names(&rows), ["Expenses:Fees", "Expenses:Misc", "Expenses:Rent"]);
+        assert_eq!(rows.rows[2][1], CellValue::Number(1200.0));
+        assert_eq!(rows.clear, Some(CellRange::parse("A5:B10").unwrap()));
+    }
+
+    #[test]
+    fn excluding_zero_balances_keeps_unreported_ones() {
+        let rows = block(Some(false)).rows(&accounts()).unwrap();
+        assert_eq!(names(&rows), ["Expenses:Misc", "Expenses:Rent"]);
+    }
+
+    #[test]
+    fn an_emptied_prefix_clears_the_whole_range() {
+        let mut accounts = accounts();
+        accounts.push(account("ExpensesOther", 3.0, Some("3.00")));
+        accounts.push(account("expenses:Travel", 4.0, Some("4.00")));
+        let config = PrefixBlockConfig { full_name_prefix: "Expenses:Payroll:".to_string(), ..block(None) };
+        let rows = config.rows(&accounts).unwrap();
+        assert!(rows.rows.is_empty());
+        assert_eq!(rows.clear, Some(CellRange::parse("A2:B10").unwrap()));
+        // The prefix is matched exactly: case and the separator count
+        assert_eq!(names(&block(None).rows(&accounts).unwrap()), ["Expenses:Fees", "Expenses:Misc", "Expenses:Rent"]);
+    }
+}
@@ -0,0 +1,200 @@
+// Typed QuickBooks errors
+// Failures callers may want to match on; they travel inside anyhow::Error and can be downcast
+
+use std::fmt;
+
+/// HRESULT returned by COM calls made on a thread that never called CoInitializeEx
+pub const CO_E_NOTINITIALIZED: i32 = 0x800401F0u32 as i32;
+
+/// QBXMLRP2: "QuickBooks did not finish its initialization. Please try again later."
+pub const QB_E_NOT_INITIALIZED: i32 = 0x80040424u32 as i32;
+
+/// QBXMLRP2: "If the QuickBooks company data file is not open, a call to BeginSession must include
+/// the name of the data file." — company_file is AUTO but no file is open
+pub const QB_E_NO_COMPANY_FILE: i32 = 0x80040416u32 as i32;
+/// QBXMLRP2: "This application has not accessed this QuickBooks company data file before."
+pub const QB_E_FIRST_ACCESS: i32 = 0x80040418u32 as i32;
+/// QBXMLRP2: "This application does not have permission to access this QuickBooks company data file."
+pub const QB_E_NO_PERMISSION: i32 = 0x8004041Au32 as i32;
+/// QBXMLRP2: "This application is not allowed to log into this QuickBooks company data file automatically."
+pub const QB_E_NO_AUTO_LOGIN: i32 = 0x8004041Du32 as i32;
+/// QBXMLRP2: "The QuickBooks user has denied access."
+pub const QB_E_ACCESS_DENIED: i32 = 0x80040420u32 as i32;
+/// QBXMLRP2: BeginSession could not open the company file because another process holds it
+pub const QB_E_FILE_IN_USE: i32 = 0x80040408u32 as i32;
+/// Windows E_ACCESSDENIED, e.g. QuickBooks running under another Windows user
+pub const E_ACCESSDENIED: i32 = 0x80070005u32 as i32;
+
+// Phrases in QuickBooks' error descriptions while a backup, verify or rebuild holds the file
+const BUSY_PHRASES: &[&str] = &["backup", "back up", "verify", "verifying", "rebuild", "busy", "try again later"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QbError {
+    /// A COM call was made before CoInitializeEx succeeded on the calling thread
+    ComNotInitialized { hresult: i32 },
+    /// The open company file's EIN is not the one the config expects
+    CompanyMismatch { expected: String, found: Option<String> },
+    /// QuickBooks is tied up (backup, data verification, still initializing) and refused the call
+    Busy { hresult: i32, detail: String },
+    /// QuickBooks has no company file open and none was named (company_file = "AUTO")
+    /// (hresult is None when inferred from an empty GetCurrentCompanyFileName)
+    NoCompanyFile { hresult: Option<i32> },
+    /// This application (or Windows user) isn't allowed into the company file
+    PermissionDenied { hresult: i32, detail: String },
+    /// The company file is in use by another process (often QuickBooks itself, mid-operation)
+    FileInUse { hresult: i32 },
+    /// QuickBooks answered a request (e.g. AccountQueryRs) with statusSeverity="Error"
+    RequestFailed { response: String, status_code: i32, severity: String, message: String },
+}
+
+impl QbError {
+    /// The typed error for `hresult`, if it is one the service reports specially
+    pub fn from_hresult(hresult: i32) -> Option<Self> {
+        match hresult {
+            CO_E_NOTINITIALIZED => Some(QbError::ComNotInitialized { hresult }),
+            QB_E_NOT_INITIALIZED => Some(QbError::Busy { hresult, detail: "QuickBooks did not finish its initialization".to_string() }),
+            QB_E_NO_COMPANY_FILE => Some(QbError::NoCompanyFile { hresult: Some(hresult) }),
+            QB_E_FILE_IN_USE => Some(QbError::FileInUse { hresult }),
+            QB_E_FIRST_ACCESS => Some(Self::permission_denied(hresult, "this application has not accessed the company file before")),
+            QB_E_NO_PERMISSION => Some(Self::permission_denied(hresult, "this application does not have permission to access the company file")),
+            QB_E_NO_AUTO_LOGIN => Some(Self::permission_denied(hresult, "this application is not allowed to log in automatically")),
+            QB_E_ACCESS_DENIED => Some(Self::permission_denied(hresult, "the QuickBooks user denied access")),
+            E_ACCESSDENIED => Some(Self::permission_denied(hresult, "access denied; QuickBooks may be open under another Windows user")),
+            _ => None,
+        }
+    }
+
+    /// Whether the same call may succeed if made again shortly (busy or file in use); access
+    /// and configuration problems are never transient
+    pub fn is_transient(&self) -> bool {
+        matches!(self, QbError::Busy { .. } | QbError::FileInUse { .. })
+    }
+
+    fn permission_denied(hresult: i32, detail: &str) -> Self {
+        QbError::PermissionDenied { hresult, detail: detail.to_string() }
+    }
+
+    /// The typed error for a failed call whose EXCEPINFO carries `scode` and `description`.
+    /// QBXMLRP2 reports its own errors as the scode of a generic DISP_E_EXCEPTION; QuickBooks
+    /// being busy is also recognized from the description when neither code says so.
+    pub fn from_exception(hresult: i32, scode: i32, description: &str) -> Option<Self> {
+        if let Some(err) = Self::from_hresult(hresult).or_else(|| Self::from_hresult(scode)) {
+            return Some(err);
+        }
+        let lower = description.to_lowercase();
+        BUSY_PHRASES.iter()
+            .any(|phrase| lower.contains(phrase))
+            .then(|| QbError::Busy { hresult, detail: description.to_string() })
+    }
+}
+
+impl fmt::Display for QbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QbError::ComNotInitialized { hresult } => write!(
+                f,
+                "COM is not initialized on this thread (HRESULT=0x{:08X}); call CoInitializeEx on this thread before creating or using a QuickBooks processor",
+                *hresult as u32
+            ),
+            QbError::Busy { hresult, detail } => write!(
+                f,
+                "QuickBooks is busy (backup/verify in progress) — try again later ({}; HRESULT=0x{:08X})",
+                detail,
+                *hresult as u32
+            ),
+            QbError::FileInUse { hresult } => write!(
+                f,
+                "the QuickBooks company file is in use by another process — try again later (HRESULT=0x{:08X})",
+                *hresult as u32
+            ),
+            QbError::NoCompanyFile { hresult } => {
+                write!(f, "QuickBooks is running but no company file is open — open the file you want to sync, or set company_file to its path")?;
+                match hresult {
+                    Some(hresult) => write!(f, " (HRESULT=0x{:08X})", *hresult as u32),
+                    None => Ok(()),
+                }
+            },
+            QbError::PermissionDenied { hresult, detail } => write!(
+                f,
+                "QuickBooks refused access: {} (HRESULT=0x{:08X}). As the QuickBooks admin, grant this application access in \
+                 QuickBooks → Edit → Preferences → Integrated Applications (Company Preferences), allowing it to log in \
+                 automatically, and run this service as the Windows user that has QuickBooks open",
+                detail,
+                *hresult as u32
+            ),
+            QbError::RequestFailed { response, status_code, severity, message } => write!(
+                f,
+                "QuickBooks rejected the request: {} statusCode={} statusSeverity={}: {}",
+                response, status_code, severity, message
+            ),
+            QbError::CompanyMismatch { expected, found } => write!(
+                f,
+                "the open company file has EIN {} but expected_company_ein is {}; refusing to sync the wrong company",
+                found.as_deref().unwrap_or("<none>"),
+                expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for QbError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_busy_and_file_in_use_are_transient() {
+        assert!(QbError::from_hresult(QB_E_FILE_IN_USE).unwrap().is_transient());
+        assert!(QbError::from_hresult(QB_E_NOT_INITIALIZED).unwrap().is_transient());
+        assert!(QbError::from_exception(0, 0, "A backup is in progress").unwrap().is_transient());
+        for hresult in [QB_E_NO_PERMISSION, QB_E_ACCESS_DENIED, E_ACCESSDENIED, QB_E_NO_COMPANY_FILE, CO_E_NOTINITIALIZED] {
+            assert!(!QbError::from_hresult(hresult).unwrap().is_transient(), "0x{:08X}", hresult as u32);
+        }
+    }
+
+    #[test]
+    fn no_company_file_gives_targeted_guidance() {
+        let from_hresult = QbError::from_hresult(QB_E_NO_COMPANY_FILE).unwrap();
+        assert!(matches!(from_hresult, QbError::NoCompanyFile { hresult: Some(QB_E_NO_COMPANY_FILE) }));
+        assert_eq!(
+            from_hresult.to_string(),
+            "QuickBooks is running but no company file is open — open the file you want to sync, \
+             or set company_file to its path (HRESULT=0x80040416)"
+        );
+        // Inferred from an empty GetCurrentCompanyFileName: attached as context to the BeginSession error
+        let inferred = anyhow::anyhow!("BeginSession failed").context(QbError::NoCompanyFile { hresult: None });
+        assert!(matches!(inferred.downcast_ref::<QbError>(), Some(QbError::NoCompanyFile { hresult: None })));
+        assert!(inferred.to_string().starts_with("QuickBooks is running but no company file is open"));
+    }
+
+    #[test]
+    fn busy_codes_and_descriptions_get_the_busy_message() {
+        // DISP_E_EXCEPTION carrying QuickBooks' own description of the backup
+        let disp_e_exception = 0x80020009u32 as i32;
+        let busy = QbError::from_exception(disp_e_exception, 0, "QuickBooks is performing a Backup. Please try again later.").unwrap();
+        assert_eq!(
+            busy.to_string(),
+            "QuickBooks is busy (backup/verify in progress) — try again later \
+             (QuickBooks is performing a Backup. Please try again later.; HRESULT=0x80020009)"
+        );
+        let initializing = QbError::from_exception(disp_e_exception, QB_E_NOT_INITIALIZED, "").unwrap();
+        assert!(matches!(initializing, QbError::Busy { hresult: QB_E_NOT_INITIALIZED, .. }));
+        assert_eq!(QbError::from_exception(disp_e_exception, 0, "Object reference not set"), None);
+    }
+
+    #[test]
+    fn permission_denied_scodes_get_integrated_applications_guidance() {
+        let disp_e_exception = 0x80020009u32 as i32;
+        let denied = QbError::from_exception(disp_e_exception, QB_E_NO_PERMISSION, "").unwrap();
+        assert!(matches!(denied, QbError::PermissionDenied { hresult: QB_E_NO_PERMISSION, .. }));
+        let message = denied.to_string();
+        assert!(message.starts_with("QuickBooks refused access: this application does not have permission to access the company file (HRESULT=0x"), "{}", message);
+        assert!(message.contains("grant this application access in QuickBooks → Edit → Preferences → Integrated Applications"), "{}", message);
+        for hresult in [QB_E_FIRST_ACCESS, QB_E_NO_AUTO_LOGIN, QB_E_ACCESS_DENIED, E_ACCESSDENIED] {
+            let guidance = QbError::from_exception(disp_e_exception, hresult, "").unwrap().to_string();
+            assert!(guidance.contains("Integrated Applications"), "0x{:08X}: {}", hresult as u32, guidance);
+        }
+        assert!(QbError::from_hresult(E_ACCESSDENIED).unwrap().to_string().contains("QuickBooks may be open under another Windows user"));
+    }
+}
@@ -0,0 +1,517 @@
+// Single-threaded COM worker
+// One OS thread initializes COM, owns the QuickBooks processor and serves queries over a
+// channel, so async callers on any runtime thread never touch the apartment-threaded objects
+
+use anyhow::{anyhow, Context, Result};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+use crate::config::Config;
+use crate::file_mode::FileMode;
+use crate::qbxml_safe::com;
+use crate::qbxml_safe::qbxml_request_processor::{begin_session_with_retry, AccountQueryOptions, SessionRetry};
+use crate::quickbooks::QuickBooksProcessor;
+
+/// What the worker needs to run a query once it has a connected processor
+#[derive(Debug, Clone)]
+pub struct WorkerSettings {
+    pub company_file: String,
+    pub query_options: AccountQueryOptions,
+    // Hold one session open across queries, pinging it at this interval; None opens a session per query
+    pub keep_alive: Option<Duration>,
+    // Space account queries at least this far apart; requests arriving sooner wait and share one query
+    pub min_query_interval: Option<Duration>,
+    // Answer lookups from the last response while it is younger than this; None queries every time
+    pub cache_ttl: Option<Duration>,
+    // Retries for BeginSession while QuickBooks is busy or the file is in use
+    pub session_retry: SessionRetry,
+}
+
+impl WorkerSettings {
+    pub fn from_config(config: &Config) -> Self {
+        let company_file = match config.quickbooks.company_file.as_str() { "AUTO" => "", path => path };
+        Self {
+            company_file: company_file.to_string(),
+            query_options: config.query_options(),
+            keep_alive: config.quickbooks.keep_alive_secs.map(Duration::from_secs),
+            min_query_interval: config.quickbooks.min_query_interval_secs.map(Duration::from_secs),
+            cache_ttl: config.quickbooks.in_memory_cache_ttl_secs.filter(|&ttl| ttl > 0).map(Duration::from_secs),
+            session_retry: config.quickbooks.session_retry(),
+        }
+    }
+}
+
+struct QueryJob {
+    full_name: String,
+    reply: oneshot::Sender<Result<f64>>,
+}
+
+/// Cloneable-by-Arc handle to the worker thread; dropping the last handle shuts it down
+pub struct QbWorker {
+    jobs: Option<mpsc::Sender<QueryJob>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl QbWorker {
+    /// Start a worker that creates a QBXML processor and opens the connection described by `config`
+    pub fn spawn_qbxml(config: &Config) -> Result<Self> {
+        let config = config.clone();
+        Self::spawn(WorkerSettings::from_config(&config), move || crate::session::connect(&config))
+    }
+
+    /// Start the worker thread. `connect` runs on that thread after COM is initialized and must
+    /// return a processor with an open connection; its error is returned from `spawn`.
+    pub fn spawn<P, F>(settings: WorkerSettings, connect: F) -> Result<Self>
+    where
+        P: QuickBooksProcessor + 'static,
+        F: FnOnce() -> Result<P> + Send + 'static,
+    {
+        let (jobs_tx, jobs_rx) = mpsc::channel::<QueryJob>();
+        let (ready_tx, ready_rx) = mpsc::channel::<Result<()>>();
+        let thread = std::thread::Builder::new()
+            .name("quickbooks-com".to_string())
+            .spawn(move || {
+                if let Err(e) = com::initialize() {
+                    let _ = ready_tx.send(Err(e));
+                    return;
+                }
+                match connect() {
+                    Ok(processor) => {
+                        let _ = ready_tx.send(Ok(()));
+                        // Serve until every handle is dropped
+                        match settings.keep_alive {
+                            Some(interval) => serve_warm(&processor, &settings, &jobs_rx, interval),
+                            None => {
+                                let mut throttle = Throttle::new(settings.min_query_interval);
+                                let mut cache = ResponseCache::new(settings.cache_ttl);
+                                while let Ok(job) = jobs_rx.recv() {
+                                    serve(&processor, job, &jobs_rx, &mut throttle, &mut cache, || query_xml(&processor, &settings));
+                                }
+                            },
+                        }
+                        if let Err(e) = processor.close_connection() {
+                            log::warn!("[WORKER] CloseConnection failed: {:#}", e);
+                        }
+                    },
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(e));
+                    },
+                }
+                com::uninitialize();
+            })
+            .context("Failed to start QuickBooks worker thread")?;
+        ready_rx
+            .recv()
+            .map_err(|_| anyhow!("QuickBooks worker thread exited during startup"))??;
+        Ok(Self { jobs: Some(jobs_tx), thread: Some(thread) })
+    }
+
+    /// Look up an account's balance on the worker thread
+    pub async fn query(&self, full_name: &str) -> Result<f64> {
+        let (reply, response) = oneshot::channel();
+        let jobs = self.jobs.as_ref().ok_or_else(|| anyhow!("QuickBooks worker has stopped"))?;
+        jobs.send(QueryJob { full_name: full_name.to_string(), reply })
+            .map_err(|_| anyhow!("QuickBooks worker has stopped"))?;
+        response.await.map_err(|_| anyhow!("QuickBooks worker dropped the request"))?
+    }
+}
+
+impl Drop for QbWorker {
+    fn drop(&mut self) {
+        // Closing the channel ends the worker loop, which closes the connection and uninitializes COM
+        self.jobs.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Enforces min_query_interval between account queries
+struct Throttle {
+    min_interval: Option<Duration>,
+    last: Option<Instant>,
+}
+
+impl Throttle {
+    fn new(min_interval: Option<Duration>) -> Self {
+        Self { min_interval, last: None }
+    }
+
+    // Sleep until the interval since the previous query has passed, then start the clock again
+    fn wait(&mut self) {
+        if let (Some(min_interval), Some(last)) = (self.min_interval, self.last) {
+            let elapsed = last.elapsed();
+            if elapsed < min_interval {
+                let delay = min_interval - elapsed;
+                log::info!("[WORKER] Delaying QuickBooks query by {:?} to respect min_query_interval_secs", delay);
+                std::thread::sleep(delay);
+            }
+        }
+        self.last = Some(Instant::now());
+    }
+}
+
+// The last successful AccountQuery response, reused until in_memory_cache_ttl_secs passes. Only
+// the worker thread touches it, so every lookup sees one consistent response without locking.
+struct ResponseCache {
+    ttl: Option<Duration>,
+    last: Option<(Instant, String)>,
+}
+
+impl ResponseCache {
+    fn new(ttl: Option<Duration>) -> Self {
+        Self { ttl, last: None }
+    }
+
+    // The cached response while it is within the TTL
+    fn fresh(&self) -> Option<&str> {
+        let ttl = self.ttl?;
+        self.last.as_ref()
+            .filter(|(at, _)| at.elapsed() < ttl)
+            .map(|(_, xml)| xml.as_str())
+    }
+
+    // Failures are never cached, so the next lookup retries QuickBooks
+    fn store(&mut self, xml: &Result<String>) {
+        if let (Some(_), Ok(xml)) = (self.ttl, xml) {
+            self.last = Some((Instant::now(), xml.clone()));
+        }
+    }
+}
+
+// Answer `job` and every job queued behind it, from the cache when it is fresh and otherwise
+// from one throttled `query`
+fn serve<P: QuickBooksProcessor>(
+    processor: &P,
+    job: QueryJob,
+    jobs: &mpsc::Receiver<QueryJob>,
+    throttle: &mut Throttle,
+    cache: &mut ResponseCache,
+    query: impl FnOnce() -> Result<String>,
+) {
+    if let Some(xml) = cache.fresh() {
+        log::debug!("[WORKER] Answering from the in-memory cache");
+        let xml = Ok(xml.to_string());
+        answer(processor, xml, pending_batch(job, jobs));
+        return;
+    }
+    throttle.wait();
+    let batch = pending_batch(job, jobs);
+    let xml = query();
+    cache.store(&xml);
+    answer(processor, xml, batch);
+}
+
+// The job that woke the worker plus every job queued behind it; they are answered from one query
+fn pending_batch(first: QueryJob, jobs: &mpsc::Receiver<QueryJob>) -> Vec<QueryJob> {
+    let mut batch = vec![first];
+    batch.extend(jobs.try_iter());
+    if batch.len() > 1 {
+        log::info!("[WORKER] Answering {} queued requests from one QuickBooks query", batch.len());
+    }
+    batch
+}
+
+// Look up each job's account in one AccountQuery response, parsed once (or share its failure)
+fn answer<P: QuickBooksProcessor>(processor: &P, xml: Result<String>, batch: Vec<QueryJob>) {
+    let balances = xml.and_then(|xml| processor.get_account_balances(&xml));
+    for job in batch {
+        let result = match &balances {
+            Ok(balances) => balances.get(&job.full_name).copied()
+                .ok_or_else(|| anyhow!("Account '{}' not found in QuickBooks", job.full_name)),
+            Err(e) => Err(anyhow!("{:#}", e)),
+        };
+        let _ = job.reply.send(result);
+    }
+}
+
+fn begin_session<P: QuickBooksProcessor>(processor: &P, settings: &WorkerSettings) -> Result<String> {
+    begin_session_with_retry(&settings.session_retry, || processor.begin_session(&settings.company_file, FileMode::DoNotCare))
+}
+
+// One session per query so a long-lived worker never holds a stale ticket
+fn query_xml<P: QuickBooksProcessor>(processor: &P, settings: &WorkerSettings) -> Result<String> {
+    let ticket = begin_session(processor, settings)?;
+    let result = processor
+        .get_account_xml(&ticket, &settings.query_options)
+        .and_then(|xml| xml.ok_or_else(|| anyhow!("QuickBooks returned no response")));
+    if let Err(e) = processor.end_session(&ticket) {
+        log::warn!("[WORKER] EndSession failed: {:#}", e);
+    }
+    result
+}
+
+// Keep-alive mode: one session serves every query. Idle gaps longer than `interval` trigger a
+// cheap HostQuery; a failed ping or query drops the session and the next query begins a new one.
+fn serve_warm<P: QuickBooksProcessor>(processor: &P, settings: &WorkerSettings, jobs: &mpsc::Receiver<QueryJob>, interval: Duration) {
+    let mut ticket: Option<String> = None;
+    let mut throttle = Throttle::new(settings.min_query_interval);
+    let mut cache = ResponseCache::new(settings.cache_ttl);
+    loop {
+        match jobs.recv_timeout(interval) {
+            Ok(job) => {
+                serve(processor, job, jobs, &mut throttle, &mut cache, || query_warm(processor, settings, &mut ticket));
+            },
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                if let Some(open) = &ticket {
+                    if let Err(e) = processor.ping(open) {
+                        log::warn!("[WORKER] Keep-alive ping failed ({:#}); the session will be re-established", e);
+                        end_session(processor, ticket.take());
+                    }
+                }
+            },
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    end_session(processor, ticket.take());
+}
+
+// Query on the warm session, beginning one if needed; a failure on a reused session is retried
+// once on a fresh session since the old one may have died between pings
+fn query_warm<P: QuickBooksProcessor>(processor: &P, settings: &WorkerSettings, ticket: &mut Option<String>) -> Result<String> {
+    let reused = ticket.is_some();
+    match query_on_ticket(processor, settings, ticket) {
+        Err(e) if reused => {
+            log::warn!("[WORKER] Query on the kept-alive session failed ({:#}); reconnecting", e);
+            end_session(processor, ticket.take());
+            query_on_ticket(processor, settings, ticket)
+        },
+        result => result,
+    }
+}
+
+fn query_on_ticket<P: QuickBooksProcessor>(processor: &P, settings: &WorkerSettings, ticket: &mut Option<String>) -> Result<String> {
+    let open = match ticket {
+        Some(open) => open.clone(),
+        None => {
+            let open = begin_session(processor, settings)?;
+            log::info!("[WORKER] Began kept-alive QuickBooks session");
+            ticket.insert(open).clone()
+        },
+    };
+    processor
+        .get_account_xml(&open, &settings.query_options)?
+        .ok_or_else(|| anyhow!("QuickBooks returned no response"))
+}
+
+fn end_session<P: QuickBooksProcessor>(processor: &P, ticket: Option<String>) {
+    if let Some(ticket) = ticket {
+        if let Err(e) = processor.end_session(&ticket) {
+            log::warn!("[WORKER] EndSession failed: {:#}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_quickbooks::MockQuickBooks;
+    use crate::qbxml_safe::qbxml_request_processor::AccountParser;
+    use std::collections::HashSet;
+    use std::sync::{Arc, Mutex};
+    use std::thread::ThreadId;
+
+    // MockQuickBooks that notes which thread each QuickBooks request ran on
+    struct ThreadRecorder {
+        mock: MockQuickBooks,
+        threads: Arc<Mutex<HashSet<ThreadId>>>,
+    }
+
+    impl QuickBooksProcessor for ThreadRecorder {
+        fn begin_session(&self, company_file: &str, file_mode: FileMode) -> Result<String> {
+            self.threads.lock().unwrap().insert(std::thread::current().id());
+            self.mock.begin_session(company_file, file_mode)
+        }
+
+        fn process_request(&self, ticket: &str, request: &str) -> Result<String> {
+            self.threads.lock().unwrap().insert(std::thread::current().id());
+            self.mock.process_request(ticket, request)
+        }
+
+        fn end_session(&self, ticket: &str) -> Result<()> {
+            self.mock.end_session(ticket)
+        }
+
+        fn close_connection(&self) -> Result<()> {
+            self.mock.close_connection()
+        }
+
+        fn current_company_file_name(&self) -> Result<String> {
+            self.mock.current_company_file_name()
+        }
+
+        fn parser(&self) -> &AccountParser {
+            self.mock.parser()
+        }
+    }
+
+    // MockQuickBooks that numbers its sessions and logs their lifecycle; `kill` makes every open
+    // session fail the way a ticket does after QuickBooks closes the company file
+    struct SessionRecorder {
+        mock: MockQuickBooks,
+        events: Arc<Mutex<Vec<String>>>,
+        dead: Arc<Mutex<bool>>,
+    }
+
+    impl SessionRecorder {
+        fn new(events: Arc<Mutex<Vec<String>>>, dead: Arc<Mutex<bool>>) -> Self {
+            let mock = MockQuickBooks::new([("Checking".to_string(), 100.25)].into());
+            Self { mock, events, dead }
+        }
+
+        fn log(&self, event: String) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    impl QuickBooksProcessor for SessionRecorder {
+        fn begin_session(&self, _company_file: &str, _file_mode: FileMode) -> Result<String> {
+            *self.dead.lock().unwrap() = false;
+            let ticket = format!("ticket-{}", self.events.lock().unwrap().iter().filter(|e| e.starts_with("begin")).count() + 1);
+            self.log(format!("begin {}", ticket));
+            Ok(ticket)
+        }
+
+        fn process_request(&self, ticket: &str, request: &str) -> Result<String> {
+            let kind = if request.contains("<HostQueryRq") { "ping" } else { "query" };
+            if *self.dead.lock().unwrap() {
+                self.log(format!("failed {} on {}", kind, ticket));
+                anyhow::bail!("The ticket parameter is invalid");
+            }
+            self.log(format!("{} on {}", kind, ticket));
+            self.mock.process_request(ticket, request)
+        }
+
+        fn end_session(&self, ticket: &str) -> Result<()> {
+            self.log(format!("end {}", ticket));
+            Ok(())
+        }
+
+        fn close_connection(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn current_company_file_name(&self) -> Result<String> {
+            self.mock.current_company_file_name()
+        }
+
+        fn parser(&self) -> &AccountParser {
+            self.mock.parser()
+        }
+    }
+
+    fn spawn_warm(keep_alive: Duration, events: &Arc<Mutex<Vec<String>>>, dead: &Arc<Mutex<bool>>) -> QbWorker {
+        let (events, dead) = (events.clone(), dead.clone());
+        let settings = WorkerSettings { keep_alive: Some(keep_alive), ..settings() };
+        QbWorker::spawn(settings, move || Ok(SessionRecorder::new(events, dead))).unwrap()
+    }
+
+    fn settings() -> WorkerSettings {
+        WorkerSettings {
+            company_file: String::new(),
+            query_options: AccountQueryOptions::default(),
+            keep_alive: None,
+            min_query_interval: None,
+            cache_ttl: None,
+            session_retry: SessionRetry::default(),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_queries_run_on_the_one_worker_thread() {
+        let threads = Arc::new(Mutex::new(HashSet::new()));
+        let recorded = threads.clone();
+        let worker = Arc::new(QbWorker::spawn(settings(), move || {
+            let balances = [("Checking", 100.25), ("Savings", 2500.0), ("Payroll", -3.5)];
+            let mock = MockQuickBooks::new(balances.iter().map(|(name, balance)| (name.to_string(), *balance)).collect());
+            Ok(ThreadRecorder { mock, threads: recorded })
+        }).unwrap());
+
+        let names = ["Checking", "Savings", "Payroll", "Checking", "Savings", "Payroll", "Missing", "Checking"];
+        let tasks: Vec<_> = names.into_iter()
+            .map(|name| {
+                let worker = worker.clone();
+                tokio::spawn(async move { worker.query(name).await })
+            })
+            .collect();
+        let results: Vec<Result<f64>> = futures::future::join_all(tasks).await.into_iter().map(|joined| joined.unwrap()).collect();
+
+        for (name, result) in names.iter().zip(&results) {
+            match *name {
+                "Checking" => assert_eq!(*result.as_ref().unwrap(), 100.25),
+                "Savings" => assert_eq!(*result.as_ref().unwrap(), 2500.0),
+                "Payroll" => assert_eq!(*result.as_ref().unwrap(), -3.5),
+                _ => assert_eq!(result.as_ref().unwrap_err().to_string(), "Account 'Missing' not found in QuickBooks"),
+            }
+        }
+        let threads = threads.lock().unwrap();
+        assert_eq!(threads.len(), 1, "QuickBooks was called from {} threads", threads.len());
+        assert!(!threads.contains(&std::thread::current().id()));
+    }
+
+    #[tokio::test]
+    async fn a_kept_alive_session_is_reused_and_replaced_when_it_dies() {
+        let (events, dead) = (Arc::new(Mutex::new(Vec::new())), Arc::new(Mutex::new(false)));
+        let worker = spawn_warm(Duration::from_secs(3600), &events, &dead);
+        for _ in 0..3 {
+            assert_eq!(worker.query("Checking").await.unwrap(), 100.25);
+        }
+        *dead.lock().unwrap() = true;
+        assert_eq!(worker.query("Checking").await.unwrap(), 100.25);
+        drop(worker);
+        assert_eq!(*events.lock().unwrap(), [
+            "begin ticket-1", "query on ticket-1", "query on ticket-1", "query on ticket-1",
+            "failed query on ticket-1", "end ticket-1", "begin ticket-2", "query on ticket-2", "end ticket-2",
+        ]);
+    }
+
+    #[tokio::test]
+    async fn a_failed_ping_drops_the_session_before_the_next_query() {
+        let (events, dead) = (Arc::new(Mutex::new(Vec::new())), Arc::new(Mutex::new(false)));
+        let worker = spawn_warm(Duration::from_millis(20), &events, &dead);
+        assert_eq!(worker.query("Checking").await.unwrap(), 100.25);
+        *dead.lock().unwrap() = true;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(worker.query("Checking").await.unwrap(), 100.25);
+        drop(worker);
+        // Pings that succeed may land anywhere in between; only the lifecycle matters here
+        let events: Vec<String> = events.lock().unwrap().iter().filter(|e| !e.starts_with("ping")).cloned().collect();
+        assert_eq!(events, [
+            "begin ticket-1", "query on ticket-1", "failed ping on ticket-1", "end ticket-1",
+            "begin ticket-2", "query on ticket-2", "end ticket-2",
+        ]);
+    }
+
+    #[tokio::test]
+    async fn back_to_back_queries_are_spaced_by_the_minimum_interval() {
+        let (events, dead) = (Arc::new(Mutex::new(Vec::new())), Arc::new(Mutex::new(false)));
+        let recorded = events.clone();
+        let settings = WorkerSettings { min_query_interval: Some(Duration::from_millis(150)), ..settings() };
+        let worker = QbWorker::spawn(settings, move || Ok(SessionRecorder::new(recorded, dead))).unwrap();
+        let started = Instant::now();
+        assert_eq!(worker.query("Checking").await.unwrap(), 100.25);
+        assert_eq!(worker.query("Checking").await.unwrap(), 100.25);
+        // The second query waited out the interval started by the first
+        assert!(started.elapsed() >= Duration::from_millis(150), "{:?}", started.elapsed());
+        drop(worker);
+        let queries = events.lock().unwrap().iter().filter(|e| e.starts_with("query")).count();
+        assert_eq!(queries, 2, "both queries reached QuickBooks");
+    }
+
+    #[tokio::test]
+    async fn lookups_within_the_ttl_are_answered_from_memory() {
+        let (events, dead) = (Arc::new(Mutex::new(Vec::new())), Arc::new(Mutex::new(false)));
+        let recorded = events.clone();
+        let settings = WorkerSettings { cache_ttl: Some(Duration::from_millis(150)), ..settings() };
+        let worker = QbWorker::spawn(settings, move || Ok(SessionRecorder::new(recorded, dead))).unwrap();
+        let queries = || events.lock().unwrap().iter().filter(|e| e.starts_with("query")).count();
+        assert_eq!(worker.query("Checking").await.unwrap(), 100.25);
+        assert_eq!(worker.query("Checking").await.unwrap(), 100.25);
+        assert_eq!(queries(), 1, "the second lookup reached QuickBooks");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(worker.query("Checking").await.unwrap(), 100.25);
+        assert_eq!(queries(), 2, "the expired cache was not refreshed");
+    }
+}
@@ -0,0 +1,5 @@
+// QBFC backend for QuickBooks Desktop
+// Sends the same QBXML documents as qbxml_safe through QBFC's session manager, so both backends
+// share request building, response parsing and the SafeVariant wrapper
+
+pub mod qbfc_request_processor;
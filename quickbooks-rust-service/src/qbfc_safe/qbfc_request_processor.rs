@@ -0,0 +1,282 @@
+// QuickBooksProcessor over QBFC's QBSessionManager
+// QBFC keeps the open session inside the session manager, so the ticket it hands out is only a
+// placeholder; requests go through DoRequestsFromXMLString and come back via ToXMLString
+
+use anyhow::Result;
+
+use crate::file_mode::FileMode;
+use crate::qbxml_safe::qbxml_request_processor::{account_by_name_request, AccountInfo, AccountParser, QBXML_VERSION};
+use crate::amount::AmountFormat;
+use crate::quickbooks::{ConnectionType, QuickBooksProcessor};
+#[cfg(windows)]
+use winapi::um::oaidl::IDispatch;
+#[cfg(windows)]
+use crate::qbxml_safe::qbxml_request_processor::{create_dispatch, invoke_dispatch, ProgIdRetry};
+#[cfg(windows)]
+use crate::qbxml_safe::qbxml_safe_variant::SafeVariant;
+
+// QBFC tracks one session per session manager, so every ticket refers to it
+const QBFC_TICKET: &str = "qbfc-session";
+
+/// The QBSessionManager calls the QBFC backend makes: COM in production, a stub in tests
+pub trait QbfcSession {
+    /// OpenConnection2 with `connection_type`, or plain OpenConnection when there is none
+    fn open_connection(&self, app_id: &str, app_name: &str, connection_type: Option<ConnectionType>) -> Result<()>;
+    /// BeginSession with an ENOpenMode value
+    fn begin_session(&self, company_file: &str, open_mode: i32) -> Result<()>;
+    /// DoRequestsFromXMLString, returning the response set's ToXMLString
+    fn do_requests(&self, request: &str) -> Result<String>;
+    fn end_session(&self) -> Result<()>;
+    fn close_connection(&self) -> Result<()>;
+    fn current_company_file_name(&self) -> Result<String>;
+}
+
+/// QBFC request processor; it sends the same QBXML as QbxmlRequestProcessor
+pub struct RequestProcessor2<S: QbfcSession> {
+    session: S,
+    parser: AccountParser,
+}
+
+impl<S: QbfcSession> RequestProcessor2<S> {
+    pub fn new(session: S) -> Self {
+        Self { session, parser: AccountParser::default() }
+    }
+
+    /// How balance strings are parsed; defaults to US formatting
+    pub fn with_amount_format(mut self, amount_format: AmountFormat) -> Self {
+        self.parser.amount_format = amount_format;
+        self
+    }
+
+    pub fn open_connection(&self, app_id: &str, app_name: &str, connection_type: Option<ConnectionType>) -> Result<()> {
+        self.session.open_connection(app_id, app_name, connection_type)
+    }
+
+    /// Every field of one account (name, number, type, description and balances) from a single
+    /// AccountQueryRq filtered by FullName; None when QuickBooks has no such account
+    // The sync itself reads account_row fields from its one full account query
+    #[allow(dead_code)]
+    pub fn query_account_full(&self, account_full_name: &str) -> Result<Option<AccountInfo>> {
+        let response = self.run_query(QBFC_TICKET, &account_by_name_request(account_full_name), QBXML_VERSION)?;
+        Ok(self.parser.accounts(&response)?.into_iter().next())
+    }
+}
+
+impl<S: QbfcSession> QuickBooksProcessor for RequestProcessor2<S> {
+    fn begin_session(&self, company_file: &str, file_mode: FileMode) -> Result<String> {
+        // ENOpenMode: omSingleUser = 0, omMultiUser = 1, omDontCare = 2; QBFC has no online mode
+        let open_mode = match file_mode {
+            FileMode::SingleUser => 0,
+            FileMode::MultiUser => 1,
+            FileMode::DoNotCare | FileMode::Online => 2,
+        };
+        self.session.begin_session(company_file, open_mode)?;
+        Ok(QBFC_TICKET.to_string())
+    }
+
+    fn process_request(&self, _ticket: &str, request: &str) -> Result<String> {
+        self.session.do_requests(request)
+    }
+
+    fn end_session(&self, _ticket: &str) -> Result<()> {
+        self.session.end_session()
+    }
+
+    fn close_connection(&self) -> Result<()> {
+        self.session.close_connection()
+    }
+
+    fn current_company_file_name(&self) -> Result<String> {
+        self.session.current_company_file_name()
+    }
+
+    fn parser(&self) -> &AccountParser {
+        &self.parser
+    }
+}
+
+/// QBFC ProgIDs, newest SDK first
+#[cfg(windows)]
+const QBFC_PROG_IDS: [&str; 4] = [
+    "QBFC16.QBSessionManager", // QB 2024/2023
+    "QBFC15.QBSessionManager", // QB 2022
+    "QBFC14.QBSessionManager", // QB 2021
+    "QBFC13.QBSessionManager", // QB 2020
+];
+
+/// The QBFC session manager COM object
+#[cfg(windows)]
+pub struct QbfcSessionManager {
+    inner: *mut IDispatch,
+}
+
+#[cfg(windows)]
+impl QbfcSessionManager {
+    /// Create the session manager from the newest registered QBFC version
+    pub fn create(retry: &ProgIdRetry) -> Result<Self> {
+        let mut failures = Vec::new();
+        for prog_id in QBFC_PROG_IDS {
+            log::info!("Trying QBFC ProgID: {}", prog_id);
+            match create_dispatch(prog_id, retry) {
+                Ok(inner) => return Ok(Self { inner }),
+                Err(e) => failures.push(format!("{:#}", e)),
+            }
+        }
+        Err(anyhow::anyhow!("Failed to create a QBFC session manager: {}", failures.join("; ")))
+    }
+
+    fn invoke(&self, method_name: &str, params: &[SafeVariant]) -> Result<SafeVariant> {
+        // `inner` came from CoCreateInstance and lives as long as self
+        unsafe { invoke_dispatch(self.inner, method_name, params) }
+    }
+}
+
+#[cfg(windows)]
+impl QbfcSession for QbfcSessionManager {
+    fn open_connection(&self, app_id: &str, app_name: &str, connection_type: Option<ConnectionType>) -> Result<()> {
+        // Arguments go in reverse, as for QBXMLRP2
+        let (app_id, app_name) = (SafeVariant::from_string(app_id), SafeVariant::from_string(app_name));
+        match connection_type {
+            // ENConnectionType matches QBXMLRPConnectionType
+            Some(connection_type) => self.invoke("OpenConnection2", &[SafeVariant::from_i32(connection_type.as_qbxmlrp()), app_name, app_id])?,
+            None => self.invoke("OpenConnection", &[app_name, app_id])?,
+        };
+        Ok(())
+    }
+
+    fn begin_session(&self, company_file: &str, open_mode: i32) -> Result<()> {
+        self.invoke("BeginSession", &[SafeVariant::from_i32(open_mode), SafeVariant::from_string(company_file)])?;
+        Ok(())
+    }
+
+    fn do_requests(&self, request: &str) -> Result<String> {
+        let response_set = self.invoke("DoRequestsFromXMLString", &[SafeVariant::from_string(request)])?;
+        let dispatch = response_set.to_dispatch()
+            .ok_or_else(|| anyhow::anyhow!("DoRequestsFromXMLString did not return a response set"))?;
+        // `response_set` holds a reference to the object until this call returns
+        unsafe { invoke_dispatch(dispatch, "ToXMLString", &[])? }
+            .to_string()
+            .ok_or_else(|| anyhow::anyhow!("ToXMLString did not return a string"))
+    }
+
+    fn end_session(&self) -> Result<()> {
+        self.invoke("EndSession", &[])?;
+        Ok(())
+    }
+
+    fn close_connection(&self) -> Result<()> {
+        self.invoke("CloseConnection", &[])?;
+        Ok(())
+    }
+
+    fn current_company_file_name(&self) -> Result<String> {
+        Ok(self.invoke("GetCurrentCompanyFileName", &[])?.to_string().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account_type::AccountType;
+    use crate::qb_error::{QbError, CO_E_NOTINITIALIZED};
+    use std::cell::RefCell;
+
+    // Records each call and request, and answers every request with `response`
+    #[derive(Default)]
+    struct StubSession {
+        calls: RefCell<Vec<String>>,
+        requests: RefCell<Vec<String>>,
+        response: String,
+        begin_error: Option<QbError>,
+    }
+
+    impl QbfcSession for StubSession {
+        fn open_connection(&self, _app_id: &str, app_name: &str, connection_type: Option<ConnectionType>) -> Result<()> {
+            self.calls.borrow_mut().push(format!("open {} {:?}", app_name, connection_type));
+            Ok(())
+        }
+
+        fn begin_session(&self, company_file: &str, open_mode: i32) -> Result<()> {
+            self.calls.borrow_mut().push(format!("begin '{}' {}", company_file, open_mode));
+            match &self.begin_error {
+                Some(err) => Err(err.clone().into()),
+                None => Ok(()),
+            }
+        }
+
+        fn do_requests(&self, request: &str) -> Result<String> {
+            assert!(request.contains("<?qbxml version="), "{}", request);
+            self.calls.borrow_mut().push("request".to_string());
+            self.requests.borrow_mut().push(request.to_string());
+            Ok(self.response.clone())
+        }
+
+        fn end_session(&self) -> Result<()> {
+            self.calls.borrow_mut().push("end".to_string());
+            Ok(())
+        }
+
+        fn close_connection(&self) -> Result<()> {
+            self.calls.borrow_mut().push("close".to_string());
+            Ok(())
+        }
+
+        fn current_company_file_name(&self) -> Result<String> {
+            Ok(String::new())
+        }
+    }
+
+    #[test]
+    fn queries_go_through_the_session_manager() {
+        let response = r#"<QBXML><QBXMLMsgsRs><AccountQueryRs statusCode="0" statusSeverity="Info">
+            <AccountRet><FullName>Checking</FullName><AccountType>Bank</AccountType><Balance>1,234.56</Balance></AccountRet>
+        </AccountQueryRs></QBXMLMsgsRs></QBXML>"#;
+        let processor = RequestProcessor2::new(StubSession { response: response.to_string(), ..Default::default() });
+        processor.open_connection("", "Sync", Some(ConnectionType::Local)).unwrap();
+        let ticket = processor.begin_session("", FileMode::DoNotCare).unwrap();
+        let xml = processor.get_account_xml(&ticket, &Default::default()).unwrap().unwrap();
+        processor.end_session(&ticket).unwrap();
+        processor.close_connection().unwrap();
+        assert_eq!(processor.get_account_balances(&xml).unwrap()["Checking"], 1234.56);
+        assert_eq!(*processor.session.calls.borrow(), ["open Sync Some(Local)", "begin '' 2", "request", "end", "close"]);
+    }
+
+    #[test]
+    fn com_not_initialized_reaches_the_caller_typed() {
+        let processor = RequestProcessor2::new(StubSession {
+            begin_error: Some(QbError::ComNotInitialized { hresult: CO_E_NOTINITIALIZED }),
+            ..Default::default()
+        });
+        let err = processor.begin_session("", FileMode::DoNotCare).unwrap_err();
+        assert_eq!(err.downcast_ref::<QbError>(), Some(&QbError::ComNotInitialized { hresult: CO_E_NOTINITIALIZED }));
+        assert!(err.to_string().contains("CoInitializeEx"), "{}", err);
+    }
+
+    #[test]
+    fn query_account_full_reads_every_field() {
+        let response = r#"<QBXML><QBXMLMsgsRs><AccountQueryRs statusCode="0" statusSeverity="Info">
+            <AccountRet><FullName>Savings:Reserve</FullName><AccountNumber>1010</AccountNumber><AccountType>Bank</AccountType>
+            <Desc>Tax reserve</Desc><Balance>2,500.00</Balance><TotalBalance>2,750.25</TotalBalance></AccountRet>
+        </AccountQueryRs></QBXMLMsgsRs></QBXML>"#;
+        let processor = RequestProcessor2::new(StubSession { response: response.to_string(), ..Default::default() });
+        let account = processor.query_account_full("Savings:Reserve").unwrap().unwrap();
+        assert_eq!(account.account_full_name, "Savings:Reserve");
+        assert_eq!(account.number, "1010");
+        assert_eq!(account.account_type, AccountType::Bank);
+        assert_eq!(account.description.as_deref(), Some("Tax reserve"));
+        assert_eq!(account.balance, 2500.0);
+        assert_eq!(account.total_balance, Some(2750.25));
+        let requests = processor.session.requests.borrow();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].contains("<FullName>Savings:Reserve</FullName>"), "{}", requests[0]);
+        assert!(requests[0].contains("<IncludeRetElement>Desc</IncludeRetElement>"), "{}", requests[0]);
+    }
+
+    #[test]
+    fn query_account_full_is_none_for_an_unknown_account() {
+        let response = r#"<QBXML><QBXMLMsgsRs><AccountQueryRs statusCode="500" statusSeverity="Warn"
+            statusMessage="The query request has not been fully completed."/></QBXMLMsgsRs></QBXML>"#;
+        let processor = RequestProcessor2::new(StubSession { response: response.to_string(), ..Default::default() });
+        assert!(processor.query_account_full("Nope").unwrap().is_none());
+    }
+}
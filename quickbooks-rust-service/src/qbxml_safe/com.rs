@@ -0,0 +1,28 @@
+// COM apartment setup for the thread that owns a QuickBooks processor
+// Off Windows there is no COM to set up, so both calls do nothing
+
+/// Initialize COM on this thread as a single-threaded apartment, which the QuickBooks objects require
+#[cfg(windows)]
+pub fn initialize() -> anyhow::Result<()> {
+    let hr = unsafe {
+        winapi::um::combaseapi::CoInitializeEx(std::ptr::null_mut(), winapi::um::objbase::COINIT_APARTMENTTHREADED)
+    };
+    if hr < 0 {
+        return Err(anyhow::anyhow!("Failed to initialize COM system: HRESULT=0x{:08X}", hr));
+    }
+    Ok(())
+}
+
+/// Balance a successful `initialize` on the same thread
+#[cfg(windows)]
+pub fn uninitialize() {
+    unsafe { winapi::um::combaseapi::CoUninitialize(); }
+}
+
+#[cfg(not(windows))]
+pub fn initialize() -> anyhow::Result<()> {
+    Ok(())
+}
+
+#[cfg(not(windows))]
+pub fn uninitialize() {}
@@ -1,5 +1,8 @@
 // Type-safe wrappers for QBXML COM/OLE API using winapi
 // This module provides SafeVariant, SafeDispatch, and helpers for QBXMLRP2.RequestProcessor
+// Only the COM wrappers need Windows; request building and response parsing work everywhere
 
+#[cfg(windows)]
 pub mod qbxml_safe_variant;
 pub mod qbxml_request_processor;
+pub mod com;
@@ -1,18 +1,38 @@
 // Type-safe wrapper for QBXMLRP2.RequestProcessor COM API
 // Mirrors the structure of request_processor.rs but uses tickets (strings) instead of pointers
 
+#[cfg(windows)]
 use winapi::shared::guiddef::{CLSID, IID_NULL};
+#[cfg(windows)]
 use winapi::um::oaidl::{IDispatch, VARIANT, EXCEPINFO};
+#[cfg(windows)]
 use crate::qbxml_safe::qbxml_safe_variant::SafeVariant;
+use crate::account_type::AccountType;
+use crate::amount::{parse_amount, AmountFormat};
+use crate::backoff::Backoff;
+#[cfg(windows)]
 use crate::file_mode::FileMode;
+#[cfg(windows)]
+use crate::quickbooks::ConnectionType;
+use serde::{Deserialize, Serialize};
+use anyhow::Context;
+use std::collections::HashMap;
+use std::sync::Mutex;
+#[cfg(windows)]
+use std::sync::OnceLock;
+use crate::qb_error::{QbError, CO_E_NOTINITIALIZED};
 
+#[cfg(windows)]
 const DISPATCH_METHOD: u16 = 1;
 
+#[cfg(windows)]
 pub struct QbxmlRequestProcessor {
     inner: *mut IDispatch,
+    parser: AccountParser,
 }
 
 // Locally define IID_IDispatch for use in CoCreateInstance
+#[cfg(windows)]
 #[allow(non_upper_case_globals)]
 pub const IID_IDispatch: winapi::shared::guiddef::GUID = winapi::shared::guiddef::GUID {
     Data1: 0x00020400,
@@ -21,50 +41,479 @@ pub const IID_IDispatch: winapi::shared::guiddef::GUID = winapi::shared::guiddef
     Data4: [0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46],
 };
 
-/* #[derive(Debug, Clone)]
+/// The identifying fields and balances of one account in an AccountQueryRs
+#[derive(Debug, Clone)]
 pub struct AccountInfo {
     pub account_full_name: String,
+    // Empty when the account has no AccountNumber
     pub number: String,
-    pub account_type: String,
+    pub account_type: AccountType,
     pub balance: f64,
-} */
+    // Desc, when the account has one
+    pub description: Option<String>,
+    // Balance including subaccounts, when QuickBooks reported it
+    pub total_balance: Option<f64>,
+    // Balance and TotalBalance text exactly as QuickBooks returned it
+    pub raw_balance: Option<String>,
+    pub raw_total_balance: Option<String>,
+}
+
+/// HRESULT from CLSIDFromProgID when the ProgID is not registered at all (QuickBooks SDK not installed)
+const CO_E_CLASSSTRING: i32 = 0x800401F3u32 as i32;
+
+/// Bounded retry for ProgID resolution; COM registration can lag a few seconds behind boot
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProgIdRetry {
+    pub attempts: u32,
+    pub backoff: Backoff,
+}
+
+// Resolve a ProgID through the registry, returning the failing HRESULT on error
+#[cfg(windows)]
+fn clsid_from_prog_id(prog_id: &str) -> Result<CLSID, i32> {
+    let prog_id_wide = widestring::U16CString::from_str(prog_id).unwrap();
+    let mut clsid: CLSID = unsafe { std::mem::zeroed() };
+    let hr = unsafe {
+        winapi::um::combaseapi::CLSIDFromProgID(
+            prog_id_wide.as_ptr(),
+            &mut clsid as *mut CLSID
+        )
+    };
+    if hr < 0 { Err(hr) } else { Ok(clsid) }
+}
+
+/// Resolve `prog_id` with `resolver`, retrying transient failures up to `retry.attempts` times.
+/// An unregistered ProgID (CO_E_CLASSSTRING) fails immediately since waiting will not install it.
+/// Generic over the resolved id so the retry policy can be exercised without COM.
+pub fn resolve_clsid_with_retry<T, F>(prog_id: &str, retry: &ProgIdRetry, mut resolver: F) -> Result<T, anyhow::Error>
+where
+    F: FnMut(&str) -> Result<T, i32>,
+{
+    let attempts = retry.attempts.max(1);
+    let mut attempt = 1;
+    loop {
+        match resolver(prog_id) {
+            Ok(clsid) => return Ok(clsid),
+            Err(hr) if hr == CO_E_NOTINITIALIZED => return Err(QbError::ComNotInitialized { hresult: hr }.into()),
+            Err(hr) if hr == CO_E_CLASSSTRING => {
+                log::error!("ProgID {} is not registered: HRESULT=0x{:08X}", prog_id, hr as u32);
+                return Err(anyhow::anyhow!("COM ProgID {} is not registered; is the QuickBooks SDK installed? (HRESULT=0x{:08X})", prog_id, hr as u32));
+            }
+            Err(hr) if attempt < attempts => {
+                let delay = retry.backoff.delay(attempt - 1);
+                log::warn!("CLSIDFromProgID({}) failed on attempt {}/{}: HRESULT=0x{:08X}; retrying in {:?}", prog_id, attempt, attempts, hr as u32, delay);
+                std::thread::sleep(delay);
+                attempt += 1;
+            }
+            Err(hr) => {
+                log::error!("ProgID {} not found or CLSIDFromProgID failed: HRESULT=0x{:08X}", prog_id, hr as u32);
+                return Err(anyhow::anyhow!("Failed to find COM ProgID: {} after {} attempt(s) (HRESULT=0x{:08X})", prog_id, attempts, hr as u32));
+            }
+        }
+    }
+}
+
+/// Bounded retry for BeginSession while QuickBooks is busy or the company file is in use
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SessionRetry {
+    pub attempts: u32,
+    pub backoff: Backoff,
+}
+
+/// Call `begin` until it returns a ticket, retrying up to `retry.attempts` times while the failure
+/// is a transient QbError. Anything else (permission denied, no company file, ...) fails immediately.
+pub fn begin_session_with_retry<F>(retry: &SessionRetry, mut begin: F) -> Result<String, anyhow::Error>
+where
+    F: FnMut() -> Result<String, anyhow::Error>,
+{
+    let attempts = retry.attempts.max(1);
+    let mut attempt = 1;
+    loop {
+        match begin() {
+            Ok(ticket) => return Ok(ticket),
+            Err(e) if attempt < attempts && e.downcast_ref::<QbError>().is_some_and(QbError::is_transient) => {
+                let delay = retry.backoff.delay(attempt - 1);
+                log::warn!("[QBXML] BeginSession failed on attempt {}/{} ({:#}); retrying in {:?}", attempt, attempts, e, delay);
+                std::thread::sleep(delay);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// CLSIDs already resolved, per ProgID. COM registration doesn't change while the service runs,
+/// so entries are never invalidated; failures are not cached.
+pub struct ClsidCache<T> {
+    resolved: Mutex<HashMap<String, T>>,
+}
+
+// Not derived: that would require T: Default, which CLSID isn't
+impl<T> Default for ClsidCache<T> {
+    fn default() -> Self {
+        Self { resolved: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl<T: Copy> ClsidCache<T> {
+    /// Like `resolve_clsid_with_retry`, but a ProgID resolved earlier is served from memory, so
+    /// watch and server modes skip the registry (and its retry logging) on every new session
+    pub fn resolve<F>(&self, prog_id: &str, retry: &ProgIdRetry, resolver: F) -> Result<T, anyhow::Error>
+    where
+        F: FnMut(&str) -> Result<T, i32>,
+    {
+        if let Some(id) = self.resolved.lock().unwrap_or_else(|e| e.into_inner()).get(prog_id) {
+            log::debug!("Using cached CLSID for {}", prog_id);
+            return Ok(*id);
+        }
+        let id = resolve_clsid_with_retry(prog_id, retry, resolver)?;
+        self.resolved.lock().unwrap_or_else(|e| e.into_inner()).insert(prog_id.to_string(), id);
+        Ok(id)
+    }
+}
+
+// The process-wide cache behind create_dispatch
+#[cfg(windows)]
+static CLSID_CACHE: OnceLock<ClsidCache<CLSID>> = OnceLock::new();
+
+/// Filters applied to the AccountQueryRq sent to QuickBooks
+#[derive(Debug, Clone, Default)]
+pub struct AccountQueryOptions {
+    // Adds ActiveStatus=ActiveOnly so inactive accounts are left out of the response
+    pub active_only: bool,
+    // Adds a NameFilter so QuickBooks returns only accounts whose name matches
+    pub name_filter: Option<NameFilter>,
+    // Adds an AccountType filter so QuickBooks returns only accounts of that type
+    pub account_type: Option<&'static str>,
+    // Send one request per account type and merge the responses instead of one large query
+    pub by_type_batches: bool,
+    // Page through the accounts with a QBXML iterator, this many per request
+    pub page_size: Option<u32>,
+}
+
+/// Every QBXML AccountType; together they cover the whole chart of accounts
+pub const ACCOUNT_TYPES: [&str; 16] = [
+    "AccountsPayable", "AccountsReceivable", "Bank", "CostOfGoodsSold", "CreditCard", "Equity",
+    "Expense", "FixedAsset", "Income", "LongTermLiability", "NonPosting", "OtherAsset",
+    "OtherCurrentAsset", "OtherCurrentLiability", "OtherExpense", "OtherIncome",
+];
+
+/// QBXML MatchCriterion values for NameFilter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchCriterion {
+    StartsWith,
+    Contains,
+    EndsWith,
+}
+
+impl MatchCriterion {
+    pub fn as_qbxml(self) -> &'static str {
+        match self {
+            MatchCriterion::StartsWith => "StartsWith",
+            MatchCriterion::Contains => "Contains",
+            MatchCriterion::EndsWith => "EndsWith",
+        }
+    }
+}
+
+/// Server-side name filter; QuickBooks compares names case-insensitively
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NameFilter {
+    pub match_criterion: MatchCriterion,
+    pub name: String,
+}
+
+impl NameFilter {
+    /// Whether QuickBooks would return `full_name` under this filter
+    pub fn matches(&self, full_name: &str) -> bool {
+        let (full_name, pattern) = (full_name.to_lowercase(), self.name.to_lowercase());
+        match self.match_criterion {
+            MatchCriterion::StartsWith => full_name.starts_with(&pattern),
+            MatchCriterion::Contains => full_name.contains(&pattern),
+            MatchCriterion::EndsWith => full_name.ends_with(&pattern),
+        }
+    }
+}
+
+// Escape text for use inside a QBXML element
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+// The account fields every AccountQueryRq asks for
+const ACCOUNT_RET_ELEMENTS: &str = "        <IncludeRetElement>FullName</IncludeRetElement>
+        <IncludeRetElement>Balance</IncludeRetElement>
+        <IncludeRetElement>TotalBalance</IncludeRetElement>
+        <IncludeRetElement>AccountType</IncludeRetElement>
+        <IncludeRetElement>AccountNumber</IncludeRetElement>
+        <IncludeRetElement>Desc</IncludeRetElement>
+";
+
+/// The AccountQueryRq element for `options` (wrap it with qbxml_document); with a page size it
+/// starts an iterator
+pub fn account_query_request(options: &AccountQueryOptions) -> String {
+    // Filter elements must precede IncludeRetElement per the QBXML schema
+    let mut filters = String::new();
+    if let Some(page_size) = options.page_size {
+        filters.push_str(&format!("        <MaxReturned>{}</MaxReturned>\n", page_size));
+    }
+    if options.active_only {
+        filters.push_str("        <ActiveStatus>ActiveOnly</ActiveStatus>\n");
+    }
+    if let Some(filter) = &options.name_filter {
+        filters.push_str(&format!(
+            "        <NameFilter>\n          <MatchCriterion>{}</MatchCriterion>\n          <Name>{}</Name>\n        </NameFilter>\n",
+            filter.match_criterion.as_qbxml(),
+            xml_escape(&filter.name)
+        ));
+    }
+    if let Some(account_type) = options.account_type {
+        filters.push_str(&format!("        <AccountType>{}</AccountType>\n", account_type));
+    }
+    let attributes = if options.page_size.is_some() { r#" requestID="1" iterator="Start""# } else { "" };
+    account_query_rq(attributes, &filters)
+}
+
+/// The AccountQueryRq element for the one account named `account_full_name`, with every field
+/// AccountInfo holds
+pub fn account_by_name_request(account_full_name: &str) -> String {
+    account_query_rq("", &format!("        <FullName>{}</FullName>\n", xml_escape(account_full_name)))
+}
+
+/// The next page of an iterator begun by account_query_request; `page` numbers the requestID
+pub fn account_query_continue_request(iterator_id: &str, page_size: u32, page: usize) -> String {
+    let attributes = format!(r#" requestID="{}" iterator="Continue" iteratorID="{}""#, page, xml_escape(iterator_id));
+    account_query_rq(&attributes, &format!("        <MaxReturned>{}</MaxReturned>\n", page_size))
+}
 
+fn account_query_rq(attributes: &str, filters: &str) -> String {
+    format!("      <AccountQueryRq{}>\n{}{}      </AccountQueryRq>\n", attributes, filters, ACCOUNT_RET_ELEMENTS)
+}
+
+/// A complete QBXML request document: `request_body` (one or more ...Rq elements, such as
+/// "<CustomerQueryRq>...</CustomerQueryRq>") inside the envelope for `qbxml_version`
+pub fn qbxml_document(request_body: &str, qbxml_version: &str) -> String {
+    // note: use xml version "1.0"; qbXML versions newer than QBXML_VERSION generate errors
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<?qbxml version="{}"?>
+<QBXML>
+   <QBXMLMsgsRq onError="continueOnError">
+{}   </QBXMLMsgsRq>
+</QBXML>"#, xml_escape(qbxml_version), request_body)
+}
+
+/// iteratorID and iteratorRemainingCount from a paged AccountQueryRs
+pub fn iterator_state(response_xml: &str) -> Result<(String, u64), anyhow::Error> {
+    let doc = parse_response(response_xml)?;
+    let rs = doc.descendants()
+        .find(|node| node.is_element() && node.tag_name().name() == "AccountQueryRs")
+        .ok_or_else(|| anyhow::anyhow!("Paged response has no AccountQueryRs"))?;
+    // statusCode 1: nothing matched, so there is no iterator to follow
+    if rs.attribute("statusCode") == Some("1") {
+        return Ok((String::new(), 0));
+    }
+    let remaining = rs.attribute("iteratorRemainingCount")
+        .and_then(|count| count.trim().parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("AccountQueryRs has no iteratorRemainingCount; this QuickBooks version may not page account queries"))?;
+    Ok((rs.attribute("iteratorID").unwrap_or_default().to_string(), remaining))
+}
+
+/// One AccountQueryRs holding every AccountRet from `responses`, each account once (first wins),
+/// and how many accounts it holds
+pub fn merge_account_responses(responses: &[String]) -> Result<(String, usize), anyhow::Error> {
+    let mut seen = std::collections::HashSet::new();
+    let mut blocks = String::new();
+    for response in responses {
+        let doc = parse_response(response)?;
+        for account in account_nodes(&doc) {
+            if seen.insert(child_text(account, "FullName").unwrap_or_default()) {
+                blocks.push_str(&response[account.range()]);
+                blocks.push('\n');
+            }
+        }
+    }
+    let merged = format!(
+        r#"<?xml version="1.0" ?>
+<QBXML>
+<QBXMLMsgsRs>
+<AccountQueryRs statusCode="0" statusSeverity="Info" statusMessage="Status OK">
+{}</AccountQueryRs>
+</QBXMLMsgsRs>
+</QBXML>"#, blocks);
+    Ok((merged, seen.len()))
+}
+
+/// The first response element (e.g. AccountQueryRs) whose statusSeverity is "Error". With
+/// onError="continueOnError" a rejected request still comes back as XML, just without results.
+/// Info and Warn statuses, such as statusCode 1 for no matches, are not errors.
+pub fn response_error(response_xml: &str) -> Option<QbError> {
+    let doc = roxmltree::Document::parse(response_xml).ok()?;
+    let failed = doc.descendants().find(|node| node.is_element() && node.attribute("statusSeverity") == Some("Error"))?;
+    Some(QbError::RequestFailed {
+        response: failed.tag_name().name().to_string(),
+        status_code: failed.attribute("statusCode").and_then(|code| code.trim().parse().ok()).unwrap_or(-1),
+        severity: "Error".to_string(),
+        message: failed.attribute("statusMessage").unwrap_or_default().to_string(),
+    })
+}
+
+/// The TransactionQueryRq element for transactions posted to `account_full_name` between two
+/// dates (inclusive). Only TxnID is returned, which is all counting needs.
+pub fn transaction_count_request(account_full_name: &str, from: chrono::NaiveDate, to: chrono::NaiveDate) -> String {
+    format!(
+        r#"      <TransactionQueryRq>
+        <TransactionDateRangeFilter>
+          <FromTxnDate>{}</FromTxnDate>
+          <ToTxnDate>{}</ToTxnDate>
+        </TransactionDateRangeFilter>
+        <TransactionAccountFilter>
+          <FullName>{}</FullName>
+        </TransactionAccountFilter>
+        <IncludeRetElement>TxnID</IncludeRetElement>
+      </TransactionQueryRq>
+"#,
+        from.format("%Y-%m-%d"),
+        to.format("%Y-%m-%d"),
+        xml_escape(account_full_name)
+    )
+}
+
+/// Distinct transactions in a TransactionQueryRs; a transaction touching the account on several
+/// lines is listed once per line, so TxnIDs are de-duplicated. No matches (statusCode 1) counts 0.
+pub fn count_transactions(response_xml: &str) -> Result<usize, anyhow::Error> {
+    let doc = parse_response(response_xml)?;
+    let rs = doc.descendants()
+        .find(|node| node.is_element() && node.tag_name().name() == "TransactionQueryRs")
+        .ok_or_else(|| anyhow::anyhow!("Response has no TransactionQueryRs"))?;
+    if rs.attribute("statusSeverity") == Some("Error") {
+        anyhow::bail!("TransactionQueryRs failed: {}", rs.attribute("statusMessage").unwrap_or_default());
+    }
+    let ids: std::collections::HashSet<String> = rs.children()
+        .filter(|node| node.is_element() && node.tag_name().name() == "TransactionRet")
+        .filter_map(|txn| child_text(txn, "TxnID"))
+        .collect();
+    Ok(ids.len())
+}
+
+/// The TransactionQueryRq element for every transaction added or changed since `since`, returning
+/// only TimeModified. Used to tell when the company's data last changed.
+pub fn last_activity_request(since: chrono::DateTime<chrono::Utc>) -> String {
+    format!(
+        r#"      <TransactionQueryRq>
+        <TransactionModifiedDateRangeFilter>
+          <FromModifiedDate>{}</FromModifiedDate>
+        </TransactionModifiedDateRangeFilter>
+        <IncludeRetElement>TimeModified</IncludeRetElement>
+      </TransactionQueryRq>
+"#,
+        since.format("%Y-%m-%dT%H:%M:%S+00:00")
+    )
+}
+
+/// The latest TimeModified in a TransactionQueryRs, or None when no transaction matched
+/// (statusCode 1). QuickBooks writes the time with its offset, e.g. "2024-01-15T10:30:00-05:00".
+pub fn latest_time_modified(response_xml: &str) -> Result<Option<chrono::DateTime<chrono::Utc>>, anyhow::Error> {
+    let doc = parse_response(response_xml)?;
+    let rs = doc.descendants()
+        .find(|node| node.is_element() && node.tag_name().name() == "TransactionQueryRs")
+        .ok_or_else(|| anyhow::anyhow!("Response has no TransactionQueryRs"))?;
+    if rs.attribute("statusSeverity") == Some("Error") {
+        anyhow::bail!("TransactionQueryRs failed: {}", rs.attribute("statusMessage").unwrap_or_default());
+    }
+    let mut latest = None;
+    for txn in rs.children().filter(|node| node.is_element() && node.tag_name().name() == "TransactionRet") {
+        let Some(text) = child_text(txn, "TimeModified") else { continue };
+        let modified = chrono::DateTime::parse_from_rfc3339(&text)
+            .with_context(|| format!("Unreadable TimeModified '{}'", text))?
+            .with_timezone(&chrono::Utc);
+        latest = latest.max(Some(modified));
+    }
+    Ok(latest)
+}
+
+/// The CustomerQueryRq element for one customer (or job, as "Customer:Job") by full name
+pub fn customer_query_request(customer_full_name: &str) -> String {
+    format!(
+        r#"      <CustomerQueryRq>
+        <FullName>{}</FullName>
+        <IncludeRetElement>FullName</IncludeRetElement>
+        <IncludeRetElement>Balance</IncludeRetElement>
+      </CustomerQueryRq>
+"#,
+        xml_escape(customer_full_name)
+    )
+}
+
+/// The qbXML version every request here declares (newer versions generate errors)
+pub const QBXML_VERSION: &str = "13.0";
+
+/// The GeneralSummaryReportQueryRq element for a standard balance sheet as of `as_of`
+pub fn balance_sheet_request(as_of: chrono::NaiveDate) -> String {
+    format!(
+        r#"      <GeneralSummaryReportQueryRq>
+        <GeneralSummaryReportType>BalanceSheetStandard</GeneralSummaryReportType>
+        <ReportPeriod>
+          <ToReportDate>{}</ToReportDate>
+        </ReportPeriod>
+      </GeneralSummaryReportQueryRq>
+"#,
+        as_of.format("%Y-%m-%d")
+    )
+}
+
+/// HostQueryRq: the cheapest request QuickBooks answers, used to check a session is alive
+pub const HOST_QUERY_REQUEST: &str = "      <HostQueryRq />\n";
+
+/// CompanyQueryRq limited to the identifying fields
+pub const COMPANY_QUERY_REQUEST: &str = "      <CompanyQueryRq>
+        <IncludeRetElement>CompanyName</IncludeRetElement>
+        <IncludeRetElement>EIN</IncludeRetElement>
+      </CompanyQueryRq>
+";
+
+/// The EIN from a CompanyQueryRs, or None when the company has none on file
+pub fn company_ein(response_xml: &str) -> Result<Option<String>, anyhow::Error> {
+    response_field(response_xml, "CompanyQueryRs", "CompanyRet", "EIN")
+}
+
+/// ProductName from a HostQueryRs
+pub fn product_name(response_xml: &str) -> Result<Option<String>, anyhow::Error> {
+    response_field(response_xml, "HostQueryRs", "HostRet", "ProductName")
+}
+
+// Non-empty `field` of the `ret` element inside the `rs` response element; an Rs whose
+// statusSeverity is "Error" fails rather than reading as a missing field
+fn response_field(response_xml: &str, rs: &str, ret: &str, field: &str) -> Result<Option<String>, anyhow::Error> {
+    let doc = parse_response(response_xml)?;
+    let rs_node = doc.descendants()
+        .find(|node| node.is_element() && node.tag_name().name() == rs)
+        .ok_or_else(|| anyhow::anyhow!("Response has no {}", rs))?;
+    if rs_node.attribute("statusSeverity") == Some("Error") {
+        anyhow::bail!("{} failed: {}", rs, rs_node.attribute("statusMessage").unwrap_or_default());
+    }
+    Ok(child_element(rs_node, ret)
+        .and_then(|ret_node| child_text(ret_node, field))
+        .filter(|value| !value.is_empty()))
+}
+
+#[cfg(windows)]
 impl QbxmlRequestProcessor {
-    pub fn new() -> Result<Self, anyhow::Error> {
+    pub fn with_progid_retry(retry: &ProgIdRetry) -> Result<Self, anyhow::Error> {
         // Use the single QBXML ProgID for RequestProcessor
         let prog_id = "QBXMLRP2.RequestProcessor";
         log::info!("Trying QBXML ProgID: {}", prog_id);
-        let prog_id_wide = widestring::U16CString::from_str(prog_id).unwrap();
-        let mut clsid: CLSID = unsafe { std::mem::zeroed() };
-        let hr = unsafe {
-            winapi::um::combaseapi::CLSIDFromProgID(
-                prog_id_wide.as_ptr(),
-                &mut clsid as *mut CLSID
-            )
-        };
-        if hr < 0 {
-            log::error!("ProgID {} not found or CLSIDFromProgID failed: HRESULT=0x{:08X}", prog_id, hr as u32);
-            return Err(anyhow::anyhow!("Failed to find QBXML COM ProgID: {} (HRESULT=0x{:08X})", prog_id, hr as u32));
-        }
-        let mut dispatch_ptr: *mut IDispatch = std::ptr::null_mut();
-        let hr = unsafe {
-            winapi::um::combaseapi::CoCreateInstance(
-                &clsid,
-                std::ptr::null_mut(),
-                winapi::shared::wtypesbase::CLSCTX_INPROC_SERVER,
-                &IID_IDispatch,
-                &mut dispatch_ptr as *mut _ as *mut _
-            )
-        };
-        if hr >= 0 && !dispatch_ptr.is_null() {
-            let instance = Self {
-                inner: dispatch_ptr,
-            };
-            Ok(instance)
-        } else {
-            log::error!("Failed to create COM instance for {}: HRESULT=0x{:08X}", prog_id, hr as u32);
-            Err(anyhow::anyhow!("Failed to create QBXML COM instance for ProgID: {} (HRESULT=0x{:08X})", prog_id, hr as u32))
-        }
+        Ok(Self {
+            inner: create_dispatch(prog_id, retry)?,
+            parser: AccountParser::default(),
+        })
+    }
+
+    /// How balance strings are parsed; defaults to US formatting
+    pub fn with_amount_format(mut self, amount_format: AmountFormat) -> Self {
+        self.parser.amount_format = amount_format;
+        self
     }
 
     pub fn open_connection(&self, _app_id: &str, app_name: &str) -> Result<(), anyhow::Error> {
@@ -72,12 +521,28 @@ impl QbxmlRequestProcessor {
         let app_id_var = SafeVariant::from_string("");
         let app_name_var = SafeVariant::from_string(app_name);
         // Parameter order matches QBFC for consistency
-        match self.invoke_method("OpenConnection", &[app_name_var, app_id_var]) {
+        Self::connection_result("OpenConnection", self.invoke_method("OpenConnection", &[app_name_var, app_id_var]))
+    }
+
+    /// OpenConnection2 with an explicit connection type (local, local launching the UI, remote)
+    pub fn open_connection2(&self, app_name: &str, connection_type: ConnectionType) -> Result<(), anyhow::Error> {
+        // As with OpenConnection, AppID stays empty
+        let app_id_var = SafeVariant::from_string("");
+        let app_name_var = SafeVariant::from_string(app_name);
+        let type_var = SafeVariant::from_i32(connection_type.as_qbxmlrp());
+        // Arguments go in reverse: OpenConnection2(appID, appName, connPref)
+        Self::connection_result("OpenConnection2", self.invoke_method("OpenConnection2", &[type_var, app_name_var, app_id_var]))
+    }
+
+    // Typed QuickBooks errors (e.g. permission denied) pass through so their guidance reaches the user
+    fn connection_result(method: &str, result: Result<SafeVariant, anyhow::Error>) -> Result<(), anyhow::Error> {
+        match result {
             Ok(_) => {
                 Ok(())
             },
+            Err(e) if e.downcast_ref::<QbError>().is_some() => Err(e),
             Err(e) => {
-                log::error!("OpenConnection failed: {:#}", e);
+                log::error!("{} failed: {:#}", method, e);
                 for cause in e.chain().skip(1) {
                     log::error!("Caused by: {:#}", cause);
                 }
@@ -97,7 +562,6 @@ impl QbxmlRequestProcessor {
         let mode_var = SafeVariant::from_i32(mode_int);
         // Correct COM parameter order: [mode_var, file_var]
         let result = self.invoke_method("BeginSession", &[mode_var, file_var])?;
-        let vt = unsafe { result.as_variant().n1.n2().vt };
         let ticket = result.to_string().unwrap_or_default();
         if ticket.is_empty() {
             log::warn!("BeginSession returned an empty ticket string!");
@@ -125,130 +589,572 @@ impl QbxmlRequestProcessor {
         Ok(())
     }
 
-    // currently not used but we may need it
     pub fn get_current_company_file_name(&self) -> Result<String, anyhow::Error> {
         let result = self.invoke_method("GetCurrentCompanyFileName", &[])?;
         Ok(result.to_string().unwrap_or_default())
     }
 
-    pub fn get_account_xml(&self, ticket: &str) -> Result<Option<String>, anyhow::Error> {
-        // Step 1: Build QBXML request for account query
-        // note: use xml version "1.0" and qbxml version "13.0" - changes to those versions generate errors
-        let qbxml_request = format!(
-            r#"<?xml version="1.0" encoding="utf-8"?>
-<?qbxml version="13.0"?>
-<QBXML>
-   <QBXMLMsgsRq onError="continueOnError">
-      <AccountQueryRq>
-        <IncludeRetElement>FullName</IncludeRetElement>
-        <IncludeRetElement>Balance</IncludeRetElement>
-      </AccountQueryRq>
-   </QBXMLMsgsRq>
-</QBXML>"#);        
-        // Step 2: Send request
-        let response_xml = match self.process_request(ticket, &qbxml_request) {
-            Ok(xml) => xml,
-            Err(e) => return Err(e),
+    /// Parses account query responses with this processor's amount format
+    pub fn parser(&self) -> &AccountParser {
+        &self.parser
+    }
+
+    fn invoke_method(&self, method_name: &str, params: &[SafeVariant]) -> Result<SafeVariant, anyhow::Error> {
+        // `inner` came from CoCreateInstance and lives as long as self
+        unsafe { invoke_dispatch(self.inner, method_name, params) }
+    }
+}
+
+/// Create the COM object registered as `prog_id`, resolving its CLSID with `retry`
+#[cfg(windows)]
+pub fn create_dispatch(prog_id: &str, retry: &ProgIdRetry) -> Result<*mut IDispatch, anyhow::Error> {
+    let clsid = CLSID_CACHE.get_or_init(ClsidCache::default).resolve(prog_id, retry, clsid_from_prog_id)?;
+    let mut dispatch_ptr: *mut IDispatch = std::ptr::null_mut();
+    let hr = unsafe {
+        winapi::um::combaseapi::CoCreateInstance(
+            &clsid,
+            std::ptr::null_mut(),
+            winapi::shared::wtypesbase::CLSCTX_INPROC_SERVER,
+            &IID_IDispatch,
+            &mut dispatch_ptr as *mut _ as *mut _
+        )
+    };
+    if hr >= 0 && !dispatch_ptr.is_null() {
+        Ok(dispatch_ptr)
+    } else if let Some(err) = QbError::from_hresult(hr) {
+        Err(err.into())
+    } else {
+        log::error!("Failed to create COM instance for {}: HRESULT=0x{:08X}", prog_id, hr as u32);
+        Err(anyhow::anyhow!("Failed to create COM instance for ProgID: {} (HRESULT=0x{:08X})", prog_id, hr as u32))
+    }
+}
+
+/// Call `method_name` on `dispatch` through IDispatch::Invoke. `params` go in reverse order, as
+/// IDispatch expects. QuickBooks failures come back as typed QbErrors where recognized.
+///
+/// # Safety
+/// `dispatch` must be a live IDispatch created on this thread's apartment.
+#[cfg(windows)]
+pub unsafe fn invoke_dispatch(dispatch: *mut IDispatch, method_name: &str, params: &[SafeVariant]) -> Result<SafeVariant, anyhow::Error> {
+    let method_name_wide = widestring::U16CString::from_str(method_name).unwrap();
+    // Instead, use VARIANT zeroed and wrap as needed
+    let mut result: VARIANT = unsafe { std::mem::zeroed() };
+    let mut excepinfo: EXCEPINFO = unsafe { std::mem::zeroed() };
+    let hr = unsafe {
+        // Correct COM call signature for Invoke
+        let mut dispid = 0i32;
+        let names = [method_name_wide.as_ptr()];
+        let get_id_hr = ((*(*dispatch).lpVtbl).GetIDsOfNames)(
+            dispatch,
+            &IID_NULL,
+            names.as_ptr() as *mut _,
+            1,
+            0x0409,
+            &mut dispid
+        );
+        if let Some(err) = QbError::from_hresult(get_id_hr) {
+            return Err(err.into());
+        }
+        if get_id_hr < 0 {
+            return Err(anyhow::anyhow!("GetIDsOfNames failed: HRESULT=0x{:08X}", get_id_hr));
+        }
+        // Invoke gets deep copies rather than the callers' VARIANTs, so the callee can never free
+        // or change what a caller still owns; the copies are cleared when `variants` drops
+        let mut variants = params.iter()
+            .map(|param| param.to_winvariant().map(SafeVariant))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut dispparams = winapi::um::oaidl::DISPPARAMS {
+            rgvarg: if variants.is_empty() { std::ptr::null_mut() } else { variants.as_mut_ptr().cast::<VARIANT>() },
+            rgdispidNamedArgs: std::ptr::null_mut(),
+            cArgs: variants.len() as u32,
+            cNamedArgs: 0,
         };
-        Ok(Some(response_xml))
-    }
-
-    pub fn get_account_balance(&self, response_xml: &str, account_full_name: &str) -> Result<Option<f64>, anyhow::Error> {
-        let mut balance = 0.0;
-        let mut found = false;
-        let mut search_start = 0;
-        while let Some(ret_start) = response_xml[search_start..].find("<AccountRet>") {
-            let ret_start = ret_start + search_start;
-            let ret_end = match response_xml[ret_start..].find("</AccountRet>") {
-                Some(e) => ret_start + e + "</AccountRet>".len(),
-                None => break,
+        let mut arg_err = 0u32;
+        let hr = ((*(*dispatch).lpVtbl).Invoke)(
+            dispatch,
+            dispid,
+            &IID_NULL,
+            0x0409,
+            DISPATCH_METHOD,
+            &mut dispparams,
+            &mut result,
+            &mut excepinfo,
+            &mut arg_err
+        );
+        drop(variants);
+        hr
+    };
+    if let Some(err) = QbError::from_hresult(hr) {
+        return Err(err.into());
+    }
+    if hr < 0 {
+        // Log EXCEPINFO details if available
+        let (description, scode) = unsafe {
+            let description = if !excepinfo.bstrDescription.is_null() {
+                let wide = widestring::U16CStr::from_ptr_str(excepinfo.bstrDescription);
+                wide.to_string_lossy()
+            } else {
+                "<no description>".to_string()
             };
-            let account_block = &response_xml[ret_start..ret_end];
-            if let Some(full_name) = Self::extract_xml_field(account_block, "<FullName>", "</FullName>") {
-                if full_name == account_full_name {
-                    balance = Self::extract_xml_field(account_block, "<Balance>", "</Balance>")
-                        .and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0);
-                    found = true;
-                    break;
-                }
-            }
-            search_start = ret_end;
+            let source = if !excepinfo.bstrSource.is_null() {
+                let wide = widestring::U16CStr::from_ptr_str(excepinfo.bstrSource);
+                wide.to_string_lossy()
+            } else {
+                "<no source>".to_string()
+            };
+            let scode = excepinfo.scode;
+            log::error!("COM Invoke failed: HRESULT=0x{:08X}, Source: {}, Description: {}, SCODE: 0x{:08X}", hr, source, description, scode);
+            (description, scode)
+        };
+        if let Some(err) = QbError::from_exception(hr, scode, &description) {
+            return Err(err.into());
         }
-        if found {
-            Ok(Some(balance))
-        } else {
-            log::warn!("No accounts found with the specified criteria");
-            Ok(None)
+        return Err(anyhow::anyhow!("Invoke failed: HRESULT=0x{:08X}", hr));
+    }
+    Ok(SafeVariant(result))
+}
+
+/// Reads accounts out of an AccountQueryRs. Needs no COM, so saved responses can be parsed anywhere.
+#[derive(Debug, Clone, Default)]
+pub struct AccountParser {
+    pub amount_format: AmountFormat,
+}
+
+impl AccountParser {
+    /// Every account in the response, in QuickBooks order, from a single parse of the document
+    pub fn accounts(&self, response_xml: &str) -> Result<Vec<AccountInfo>, anyhow::Error> {
+        let doc = parse_response(response_xml)?;
+        Ok(account_nodes(&doc).filter_map(|account| self.account_info(account)).collect())
+    }
+
+    /// Balance by FullName of every account in the response, for answering many lookups from one
+    /// parse; when a name repeats, the first account wins
+    pub fn get_account_balances(&self, response_xml: &str) -> Result<HashMap<String, f64>, anyhow::Error> {
+        let mut balances = HashMap::new();
+        for account in self.accounts(response_xml)? {
+            balances.entry(account.account_full_name).or_insert(account.balance);
         }
+        Ok(balances)
     }
 
-    fn invoke_method(&self, method_name: &str, params: &[SafeVariant]) -> Result<SafeVariant, anyhow::Error> {
-        let method_name_wide = widestring::U16CString::from_str(method_name).unwrap();
-        // Instead, use VARIANT zeroed and wrap as needed
-        let mut result: VARIANT = unsafe { std::mem::zeroed() };
-        let mut excepinfo: EXCEPINFO = unsafe { std::mem::zeroed() };
-        let hr = unsafe {
-            // Correct COM call signature for Invoke
-            let mut dispid = 0i32;
-            let names = [method_name_wide.as_ptr()];
-            let get_id_hr = ((*(*self.inner).lpVtbl).GetIDsOfNames)(
-                self.inner,
-                &IID_NULL,
-                names.as_ptr() as *mut _,
-                1,
-                0x0409,
-                &mut dispid
-            );
-            if get_id_hr < 0 {
-                return Err(anyhow::anyhow!("GetIDsOfNames failed: HRESULT=0x{:08X}", get_id_hr));
-            }
-            let mut variants: Vec<VARIANT> = params.iter().map(|v| v.0).collect();
-            let mut dispparams = winapi::um::oaidl::DISPPARAMS {
-                rgvarg: if variants.is_empty() { std::ptr::null_mut() } else { variants.as_mut_ptr() },
-                rgdispidNamedArgs: std::ptr::null_mut(),
-                cArgs: variants.len() as u32,
-                cNamedArgs: 0,
-            };
-            let mut arg_err = 0u32;
-            ((*(*self.inner).lpVtbl).Invoke)(
-                self.inner,
-                dispid,
-                &IID_NULL,
-                0x0409,
-                DISPATCH_METHOD,
-                &mut dispparams,
-                &mut result,
-                &mut excepinfo,
-                &mut arg_err
-            )
+    // Every field of one AccountRet; None when it has no FullName
+    fn account_info(&self, account: roxmltree::Node) -> Option<AccountInfo> {
+        let account_full_name = child_text(account, "FullName")?;
+        let raw_total_balance = child_text(account, "TotalBalance");
+        Some(AccountInfo {
+            number: child_text(account, "AccountNumber").unwrap_or_default(),
+            account_type: child_text(account, "AccountType").as_deref().map(AccountType::from).unwrap_or_default(),
+            // raw_balance tells a reported zero from a missing balance
+            balance: self.balance(account, &account_full_name).unwrap_or(0.0),
+            description: child_text(account, "Desc").filter(|desc| !desc.is_empty()),
+            total_balance: raw_total_balance.as_deref().and_then(|s| parse_amount(s, &self.amount_format)),
+            raw_balance: child_text(account, "Balance"),
+            raw_total_balance,
+            account_full_name,
+        })
+    }
+
+    /// Balance of the CustomerRet whose FullName matches exactly, from a CustomerQueryRs; None
+    /// when there is no such customer or its Balance is missing or unreadable
+    pub fn get_customer_balance(&self, response_xml: &str, customer_full_name: &str) -> Result<Option<f64>, anyhow::Error> {
+        let doc = parse_response(response_xml)?;
+        Ok(doc.descendants()
+            .filter(|node| node.is_element() && node.tag_name().name() == "CustomerRet")
+            .find(|customer| child_text(*customer, "FullName").as_deref() == Some(customer_full_name))
+            .and_then(|customer| self.balance(customer, customer_full_name)))
+    }
+
+    // Balance element of an AccountRet/CustomerRet; None (with a warning) when it is absent or not
+    // a number even after stripping currency formatting, so it is never passed off as a zero
+    fn balance(&self, node: roxmltree::Node, full_name: &str) -> Option<f64> {
+        let Some(text) = child_text(node, "Balance") else {
+            log::warn!("[QBXML] '{}' has no Balance in the response", full_name);
+            return None;
         };
-        if hr < 0 {
-            // Log EXCEPINFO details if available
-            unsafe {
-                let description = if !excepinfo.bstrDescription.is_null() {
-                    let wide = widestring::U16CStr::from_ptr_str(excepinfo.bstrDescription);
-                    wide.to_string_lossy()
-                } else {
-                    "<no description>".to_string()
-                };
-                let source = if !excepinfo.bstrSource.is_null() {
-                    let wide = widestring::U16CStr::from_ptr_str(excepinfo.bstrSource);
-                    wide.to_string_lossy()
-                } else {
-                    "<no source>".to_string()
-                };
-                let scode = excepinfo.scode;
-                log::error!("COM Invoke failed: HRESULT=0x{:08X}, Source: {}, Description: {}, SCODE: 0x{:08X}", hr, source, description, scode);
-            }
-            return Err(anyhow::anyhow!("Invoke failed: HRESULT=0x{:08X}", hr));
+        let balance = parse_amount(&text, &self.amount_format);
+        if balance.is_none() {
+            log::warn!("[QBXML] Balance '{}' of '{}' is not a number", text, full_name);
+        }
+        balance
+    }
+
+    /// FullName of every account in the response, in QuickBooks order
+    pub fn get_account_names(&self, response_xml: &str) -> Vec<String> {
+        match parse_response(response_xml) {
+            Ok(doc) => account_nodes(&doc).filter_map(|account| child_text(account, "FullName")).collect(),
+            Err(e) => {
+                log::warn!("{:#}", e);
+                Vec::new()
+            },
         }
-        Ok(SafeVariant(result))
     }
 
-    // Helper function for minimal XML field extraction
-    fn extract_xml_field(xml: &str, start_tag: &str, end_tag: &str) -> Option<String> {
-        let start = xml.find(start_tag)? + start_tag.len();
-        let end = xml[start..].find(end_tag)? + start;
-        Some(xml[start..end].trim().to_string())
+    /// The account's amount in a summary report (e.g. the balance sheet). The account's DataRow
+    /// wins; a parent shown only as a subtotal falls back to its SubtotalRow. The amount is
+    /// the row's last column.
+    pub fn get_report_amount(&self, report_xml: &str, account_full_name: &str) -> Option<f64> {
+        let doc = match parse_response(report_xml) {
+            Ok(doc) => doc,
+            Err(e) => {
+                log::warn!("{:#}", e);
+                return None;
+            },
+        };
+        let row_for = |tag: &str| doc.descendants()
+            .filter(|node| node.is_element() && node.tag_name().name() == tag)
+            .find(|row| child_element(*row, "RowData").and_then(|data| data.attribute("value")) == Some(account_full_name));
+        let row = row_for("DataRow").or_else(|| row_for("SubtotalRow"))?;
+        let amount = row.children()
+            .rfind(|child| child.is_element() && child.tag_name().name() == "ColData")?
+            .attribute("value")?;
+        parse_amount(amount, &self.amount_format)
+    }
+}
+
+fn parse_response(response_xml: &str) -> Result<roxmltree::Document<'_>, anyhow::Error> {
+    roxmltree::Document::parse(response_xml).context("QuickBooks response is not well-formed XML")
+}
+
+// Every AccountRet element in the response, matched by local name so prefixes and attributes don't matter
+fn account_nodes<'a, 'input>(doc: &'a roxmltree::Document<'input>) -> impl Iterator<Item = roxmltree::Node<'a, 'input>> {
+    doc.descendants().filter(|node| node.is_element() && node.tag_name().name() == "AccountRet")
+}
+
+// The first direct child element named `name`
+fn child_element<'a, 'input>(node: roxmltree::Node<'a, 'input>, name: &str) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|child| child.is_element() && child.tag_name().name() == name)
+}
+
+// Trimmed, entity-decoded text of the direct child element `name` (ParentRef's FullName is not a match)
+fn child_text(node: roxmltree::Node, name: &str) -> Option<String> {
+    child_element(node, name).map(|child| child.text().unwrap_or_default().trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn retry(attempts: u32) -> ProgIdRetry {
+        ProgIdRetry { attempts, backoff: Backoff::default() }
+    }
+
+    #[test]
+    fn clsid_resolution_retries_transient_failures() {
+        let calls = Cell::new(0);
+        let resolver = |_: &str| {
+            calls.set(calls.get() + 1);
+            if calls.get() < 3 { Err(0x80004005u32 as i32) } else { Ok(7u32) }
+        };
+        assert_eq!(resolve_clsid_with_retry("QBXMLRP2.RequestProcessor", &retry(3), resolver).unwrap(), 7);
+        assert_eq!(calls.get(), 3);
+
+        calls.set(0);
+        let err = resolve_clsid_with_retry("QBXMLRP2.RequestProcessor", &retry(2), resolver).unwrap_err();
+        assert!(err.to_string().contains("after 2 attempt(s)"), "{}", err);
+    }
+
+    #[test]
+    fn clsid_resolution_does_not_retry_permanent_failures() {
+        let calls = Cell::new(0);
+        let resolver = |_: &str| -> Result<u32, i32> {
+            calls.set(calls.get() + 1);
+            Err(CO_E_CLASSSTRING)
+        };
+        let err = resolve_clsid_with_retry("QBXMLRP2.RequestProcessor", &retry(5), resolver).unwrap_err();
+        assert!(err.to_string().contains("is not registered"), "{}", err);
+        assert_eq!(calls.get(), 1);
+
+        let err = resolve_clsid_with_retry("QBXMLRP2.RequestProcessor", &retry(5), |_: &str| -> Result<u32, i32> { Err(CO_E_NOTINITIALIZED) }).unwrap_err();
+        assert!(matches!(err.downcast_ref::<QbError>(), Some(QbError::ComNotInitialized { .. })));
+    }
+
+    #[test]
+    fn cached_clsids_are_resolved_once_per_prog_id() {
+        let cache = ClsidCache::default();
+        let calls = Cell::new(0);
+        let resolver = |prog_id: &str| {
+            calls.set(calls.get() + 1);
+            Ok(prog_id.len())
+        };
+        for _ in 0..3 {
+            assert_eq!(cache.resolve("QBFC16.QBSessionManager", &retry(1), resolver).unwrap(), 23);
+        }
+        assert_eq!(cache.resolve("QBXMLRP2.RequestProcessor", &retry(1), resolver).unwrap(), 25);
+        assert_eq!(calls.get(), 2);
+
+        // A failure is not remembered
+        assert!(cache.resolve("QBFC15.QBSessionManager", &retry(1), |_: &str| Err(CO_E_CLASSSTRING)).is_err());
+        assert_eq!(cache.resolve("QBFC15.QBSessionManager", &retry(1), resolver).unwrap(), 23);
+    }
+
+    #[test]
+    fn count_transactions_dedupes_txn_ids() {
+        let xml = r#"<QBXML><QBXMLMsgsRs><TransactionQueryRs statusCode="0" statusSeverity="Info">
+            <TransactionRet><TxnID>1-A</TxnID></TransactionRet>
+            <TransactionRet><TxnID> 1-A </TxnID></TransactionRet>
+            <TransactionRet><TxnID>2-B</TxnID></TransactionRet>
+        </TransactionQueryRs></QBXMLMsgsRs></QBXML>"#;
+        assert_eq!(count_transactions(xml).unwrap(), 2);
+    }
+
+    #[test]
+    fn count_transactions_no_matches_is_zero() {
+        let xml = r#"<QBXML><QBXMLMsgsRs><TransactionQueryRs statusCode="1" statusSeverity="Info" statusMessage="No match" /></QBXMLMsgsRs></QBXML>"#;
+        assert_eq!(count_transactions(xml).unwrap(), 0);
+    }
+
+    #[test]
+    fn count_transactions_rejects_error_status_and_bad_xml() {
+        let xml = r#"<QBXML><QBXMLMsgsRs><TransactionQueryRs statusCode="3120" statusSeverity="Error" statusMessage="bad" /></QBXMLMsgsRs></QBXML>"#;
+        assert!(count_transactions(xml).is_err());
+        assert!(count_transactions("<QBXML><TxnID>1</TxnID>").is_err());
+    }
+
+    #[test]
+    fn latest_time_modified_reads_the_newest_transaction() {
+        let xml = r#"<QBXML><QBXMLMsgsRs><TransactionQueryRs statusCode="0" statusSeverity="Info">
+            <TransactionRet><TxnType>Check</TxnType><TimeModified>2024-01-15T10:30:00-05:00</TimeModified></TransactionRet>
+            <TransactionRet><TxnType>Deposit</TxnType><TimeModified>2024-01-16T08:05:12-05:00</TimeModified></TransactionRet>
+            <TransactionRet><TxnType>Invoice</TxnType><TimeModified>2024-01-14T23:59:59-05:00</TimeModified></TransactionRet>
+        </TransactionQueryRs></QBXMLMsgsRs></QBXML>"#;
+        let expected = chrono::DateTime::parse_from_rfc3339("2024-01-16T13:05:12Z").unwrap();
+        assert_eq!(latest_time_modified(xml).unwrap(), Some(expected.with_timezone(&chrono::Utc)));
+
+        let none = r#"<QBXML><QBXMLMsgsRs><TransactionQueryRs statusCode="1" statusSeverity="Info" statusMessage="No match" /></QBXMLMsgsRs></QBXML>"#;
+        assert_eq!(latest_time_modified(none).unwrap(), None);
+        let bad = r#"<QBXML><QBXMLMsgsRs><TransactionQueryRs statusCode="0" statusSeverity="Info">
+            <TransactionRet><TimeModified>yesterday</TimeModified></TransactionRet>
+        </TransactionQueryRs></QBXMLMsgsRs></QBXML>"#;
+        assert!(latest_time_modified(bad).is_err());
+    }
+
+    #[test]
+    fn last_activity_request_filters_on_modified_time() {
+        let since = chrono::DateTime::parse_from_rfc3339("2024-01-01T06:00:00Z").unwrap().with_timezone(&chrono::Utc);
+        let request = last_activity_request(since);
+        assert!(request.contains("<FromModifiedDate>2024-01-01T06:00:00+00:00</FromModifiedDate>"), "{}", request);
+        assert!(request.contains("<IncludeRetElement>TimeModified</IncludeRetElement>"));
+    }
+
+    const BALANCE_SHEET: &str = r#"<QBXML><QBXMLMsgsRs><GeneralSummaryReportQueryRs statusCode="0" statusSeverity="Info">
+        <ReportRet><ReportData>
+            <DataRow rowNumber="1"><RowData rowType='account' value='Cash &amp; Equivalents' /><ColData colID="1" value="Cash &amp; Equivalents" /><ColData colID="2" value="1,234.56" /></DataRow>
+            <TextRow rowNumber="2" value="Fixed Assets" />
+            <SubtotalRow rowNumber="3">
+                <RowData rowType="account"   value="Fixed Assets" />
+                <ColData colID="1" value="Total Fixed Assets" />
+                <ColData colID="2"   value="(500.00)" />
+            </SubtotalRow>
+        </ReportData></ReportRet>
+    </GeneralSummaryReportQueryRs></QBXMLMsgsRs></QBXML>"#;
+
+    #[test]
+    fn report_amount_reads_escaped_single_quoted_names() {
+        let parser = AccountParser::default();
+        assert_eq!(parser.get_report_amount(BALANCE_SHEET, "Cash & Equivalents"), Some(1234.56));
+    }
+
+    #[test]
+    fn report_amount_falls_back_to_subtotal_row() {
+        let parser = AccountParser::default();
+        assert_eq!(parser.get_report_amount(BALANCE_SHEET, "Fixed Assets"), Some(-500.0));
+        assert_eq!(parser.get_report_amount(BALANCE_SHEET, "Savings"), None);
+    }
+
+    const COMPANY_RS: &str = r#"<QBXML><QBXMLMsgsRs><CompanyQueryRs statusCode="0" statusSeverity="Info">
+        <CompanyRet><CompanyName>Acme &amp; Sons</CompanyName><EIN> 12-3456789 </EIN></CompanyRet>
+    </CompanyQueryRs></QBXMLMsgsRs></QBXML>"#;
+
+    #[test]
+    fn company_ein_reads_company_ret() {
+        assert_eq!(company_ein(COMPANY_RS).unwrap().as_deref(), Some("12-3456789"));
+    }
+
+    #[test]
+    fn company_ein_missing_or_empty_is_none() {
+        let xml = r#"<QBXML><QBXMLMsgsRs><CompanyQueryRs statusCode="0" statusSeverity="Info"><CompanyRet><EIN /></CompanyRet></CompanyQueryRs></QBXMLMsgsRs></QBXML>"#;
+        assert_eq!(company_ein(xml).unwrap(), None);
+    }
+
+    #[test]
+    fn company_ein_fails_on_error_status() {
+        let xml = r#"<QBXML><QBXMLMsgsRs><CompanyQueryRs statusCode="3250" statusSeverity="Error" statusMessage="Not permitted"><CompanyRet><EIN>1</EIN></CompanyRet></CompanyQueryRs></QBXMLMsgsRs></QBXML>"#;
+        assert!(company_ein(xml).is_err());
+    }
+
+    #[test]
+    fn every_request_declares_qbxml_version() {
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 12, 31).unwrap();
+        let bodies = [
+            transaction_count_request("Checking", date, date),
+            balance_sheet_request(date),
+            HOST_QUERY_REQUEST.to_string(),
+            COMPANY_QUERY_REQUEST.to_string(),
+        ];
+        for body in bodies {
+            assert!(!body.contains("<?qbxml"), "{} carries its own envelope", body);
+            let document = qbxml_document(&body, QBXML_VERSION);
+            assert!(document.contains(&format!("<?qbxml version=\"{}\"?>", QBXML_VERSION)));
+            roxmltree::Document::parse(&document).unwrap();
+        }
+    }
+
+    #[test]
+    fn active_only_adds_the_active_status_filter() {
+        let request = account_query_request(&AccountQueryOptions { active_only: true, ..Default::default() });
+        assert!(request.contains("<ActiveStatus>ActiveOnly</ActiveStatus>"), "{}", request);
+        // Filters precede IncludeRetElement per the QBXML schema
+        assert!(request.find("<ActiveStatus>").unwrap() < request.find("<IncludeRetElement>").unwrap());
+        roxmltree::Document::parse(&qbxml_document(&request, QBXML_VERSION)).unwrap();
+
+        let request = account_query_request(&AccountQueryOptions::default());
+        assert!(!request.contains("ActiveStatus"), "{}", request);
+    }
+
+    #[test]
+    fn name_filter_adds_a_name_filter_block() {
+        let filter = NameFilter { match_criterion: MatchCriterion::StartsWith, name: "Loans & Notes".to_string() };
+        let request = account_query_request(&AccountQueryOptions { name_filter: Some(filter), ..Default::default() });
+        assert!(request.contains(
+            "        <NameFilter>\n          <MatchCriterion>StartsWith</MatchCriterion>\n          <Name>Loans &amp; Notes</Name>\n        </NameFilter>\n"
+        ), "{}", request);
+        assert_eq!(MatchCriterion::Contains.as_qbxml(), "Contains");
+        assert_eq!(MatchCriterion::EndsWith.as_qbxml(), "EndsWith");
+        roxmltree::Document::parse(&qbxml_document(&request, QBXML_VERSION)).unwrap();
+    }
+
+    #[test]
+    fn response_error_reports_the_rejected_request() {
+        let xml = r#"<?xml version="1.0" ?><QBXML><QBXMLMsgsRs>
+            <AccountQueryRs requestID="1" statusCode="3100" statusSeverity="Error" statusMessage="The name &quot;Chekcing&quot; of the list element is already in use." />
+        </QBXMLMsgsRs></QBXML>"#;
+        let err = response_error(xml).unwrap();
+        assert_eq!(err, QbError::RequestFailed {
+            response: "AccountQueryRs".to_string(),
+            status_code: 3100,
+            severity: "Error".to_string(),
+            message: "The name \"Chekcing\" of the list element is already in use.".to_string(),
+        });
+        assert!(err.to_string().starts_with("QuickBooks rejected the request: AccountQueryRs statusCode=3100 statusSeverity=Error"), "{}", err);
+        // No matches and warnings are not errors
+        assert_eq!(response_error(r#"<QBXML><QBXMLMsgsRs><AccountQueryRs statusCode="1" statusSeverity="Info" statusMessage="A query request did not find a matching object" /></QBXMLMsgsRs></QBXML>"#), None);
+        assert_eq!(response_error(r#"<QBXML><QBXMLMsgsRs><AccountQueryRs statusCode="530" statusSeverity="Warn" statusMessage="Partial" /></QBXMLMsgsRs></QBXML>"#), None);
+    }
+
+    const ACCOUNTS_RS: &str = r#"<QBXML><QBXMLMsgsRs><AccountQueryRs statusCode="0" statusSeverity="Info">
+        <AccountRet><FullName>Checking</FullName><AccountType>Bank</AccountType><AccountNumber>1000</AccountNumber>
+            <Desc>Main account</Desc><Balance>1,234.56</Balance><TotalBalance>1,300.00</TotalBalance></AccountRet>
+        <AccountRet><FullName>Visa</FullName><AccountType>CreditCard</AccountType><Desc> </Desc></AccountRet>
+    </AccountQueryRs></QBXMLMsgsRs></QBXML>"#;
+
+    #[test]
+    fn accounts_reads_every_field_of_every_account() {
+        let accounts = AccountParser::default().accounts(ACCOUNTS_RS).unwrap();
+        assert_eq!(accounts.len(), 2);
+        let checking = &accounts[0];
+        assert_eq!(checking.account_full_name, "Checking");
+        assert_eq!(checking.number, "1000");
+        assert_eq!(checking.account_type, AccountType::Bank);
+        assert_eq!(checking.balance, 1234.56);
+        assert_eq!(checking.description.as_deref(), Some("Main account"));
+        assert_eq!(checking.total_balance, Some(1300.0));
+        assert_eq!(checking.raw_balance.as_deref(), Some("1,234.56"));
+        let visa = &accounts[1];
+        assert_eq!(visa.account_type, AccountType::CreditCard);
+        assert_eq!(visa.description, None);
+        assert_eq!(visa.raw_balance, None);
+        assert_eq!(visa.total_balance, None);
+    }
+
+    #[test]
+    fn account_info_carries_the_number_and_type_it_requests() {
+        let request = account_by_name_request("Checking");
+        assert!(request.contains("<FullName>Checking</FullName>"), "{}", request);
+        for element in ["FullName", "AccountNumber", "AccountType", "Balance"] {
+            assert!(request.contains(&format!("<IncludeRetElement>{}</IncludeRetElement>", element)), "{} not requested", element);
+        }
+        let info = AccountParser::default().accounts(ACCOUNTS_RS).unwrap().into_iter()
+            .find(|account| account.account_full_name == "Checking")
+            .unwrap();
+        assert_eq!((info.number.as_str(), info.account_type, info.balance), ("1000", AccountType::Bank, 1234.56));
+    }
+
+    #[test]
+    fn merge_keeps_the_first_of_each_account() {
+        let page = |names: &[&str]| format!(
+            r#"<QBXML><QBXMLMsgsRs><AccountQueryRs statusCode="0" statusSeverity="Info">{}</AccountQueryRs></QBXMLMsgsRs></QBXML>"#,
+            names.iter().map(|name| format!("<AccountRet><FullName>{}</FullName><Balance>1.00</Balance></AccountRet>", name)).collect::<String>()
+        );
+        let (merged, count) = merge_account_responses(&[page(&["Checking", "Savings"]), page(&["Savings", "Visa"])]).unwrap();
+        assert_eq!(count, 3);
+        let balances = AccountParser::default().get_account_balances(&merged).unwrap();
+        assert_eq!(balances.len(), 3);
+        assert_eq!(balances["Visa"], 1.0);
+    }
+
+    fn session_retry(attempts: u32) -> SessionRetry {
+        SessionRetry { attempts, backoff: Backoff::default() }
+    }
+
+    #[test]
+    fn begin_session_retries_transient_failures() {
+        let mut calls = 0;
+        let ticket = begin_session_with_retry(&session_retry(3), || {
+            calls += 1;
+            match calls {
+                1 => Err(QbError::FileInUse { hresult: crate::qb_error::QB_E_FILE_IN_USE }.into()),
+                2 => Err(QbError::Busy { hresult: 0, detail: "backup in progress".to_string() }.into()),
+                _ => Ok("ticket".to_string()),
+            }
+        }).unwrap();
+        assert_eq!(ticket, "ticket");
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn begin_session_fails_fast_on_other_errors() {
+        let mut calls = 0;
+        let err = begin_session_with_retry(&session_retry(5), || {
+            calls += 1;
+            Err(QbError::PermissionDenied { hresult: 0, detail: "denied".to_string() }.into())
+        }).unwrap_err();
+        assert!(matches!(err.downcast_ref::<QbError>(), Some(QbError::PermissionDenied { .. })));
+        assert_eq!(calls, 1);
+
+        let mut calls = 0;
+        let err = begin_session_with_retry(&session_retry(5), || {
+            calls += 1;
+            Err(anyhow::anyhow!("not a QbError"))
+        }).unwrap_err();
+        assert_eq!(err.to_string(), "not a QbError");
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn begin_session_gives_up_after_the_last_attempt() {
+        let mut calls = 0;
+        let err = begin_session_with_retry(&session_retry(3), || {
+            calls += 1;
+            Err(QbError::FileInUse { hresult: calls }.into())
+        }).unwrap_err();
+        assert_eq!(err.downcast_ref::<QbError>(), Some(&QbError::FileInUse { hresult: 3 }));
+        assert_eq!(calls, 3);
+        // Zero attempts still makes the one call
+        let mut calls = 0;
+        assert!(begin_session_with_retry(&session_retry(0), || { calls += 1; Ok(String::new()) }).is_ok());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn customer_without_a_readable_balance_is_none() {
+        let parser = AccountParser::default();
+        let rs = |ret: &str| format!(r#"<QBXML><QBXMLMsgsRs><CustomerQueryRs statusCode="0" statusSeverity="Info">{}</CustomerQueryRs></QBXMLMsgsRs></QBXML>"#, ret);
+        let with = rs("<CustomerRet><FullName>Acme</FullName><Balance>($1,250.00)</Balance></CustomerRet>");
+        assert_eq!(parser.get_customer_balance(&with, "Acme").unwrap(), Some(-1250.0));
+        let missing = rs("<CustomerRet><FullName>Acme</FullName></CustomerRet>");
+        assert_eq!(parser.get_customer_balance(&missing, "Acme").unwrap(), None);
+        let garbled = rs("<CustomerRet><FullName>Acme</FullName><Balance>n/a</Balance></CustomerRet>");
+        assert_eq!(parser.get_customer_balance(&garbled, "Acme").unwrap(), None);
     }
 }
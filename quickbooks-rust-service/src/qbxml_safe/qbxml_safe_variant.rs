@@ -1,11 +1,18 @@
 // SafeVariant for QBXML API, similar to the one used for QBFC
 // Provides type-safe wrappers for VARIANTs for COM interop
+//
+// Reader contract: every `to_*` reader returns None for VT_NULL and VT_EMPTY (QuickBooks'
+// way of saying a field is missing) rather than an error or a default value, so a missing
+// field can never masquerade as a zero or an empty string downstream.
 
-use winapi::um::oaidl::VARIANT;
-use winapi::shared::wtypes::{VT_BSTR, VT_I4};
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use winapi::um::oaidl::{IDispatch, VARIANT};
+use winapi::shared::wtypes::{VARIANT_BOOL, VARIANT_FALSE, VARIANT_TRUE, VT_BOOL, VT_BSTR, VT_DATE, VT_DISPATCH, VT_EMPTY, VT_I4, VT_NULL, VT_R8};
 use widestring::U16CString;
-use winapi::um::oleauto::{SysAllocStringLen, SysFreeString, SysStringLen};
+use winapi::um::oleauto::{SysAllocStringLen, SysStringLen, VariantClear, VariantCopy, VariantInit};
 
+// Transparent so a slice of SafeVariants can be passed to COM as a VARIANT array
+#[repr(transparent)]
 pub struct SafeVariant(pub VARIANT);
 
 impl SafeVariant {
@@ -27,11 +34,52 @@ impl SafeVariant {
         }
         SafeVariant(var)
     }
+    // No QBXML call passes or returns a VT_R8 yet
+    #[allow(dead_code)]
+    pub fn from_f64(value: f64) -> Self {
+        let mut var: VARIANT = unsafe { std::mem::zeroed() };
+        unsafe {
+            *var.n1.n2_mut().n3.dblVal_mut() = value;
+            var.n1.n2_mut().vt = VT_R8 as u16;
+        }
+        SafeVariant(var)
+    }
+    // No QBXML call passes or returns a VARIANT_BOOL yet
+    #[allow(dead_code)]
+    pub fn from_bool(b: bool) -> Self {
+        let mut var: VARIANT = unsafe { std::mem::zeroed() };
+        unsafe {
+            *var.n1.n2_mut().n3.boolVal_mut() = if b { VARIANT_TRUE } else { VARIANT_FALSE };
+            var.n1.n2_mut().vt = VT_BOOL as u16;
+        }
+        SafeVariant(var)
+    }
+    // No QBXML call passes or returns a VT_DATE yet
+    #[allow(dead_code)]
+    pub fn from_date(at: NaiveDateTime) -> Self {
+        let mut var: VARIANT = unsafe { std::mem::zeroed() };
+        unsafe {
+            *var.n1.n2_mut().n3.date_mut() = Self::naive_to_ole_date(at);
+            var.n1.n2_mut().vt = VT_DATE as u16;
+        }
+        SafeVariant(var)
+    }
     pub fn as_variant(&self) -> &VARIANT {
         &self.0
     }
+    pub fn vt(&self) -> u16 {
+        unsafe { self.0.n1.n2().vt }
+    }
+    /// True for VT_NULL and VT_EMPTY, which every reader maps to None
+    pub fn is_null_or_empty(&self) -> bool {
+        let vt = self.vt();
+        vt == VT_NULL as u16 || vt == VT_EMPTY as u16
+    }
     pub fn to_string(&self) -> Option<String> {
-        let vt = unsafe { self.0.n1.n2().vt };
+        if self.is_null_or_empty() {
+            return None;
+        }
+        let vt = self.vt();
         if vt == VT_BSTR as u16 {
             let bstr = unsafe { *self.0.n1.n2().n3.bstrVal() };
             if bstr.is_null() {
@@ -44,28 +92,218 @@ impl SafeVariant {
             None
         }
     }
-    pub fn to_winvariant(&self) -> VARIANT {
-        // Clone the underlying VARIANT for COM interop
-        unsafe { std::ptr::read(self.as_variant()) }
+    /// The object held by a VT_DISPATCH (QBFC returns its response sets this way). This
+    /// SafeVariant owns the reference, so the pointer is only valid while it lives.
+    pub fn to_dispatch(&self) -> Option<*mut IDispatch> {
+        if self.is_null_or_empty() {
+            return None;
+        }
+        if self.vt() == VT_DISPATCH as u16 {
+            let dispatch = unsafe { *self.0.n1.n2().n3.pdispVal() };
+            (!dispatch.is_null()).then_some(dispatch)
+        } else {
+            None
+        }
+    }
+    /// Only VT_R8; other numeric types are not converted, as in the QBFC SafeVariant
+    #[allow(dead_code)]
+    pub fn to_f64(&self) -> Option<f64> {
+        if self.is_null_or_empty() {
+            return None;
+        }
+        if self.vt() == VT_R8 as u16 {
+            Some(unsafe { *self.0.n1.n2().n3.dblVal() })
+        } else {
+            None
+        }
     }
-    pub fn from_winvariant(win: &VARIANT) -> Self {
-        // Construct a SafeVariant from a raw VARIANT pointer (deep copy)
-        let mut var: VARIANT = unsafe { std::mem::zeroed() };
-        unsafe { std::ptr::copy_nonoverlapping(win, &mut var, 1); }
-        SafeVariant(var)
+    /// VARIANT_BOOL is VARIANT_TRUE (-1, 0xFFFF) or VARIANT_FALSE (0); any other non-zero value
+    /// is read as true too, as COM callers conventionally do
+    #[allow(dead_code)]
+    pub fn to_bool(&self) -> Option<bool> {
+        if self.is_null_or_empty() {
+            return None;
+        }
+        if self.vt() == VT_BOOL as u16 {
+            let value: VARIANT_BOOL = unsafe { *self.0.n1.n2().n3.boolVal() };
+            Some(value != VARIANT_FALSE)
+        } else {
+            None
+        }
+    }
+    /// A VT_DATE as local wall-clock time, as QuickBooks stores it (no timezone)
+    #[allow(dead_code)]
+    pub fn to_date(&self) -> Option<NaiveDateTime> {
+        if self.is_null_or_empty() {
+            return None;
+        }
+        if self.vt() == VT_DATE as u16 {
+            Self::ole_date_to_naive(unsafe { *self.0.n1.n2().n3.date() })
+        } else {
+            None
+        }
+    }
+    /// A deep copy for COM interop (a BSTR is duplicated, not shared), so the copy and this
+    /// SafeVariant each free only their own allocation. The caller owns the copy and releases
+    /// it with VariantClear.
+    pub fn to_winvariant(&self) -> anyhow::Result<VARIANT> {
+        let mut copy: VARIANT = unsafe { std::mem::zeroed() };
+        let hr = unsafe {
+            VariantInit(&mut copy);
+            VariantCopy(&mut copy, self.as_variant())
+        };
+        if hr < 0 {
+            anyhow::bail!("VariantCopy failed: HRESULT=0x{:08X}", hr);
+        }
+        Ok(copy)
+    }
+
+    const MILLIS_PER_DAY: f64 = 86_400_000.0;
+
+    // Day 0 of an OLE automation date
+    fn ole_epoch() -> NaiveDate {
+        NaiveDate::from_ymd_opt(1899, 12, 30).expect("valid date")
+    }
+
+    /// An OLE automation date (days since 1899-12-30, time of day as the fraction) as a chrono
+    /// value, to the millisecond. Before the epoch the fraction still counts forward from
+    /// midnight: -1.25 is 1899-12-29 06:00.
+    fn ole_date_to_naive(value: f64) -> Option<NaiveDateTime> {
+        if !value.is_finite() {
+            return None;
+        }
+        let days = value.trunc();
+        let millis = ((value - days).abs() * Self::MILLIS_PER_DAY).round() as i64;
+        Self::ole_epoch().and_time(NaiveTime::MIN)
+            .checked_add_signed(Duration::try_days(days as i64)?)?
+            .checked_add_signed(Duration::milliseconds(millis))
+    }
+
+    fn naive_to_ole_date(at: NaiveDateTime) -> f64 {
+        let days = (at.date() - Self::ole_epoch()).num_days() as f64;
+        let fraction = (at.time() - NaiveTime::MIN).num_milliseconds() as f64 / Self::MILLIS_PER_DAY;
+        if days < 0.0 { days - fraction } else { days + fraction }
     }
 }
 
+// VariantClear releases whatever the VARIANT owns: a BSTR, a SAFEARRAY, or a reference on a
+// VT_DISPATCH/VT_UNKNOWN that VariantCopy added. Inline values such as VT_I4 or VT_DATE need nothing.
 impl Drop for SafeVariant {
     fn drop(&mut self) {
-        let vt = unsafe { self.0.n1.n2().vt };
-        if vt == VT_BSTR as u16 {
-            let bstr = unsafe { *self.0.n1.n2().n3.bstrVal() };
-            if !bstr.is_null() {
-                unsafe { SysFreeString(bstr) };
-            }
+        let hr = unsafe { VariantClear(&mut self.0) };
+        if hr < 0 {
+            log::warn!("VariantClear failed: HRESULT=0x{:08X}", hr);
         }
     }
 }
 
 // Add more helpers as needed for QBXML
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bstr_ptr(variant: &SafeVariant) -> *mut u16 {
+        unsafe { *variant.0.n1.n2().n3.bstrVal() }
+    }
+
+    #[test]
+    fn winvariant_copy_owns_its_own_bstr() {
+        let original = SafeVariant::from_string("Checking");
+        let copy = SafeVariant(original.to_winvariant().unwrap());
+        assert_ne!(bstr_ptr(&original), bstr_ptr(&copy));
+        drop(original);
+        assert_eq!(copy.to_string().as_deref(), Some("Checking"));
+    }
+
+    fn of_type(vt: u32) -> SafeVariant {
+        let mut var: VARIANT = unsafe { std::mem::zeroed() };
+        unsafe { var.n1.n2_mut().vt = vt as u16 };
+        SafeVariant(var)
+    }
+
+    #[test]
+    fn null_and_empty_read_as_none() {
+        for variant in [of_type(VT_NULL), of_type(VT_EMPTY)] {
+            assert!(variant.is_null_or_empty());
+            assert_eq!(variant.to_string(), None);
+            assert_eq!(variant.to_f64(), None);
+            assert_eq!(variant.to_bool(), None);
+            assert_eq!(variant.to_date(), None);
+            assert_eq!(variant.to_dispatch(), None);
+        }
+    }
+
+    #[test]
+    fn bool_round_trips() {
+        assert_eq!(SafeVariant::from_bool(true).to_bool(), Some(true));
+        assert_eq!(SafeVariant::from_bool(false).to_bool(), Some(false));
+        assert_eq!(SafeVariant::from_bool(true).vt(), VT_BOOL as u16);
+    }
+
+    #[test]
+    fn bool_reads_variant_bool_values() {
+        let mut variant = SafeVariant::from_bool(false);
+        unsafe { *variant.0.n1.n2_mut().n3.boolVal_mut() = -1 };
+        assert_eq!(variant.to_bool(), Some(true));
+        unsafe { *variant.0.n1.n2_mut().n3.boolVal_mut() = 0 };
+        assert_eq!(variant.to_bool(), Some(false));
+        assert_eq!(SafeVariant::from_i32(1).to_bool(), None);
+    }
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32, sec: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap().and_hms_opt(h, min, sec).unwrap()
+    }
+
+    // OLE serials and the wall-clock times they stand for
+    const OLE_DATES: [(f64, &str); 6] = [
+        (0.0, "1899-12-30 00:00:00"),
+        (1.0, "1899-12-31 00:00:00"),
+        (36526.0, "2000-01-01 00:00:00"),
+        (45000.5, "2023-03-15 12:00:00"),
+        (45658.75, "2025-01-01 18:00:00"),
+        (-1.25, "1899-12-29 06:00:00"),
+    ];
+
+    #[test]
+    fn ole_dates_convert_both_ways() {
+        for (serial, text) in OLE_DATES {
+            let expected = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap();
+            assert_eq!(SafeVariant::ole_date_to_naive(serial), Some(expected), "serial {}", serial);
+            assert_eq!(SafeVariant::naive_to_ole_date(expected), serial, "{}", text);
+        }
+    }
+
+    #[test]
+    fn ole_date_rounds_to_the_millisecond() {
+        // 23:59:59 is 86399/86400 of a day, which a double can't hold exactly
+        assert_eq!(SafeVariant::ole_date_to_naive(43831.999988426), Some(at(2020, 1, 1, 23, 59, 59)));
+        assert_eq!(SafeVariant::ole_date_to_naive(f64::NAN), None);
+    }
+
+    #[test]
+    fn date_round_trips_through_a_variant() {
+        let noon = at(2024, 2, 29, 12, 0, 0);
+        let variant = SafeVariant::from_date(noon);
+        assert_eq!(variant.vt(), VT_DATE as u16);
+        assert_eq!(variant.to_date(), Some(noon));
+        assert_eq!(SafeVariant::from_i32(45000).to_date(), None);
+    }
+
+    #[test]
+    fn f64_round_trips() {
+        for value in [0.0, -1234.56, 1e-9, f64::MAX] {
+            let variant = SafeVariant::from_f64(value);
+            assert_eq!(variant.vt(), VT_R8 as u16);
+            assert_eq!(variant.to_f64(), Some(value));
+        }
+    }
+
+    #[test]
+    fn typed_readers_reject_other_types() {
+        assert_eq!(SafeVariant::from_i32(42).to_f64(), None);
+        assert_eq!(SafeVariant::from_i32(42).to_string(), None);
+        assert_eq!(SafeVariant::from_f64(42.0).to_bool(), None);
+        assert_eq!(SafeVariant::from_string("42").to_f64(), None);
+    }
+}
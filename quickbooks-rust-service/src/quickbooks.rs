@@ -0,0 +1,398 @@
+// Backend-agnostic QuickBooks processor interface
+// Lets the sync flow and the COM worker run against any processor implementation, including the
+// mock that stands in for QuickBooks off Windows
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::file_mode::FileMode;
+use crate::qb_error::QbError;
+use crate::qbxml_safe::qbxml_request_processor::{
+    account_query_continue_request, account_query_request, iterator_state, merge_account_responses, qbxml_document,
+    response_error, AccountParser, AccountQueryOptions, ACCOUNT_TYPES, HOST_QUERY_REQUEST, QBXML_VERSION,
+};
+#[cfg(windows)]
+use crate::qbxml_safe::qbxml_request_processor::QbxmlRequestProcessor;
+
+/// How QBXMLRP2 reaches QuickBooks (OpenConnection2's QBXMLRPConnectionType)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionType {
+    // QuickBooks on this machine, as OpenConnection does
+    #[default]
+    Local,
+    // Local, starting QuickBooks with its UI if it isn't running
+    LocalWithUi,
+    // QuickBooks on another machine through the Remote Data Sharing service
+    Remote,
+    // QuickBooks Online Edition through the remote connector
+    RemoteQboe,
+}
+
+impl ConnectionType {
+    /// The QBXMLRPConnectionType constant passed to OpenConnection2
+    pub fn as_qbxmlrp(self) -> i32 {
+        match self {
+            ConnectionType::Local => 1,       // localQBD
+            ConnectionType::Remote => 2,      // remoteQBD
+            ConnectionType::LocalWithUi => 3, // localQBDLaunchUI
+            ConnectionType::RemoteQboe => 4,  // remoteQBOE
+        }
+    }
+}
+
+/// Which SDK carries requests to QuickBooks ([quickbooks] connection_mode)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    // QBXMLRP2.RequestProcessor
+    #[default]
+    Qbxml,
+    // QBFCnn.QBSessionManager
+    Qbfc,
+    // QBFC when it can be created, otherwise QBXML
+    Auto,
+}
+
+impl Backend {
+    /// connection_mode once only held file-mode values such as "multi-user"; anything other than
+    /// "auto" or "qbfc" keeps the QBXML backend
+    pub fn from_connection_mode(connection_mode: Option<&str>) -> Self {
+        match connection_mode.map(|mode| mode.trim().to_ascii_lowercase()).as_deref() {
+            Some("auto") => Backend::Auto,
+            Some("qbfc") => Backend::Qbfc,
+            _ => Backend::Qbxml,
+        }
+    }
+}
+
+/// Create and connect the processor for `backend` with the matching factory. Auto tries QBFC
+/// first and falls back to QBXML when QBFC can't be created or connected. Returns the backend
+/// actually used, which is logged.
+pub fn connect_backend<Q, X>(backend: Backend, qbfc: Q, qbxml: X) -> Result<(Backend, Box<dyn QuickBooksProcessor>)>
+where
+    Q: FnOnce() -> Result<Box<dyn QuickBooksProcessor>>,
+    X: FnOnce() -> Result<Box<dyn QuickBooksProcessor>>,
+{
+    let (used, processor) = match backend {
+        Backend::Qbxml => (Backend::Qbxml, qbxml()?),
+        Backend::Qbfc => (Backend::Qbfc, qbfc()?),
+        Backend::Auto => match qbfc() {
+            Ok(processor) => (Backend::Qbfc, processor),
+            // QBXML would fail the same way on this thread, so report the typed error instead
+            Err(e) if matches!(e.downcast_ref::<QbError>(), Some(QbError::ComNotInitialized { .. })) => return Err(e),
+            Err(e) => {
+                log::warn!("[QBFC] QBFC is not available ({:#}); falling back to QBXML", e);
+                (Backend::Qbxml, qbxml()?)
+            },
+        },
+    };
+    log::info!("[QBXML] Using the {:?} backend", used);
+    Ok((used, processor))
+}
+
+/// The session-level operations the service needs from a QuickBooks backend. Implementors only
+/// carry requests to QuickBooks; the account queries built from them are provided here.
+/// COM implementations are apartment-threaded: create and use them on one thread.
+pub trait QuickBooksProcessor {
+    fn begin_session(&self, company_file: &str, file_mode: FileMode) -> Result<String>;
+    /// Send one complete QBXML request document on `ticket`, returning the raw response
+    fn process_request(&self, ticket: &str, request: &str) -> Result<String>;
+    fn end_session(&self, ticket: &str) -> Result<()>;
+    fn close_connection(&self) -> Result<()>;
+    /// Path of the open company file; empty when QuickBooks is running without one
+    fn current_company_file_name(&self) -> Result<String>;
+    /// Parses account query responses with this processor's amount format
+    fn parser(&self) -> &AccountParser;
+
+    /// Run any query: `request_body` is the inner ...Rq element(s), e.g.
+    /// `<CustomerQueryRq><MaxReturned>10</MaxReturned></CustomerQueryRq>`, which are wrapped in
+    /// the QBXML envelope for `qbxml_version` (QBXML_VERSION is known to work). Returns the raw
+    /// response; an Rs with statusSeverity="Error" is returned as QbError::RequestFailed.
+    fn run_query(&self, ticket: &str, request_body: &str, qbxml_version: &str) -> Result<String> {
+        let response = self.process_request(ticket, &qbxml_document(request_body, qbxml_version))?;
+        if let Some(err) = response_error(&response) {
+            return Err(err.into());
+        }
+        Ok(response)
+    }
+
+    fn get_account_xml(&self, ticket: &str, options: &AccountQueryOptions) -> Result<Option<String>> {
+        if options.by_type_batches {
+            return account_xml_by_type(self, ticket, options).map(Some);
+        }
+        if let Some(page_size) = options.page_size {
+            return account_xml_paged(self, ticket, options, page_size).map(Some);
+        }
+        self.run_query(ticket, &account_query_request(options), QBXML_VERSION).map(Some)
+    }
+
+    /// Balance by FullName of every account in one account query response
+    fn get_account_balances(&self, response_xml: &str) -> Result<HashMap<String, f64>> {
+        self.parser().get_account_balances(response_xml)
+    }
+
+    /// Send a HostQueryRq on `ticket`, returning the response XML
+    fn host_query(&self, ticket: &str) -> Result<String> {
+        self.run_query(ticket, HOST_QUERY_REQUEST, QBXML_VERSION)
+    }
+
+    /// Cheap round trip proving the session is still alive
+    fn ping(&self, ticket: &str) -> Result<()> {
+        self.host_query(ticket).map(|_| ())
+    }
+}
+
+/// Lets the session hold whichever backend was chosen at run time
+impl<P: QuickBooksProcessor + ?Sized> QuickBooksProcessor for Box<P> {
+    fn begin_session(&self, company_file: &str, file_mode: FileMode) -> Result<String> {
+        (**self).begin_session(company_file, file_mode)
+    }
+
+    fn process_request(&self, ticket: &str, request: &str) -> Result<String> {
+        (**self).process_request(ticket, request)
+    }
+
+    fn end_session(&self, ticket: &str) -> Result<()> {
+        (**self).end_session(ticket)
+    }
+
+    fn close_connection(&self) -> Result<()> {
+        (**self).close_connection()
+    }
+
+    fn current_company_file_name(&self) -> Result<String> {
+        (**self).current_company_file_name()
+    }
+
+    fn parser(&self) -> &AccountParser {
+        (**self).parser()
+    }
+}
+
+// One query per account type, merged; any failed batch fails the whole query so no account goes missing
+fn account_xml_by_type<P: QuickBooksProcessor + ?Sized>(processor: &P, ticket: &str, options: &AccountQueryOptions) -> Result<String> {
+    let mut responses = Vec::with_capacity(ACCOUNT_TYPES.len());
+    for account_type in ACCOUNT_TYPES {
+        let batch = AccountQueryOptions { account_type: Some(account_type), by_type_batches: false, ..options.clone() };
+        let response = processor.get_account_xml(ticket, &batch)
+            .with_context(|| format!("AccountQueryRq for AccountType {} failed", account_type))?
+            .unwrap_or_default();
+        log::debug!("[QBXML] AccountType {}: {} bytes", account_type, response.len());
+        responses.push(response);
+    }
+    let (merged, count) = merge_account_responses(&responses)?;
+    log::info!("[QBXML] Merged {} account type batches ({} accounts)", responses.len(), count);
+    Ok(merged)
+}
+
+// Start an iterator and follow it until QuickBooks reports no accounts remaining, merging the pages
+fn account_xml_paged<P: QuickBooksProcessor + ?Sized>(processor: &P, ticket: &str, options: &AccountQueryOptions, page_size: u32) -> Result<String> {
+    let mut pages = vec![processor.run_query(ticket, &account_query_request(options), QBXML_VERSION)?];
+    loop {
+        let (iterator_id, remaining) = iterator_state(&pages[pages.len() - 1])?;
+        if remaining == 0 {
+            break;
+        }
+        if iterator_id.is_empty() {
+            anyhow::bail!("AccountQueryRs reports {} accounts remaining but no iteratorID", remaining);
+        }
+        log::debug!("[QBXML] {} account(s) remaining; requesting page {}", remaining, pages.len() + 1);
+        pages.push(processor.run_query(ticket, &account_query_continue_request(&iterator_id, page_size, pages.len() + 1), QBXML_VERSION)?);
+    }
+    if pages.len() == 1 {
+        return Ok(pages.remove(0));
+    }
+    let (merged, count) = merge_account_responses(&pages)?;
+    log::info!("[QBXML] Read {} account(s) in {} pages of up to {}", count, pages.len(), page_size);
+    Ok(merged)
+}
+
+#[cfg(windows)]
+impl QuickBooksProcessor for QbxmlRequestProcessor {
+    fn begin_session(&self, company_file: &str, file_mode: FileMode) -> Result<String> {
+        QbxmlRequestProcessor::begin_session(self, company_file, file_mode)
+    }
+
+    fn process_request(&self, ticket: &str, request: &str) -> Result<String> {
+        QbxmlRequestProcessor::process_request(self, ticket, request)
+    }
+
+    fn end_session(&self, ticket: &str) -> Result<()> {
+        QbxmlRequestProcessor::end_session(self, ticket)
+    }
+
+    fn close_connection(&self) -> Result<()> {
+        QbxmlRequestProcessor::close_connection(self)
+    }
+
+    fn current_company_file_name(&self) -> Result<String> {
+        QbxmlRequestProcessor::get_current_company_file_name(self)
+    }
+
+    fn parser(&self) -> &AccountParser {
+        QbxmlRequestProcessor::parser(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_quickbooks::MockQuickBooks;
+
+    fn mock() -> Result<Box<dyn QuickBooksProcessor>> {
+        Ok(Box::new(MockQuickBooks::default()))
+    }
+
+    fn unavailable() -> Result<Box<dyn QuickBooksProcessor>> {
+        Err(anyhow::anyhow!("Failed to create a QBFC session manager"))
+    }
+
+    fn not_called() -> Result<Box<dyn QuickBooksProcessor>> {
+        panic!("this backend should not be tried")
+    }
+
+    #[test]
+    fn auto_falls_back_to_qbxml_when_qbfc_cannot_be_created() {
+        let (used, _) = connect_backend(Backend::Auto, unavailable, mock).unwrap();
+        assert_eq!(used, Backend::Qbxml);
+    }
+
+    #[test]
+    fn auto_uses_qbfc_when_it_can_be_created() {
+        let (used, _) = connect_backend(Backend::Auto, mock, not_called).unwrap();
+        assert_eq!(used, Backend::Qbfc);
+    }
+
+    #[test]
+    fn auto_does_not_fall_back_when_com_is_not_initialized() {
+        let uninitialized = || -> Result<Box<dyn QuickBooksProcessor>> {
+            Err(QbError::ComNotInitialized { hresult: crate::qb_error::CO_E_NOTINITIALIZED }.into())
+        };
+        let err = connect_backend(Backend::Auto, uninitialized, not_called).err().unwrap();
+        assert!(matches!(err.downcast_ref::<QbError>(), Some(QbError::ComNotInitialized { .. })));
+    }
+
+    #[test]
+    fn explicit_backends_do_not_fall_back() {
+        assert!(connect_backend(Backend::Qbfc, unavailable, not_called).is_err());
+        let (used, _) = connect_backend(Backend::Qbxml, not_called, mock).unwrap();
+        assert_eq!(used, Backend::Qbxml);
+    }
+
+    #[test]
+    fn connection_mode_selects_the_backend() {
+        assert_eq!(Backend::from_connection_mode(Some("auto")), Backend::Auto);
+        assert_eq!(Backend::from_connection_mode(Some("QBFC")), Backend::Qbfc);
+        assert_eq!(Backend::from_connection_mode(Some("multi-user")), Backend::Qbxml);
+        assert_eq!(Backend::from_connection_mode(None), Backend::Qbxml);
+    }
+
+    // Answers each request with the next canned response, keeping the requests it was sent
+    #[derive(Default)]
+    struct CannedResponses {
+        responses: Vec<&'static str>,
+        requests: std::cell::RefCell<Vec<String>>,
+        parser: AccountParser,
+    }
+
+    impl QuickBooksProcessor for CannedResponses {
+        fn begin_session(&self, _company_file: &str, _file_mode: FileMode) -> Result<String> {
+            Ok("ticket".to_string())
+        }
+
+        fn process_request(&self, _ticket: &str, request: &str) -> Result<String> {
+            let mut requests = self.requests.borrow_mut();
+            requests.push(request.to_string());
+            Ok(self.responses[requests.len() - 1].to_string())
+        }
+
+        fn end_session(&self, _ticket: &str) -> Result<()> {
+            Ok(())
+        }
+
+        fn close_connection(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn current_company_file_name(&self) -> Result<String> {
+            Ok(String::new())
+        }
+
+        fn parser(&self) -> &AccountParser {
+            &self.parser
+        }
+    }
+
+    #[test]
+    fn paged_query_follows_the_iterator_to_the_last_page() {
+        let processor = CannedResponses {
+            responses: vec![
+                r#"<QBXML><QBXMLMsgsRs><AccountQueryRs requestID="1" statusCode="0" statusSeverity="Info" iteratorRemainingCount="1" iteratorID="{eb05f701-e727-472f-8ade-6753c4f67a46}">
+                    <AccountRet><FullName>Checking</FullName><Balance>100.00</Balance></AccountRet>
+                    <AccountRet><FullName>Savings</FullName><Balance>200.00</Balance></AccountRet>
+                </AccountQueryRs></QBXMLMsgsRs></QBXML>"#,
+                r#"<QBXML><QBXMLMsgsRs><AccountQueryRs requestID="2" statusCode="0" statusSeverity="Info" iteratorRemainingCount="0" iteratorID="{eb05f701-e727-472f-8ade-6753c4f67a46}">
+                    <AccountRet><FullName>Visa</FullName><Balance>-50.25</Balance></AccountRet>
+                </AccountQueryRs></QBXMLMsgsRs></QBXML>"#,
+            ],
+            ..Default::default()
+        };
+        let options = AccountQueryOptions { page_size: Some(2), ..Default::default() };
+        let xml = processor.get_account_xml("ticket", &options).unwrap().unwrap();
+        let balances = processor.get_account_balances(&xml).unwrap();
+        assert_eq!(balances.len(), 3);
+        assert_eq!(balances["Visa"], -50.25);
+
+        let requests = processor.requests.borrow();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].contains(r#"<AccountQueryRq requestID="1" iterator="Start">"#), "{}", requests[0]);
+        assert!(requests[0].contains("<MaxReturned>2</MaxReturned>"));
+        assert!(requests[1].contains(r#"<AccountQueryRq requestID="2" iterator="Continue" iteratorID="{eb05f701-e727-472f-8ade-6753c4f67a46}">"#), "{}", requests[1]);
+    }
+
+    #[test]
+    fn type_batches_merge_into_the_full_account_set() {
+        const NONE_FOUND: &str = r#"<QBXML><QBXMLMsgsRs><AccountQueryRs requestID="1" statusCode="1" statusSeverity="Info" statusMessage="A query request did not find a matching object in QuickBooks" /></QBXMLMsgsRs></QBXML>"#;
+        let mut responses = vec![NONE_FOUND; ACCOUNT_TYPES.len()];
+        responses[2] = r#"<QBXML><QBXMLMsgsRs><AccountQueryRs requestID="1" statusCode="0" statusSeverity="Info">
+            <AccountRet><FullName>Checking</FullName><AccountType>Bank</AccountType><Balance>100.00</Balance></AccountRet>
+            <AccountRet><FullName>Savings</FullName><AccountType>Bank</AccountType><Balance>200.00</Balance></AccountRet>
+        </AccountQueryRs></QBXMLMsgsRs></QBXML>"#;
+        // Savings again, as if it moved type between batches: kept once
+        responses[4] = r#"<QBXML><QBXMLMsgsRs><AccountQueryRs requestID="1" statusCode="0" statusSeverity="Info">
+            <AccountRet><FullName>Visa</FullName><AccountType>CreditCard</AccountType><Balance>-50.25</Balance></AccountRet>
+            <AccountRet><FullName>Savings</FullName><AccountType>Bank</AccountType><Balance>200.00</Balance></AccountRet>
+        </AccountQueryRs></QBXMLMsgsRs></QBXML>"#;
+        let processor = CannedResponses { responses, ..Default::default() };
+        let options = AccountQueryOptions { by_type_batches: true, ..Default::default() };
+        let xml = processor.get_account_xml("ticket", &options).unwrap().unwrap();
+        let balances = processor.get_account_balances(&xml).unwrap();
+        let mut names: Vec<&str> = balances.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        assert_eq!(names, ["Checking", "Savings", "Visa"]);
+        assert_eq!(balances["Visa"], -50.25);
+
+        let requests = processor.requests.borrow();
+        assert_eq!(requests.len(), ACCOUNT_TYPES.len());
+        assert!(requests[2].contains("<AccountType>Bank</AccountType>"), "{}", requests[2]);
+        assert!(requests[4].contains("<AccountType>CreditCard</AccountType>"), "{}", requests[4]);
+    }
+
+    #[test]
+    fn each_connection_type_maps_to_its_qbxmlrp_constant() {
+        use figment::{Figment, providers::{Format, Toml}};
+
+        let connection_type = |value: &str| {
+            let toml = format!("company_file = \"AUTO\"\nconnection_type = \"{}\"", value);
+            Figment::from(Toml::string(&toml)).extract::<crate::config::QuickBooksConfig>().ok().map(|qb| qb.connection_type)
+        };
+        let constants: Vec<i32> = ["local", "remote", "local_with_ui", "remote_qboe"].iter()
+            .map(|value| connection_type(value).unwrap().unwrap().as_qbxmlrp())
+            .collect();
+        // localQBD, remoteQBD, localQBDLaunchUI, remoteQBOE
+        assert_eq!(constants, [1, 2, 3, 4]);
+        assert_eq!(ConnectionType::default(), ConnectionType::Local);
+        assert!(connection_type("LocalWithUI").is_none());
+    }
+}
@@ -0,0 +1,314 @@
+// Sync report and run manifest
+// Records what each block did during a run; it is printed as JSON at the end of every run (or
+// written to --report-file), and `--manifest` adds it to a versioned record of the run
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::config::AccountSyncConfig;
+
+/// Bump when a manifest field is renamed or removed; adding fields keeps the version
+pub const MANIFEST_SCHEMA_VERSION: u32 = 1;
+
+/// This run's ID (a random UUID, fixed for the life of the process). Every Sheets request
+/// carries it as runId, so a cell's value can be traced back to the run that wrote it.
+pub fn run_id() -> &'static str {
+    static RUN_ID: OnceLock<String> = OnceLock::new();
+    RUN_ID.get_or_init(|| uuid::Uuid::new_v4().to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockStatus {
+    Written,
+    Missing,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockOutcome {
+    // Position of the sync block in the config (TOML blocks, then CSV rows)
+    pub block_index: usize,
+    pub account_full_name: String,
+    pub spreadsheet_id: String,
+    pub sheet_name: String,
+    pub cells: Vec<String>,
+    pub status: BlockStatus,
+    // The balance written, after sign conventions
+    pub value: Option<f64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncReport {
+    pub company_file: Option<String>,
+    pub quickbooks_product: Option<String>,
+    // "live", "cached" or "no_data"
+    pub source: Option<String>,
+    pub blocks: Vec<BlockOutcome>,
+    // One entry per output kind (sync blocks, type_report, customer_sync, ...) that failed
+    #[serde(default)]
+    pub output_errors: Vec<String>,
+}
+
+impl SyncReport {
+    pub fn record(&mut self, block_index: usize, sync: &AccountSyncConfig, status: BlockStatus, value: Option<f64>, error: Option<String>) {
+        self.blocks.push(BlockOutcome {
+            block_index,
+            account_full_name: sync.account_full_name.clone(),
+            spreadsheet_id: sync.spreadsheet_id.clone(),
+            sheet_name: sync.sheet_name.clone(),
+            cells: sync.cell_address.addresses().to_vec(),
+            status,
+            value,
+            error,
+        });
+    }
+
+    /// Note a failed output kind; the run carries on with the others and fails at the end
+    pub fn record_output(&mut self, output: &str, result: Result<()>) {
+        if let Err(e) = result {
+            self.output_errors.push(format!("{}: {:#}", output, e));
+        }
+    }
+
+    /// Err listing every failed output kind, once all of them have been attempted
+    pub fn outputs_result(&self) -> Result<()> {
+        if !self.output_errors.is_empty() {
+            anyhow::bail!("{} output(s) failed: {}", self.output_errors.len(), self.output_errors.join("; "));
+        }
+        Ok(())
+    }
+
+    /// block_index of each failed block, in config order
+    pub fn failed_blocks(&self) -> Vec<usize> {
+        let mut failed: Vec<usize> = self.blocks.iter()
+            .filter(|block| block.status == BlockStatus::Failed)
+            .map(|block| block.block_index)
+            .collect();
+        failed.sort_unstable();
+        failed.dedup();
+        failed
+    }
+
+    /// Write the report as JSON to `path`, or print it to stdout as one line when there is none
+    pub fn emit(&self, path: Option<&Path>) -> Result<()> {
+        let Some(path) = path else {
+            println!("{}", serde_json::to_string(self)?);
+            return Ok(());
+        };
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create report directory {}", dir.display()))?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write sync report {}", path.display()))
+    }
+
+    /// Put blocks in config order (then by account name) so reports from runs whose writes
+    /// finished in a different order still compare equal
+    pub fn sort_blocks(&mut self) {
+        self.blocks.sort_by(|a, b| {
+            (a.block_index, &a.account_full_name).cmp(&(b.block_index, &b.account_full_name))
+        });
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunManifest {
+    pub schema_version: u32,
+    pub run_id: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub duration_ms: i64,
+    pub config_hash: String,
+    pub qbxml_version: String,
+    pub succeeded: bool,
+    pub error: Option<String>,
+    // block_index of every block that failed, for `--retry-failed`
+    #[serde(default)]
+    pub failed_blocks: Vec<usize>,
+    #[serde(flatten)]
+    pub report: SyncReport,
+}
+
+impl RunManifest {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read run manifest {}", path.display()))?;
+        let manifest: RunManifest = serde_json::from_str(&text)
+            .with_context(|| format!("Failed to parse run manifest {}", path.display()))?;
+        if manifest.schema_version != MANIFEST_SCHEMA_VERSION {
+            anyhow::bail!(
+                "Run manifest {} has schema version {}; this build reads version {}",
+                path.display(), manifest.schema_version, MANIFEST_SCHEMA_VERSION
+            );
+        }
+        Ok(manifest)
+    }
+
+    /// Write the manifest to `path`, or to stdout as one JSON object when `path` is "-"
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if path == Path::new("-") {
+            println!("{}", serde_json::to_string(self)?);
+            return Ok(());
+        }
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create manifest directory {}", dir.display()))?;
+        }
+        let text = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, text)
+            .with_context(|| format!("Failed to write run manifest {}", path.display()))
+    }
+}
+
+/// Stable 64-bit FNV-1a hash of the config file, hex encoded, so manifests from the same
+/// config compare equal across builds
+pub fn config_hash(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf29ce484222325u64, |hash, b| (hash ^ *b as u64).wrapping_mul(0x100000001b3));
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AccountSyncConfig, CellTargets};
+
+    fn sync(account: &str) -> AccountSyncConfig {
+        AccountSyncConfig {
+            spreadsheet_id: "sheet".to_string(),
+            account_full_name: account.to_string(),
+            sheet_name: "Balances".to_string(),
+            cell_address: CellTargets::One("B2".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn manifest(report: SyncReport) -> RunManifest {
+        let started_at = DateTime::parse_from_rfc3339("2026-01-05T09:00:00Z").unwrap().with_timezone(&Utc);
+        RunManifest {
+            schema_version: MANIFEST_SCHEMA_VERSION,
+            run_id: "run-1".to_string(),
+            started_at,
+            finished_at: started_at,
+            duration_ms: 0,
+            config_hash: "hash".to_string(),
+            qbxml_version: "13.0".to_string(),
+            succeeded: false,
+            error: Some("1 output(s) failed".to_string()),
+            failed_blocks: report.failed_blocks(),
+            report,
+        }
+    }
+
+    #[test]
+    fn failed_blocks_are_sorted_and_unique() {
+        let mut report = SyncReport::default();
+        report.record(3, &sync("Visa"), BlockStatus::Failed, None, Some("HTTP 500".to_string()));
+        report.record(0, &sync("Checking"), BlockStatus::Written, Some(10.0), None);
+        report.record(1, &sync("Savings"), BlockStatus::Failed, None, None);
+        report.record(3, &sync("Visa"), BlockStatus::Failed, None, None);
+        assert_eq!(report.failed_blocks(), [1, 3]);
+        report.sort_blocks();
+        let order: Vec<usize> = report.blocks.iter().map(|block| block.block_index).collect();
+        assert_eq!(order, [0, 1, 3, 3]);
+    }
+
+    #[test]
+    fn shuffled_completion_orders_report_identically() {
+        let outcomes = [
+            (0, "Checking", BlockStatus::Written, Some(10.0)),
+            (1, "Visa", BlockStatus::Failed, None),
+            (2, "Loans:Long Term", BlockStatus::Written, Some(-5.0)),
+            (2, "Loans", BlockStatus::Written, Some(-7.5)),
+            (3, "Payroll", BlockStatus::Missing, None),
+        ];
+        let run = |order: &[usize]| {
+            let mut report = SyncReport::default();
+            for &i in order {
+                let (index, account, status, value) = outcomes[i];
+                report.record(index, &sync(account), status, value, None);
+            }
+            report.sort_blocks();
+            serde_json::to_string(&manifest(report)).unwrap()
+        };
+        let first = run(&[0, 1, 2, 3, 4]);
+        assert_eq!(first, run(&[4, 2, 0, 3, 1]));
+        assert_eq!(first, run(&[3, 1, 4, 2, 0]));
+        let value: serde_json::Value = serde_json::from_str(&first).unwrap();
+        let accounts: Vec<&str> = value["blocks"].as_array().unwrap().iter().map(|block| block["account_full_name"].as_str().unwrap()).collect();
+        assert_eq!(accounts, ["Checking", "Visa", "Loans", "Loans:Long Term", "Payroll"]);
+    }
+
+    #[test]
+    fn manifest_flattens_the_report_and_round_trips() {
+        let mut report = SyncReport { source: Some("live".to_string()), ..Default::default() };
+        report.record(0, &sync("Checking"), BlockStatus::Written, Some(10.0), None);
+        report.record(1, &sync("Visa"), BlockStatus::Failed, None, Some("HTTP 500".to_string()));
+        let manifest = manifest(report);
+        let json = serde_json::to_string(&manifest).unwrap();
+        // --manifest - prints it as a single line
+        assert!(!json.contains('\n'));
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["source"], "live");
+        assert_eq!(value["failed_blocks"], serde_json::json!([1]));
+        assert_eq!(value["blocks"][1]["status"], "failed");
+        assert_eq!(value["blocks"][0]["cells"], serde_json::json!(["B2"]));
+
+        let path = std::env::temp_dir().join(format!("qb_sync_manifest_{}.json", std::process::id()));
+        manifest.save(&path).unwrap();
+        let loaded = RunManifest::load(&path);
+        std::fs::remove_file(&path).ok();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.report.blocks.len(), 2);
+        assert_eq!(loaded.failed_blocks, [1]);
+        assert_eq!(loaded.started_at, manifest.started_at);
+    }
+
+    #[test]
+    fn report_file_holds_every_block_outcome() {
+        let mut report = SyncReport { source: Some("live".to_string()), ..Default::default() };
+        report.record(0, &sync("Checking"), BlockStatus::Written, Some(1234.56), None);
+        report.record(1, &sync("Visa"), BlockStatus::Failed, None, Some("HTTP 500".to_string()));
+        report.record(2, &sync("Payroll"), BlockStatus::Missing, None, None);
+        let path = std::env::temp_dir().join(format!("qb_sync_report_{}.json", std::process::id()));
+        report.emit(Some(&path)).unwrap();
+        let text = std::fs::read_to_string(&path);
+        std::fs::remove_file(&path).ok();
+        let value: serde_json::Value = serde_json::from_str(&text.unwrap()).unwrap();
+        let statuses: Vec<&str> = value["blocks"].as_array().unwrap().iter().map(|block| block["status"].as_str().unwrap()).collect();
+        assert_eq!(statuses, ["written", "failed", "missing"]);
+        assert_eq!(value["blocks"][0]["value"], 1234.56);
+        assert_eq!(value["blocks"][1]["error"], "HTTP 500");
+        assert_eq!(value["source"], "live");
+    }
+
+    #[test]
+    fn manifests_from_another_schema_version_are_rejected() {
+        let mut manifest = manifest(SyncReport::default());
+        manifest.schema_version = MANIFEST_SCHEMA_VERSION + 1;
+        let path = std::env::temp_dir().join(format!("qb_sync_manifest_v2_{}.json", std::process::id()));
+        manifest.save(&path).unwrap();
+        let loaded = RunManifest::load(&path);
+        std::fs::remove_file(&path).ok();
+        let expected = format!("has schema version {}; this build reads version {}", MANIFEST_SCHEMA_VERSION + 1, MANIFEST_SCHEMA_VERSION);
+        assert!(loaded.unwrap_err().to_string().contains(&expected));
+    }
+
+    #[test]
+    fn failed_outputs_are_collected_and_reported_together() {
+        let mut report = SyncReport::default();
+        report.record_output("sync blocks", Ok(()));
+        assert!(report.outputs_result().is_ok());
+        report.record_output("type_report", Err(anyhow::anyhow!("HTTP 500")));
+        report.record_output("customer_sync", Ok(()));
+        report.record_output("file_modified", Err(anyhow::anyhow!("timed out")));
+        assert_eq!(report.output_errors, ["type_report: HTTP 500", "file_modified: timed out"]);
+        let error = report.outputs_result().unwrap_err().to_string();
+        assert_eq!(error, "2 output(s) failed: type_report: HTTP 500; file_modified: timed out");
+    }
+}
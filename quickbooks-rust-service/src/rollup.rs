@@ -0,0 +1,63 @@
+// Subaccount rollup advisory
+// Flags configs that would double-count a subaccount in any sheet-side sum
+
+use crate::config::Config;
+
+/// One warning per (parent, child) pair where a block writes the parent's TotalBalance (which
+/// already includes its subaccounts) and another block writes a subaccount beneath it.
+/// Subaccounts are recognized from QuickBooks full names ("Parent:Child").
+pub fn double_count_warnings(config: &Config) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for parent in config.sync_blocks.iter().filter(|b| b.total_balance.unwrap_or(false)) {
+        let prefix = format!("{}:", parent.account_full_name);
+        for child in config.sync_blocks.iter().filter(|b| b.account_full_name.starts_with(&prefix)) {
+            warnings.push(format!(
+                "'{}' is written with total_balance, which already includes subaccount '{}' that is also written to '{}'!{}; summing both in the sheet would count it twice",
+                parent.account_full_name,
+                child.account_full_name,
+                child.sheet_name,
+                child.cell_address.addresses().first().map(String::as_str).unwrap_or(""),
+            ));
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use figment::{Figment, providers::{Format, Toml}};
+
+    // One block per (account, total_balance, cell)
+    fn config(blocks: &[(&str, bool, &str)]) -> Config {
+        let mut toml = r#"
+            [quickbooks]
+            company_file = "AUTO"
+
+            [google_sheets]
+            webapp_url = "https://script.google.com/macros/s/abc123/exec"
+            api_key = "key"
+        "#.to_string();
+        for (account, total_balance, cell) in blocks {
+            toml.push_str(&format!(
+                "\n[[sync_blocks]]\nspreadsheet_id = \"1AbC\"\naccount_full_name = \"{}\"\nsheet_name = \"Balances\"\ncell_address = \"{}\"\ntotal_balance = {}\n",
+                account, cell, total_balance
+            ));
+        }
+        Figment::from(Toml::string(&toml)).extract().unwrap()
+    }
+
+    #[test]
+    fn a_parent_total_and_its_subaccount_are_flagged() {
+        let warnings = double_count_warnings(&config(&[("Loans", true, "B2"), ("Loans:Long Term", false, "B3")]));
+        assert_eq!(warnings, ["'Loans' is written with total_balance, which already includes subaccount 'Loans:Long Term' that is also written to 'Balances'!B3; summing both in the sheet would count it twice"]);
+    }
+
+    #[test]
+    fn non_overlapping_mappings_are_not_flagged() {
+        // The parent's own Balance excludes its subaccounts
+        assert!(double_count_warnings(&config(&[("Loans", false, "B2"), ("Loans:Long Term", false, "B3")])).is_empty());
+        // A shared name prefix without the ':' separator is not a subaccount
+        assert!(double_count_warnings(&config(&[("Loans", true, "B2"), ("Loans Payable", false, "B3")])).is_empty());
+    }
+}
@@ -0,0 +1,34 @@
+// Row blocks
+// A block of rows written from an anchor, plus the stale rows beneath it to clear
+
+use anyhow::Result;
+
+use crate::a1::{CellRange, CellRef};
+use crate::google_sheets::CellValue;
+
+/// The rows to write at the anchor and the trailing area of `clear_range` to clear beneath them
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowBlock {
+    pub rows: Vec<Vec<CellValue>>,
+    pub clear: Option<CellRange>,
+}
+
+impl RowBlock {
+    /// `clear_range` is the area the block may occupy; whatever part of it lies below the
+    /// rows written this run is cleared so entries from a longer previous run don't linger
+    pub fn new(anchor: &str, rows: Vec<Vec<CellValue>>, clear_range: Option<&str>) -> Result<Self> {
+        let anchor = CellRef::parse(anchor)?;
+        let first_unused_row = anchor.row + rows.len() as u32;
+        let clear = match clear_range {
+            Some(range) => {
+                let range = CellRange::parse(range)?;
+                (range.end.row >= first_unused_row).then(|| CellRange {
+                    start: CellRef { col: range.start.col, row: range.start.row.max(first_unused_row) },
+                    end: range.end,
+                })
+            },
+            None => None,
+        };
+        Ok(Self { rows, clear })
+    }
+}
@@ -0,0 +1,149 @@
+// Secret providers
+// Resolve the web app API key from config.toml, an environment variable or the OS credential store
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::config::{ApiKeySource, GoogleSheetsConfig};
+
+/// Environment variable read by `api_key_source = "env"` when `api_key_env` is not set
+pub const DEFAULT_API_KEY_ENV: &str = "QB_SHEETS_API_KEY";
+/// Keyring service/user looked up by `api_key_source = "keyring"` unless overridden
+#[cfg(feature = "keyring")]
+pub const DEFAULT_KEYRING_SERVICE: &str = "quickbooks-sheets-sync";
+#[cfg(feature = "keyring")]
+pub const DEFAULT_KEYRING_USER: &str = "api_key";
+
+/// Somewhere a secret can be read from
+pub trait SecretProvider {
+    /// Where the secret comes from, for error messages (never the secret itself)
+    fn describe(&self) -> String;
+    fn get(&self) -> Result<String>;
+}
+
+/// The value written in config.toml
+pub struct InlineSecret(pub String);
+
+impl SecretProvider for InlineSecret {
+    fn describe(&self) -> String {
+        "config.toml".to_string()
+    }
+
+    fn get(&self) -> Result<String> {
+        Ok(self.0.clone())
+    }
+}
+
+/// A named environment variable
+pub struct EnvSecret {
+    pub var: String,
+}
+
+impl SecretProvider for EnvSecret {
+    fn describe(&self) -> String {
+        format!("environment variable {}", self.var)
+    }
+
+    fn get(&self) -> Result<String> {
+        std::env::var(&self.var).with_context(|| format!("{} is not set", self.describe()))
+    }
+}
+
+/// An entry in the OS credential store (Windows Credential Manager)
+#[cfg(feature = "keyring")]
+pub struct KeyringSecret {
+    pub service: String,
+    pub user: String,
+}
+
+#[cfg(feature = "keyring")]
+impl SecretProvider for KeyringSecret {
+    fn describe(&self) -> String {
+        format!("keyring entry {}/{}", self.service, self.user)
+    }
+
+    fn get(&self) -> Result<String> {
+        keyring::Entry::new(&self.service, &self.user)
+            .and_then(|entry| entry.get_password())
+            .with_context(|| format!("Failed to read {}", self.describe()))
+    }
+}
+
+/// The provider `api_key_source` selects
+pub fn api_key_provider(config: &GoogleSheetsConfig) -> Result<Box<dyn SecretProvider>> {
+    match config.api_key_source.unwrap_or_default() {
+        ApiKeySource::Inline => Ok(Box::new(InlineSecret(config.api_key.clone()))),
+        ApiKeySource::Env => Ok(Box::new(EnvSecret {
+            var: config.api_key_env.clone().unwrap_or_else(|| DEFAULT_API_KEY_ENV.to_string()),
+        })),
+        #[cfg(feature = "keyring")]
+        ApiKeySource::Keyring => Ok(Box::new(KeyringSecret {
+            service: config.keyring_service.clone().unwrap_or_else(|| DEFAULT_KEYRING_SERVICE.to_string()),
+            user: config.keyring_user.clone().unwrap_or_else(|| DEFAULT_KEYRING_USER.to_string()),
+        })),
+        #[cfg(not(feature = "keyring"))]
+        ApiKeySource::Keyring => Err(anyhow!(
+            "api_key_source = \"keyring\" requires a build with the keyring feature (cargo build --features keyring)"
+        )),
+    }
+}
+
+/// Read the API key from its configured source; an empty key is an error unless it is inline
+/// (the sheets_api backend needs no key)
+pub fn resolve_api_key(config: &GoogleSheetsConfig) -> Result<String> {
+    let provider = api_key_provider(config)?;
+    let key = provider.get()?;
+    if key.trim().is_empty() && config.api_key_source.unwrap_or_default() != ApiKeySource::Inline {
+        return Err(anyhow!("The API key from {} is empty", provider.describe()));
+    }
+    Ok(key.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use figment::{Figment, providers::{Format, Toml}};
+
+    fn google_sheets(toml: &str) -> GoogleSheetsConfig {
+        let toml = format!("webapp_url = \"https://script.google.com/macros/s/abc123/exec\"\n{}", toml);
+        Figment::from(Toml::string(&toml)).extract().unwrap()
+    }
+
+    #[test]
+    fn inline_keys_come_from_the_config() {
+        assert_eq!(resolve_api_key(&google_sheets("api_key = \" inline-key \"")).unwrap(), "inline-key");
+        // The sheets_api backend needs no key
+        assert_eq!(resolve_api_key(&google_sheets("api_key = \"\"\napi_key_source = \"inline\"")).unwrap(), "");
+    }
+
+    #[test]
+    fn env_keys_come_from_the_named_variable() {
+        let var = format!("QB_SYNC_TEST_API_KEY_{}", std::process::id());
+        let config = google_sheets(&format!("api_key = \"ignored\"\napi_key_source = \"env\"\napi_key_env = \"{}\"", var));
+        let err = resolve_api_key(&config).unwrap_err();
+        assert_eq!(err.to_string(), format!("environment variable {} is not set", var));
+        std::env::set_var(&var, "  ");
+        assert_eq!(resolve_api_key(&config).unwrap_err().to_string(), format!("The API key from environment variable {} is empty", var));
+        std::env::set_var(&var, "env-key\n");
+        assert_eq!(resolve_api_key(&config).unwrap(), "env-key");
+        std::env::remove_var(&var);
+        assert_eq!(api_key_provider(&google_sheets("api_key = \"\"\napi_key_source = \"env\"")).unwrap().describe(), "environment variable QB_SHEETS_API_KEY");
+    }
+
+    #[cfg(feature = "keyring")]
+    #[test]
+    fn keyring_keys_are_read_from_the_credential_store() {
+        keyring::set_default_credential_builder(keyring::mock::default_credential_builder());
+        let config = google_sheets("api_key = \"\"\napi_key_source = \"keyring\"\nkeyring_user = \"ci\"");
+        let provider = api_key_provider(&config).unwrap();
+        assert_eq!(provider.describe(), "keyring entry quickbooks-sheets-sync/ci");
+        // The mock store starts empty
+        assert_eq!(provider.get().unwrap_err().to_string(), "Failed to read keyring entry quickbooks-sheets-sync/ci");
+    }
+
+    #[cfg(not(feature = "keyring"))]
+    #[test]
+    fn keyring_needs_the_feature() {
+        let err = api_key_provider(&google_sheets("api_key = \"\"\napi_key_source = \"keyring\"")).err().unwrap();
+        assert!(err.to_string().contains("--features keyring"), "{}", err);
+    }
+}
@@ -0,0 +1,548 @@
+// QuickBooks session lifecycle
+// Connects and begins a session, then guarantees it is ended, closed and COM released
+
+use anyhow::{Context, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::config::Config;
+use crate::qb_error::QbError;
+use crate::quickbooks::QuickBooksProcessor;
+use crate::qbxml_safe::com;
+use crate::qbxml_safe::qbxml_request_processor::{
+    balance_sheet_request, begin_session_with_retry, company_ein, count_transactions, customer_query_request,
+    last_activity_request, latest_time_modified, product_name, transaction_count_request, AccountQueryOptions, COMPANY_QUERY_REQUEST, QBXML_VERSION,
+};
+#[cfg(windows)]
+use crate::qbxml_safe::qbxml_request_processor::QbxmlRequestProcessor;
+#[cfg(windows)]
+use crate::qbfc_safe::qbfc_request_processor::{QbfcSessionManager, RequestProcessor2};
+#[cfg(windows)]
+use crate::quickbooks::{connect_backend, Backend};
+
+// Ctrl+C while a session is open marks a shutdown: the next session query fails, the session is
+// released as usual and the process then exits. With no session open, Ctrl+C exits at once.
+struct Shutdown {
+    session_open: AtomicBool,
+    requested: AtomicBool,
+}
+
+impl Shutdown {
+    const fn new() -> Self {
+        Self { session_open: AtomicBool::new(false), requested: AtomicBool::new(false) }
+    }
+
+    // True when Ctrl+C should exit at once: no session is open, or this is the second Ctrl+C
+    fn ctrl_c(&self) -> bool {
+        !self.session_open.load(Ordering::SeqCst) || self.requested.swap(true, Ordering::SeqCst)
+    }
+
+    fn requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.requested() {
+            anyhow::bail!("Interrupted by Ctrl+C");
+        }
+        Ok(())
+    }
+}
+
+static SHUTDOWN: Shutdown = Shutdown::new();
+
+/// Exit code of a run stopped by Ctrl+C (128 + SIGINT, as shells report it)
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Handle Ctrl+C for the one-shot sync so an interrupted run still ends its QuickBooks session.
+/// A second Ctrl+C exits immediately.
+pub fn install_ctrl_c_handler() {
+    tokio::spawn(async {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            if SHUTDOWN.ctrl_c() {
+                std::process::exit(INTERRUPTED_EXIT_CODE);
+            }
+            log::warn!("[QBXML] Ctrl+C: ending the QuickBooks session before exiting (press again to exit now)");
+        }
+    });
+}
+
+/// An open session. `end` releases it explicitly; otherwise dropping it (including during a
+/// panic) ends the session, closes the connection and, when this session initialized COM,
+/// uninitializes it, logging cleanup errors instead of raising them.
+pub struct Session<P: QuickBooksProcessor = Box<dyn QuickBooksProcessor>> {
+    processor: P,
+    ticket: String,
+    owns_com: bool,
+    // Cleared by `end` so Drop doesn't release twice
+    open: bool,
+    shutdown: &'static Shutdown,
+}
+
+impl<P: QuickBooksProcessor> Session<P> {
+    pub fn new(processor: P, ticket: String, owns_com: bool) -> Self {
+        Self::open(processor, ticket, owns_com, &SHUTDOWN)
+    }
+
+    fn open(processor: P, ticket: String, owns_com: bool, shutdown: &'static Shutdown) -> Self {
+        shutdown.session_open.store(true, Ordering::SeqCst);
+        Self { processor, ticket, owns_com, open: true, shutdown }
+    }
+
+    pub fn processor(&self) -> &P {
+        &self.processor
+    }
+
+    /// End the session and close the connection now, returning the first failure
+    pub fn end(mut self) -> Result<()> {
+        self.open = false;
+        let ended = self.processor.end_session(&self.ticket).context("Failed to end session");
+        let closed = self.processor.close_connection().context("Failed to close connection");
+        if self.owns_com {
+            com::uninitialize();
+        }
+        self.shutdown.session_open.store(false, Ordering::SeqCst);
+        ended.and(closed)
+    }
+}
+
+// Queries scoped to this session's ticket, so callers never pass one around
+impl<P: QuickBooksProcessor> Session<P> {
+    pub fn account_xml(&self, options: &AccountQueryOptions) -> Result<Option<String>> {
+        self.shutdown.check()?;
+        self.processor.get_account_xml(&self.ticket, options)
+    }
+
+    /// Number of distinct transactions posted to the account between `from` and `to` (inclusive)
+    pub fn transaction_count(&self, account_full_name: &str, from: chrono::NaiveDate, to: chrono::NaiveDate) -> Result<usize> {
+        let response = self.query(&transaction_count_request(account_full_name, from, to))?;
+        count_transactions(&response)
+    }
+
+    /// When a transaction was last added or changed, looking back no further than `since`
+    pub fn last_activity(&self, since: chrono::DateTime<chrono::Utc>) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+        latest_time_modified(&self.query(&last_activity_request(since))?)
+    }
+
+    /// The customer's open A/R Balance (excluding its jobs), or None when QuickBooks has no such customer
+    pub fn customer_balance(&self, customer_full_name: &str) -> Result<Option<f64>> {
+        let response = self.query(&customer_query_request(customer_full_name))?;
+        self.processor.parser().get_customer_balance(&response, customer_full_name)
+    }
+
+    /// Standard balance sheet report as of `as_of`, as raw response XML
+    pub fn balance_sheet(&self, as_of: chrono::NaiveDate) -> Result<String> {
+        self.query(&balance_sheet_request(as_of))
+    }
+
+    /// ProductName from a HostQuery, e.g. "QuickBooks Enterprise Solutions: Accountant Edition 24.0"
+    pub fn product_name(&self) -> Result<Option<String>> {
+        self.shutdown.check()?;
+        product_name(&self.processor.host_query(&self.ticket)?)
+    }
+
+    pub fn company_file_name(&self) -> Result<String> {
+        self.shutdown.check()?;
+        self.processor.current_company_file_name()
+    }
+
+    /// The open company's EIN, the identifier that tells apart files with the same name
+    pub fn company_ein(&self) -> Result<Option<String>> {
+        company_ein(&self.query(COMPANY_QUERY_REQUEST)?)
+    }
+
+    fn query(&self, request_body: &str) -> Result<String> {
+        self.shutdown.check()?;
+        self.processor.run_query(&self.ticket, request_body, QBXML_VERSION)
+    }
+}
+
+impl<P: QuickBooksProcessor> Drop for Session<P> {
+    fn drop(&mut self) {
+        if !self.open {
+            return;
+        }
+        if let Err(e) = self.processor.end_session(&self.ticket) {
+            log::warn!("[QBXML] Failed to end session: {:#}", e);
+        }
+        if let Err(e) = self.processor.close_connection() {
+            log::warn!("[QBXML] Failed to close connection: {:#}", e);
+        }
+        if self.owns_com {
+            com::uninitialize();
+        }
+        self.shutdown.session_open.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Run `f` against `session`, then release it however `f` returns (including by panicking).
+/// A failure to release the session is only logged. If Ctrl+C arrived meanwhile, the process
+/// exits once the session is released.
+pub fn run_in_session<P, F, T>(session: Session<P>, f: F) -> Result<T>
+where
+    P: QuickBooksProcessor,
+    F: FnOnce(&Session<P>) -> Result<T>,
+{
+    let result = f(&session);
+    let shutdown = session.shutdown;
+    if let Err(e) = session.end() {
+        log::warn!("[QBXML] {:#}", e);
+    }
+    if shutdown.requested() {
+        log::warn!("[QBXML] QuickBooks session ended; exiting after Ctrl+C");
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+    result
+}
+
+/// Start QuickBooks (retrying the startup once if configured), run `f` in the session and clean up
+pub async fn with_quickbooks_session<F, T>(config: &Config, f: F) -> Result<T>
+where
+    F: FnOnce(&Session) -> Result<T>,
+{
+    let (processor, ticket) = start_quickbooks_with_retry(config).await?;
+    run_in_session(Session::new(processor, ticket, true), |session| {
+        verify_company(config, session)?;
+        f(session)
+    })
+}
+
+/// Fail with QbError::CompanyMismatch when expected_company_ein is set and the open file differs
+fn verify_company(config: &Config, session: &Session) -> Result<()> {
+    let Some(expected) = config.quickbooks.expected_company_ein.as_deref() else {
+        return Ok(());
+    };
+    let found = session.company_ein()
+        .context("Failed to query company info to check expected_company_ein")?;
+    if !ein_matches(expected, found.as_deref()) {
+        return Err(QbError::CompanyMismatch { expected: expected.to_string(), found }.into());
+    }
+    log::info!("[QBXML] Company EIN matches expected_company_ein");
+    Ok(())
+}
+
+// EINs are compared on their alphanumerics so "12-3456789" matches "123456789"
+fn ein_matches(expected: &str, found: Option<&str>) -> bool {
+    let normalize = |ein: &str| ein.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>();
+    found.is_some_and(|found| normalize(found) == normalize(expected))
+}
+
+/// Initialize COM, create the processor, connect and begin a session.
+/// On failure everything acquired so far is released (including COM) so the caller can start over cleanly.
+fn start_quickbooks(config: &Config) -> Result<(Box<dyn QuickBooksProcessor>, String)> {
+    com::initialize()?;
+    let processor = match connect(config) {
+        Ok(processor) => processor,
+        Err(e) => {
+            com::uninitialize();
+            return Err(e);
+        }
+    };
+
+    let company_file = match config.quickbooks.company_file.as_str() { "AUTO" => "", path => path };
+    log::debug!("[QBXML] Company file: {}", company_file);
+    let retry = config.quickbooks.session_retry();
+    match begin_session_with_retry(&retry, || processor.begin_session(company_file, crate::FileMode::DoNotCare)) {
+        Ok(ticket) => Ok((processor, ticket)),
+        Err(e) => {
+            let e = if company_file.is_empty() && e.downcast_ref::<QbError>().is_none() && no_file_open(&processor) {
+                e.context(QbError::NoCompanyFile { hresult: None })
+            } else {
+                e
+            };
+            let _ = processor.close_connection();
+            com::uninitialize();
+            Err(e)
+        }
+    }
+}
+
+/// Create the processor for the configured backend and open its connection. Runs on the thread
+/// that initialized COM.
+#[cfg(windows)]
+pub fn connect(config: &Config) -> Result<Box<dyn QuickBooksProcessor>> {
+    let backend = Backend::from_connection_mode(config.quickbooks.connection_mode.as_deref());
+    let (_, processor) = connect_backend(backend, || connect_qbfc(config), || connect_qbxml(config))?;
+    Ok(processor)
+}
+
+#[cfg(windows)]
+fn connect_qbxml(config: &Config) -> Result<Box<dyn QuickBooksProcessor>> {
+    let processor = QbxmlRequestProcessor::with_progid_retry(&config.quickbooks.progid_retry())
+        .context("Failed to create QBXML request processor")?
+        .with_amount_format(config.quickbooks.amount_format());
+    let (app_id, app_name) = app_identity(config);
+    // OpenConnection2 only when a connection type is configured; plain OpenConnection is local
+    match config.quickbooks.connection_type {
+        Some(connection_type) => {
+            log::info!("[QBXML] Opening a {:?} connection", connection_type);
+            processor.open_connection2(app_name, connection_type)?;
+        },
+        None => processor.open_connection(app_id, app_name)?,
+    }
+    Ok(Box::new(processor))
+}
+
+#[cfg(windows)]
+fn connect_qbfc(config: &Config) -> Result<Box<dyn QuickBooksProcessor>> {
+    let session = QbfcSessionManager::create(&config.quickbooks.progid_retry())?;
+    let processor = RequestProcessor2::new(session).with_amount_format(config.quickbooks.amount_format());
+    let (_, app_name) = app_identity(config);
+    // As with QBXML, AppID stays empty to avoid accidental registration
+    processor.open_connection("", app_name, config.quickbooks.connection_type)?;
+    Ok(Box::new(processor))
+}
+
+#[cfg(windows)]
+fn app_identity(config: &Config) -> (&str, &str) {
+    let app_id = config.quickbooks.application_id.as_deref().unwrap_or("QuickBooks-Sheets-Sync");
+    let app_name = config.quickbooks.application_name.as_deref().unwrap_or("QuickBooks Sheets Sync");
+    (app_id, app_name)
+}
+
+/// QuickBooks Desktop is only reachable through COM, so elsewhere only the mock and saved
+/// responses can stand in for it
+#[cfg(not(windows))]
+pub fn connect(_config: &Config) -> Result<Box<dyn QuickBooksProcessor>> {
+    anyhow::bail!("QuickBooks Desktop can only be queried on Windows; use --mock <path> or --from-response <path> on this platform")
+}
+
+// QuickBooks reports an empty current file name when it is running without a company file open
+fn no_file_open<P: QuickBooksProcessor + ?Sized>(processor: &P) -> bool {
+    processor.current_company_file_name().is_ok_and(|name| name.is_empty())
+}
+
+async fn start_quickbooks_with_retry(config: &Config) -> Result<(Box<dyn QuickBooksProcessor>, String)> {
+    retry_startup(config, start_quickbooks).await
+}
+
+/// Run `start`, and once more after the startup backoff if it fails and
+/// retry_whole_run_on_startup_failure is set
+async fn retry_startup<T>(config: &Config, mut start: impl FnMut(&Config) -> Result<T>) -> Result<T> {
+    match start(config) {
+        Ok(started) => Ok(started),
+        Err(e) if config.quickbooks.retry_whole_run_on_startup_failure.unwrap_or(false) => {
+            let delay = config.quickbooks.startup_backoff().delay(0);
+            log::warn!("QuickBooks was not ready ({:#}); retrying the run once in {:?}", e, delay);
+            tokio::time::sleep(delay).await;
+            start(config).context("QuickBooks still not ready after retrying the run")
+        },
+        Err(e) => Err(e),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_mode::FileMode;
+    use crate::mock_quickbooks::MockQuickBooks;
+    use crate::qbxml_safe::qbxml_request_processor::AccountParser;
+    use figment::{Figment, providers::{Format, Toml}};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn config(retry: bool) -> Config {
+        let toml = format!(r#"
+            [quickbooks]
+            company_file = "AUTO"
+            retry_whole_run_on_startup_failure = {}
+            startup_retry_delay_secs = 0
+
+            [google_sheets]
+            webapp_url = "https://script.google.com/macros/s/abc123/exec"
+            api_key = "key"
+        "#, retry);
+        Figment::from(Toml::string(&toml)).extract().unwrap()
+    }
+
+    // Fails with QuickBooks still initializing on the first call, then starts a mock session
+    fn flaky_start(calls: &mut u32) -> impl FnMut(&Config) -> Result<(MockQuickBooks, String)> + '_ {
+        move |_| {
+            *calls += 1;
+            if *calls == 1 {
+                return Err(QbError::from_hresult(crate::qb_error::QB_E_NOT_INITIALIZED).unwrap().into());
+            }
+            let mock = MockQuickBooks::new([("Checking".to_string(), 12.5)].into());
+            let ticket = mock.begin_session("", FileMode::DoNotCare)?;
+            Ok((mock, ticket))
+        }
+    }
+
+    #[tokio::test]
+    async fn a_failed_start_is_retried_once_when_enabled() {
+        let mut calls = 0;
+        let (mock, ticket) = retry_startup(&config(true), flaky_start(&mut calls)).await.unwrap();
+        assert_eq!(calls, 2);
+        assert_eq!(ticket, "mock-ticket");
+        assert!(mock.get_account_xml(&ticket, &AccountQueryOptions::default()).unwrap().unwrap().contains("Checking"));
+
+        let mut calls = 0;
+        let err = retry_startup(&config(false), flaky_start(&mut calls)).await.unwrap_err();
+        assert_eq!(calls, 1);
+        assert!(matches!(err.downcast_ref::<QbError>(), Some(QbError::Busy { .. })), "{:#}", err);
+    }
+
+    #[tokio::test]
+    async fn a_second_failure_fails_the_run() {
+        let mut calls = 0;
+        let err = retry_startup(&config(true), |_| -> Result<()> {
+            calls += 1;
+            anyhow::bail!("QuickBooks is not running")
+        }).await.unwrap_err();
+        assert_eq!(calls, 2);
+        assert!(format!("{:#}", err).starts_with("QuickBooks still not ready after retrying the run"), "{:#}", err);
+    }
+
+    // MockQuickBooks that logs the session lifecycle calls made on it
+    struct Lifecycle {
+        mock: MockQuickBooks,
+        calls: Rc<RefCell<Vec<&'static str>>>,
+    }
+
+    impl QuickBooksProcessor for Lifecycle {
+        fn begin_session(&self, company_file: &str, file_mode: FileMode) -> Result<String> {
+            self.mock.begin_session(company_file, file_mode)
+        }
+
+        fn process_request(&self, ticket: &str, request: &str) -> Result<String> {
+            self.mock.process_request(ticket, request)
+        }
+
+        fn end_session(&self, _ticket: &str) -> Result<()> {
+            self.calls.borrow_mut().push("end_session");
+            Ok(())
+        }
+
+        fn close_connection(&self) -> Result<()> {
+            self.calls.borrow_mut().push("close_connection");
+            Ok(())
+        }
+
+        fn current_company_file_name(&self) -> Result<String> {
+            self.mock.current_company_file_name()
+        }
+
+        fn parser(&self) -> &AccountParser {
+            self.mock.parser()
+        }
+    }
+
+    fn session(calls: &Rc<RefCell<Vec<&'static str>>>) -> Session<Lifecycle> {
+        let mock = MockQuickBooks::new([("Checking".to_string(), 12.5)].into());
+        let ticket = mock.begin_session("", FileMode::DoNotCare).unwrap();
+        Session::new(Lifecycle { mock, calls: calls.clone() }, ticket, false)
+    }
+
+    #[test]
+    fn the_session_is_released_after_success_and_error() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let file = run_in_session(session(&calls), |session| session.company_file_name()).unwrap();
+        assert_eq!(file, "Mock Company.QBW");
+        assert_eq!(*calls.borrow(), ["end_session", "close_connection"]);
+
+        calls.borrow_mut().clear();
+        let err = run_in_session(session(&calls), |_| -> Result<()> { anyhow::bail!("query failed") }).unwrap_err();
+        assert_eq!(err.to_string(), "query failed");
+        assert_eq!(*calls.borrow(), ["end_session", "close_connection"]);
+    }
+
+    #[test]
+    fn the_session_is_released_when_the_work_panics() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let open = session(&calls);
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            run_in_session(open, |_| -> Result<()> { panic!("parser bug") })
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(*calls.borrow(), ["end_session", "close_connection"]);
+    }
+
+    #[test]
+    fn queries_share_the_session_and_end_releases_it_once() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let open = session(&calls);
+        for _ in 0..3 {
+            assert!(open.account_xml(&AccountQueryOptions::default()).unwrap().unwrap().contains("Checking"));
+        }
+        assert_eq!(open.product_name().unwrap().as_deref(), Some("QuickBooks Mock"));
+        assert!(calls.borrow().is_empty(), "the session closed between queries");
+        open.end().unwrap();
+        // end consumed the session; dropping it didn't release it a second time
+        assert_eq!(*calls.borrow(), ["end_session", "close_connection"]);
+    }
+
+    // Answers CustomerQueryRq for "Acme" and reports an error for "Broken"; anyone else is not found
+    struct Customers {
+        parser: AccountParser,
+        requests: RefCell<Vec<String>>,
+    }
+
+    impl QuickBooksProcessor for Customers {
+        fn begin_session(&self, _company_file: &str, _file_mode: FileMode) -> Result<String> {
+            Ok("ticket".to_string())
+        }
+
+        fn process_request(&self, _ticket: &str, request: &str) -> Result<String> {
+            self.requests.borrow_mut().push(request.to_string());
+            let rs = if request.contains("<FullName>Acme</FullName>") {
+                r#"<CustomerQueryRs statusCode="0" statusSeverity="Info"><CustomerRet><FullName>Acme</FullName><Balance>1250.00</Balance></CustomerRet></CustomerQueryRs>"#
+            } else if request.contains("<FullName>Broken</FullName>") {
+                r#"<CustomerQueryRs statusCode="3120" statusSeverity="Error" statusMessage="Object not found" />"#
+            } else {
+                r#"<CustomerQueryRs statusCode="1" statusSeverity="Info" statusMessage="A query request did not find a matching object in QuickBooks" />"#
+            };
+            Ok(format!("<QBXML><QBXMLMsgsRs>{}</QBXMLMsgsRs></QBXML>", rs))
+        }
+
+        fn end_session(&self, _ticket: &str) -> Result<()> {
+            Ok(())
+        }
+
+        fn close_connection(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn current_company_file_name(&self) -> Result<String> {
+            Ok(String::new())
+        }
+
+        fn parser(&self) -> &AccountParser {
+            &self.parser
+        }
+    }
+
+    #[test]
+    fn customer_balances_come_from_a_customer_query() {
+        let session = Session::new(Customers { parser: AccountParser::default(), requests: RefCell::new(Vec::new()) }, "ticket".to_string(), false);
+        assert_eq!(session.customer_balance("Acme").unwrap(), Some(1250.0));
+        assert_eq!(session.customer_balance("Nobody").unwrap(), None);
+        let err = session.customer_balance("Broken").unwrap_err();
+        assert!(matches!(err.downcast_ref::<QbError>(), Some(QbError::RequestFailed { status_code: 3120, .. })), "{:#}", err);
+
+        let requests = session.processor().requests.borrow();
+        assert!(requests[0].contains("<CustomerQueryRq>"), "{}", requests[0]);
+        assert!(requests[0].contains("<IncludeRetElement>Balance</IncludeRetElement>"), "{}", requests[0]);
+    }
+
+    #[test]
+    fn ctrl_c_stops_the_session_queries_and_still_releases_it() {
+        let shutdown: &'static Shutdown = Box::leak(Box::new(Shutdown::new()));
+        // Nothing to clean up yet: exit at once
+        assert!(shutdown.ctrl_c());
+
+        let shutdown: &'static Shutdown = Box::leak(Box::new(Shutdown::new()));
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mock = MockQuickBooks::new([("Checking".to_string(), 12.5)].into());
+        let open = Session::open(Lifecycle { mock, calls: calls.clone() }, "mock-ticket".to_string(), false, shutdown);
+        assert!(open.account_xml(&AccountQueryOptions::default()).unwrap().is_some());
+        assert!(!shutdown.ctrl_c(), "the first Ctrl+C must wait for the session to end");
+        let err = open.account_xml(&AccountQueryOptions::default()).unwrap_err();
+        assert_eq!(err.to_string(), "Interrupted by Ctrl+C");
+        assert!(open.company_file_name().is_err());
+        open.end().unwrap();
+        assert_eq!(*calls.borrow(), ["end_session", "close_connection"]);
+        assert!(!shutdown.session_open.load(Ordering::SeqCst));
+        // A second Ctrl+C exits without waiting
+        assert!(shutdown.ctrl_c());
+    }
+}
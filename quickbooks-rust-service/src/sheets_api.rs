@@ -0,0 +1,218 @@
+// Google Sheets API backend
+// Writes cells with spreadsheets.values.update using a service-account OAuth token,
+// so no Apps Script web app needs to be deployed
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use tokio::sync::Mutex;
+
+use crate::google_sheets::CellValue;
+use crate::sink::{BalanceSink, BalanceUpdate};
+
+const SHEETS_SCOPE: &str = "https://www.googleapis.com/auth/spreadsheets";
+const SHEETS_ENDPOINT: &str = "https://sheets.googleapis.com/v4/spreadsheets";
+const DEFAULT_TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+
+/// The fields used from a Google service-account JSON key file
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServiceAccountKey {
+    pub client_email: String,
+    pub private_key: String,
+    #[serde(default)]
+    pub token_uri: Option<String>,
+}
+
+impl ServiceAccountKey {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read service account key {}", path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("Failed to parse service account key {}", path.display()))
+    }
+}
+
+#[derive(Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: i64,
+    exp: i64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: i64,
+}
+
+#[derive(Serialize)]
+struct ValueRange<'a> {
+    range: &'a str,
+    values: [[&'a CellValue; 1]; 1],
+}
+
+pub struct SheetsApiClient {
+    key: ServiceAccountKey,
+    endpoint: String,
+    token: Mutex<Option<(String, DateTime<Utc>)>>,
+}
+
+static SHARED: OnceLock<Arc<SheetsApiClient>> = OnceLock::new();
+
+impl SheetsApiClient {
+    pub fn new(key: ServiceAccountKey) -> Self {
+        Self { key, endpoint: SHEETS_ENDPOINT.to_string(), token: Mutex::new(None) }
+    }
+
+    #[cfg(test)]
+    fn with_endpoint(mut self, endpoint: &str) -> Self {
+        self.endpoint = endpoint.to_string();
+        self
+    }
+
+    /// One client per process so the access token is reused across blocks
+    pub fn shared(key_path: &str) -> Result<Arc<Self>> {
+        if let Some(client) = SHARED.get() {
+            return Ok(client.clone());
+        }
+        let client = Arc::new(Self::new(ServiceAccountKey::load(Path::new(key_path))?));
+        Ok(SHARED.get_or_init(|| client).clone())
+    }
+
+    /// A cached access token, refreshed a minute before it expires
+    async fn access_token(&self) -> Result<String> {
+        let mut token = self.token.lock().await;
+        if let Some((value, expires_at)) = token.as_ref() {
+            if *expires_at - Duration::seconds(60) > Utc::now() {
+                return Ok(value.clone());
+            }
+        }
+        let token_uri = self.key.token_uri.as_deref().unwrap_or(DEFAULT_TOKEN_URI);
+        let now = Utc::now();
+        let claims = Claims {
+            iss: &self.key.client_email,
+            scope: SHEETS_SCOPE,
+            aud: token_uri,
+            iat: now.timestamp(),
+            exp: (now + Duration::hours(1)).timestamp(),
+        };
+        let signing_key = jsonwebtoken::EncodingKey::from_rsa_pem(self.key.private_key.as_bytes())
+            .context("Service account private_key is not a valid RSA PEM key")?;
+        let assertion = jsonwebtoken::encode(&jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256), &claims, &signing_key)
+            .context("Failed to sign service account token request")?;
+        let res = reqwest::Client::new()
+            .post(token_uri)
+            .form(&[("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"), ("assertion", assertion.as_str())])
+            .send()
+            .await
+            .context("Failed to request a Google OAuth token")?;
+        if !res.status().is_success() {
+            let status = res.status();
+            let text = res.text().await.unwrap_or_default();
+            anyhow::bail!("Google OAuth token request returned error: {} - {}", status, text);
+        }
+        let response: TokenResponse = res.json().await.context("Failed to parse Google OAuth token response")?;
+        *token = Some((response.access_token.clone(), now + Duration::seconds(response.expires_in)));
+        Ok(response.access_token)
+    }
+
+    /// Write one cell; `range` is A1 notation with the sheet, e.g. "'My Sheet'!B2"
+    pub async fn update_cell(&self, spreadsheet_id: &str, range: &str, value: &CellValue) -> Result<()> {
+        let token = self.access_token().await?;
+        let mut url = reqwest::Url::parse(&self.endpoint)?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("Invalid Google Sheets API endpoint"))?
+            .extend([spreadsheet_id, "values", range]);
+        let res = reqwest::Client::new()
+            .put(url)
+            .bearer_auth(token)
+            .query(&[("valueInputOption", "USER_ENTERED")])
+            .json(&ValueRange { range, values: [[value]] })
+            .send()
+            .await
+            .context("Failed to send update to the Google Sheets API")?;
+        if !res.status().is_success() {
+            let status = res.status();
+            let text = res.text().await.unwrap_or_default();
+            anyhow::bail!("Google Sheets API returned error writing {}: {} - {}", range, status, text);
+        }
+        Ok(())
+    }
+}
+
+/// One target cell written through the Sheets API
+pub struct SheetsApiSink {
+    pub client: Arc<SheetsApiClient>,
+    pub spreadsheet_id: String,
+    pub range: String,
+}
+
+#[async_trait]
+impl BalanceSink for SheetsApiSink {
+    fn describe(&self) -> String {
+        format!("Google Sheets API {}", self.range)
+    }
+
+    async fn send(&self, update: &BalanceUpdate<'_>) -> Result<()> {
+        self.client.update_cell(&self.spreadsheet_id, &self.range, &CellValue::Number(update.balance)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+
+    // Responses as recorded from the OAuth token endpoint and spreadsheets.values.update
+    const TOKEN_RESPONSE: &str = r#"{"access_token": "ya29.recorded-token", "expires_in": 3599, "token_type": "Bearer"}"#;
+    const UPDATE_RESPONSE: &str = r#"{"spreadsheetId": "1AbC", "updatedRange": "'My Sheet'!B2", "updatedRows": 1, "updatedColumns": 1, "updatedCells": 1}"#;
+    const PERMISSION_DENIED: &str = r#"{"error": {"code": 403, "message": "The caller does not have permission", "status": "PERMISSION_DENIED"}}"#;
+
+    fn client(server: &TestServer) -> SheetsApiClient {
+        let key = ServiceAccountKey {
+            client_email: "qb-sync@example.iam.gserviceaccount.com".to_string(),
+            private_key: include_str!("../tests/fixtures/service_account_key.pem").to_string(),
+            token_uri: Some(format!("{}/token", server.url)),
+        };
+        SheetsApiClient::new(key).with_endpoint(&format!("{}/v4/spreadsheets", server.url))
+    }
+
+    #[tokio::test]
+    async fn update_cell_signs_in_once_and_puts_the_value() {
+        let server = TestServer::start(vec![(200, TOKEN_RESPONSE.to_string()), (200, UPDATE_RESPONSE.to_string())]).await;
+        let client = client(&server);
+        client.update_cell("1AbC", "'My Sheet'!B2", &CellValue::Number(1234.5)).await.unwrap();
+        client.update_cell("1AbC", "'My Sheet'!B3", &CellValue::Text("1,234.50".to_string())).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3, "the token is reused for the second write");
+        assert!(requests[0].request_line.starts_with("POST /token "));
+        assert!(requests[0].body.contains("grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Ajwt-bearer&assertion="), "{}", requests[0].body);
+        assert!(requests[1].request_line.starts_with("PUT /v4/spreadsheets/1AbC/values/'My%20Sheet'!B2?valueInputOption=USER_ENTERED "), "{}", requests[1].request_line);
+        assert_eq!(requests[1].header("authorization"), Some("Bearer ya29.recorded-token"));
+        assert_eq!(requests[1].json(), serde_json::json!({"range": "'My Sheet'!B2", "values": [[1234.5]]}));
+        assert_eq!(requests[2].json(), serde_json::json!({"range": "'My Sheet'!B3", "values": [["1,234.50"]]}));
+    }
+
+    #[tokio::test]
+    async fn api_errors_name_the_range() {
+        let server = TestServer::start(vec![(200, TOKEN_RESPONSE.to_string()), (403, PERMISSION_DENIED.to_string())]).await;
+        let err = client(&server).update_cell("1AbC", "Balances!B2", &CellValue::Number(1.0)).await.unwrap_err();
+        assert!(err.to_string().starts_with("Google Sheets API returned error writing Balances!B2: 403"), "{}", err);
+        assert!(err.to_string().contains("The caller does not have permission"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn a_rejected_token_request_fails_before_writing() {
+        let denied = r#"{"error": "invalid_grant", "error_description": "Invalid JWT Signature."}"#;
+        let server = TestServer::start(vec![(400, denied.to_string())]).await;
+        let err = client(&server).update_cell("1AbC", "Balances!B2", &CellValue::Number(1.0)).await.unwrap_err();
+        assert!(err.to_string().starts_with("Google OAuth token request returned error: 400"), "{}", err);
+        assert_eq!(server.requests().len(), 1);
+    }
+}
@@ -0,0 +1,31 @@
+// Balance sinks
+// Destinations that receive each synced account balance (Google Sheets, webhooks, ...)
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+/// One account's balance as delivered to every sink
+#[derive(Debug, Clone, Copy)]
+pub struct BalanceUpdate<'a> {
+    pub account_full_name: &'a str,
+    // The QuickBooks AccountNumber, when known and not empty
+    pub account_number: Option<&'a str>,
+    pub balance: f64,
+    pub timestamp: &'a str,
+}
+
+#[async_trait]
+pub trait BalanceSink: Send + Sync {
+    /// Short human-readable name for logs
+    fn describe(&self) -> String;
+
+    async fn send(&self, update: &BalanceUpdate<'_>) -> Result<()>;
+
+    /// Deliver a whole run's balances; sinks that keep one snapshot per run override this
+    async fn send_all(&self, updates: &[BalanceUpdate<'_>]) -> Result<()> {
+        for update in updates {
+            self.send(update).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+// Timestamp cells
+// Formats the run time for a sync block's timestamp cell with a strftime pattern and time zone
+
+use anyhow::{anyhow, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, Utc};
+use chrono_tz::Tz;
+
+/// RFC 3339, e.g. 2024-01-15T10:00:00-05:00
+pub const DEFAULT_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%:z";
+
+/// Refuse patterns chrono can't render; formatting one would panic mid-run
+pub fn validate_format(format: &str) -> Result<()> {
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(anyhow!("'{}' is not a valid strftime pattern", format));
+    }
+    Ok(())
+}
+
+/// An IANA time zone name such as "America/New_York"
+pub fn parse_timezone(name: &str) -> Result<Tz> {
+    name.parse::<Tz>().map_err(|_| anyhow!("'{}' is not a known IANA time zone", name))
+}
+
+/// `now` rendered with `format` in `timezone`, or in the machine's local zone when none is given
+pub fn format_timestamp(now: DateTime<Utc>, format: &str, timezone: Option<Tz>) -> String {
+    match timezone {
+        Some(tz) => now.with_timezone(&tz).format(format).to_string(),
+        None => now.with_timezone(&Local).format(format).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2024-01-15T15:00:00Z").unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn formats_render_in_the_configured_zone() {
+        let new_york = parse_timezone("America/New_York").ok();
+        assert_eq!(format_timestamp(now(), DEFAULT_FORMAT, new_york), "2024-01-15T10:00:00-05:00");
+        assert_eq!(format_timestamp(now(), "%-m/%-d/%Y %-I:%M %p", new_york), "1/15/2024 10:00 AM");
+        assert_eq!(format_timestamp(now(), "%d.%m.%Y %H:%M", parse_timezone("Europe/Berlin").ok()), "15.01.2024 16:00");
+        assert_eq!(format_timestamp(now(), "%Y-%m-%d %H:%M %Z", parse_timezone("UTC").ok()), "2024-01-15 15:00 UTC");
+    }
+
+    #[test]
+    fn bad_patterns_and_zones_are_refused() {
+        validate_format("%-m/%-d/%Y %-I:%M %p").unwrap();
+        assert_eq!(validate_format("%Y-%Q").unwrap_err().to_string(), "'%Y-%Q' is not a valid strftime pattern");
+        assert_eq!(parse_timezone("Eastern").unwrap_err().to_string(), "'Eastern' is not a known IANA time zone");
+    }
+}
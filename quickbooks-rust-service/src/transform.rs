@@ -0,0 +1,35 @@
+// Per-block balance transforms
+// Adjustments applied to a QuickBooks balance before it is written to a block's cells
+
+use crate::account_type::AccountType;
+use crate::config::SignConvention;
+
+impl SignConvention {
+    pub fn apply(self, balance: f64, account_type: &AccountType) -> f64 {
+        match self {
+            SignConvention::AsIs => balance,
+            SignConvention::FlipLiability if account_type.is_liability() => -balance,
+            SignConvention::FlipLiability => balance,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flip_liability_negates_only_liabilities() {
+        let flip = SignConvention::FlipLiability;
+        assert_eq!(flip.apply(1500.25, &AccountType::CreditCard), -1500.25);
+        assert_eq!(flip.apply(-200.0, &AccountType::from("LongTermLiability")), 200.0);
+        assert_eq!(flip.apply(1500.25, &AccountType::Bank), 1500.25);
+        assert_eq!(flip.apply(-3.0, &AccountType::from("OtherCurrentAsset")), -3.0);
+    }
+
+    #[test]
+    fn as_is_leaves_every_balance_alone() {
+        assert_eq!(SignConvention::AsIs.apply(1500.25, &AccountType::CreditCard), 1500.25);
+        assert_eq!(SignConvention::default().apply(-7.5, &AccountType::from("AccountsPayable")), -7.5);
+    }
+}
@@ -0,0 +1,123 @@
+// Type-grouped report
+// Lays out every account under a header row per account type, starting at the report's anchor
+
+use anyhow::Result;
+
+use crate::account_type::AccountType;
+use crate::config::TypeReportConfig;
+use crate::google_sheets::CellValue;
+use crate::row_block::RowBlock;
+use crate::AccountData;
+
+const UNKNOWN_TYPE_HEADER: &str = "Other";
+
+impl TypeReportConfig {
+    /// Group `accounts` by type, keeping the order in which each type first appears
+    /// (QuickBooks returns accounts sorted by type) and the account order within a type
+    pub fn layout(&self, accounts: &[AccountData]) -> Result<RowBlock> {
+        let mut groups: Vec<(&AccountType, Vec<&AccountData>)> = Vec::new();
+        let include_zero = self.include_zero_balance.unwrap_or(true);
+        for account in accounts.iter().filter(|a| include_zero || !a.has_zero_balance()) {
+            match groups.iter_mut().find(|(t, _)| **t == account.account_type) {
+                Some((_, members)) => members.push(account),
+                None => groups.push((&account.account_type, vec![account])),
+            }
+        }
+        let sign = self.sign_convention.unwrap_or_default();
+        let mut rows = Vec::with_capacity(groups.len() + accounts.len());
+        for (account_type, members) in groups {
+            let header = match account_type.as_str() { "" => UNKNOWN_TYPE_HEADER, text => text };
+            rows.push(vec![CellValue::Text(header.to_string()), CellValue::Text(String::new())]);
+            for account in members {
+                rows.push(vec![
+                    CellValue::Text(account.account_full_name.clone()),
+                    CellValue::Number(sign.apply(account.balance, &account.account_type)),
+                ]);
+            }
+        }
+        RowBlock::new(&self.anchor, rows, self.clear_range.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SignConvention;
+
+    fn account(name: &str, account_type: AccountType, balance: f64, raw_balance: Option<&str>) -> AccountData {
+        AccountData {
+            account_full_name: name.to_string(),
+            number: String::new(),
+            account_type,
+            description: None,
+            balance,
+            total_balance: None,
+            raw_balance: raw_balance.map(str::to_string),
+            raw_total_balance: None,
+        }
+    }
+
+    fn report(include_zero_balance: Option<bool>) -> TypeReportConfig {
+        TypeReportConfig {
+            spreadsheet_id: "sheet".to_string(),
+            sheet_name: "Accounts".to_string(),
+            anchor: "A1".to_string(),
+            clear_range: None,
+            sign_convention: Some(SignConvention::FlipLiability),
+            include_zero_balance,
+        }
+    }
+
+    fn accounts() -> Vec<AccountData> {
+        vec![
+            account("Checking", AccountType::Bank, 0.0, Some("0.00")),
+            account("Savings", AccountType::Bank, 250.0, Some("250.00")),
+            // No Balance reported: 0.0 is only the fallback
+            account("Visa", AccountType::CreditCard, 0.0, None),
+            account("Amex", AccountType::CreditCard, 75.0, Some("75.00")),
+            account("Suspense", AccountType::default(), 10.0, Some("10.00")),
+        ]
+    }
+
+    fn text(value: &str) -> CellValue {
+        CellValue::Text(value.to_string())
+    }
+
+    #[test]
+    fn layout_groups_accounts_under_type_headers() {
+        let layout = report(None).layout(&accounts()).unwrap();
+        assert_eq!(layout.rows, vec![
+            vec![text("Bank"), text("")],
+            vec![text("Checking"), CellValue::Number(0.0)],
+            vec![text("Savings"), CellValue::Number(250.0)],
+            vec![text("CreditCard"), text("")],
+            vec![text("Visa"), CellValue::Number(-0.0)],
+            vec![text("Amex"), CellValue::Number(-75.0)],
+            vec![text(UNKNOWN_TYPE_HEADER), text("")],
+            vec![text("Suspense"), CellValue::Number(10.0)],
+        ]);
+        assert_eq!(layout.clear, None);
+    }
+
+    #[test]
+    fn excluding_zero_balances_keeps_unreported_ones() {
+        let layout = report(Some(false)).layout(&accounts()).unwrap();
+        let names: Vec<&CellValue> = layout.rows.iter().map(|row| &row[0]).collect();
+        assert_eq!(names, [&text("Bank"), &text("Savings"), &text("CreditCard"), &text("Visa"), &text("Amex"), &text(UNKNOWN_TYPE_HEADER), &text("Suspense")]);
+    }
+
+    #[test]
+    fn rows_left_over_from_a_longer_run_are_cleared() {
+        let mut config = TypeReportConfig { anchor: "A3".to_string(), clear_range: Some("A3:B20".to_string()), ..report(None) };
+        // Eight rows fill A3:B10, leaving A11:B20 to clear
+        let layout = config.layout(&accounts()).unwrap();
+        assert_eq!(layout.clear.map(|range| range.to_string()), Some("A11:B20".to_string()));
+        // One account fewer empties one more row
+        let layout = config.layout(&accounts()[1..]).unwrap();
+        assert_eq!(layout.rows.len(), 7);
+        assert_eq!(layout.clear.map(|range| range.to_string()), Some("A10:B20".to_string()));
+        // A report that fills the whole range leaves nothing to clear
+        config.clear_range = Some("A3:B10".to_string());
+        assert_eq!(config.layout(&accounts()).unwrap().clear, None);
+    }
+}
@@ -0,0 +1,61 @@
+// Value guard
+// Checks numbers before they reach Google Sheets, which rejects some values with opaque errors
+
+use anyhow::Result;
+
+use crate::config::{GoogleSheetsConfig, OutOfRangePolicy};
+use crate::google_sheets::CellValue;
+
+/// Preflight for numeric cell values: non-finite numbers are always refused (JSON has no NaN or
+/// infinity), magnitudes above `max_abs` are refused or clamped, and `round_decimals` rounds.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ValueGuard {
+    pub max_abs: Option<f64>,
+    pub policy: OutOfRangePolicy,
+    pub round_decimals: Option<u32>,
+}
+
+impl ValueGuard {
+    pub fn from_config(config: &GoogleSheetsConfig) -> Self {
+        Self {
+            max_abs: config.max_abs_value,
+            policy: config.out_of_range.unwrap_or_default(),
+            round_decimals: config.round_decimals,
+        }
+    }
+
+    /// The value to send to `cell`; text passes through untouched
+    pub fn check(&self, value: &CellValue, cell: &str) -> Result<CellValue> {
+        let CellValue::Number(number) = value else {
+            return Ok(value.clone());
+        };
+        let mut number = *number;
+        if !number.is_finite() {
+            anyhow::bail!("value for {} is {}, which Google Sheets cannot store; check the QuickBooks balance", cell, number);
+        }
+        if let Some(max_abs) = self.max_abs {
+            if number.abs() > max_abs {
+                match self.policy {
+                    OutOfRangePolicy::Error => anyhow::bail!(
+                        "value {} for {} exceeds max_abs_value ({}); this usually means a bad balance upstream",
+                        number, cell, max_abs
+                    ),
+                    OutOfRangePolicy::Clamp => {
+                        let clamped = number.clamp(-max_abs, max_abs);
+                        log::warn!("[SHEETS] Clamping {} for {} to {}", number, cell, clamped);
+                        number = clamped;
+                    },
+                }
+            }
+        }
+        if let Some(decimals) = self.round_decimals {
+            let scale = 10f64.powi(decimals.min(15) as i32);
+            let rounded = (number * scale).round() / scale;
+            // Scaling a huge value can overflow; keep the original rather than send infinity
+            if rounded.is_finite() {
+                number = rounded;
+            }
+        }
+        Ok(CellValue::Number(number))
+    }
+}
@@ -0,0 +1,141 @@
+// Generic webhook sink
+// POSTs each balance as a JSON body rendered from a configurable template
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+
+use crate::config::WebhookSinkConfig;
+use crate::sink::{BalanceSink, BalanceUpdate};
+
+const DEFAULT_TEMPLATE: &str = r#"{"account": "{{account}}", "balance": {{balance}}, "timestamp": "{{timestamp}}"}"#;
+
+pub struct WebhookSink {
+    pub url: String,
+    pub template: String,
+}
+
+impl WebhookSink {
+    pub fn from_config(config: &WebhookSinkConfig) -> Self {
+        Self {
+            url: config.url.clone(),
+            template: config.body_template.clone().unwrap_or_else(|| DEFAULT_TEMPLATE.to_string()),
+        }
+    }
+
+    /// Render with sample values and make sure the result is JSON
+    pub fn check_template(&self) -> Result<()> {
+        let sample = BalanceUpdate {
+            account_full_name: "Sample \"Account\"",
+            account_number: Some("1000"),
+            balance: -1234.56,
+            timestamp: "2024-01-01T00:00:00+00:00",
+        };
+        let body = render_template(&self.template, &sample)?;
+        serde_json::from_str::<serde_json::Value>(&body)
+            .with_context(|| format!("Template does not render to valid JSON: {}", body))?;
+        Ok(())
+    }
+}
+
+/// Substitute placeholders in a single pass so values containing "{{...}}" are never re-expanded.
+/// Strings are inserted JSON-escaped without quotes, so templates write `"{{account}}"`.
+pub fn render_template(template: &str, update: &BalanceUpdate<'_>) -> Result<String> {
+    let mut out = String::with_capacity(template.len() + 64);
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| anyhow::anyhow!("Unterminated placeholder in webhook template"))?;
+        match after[..end].trim() {
+            "account" => out.push_str(&json_escape(update.account_full_name)),
+            "balance" => out.push_str(&update.balance.to_string()),
+            "timestamp" => out.push_str(&json_escape(update.timestamp)),
+            other => anyhow::bail!("Unknown placeholder '{{{{{}}}}}' in webhook template", other),
+        }
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+// JSON string-literal escaping without the surrounding quotes
+fn json_escape(value: &str) -> String {
+    let quoted = serde_json::to_string(value).unwrap_or_default();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+#[async_trait]
+impl BalanceSink for WebhookSink {
+    fn describe(&self) -> String {
+        format!("webhook {}", self.url)
+    }
+
+    async fn send(&self, update: &BalanceUpdate<'_>) -> Result<()> {
+        let body = render_template(&self.template, update)?;
+        let client = reqwest::Client::new();
+        let res = client.post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .with_context(|| format!("Failed to send POST to webhook {}", self.url))?;
+        if !res.status().is_success() {
+            let status = res.status();
+            let text = res.text().await.unwrap_or_default();
+            anyhow::bail!("Webhook {} returned error: {} - {}", self.url, status, text);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+
+    const UPDATE: BalanceUpdate<'static> = BalanceUpdate {
+        account_full_name: "Loans \"Long-term\" {{balance}}",
+        account_number: None,
+        balance: -1234.5,
+        timestamp: "2024-03-01T12:00:00+00:00",
+    };
+
+    #[test]
+    fn render_escapes_values_and_never_re_expands_them() {
+        let body = render_template(DEFAULT_TEMPLATE, &UPDATE).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json, serde_json::json!({
+            "account": "Loans \"Long-term\" {{balance}}",
+            "balance": -1234.5,
+            "timestamp": "2024-03-01T12:00:00+00:00",
+        }));
+        assert_eq!(render_template("{{ balance }}", &UPDATE).unwrap(), "-1234.5");
+    }
+
+    #[test]
+    fn render_rejects_bad_placeholders() {
+        assert_eq!(render_template("{{owner}}", &UPDATE).unwrap_err().to_string(), "Unknown placeholder '{{owner}}' in webhook template");
+        assert!(render_template(r#"{"account": "{{account"}"#, &UPDATE).is_err());
+        let sink = WebhookSink { url: String::new(), template: r#"{"balance": "{{balance}}"#.to_string() };
+        assert!(sink.check_template().is_err());
+    }
+
+    #[tokio::test]
+    async fn send_posts_the_rendered_body() {
+        let server = TestServer::start(vec![(200, String::new()), (500, "boom".to_string())]).await;
+        let sink = WebhookSink {
+            url: format!("{}/hook", server.url),
+            template: r#"{"text": "{{account}} is {{balance}}"}"#.to_string(),
+        };
+        sink.send(&UPDATE).await.unwrap();
+        let err = sink.send(&UPDATE).await.unwrap_err();
+        assert!(err.to_string().ends_with("returned error: 500 Internal Server Error - boom"), "{}", err);
+
+        let requests = server.requests();
+        assert!(requests[0].request_line.starts_with("POST /hook "));
+        assert_eq!(requests[0].header("content-type"), Some("application/json"));
+        assert_eq!(requests[0].json(), serde_json::json!({"text": "Loans \"Long-term\" {{balance}} is -1234.5"}));
+    }
+}
@@ -0,0 +1,186 @@
+// Write planning
+// Works out the value and target cells of each sync block before anything is written,
+// and coalesces writes that would hit the same cell twice in one run
+
+use anyhow::Result;
+use log::info;
+use std::collections::HashMap;
+
+use crate::config::{AccountSyncConfig, DuplicateCellPolicy};
+use crate::google_sheets::CellValue;
+use crate::AccountData;
+
+/// What one sync block writes: the balance (after sign conventions) and the cells it goes to
+pub struct BlockWrite<'a> {
+    // Position of the sync block in the config, for reporting
+    pub index: usize,
+    pub sync: &'a AccountSyncConfig,
+    pub balance: f64,
+    pub cell_value: CellValue,
+    pub targets: Vec<(String, String)>,
+}
+
+impl<'a> BlockWrite<'a> {
+    pub fn plan(index: usize, sync: &'a AccountSyncConfig, account: &AccountData) -> Self {
+        let (balance, raw) = match (sync.total_balance.unwrap_or(false), account.total_balance) {
+            (true, Some(total)) => (total, account.raw_total_balance.as_deref()),
+            (true, None) => {
+                log::warn!("[QBXML] No TotalBalance for '{}'; writing its own Balance", sync.account_full_name);
+                (account.balance, account.raw_balance.as_deref())
+            },
+            (false, _) => (account.balance, account.raw_balance.as_deref()),
+        };
+        let account_balance = sync.sign_convention.unwrap_or_default().apply(balance, &account.account_type);
+        let flipped = account_balance != balance;
+        if flipped {
+            info!("[QBXML] Account '{}' is a {} account; writing {} per sign_convention", sync.account_full_name, account.account_type, account_balance);
+        }
+        let cell_value = match (sync.raw_balance.unwrap_or(false), raw, &sync.currency_format) {
+            (_, _, Some(format)) => CellValue::Text(format.format(account_balance)),
+            (true, Some(raw), None) => CellValue::Text(if flipped { negate_amount_text(raw) } else { raw.to_string() }),
+            (true, None, None) => {
+                log::warn!("[QBXML] No raw balance text for '{}' (cached data?); writing the parsed number", sync.account_full_name);
+                CellValue::Number(account_balance)
+            },
+            (false, _, None) => CellValue::Number(account_balance),
+        };
+        Self { index, sync, balance: account_balance, cell_value, targets: sync.targets() }
+    }
+}
+
+// Flip the sign of an amount written as text, keeping its digits untouched
+fn negate_amount_text(raw: &str) -> String {
+    match raw.trim().strip_prefix('-') {
+        Some(positive) => positive.to_string(),
+        None => format!("-{}", raw.trim()),
+    }
+}
+
+/// Make sure no cell is written twice. When blocks target the same spreadsheet/sheet/cell the
+/// last block in config order keeps it; earlier blocks drop that target. Conflicting values
+/// are an error under `strict` and a warning under `lenient`; identical values pass quietly.
+pub fn coalesce(writes: &mut [BlockWrite<'_>], policy: DuplicateCellPolicy) -> Result<()> {
+    let mut owner: HashMap<(String, String, String), usize> = HashMap::new();
+    let mut dropped: Vec<(usize, String, String)> = Vec::new();
+    let mut conflicts = Vec::new();
+    for (index, write) in writes.iter().enumerate() {
+        for (sheet, cell) in &write.targets {
+            let key = (write.sync.spreadsheet_id.clone(), sheet.clone(), cell.trim().to_ascii_uppercase());
+            if let Some(previous) = owner.insert(key, index) {
+                let earlier = &writes[previous];
+                if earlier.cell_value != write.cell_value {
+                    conflicts.push(format!(
+                        "'{}'!{} is written by '{}' ({:?}) and '{}' ({:?})",
+                        sheet, cell, earlier.sync.account_full_name, earlier.cell_value, write.sync.account_full_name, write.cell_value
+                    ));
+                }
+                dropped.push((previous, sheet.clone(), cell.trim().to_ascii_uppercase()));
+            }
+        }
+    }
+    if !conflicts.is_empty() {
+        match policy {
+            DuplicateCellPolicy::Strict => anyhow::bail!("Conflicting writes to the same cell: {}", conflicts.join("; ")),
+            DuplicateCellPolicy::Lenient => {
+                for conflict in &conflicts {
+                    log::warn!("[SHEETS] {}; the last block in config order wins", conflict);
+                }
+            },
+        }
+    }
+    for (index, sheet, cell) in dropped {
+        writes[index].targets.retain(|(s, c)| !(s == &sheet && c.trim().to_ascii_uppercase() == cell));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account_type::AccountType;
+    use crate::config::{CellTargets, CurrencyFormat, SignConvention};
+
+    fn sync(account: &str, spreadsheet_id: &str, cells: &[&str]) -> AccountSyncConfig {
+        AccountSyncConfig {
+            spreadsheet_id: spreadsheet_id.to_string(),
+            account_full_name: account.to_string(),
+            sheet_name: "Balances".to_string(),
+            cell_address: CellTargets::Many(cells.iter().map(|cell| cell.to_string()).collect()),
+            ..Default::default()
+        }
+    }
+
+    fn account(account_type: AccountType, balance: f64, raw_balance: &str) -> AccountData {
+        AccountData {
+            account_full_name: String::new(),
+            number: String::new(),
+            account_type,
+            description: None,
+            balance,
+            total_balance: None,
+            raw_balance: Some(raw_balance.to_string()),
+            raw_total_balance: None,
+        }
+    }
+
+    #[test]
+    fn plan_applies_the_sign_convention_to_numbers_and_raw_text() {
+        let visa = account(AccountType::CreditCard, 1500.25, "1500.25");
+        let mut block = sync("Visa", "sheet", &["B2", "Summary!C3"]);
+        block.sign_convention = Some(SignConvention::FlipLiability);
+        let write = BlockWrite::plan(4, &block, &visa);
+        assert_eq!(write.index, 4);
+        assert_eq!(write.cell_value, CellValue::Number(-1500.25));
+        assert_eq!(write.targets, [("Balances".to_string(), "B2".to_string()), ("Summary".to_string(), "C3".to_string())]);
+
+        block.raw_balance = Some(true);
+        assert_eq!(BlockWrite::plan(0, &block, &visa).cell_value, CellValue::Text("-1500.25".to_string()));
+        assert_eq!(negate_amount_text(" -20.00 "), "20.00");
+    }
+
+    #[test]
+    fn raw_balance_forwards_the_quickbooks_text_unmodified() {
+        let checking = account(AccountType::Bank, 1234.5, "1234.50");
+        let mut block = sync("Checking", "sheet", &["B2"]);
+        assert_eq!(BlockWrite::plan(0, &block, &checking).cell_value, CellValue::Number(1234.5));
+        block.raw_balance = Some(true);
+        let raw = BlockWrite::plan(0, &block, &checking).cell_value;
+        assert_eq!(raw, CellValue::Text("1234.50".to_string()));
+        assert_eq!(serde_json::to_value(&raw).unwrap(), serde_json::json!("1234.50"));
+        // Without the text (e.g. cached data) the parsed number is written instead
+        let cached = AccountData { raw_balance: None, ..checking };
+        assert_eq!(BlockWrite::plan(0, &block, &cached).cell_value, CellValue::Number(1234.5));
+    }
+
+    #[test]
+    fn currency_format_writes_the_balance_as_text() {
+        let checking = account(AccountType::Bank, 1234.56, "1234.56");
+        let mut block = sync("Checking", "sheet", &["B2"]);
+        block.currency_format = Some(CurrencyFormat::default());
+        assert_eq!(BlockWrite::plan(0, &block, &checking).cell_value, CellValue::Text("$1,234.56".to_string()));
+    }
+
+    #[test]
+    fn coalesce_keeps_the_last_writer_of_a_cell() {
+        let checking = account(AccountType::Bank, 100.0, "100.00");
+        let (first, second, other) = (sync("Checking", "sheet", &["B2", "B3"]), sync("Checking", "sheet", &["b2"]), sync("Checking", "other", &["B2"]));
+        let mut writes = vec![BlockWrite::plan(0, &first, &checking), BlockWrite::plan(1, &second, &checking), BlockWrite::plan(2, &other, &checking)];
+        coalesce(&mut writes, DuplicateCellPolicy::Strict).unwrap();
+        let targets: Vec<usize> = writes.iter().map(|write| write.targets.len()).collect();
+        assert_eq!(targets, [1, 1, 1]);
+        assert_eq!(writes[0].targets[0].1, "B3");
+    }
+
+    #[test]
+    fn conflicting_values_fail_only_under_strict() {
+        let (low, high) = (account(AccountType::Bank, 1.0, "1.00"), account(AccountType::Bank, 2.0, "2.00"));
+        let (first, second) = (sync("Checking", "sheet", &["B2"]), sync("Savings", "sheet", &["B2"]));
+        let plan = || vec![BlockWrite::plan(0, &first, &low), BlockWrite::plan(1, &second, &high)];
+        let err = coalesce(&mut plan(), DuplicateCellPolicy::Strict).unwrap_err();
+        assert!(err.to_string().starts_with("Conflicting writes to the same cell: 'Balances'!B2"), "{}", err);
+        let mut writes = plan();
+        coalesce(&mut writes, DuplicateCellPolicy::Lenient).unwrap();
+        assert!(writes[0].targets.is_empty());
+        assert_eq!(writes[1].targets.len(), 1);
+    }
+}
@@ -0,0 +1,62 @@
+// Drives MockQuickBooks through the QuickBooksProcessor trait, as the sync does off Windows
+
+use std::collections::BTreeMap;
+
+use quickbooks_sheets_sync::file_mode::FileMode;
+use quickbooks_sheets_sync::mock_quickbooks::MockQuickBooks;
+use quickbooks_sheets_sync::qbxml_safe::qbxml_request_processor::{
+    count_transactions, customer_query_request, product_name, transaction_count_request, AccountQueryOptions,
+    MatchCriterion, NameFilter, QBXML_VERSION,
+};
+use quickbooks_sheets_sync::quickbooks::QuickBooksProcessor;
+
+fn mock() -> MockQuickBooks {
+    let balances = [("Checking", 1234.567), ("Savings", 0.1 + 0.2), ("Petty Cash", -5.0), ("Payroll", 10.0), ("Escrow", 99.99)];
+    MockQuickBooks::new(balances.iter().map(|(name, balance)| (name.to_string(), *balance)).collect::<BTreeMap<_, _>>())
+}
+
+fn balances(processor: &dyn QuickBooksProcessor, options: &AccountQueryOptions) -> BTreeMap<String, f64> {
+    let ticket = processor.begin_session("", FileMode::DoNotCare).unwrap();
+    let xml = processor.get_account_xml(&ticket, options).unwrap().unwrap();
+    processor.end_session(&ticket).unwrap();
+    processor.close_connection().unwrap();
+    processor.get_account_balances(&xml).unwrap().into_iter().collect()
+}
+
+#[test]
+fn balances_round_trip_unrounded() {
+    let read = balances(&mock(), &AccountQueryOptions::default());
+    assert_eq!(read.len(), 5);
+    assert_eq!(read["Checking"], 1234.567);
+    assert_eq!(read["Savings"], 0.1 + 0.2);
+    assert_eq!(read["Petty Cash"], -5.0);
+}
+
+#[test]
+fn paged_and_batched_queries_read_every_account() {
+    let all = balances(&mock(), &AccountQueryOptions::default());
+    let paged = balances(&mock(), &AccountQueryOptions { page_size: Some(2), ..Default::default() });
+    let batched = balances(&mock(), &AccountQueryOptions { by_type_batches: true, ..Default::default() });
+    assert_eq!(paged, all);
+    assert_eq!(batched, all);
+}
+
+#[test]
+fn name_filter_limits_the_accounts_returned() {
+    let filter = NameFilter { match_criterion: MatchCriterion::StartsWith, name: "p".to_string() };
+    let read = balances(&mock(), &AccountQueryOptions { name_filter: Some(filter), ..Default::default() });
+    assert_eq!(read.keys().collect::<Vec<_>>(), ["Payroll", "Petty Cash"]);
+}
+
+#[test]
+fn other_queries_find_nothing() {
+    let mock = mock();
+    let ticket = mock.begin_session("", FileMode::DoNotCare).unwrap();
+    assert_eq!(product_name(&mock.host_query(&ticket).unwrap()).unwrap().as_deref(), Some("QuickBooks Mock"));
+    mock.ping(&ticket).unwrap();
+    let day = chrono::NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+    let txns = mock.run_query(&ticket, &transaction_count_request("Checking", day, day), QBXML_VERSION).unwrap();
+    assert_eq!(count_transactions(&txns).unwrap(), 0);
+    let customer = mock.run_query(&ticket, &customer_query_request("Acme"), QBXML_VERSION).unwrap();
+    assert_eq!(mock.parser().get_customer_balance(&customer, "Acme").unwrap(), None);
+}